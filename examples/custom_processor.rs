@@ -0,0 +1,66 @@
+//! Opens the viewer with an extra slice post-processor to choose from, one that traces the slice as it is, without
+//! closing any gaps. Run with `cargo run --example custom_processor -- <point cloud file>`.
+
+use glium::glutin;
+use image::{Rgba, RgbaImage};
+
+use point_cloud_cutaway::{config::{self, Config}, context::{self, GlProfile}, slice::{PostProcessors, ProcessContext, SlicePostProcessor}, viewer::{Options, Viewer}};
+
+/// Keeps the points crossing the clipping plane and nothing else
+struct PointsOnly;
+
+impl SlicePostProcessor for PointsOnly {
+    fn name(&self) -> &str {
+        "Points Only"
+    }
+
+    fn process(&self, slice: &RgbaImage, context: &ProcessContext) -> Result<RgbaImage, String> {
+        if context.job.is_cancelled() {
+            return Err("Processing the slice was cancelled".to_owned());
+        }
+
+        Ok(RgbaImage::from_fn(slice.width(), slice.height(), |x, y| {
+            if slice.get_pixel(x, y)[3] > context.alpha_threshold {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        }))
+    }
+}
+
+fn main() {
+    let (config, config_errors) = Config::load(config::organisation_file().as_deref(), None);
+
+    let mut slice_processors = PostProcessors::default();
+    slice_processors.register(Box::new(PointsOnly));
+
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let wb = glutin::window::WindowBuilder::new()
+        .with_title("Point Cloud Cutaway Renderer");
+    let display = context::create_display(wb, &event_loop, 4, GlProfile::Core, false).expect("Unable to create an OpenGL context");
+
+    let options = Options {
+        file: std::env::args().nth(1),
+        point_size: 0.1,
+        num_points: 0,
+        output_dir: ".".to_owned(),
+        gl_profile: GlProfile::Core,
+        vsync: false,
+        profile: false,
+        reduced: false,
+        plugin_dir: None,
+        pdal_pipeline: None,
+        watch: None,
+        reproject: None,
+        listen: None,
+        auto_orbit_after: None,
+    };
+    let mut viewer = Viewer::new(display, &event_loop, options, slice_processors, config, &config_errors);
+
+    event_loop.run(move |event, window_target, control_flow| {
+        if viewer.handle_event(event, control_flow) {
+            viewer.frame(window_target, control_flow);
+        }
+    });
+}
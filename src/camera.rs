@@ -0,0 +1,13 @@
+pub const Z_NEAR: f32 = 0.1;
+pub const Z_FAR: f32 = 1000.0;
+
+/// World to camera transform, the camera looks along +z before rotating
+pub fn view(position: glam::Vec3, rotation: glam::Vec2) -> glam::Mat4 {
+    glam::Mat4::from_rotation_translation(glam::Quat::from_euler(glam::EulerRot::YXZ, rotation.x, rotation.y, 0.0), position).inverse()
+}
+
+/// Orthographic projection `zoom` units wide, clipping at the near plane
+pub fn projection(zoom: f32, width: u32, height: u32) -> glam::Mat4 {
+    let aspect = height as f32 / width as f32;
+    glam::Mat4::orthographic_lh(-0.5 * zoom, 0.5 * zoom, -aspect * 0.5 * zoom, aspect * 0.5 * zoom, Z_NEAR, Z_FAR)
+}
@@ -0,0 +1,186 @@
+use std::path::Path;
+
+use clap::Parser;
+use glium::glutin;
+
+use point_cloud_cutaway::{config::Config, context::{self, GlProfile}, jobs::JobContext, script};
+
+use crate::sweep;
+
+#[derive(Parser, Debug)]
+#[clap(author="Luke Davis", version, about="Renders point cloud information and generated cutaway given specific clipping distance.")]
+pub struct Args {
+    #[clap(short, long, value_parser, about)]
+    /// Point cloud file path
+    pub file: Option<String>,
+    #[clap(short, long, value_parser, about, default_value_t = 0.1)]
+    /// Base size of the points, in same units as the file
+    pub point_size: f32,
+    #[clap(short, long, value_parser, about, default_value_t = 0)]
+    /// Number of points to render, only load first n points. (0 to load all points)
+    pub num_points: u64,
+    #[clap(short, long, visible_alias = "output", value_parser, about, default_value = ".")]
+    /// Directory quick renders (F5) and --headless renders are written to
+    pub output_dir: String,
+    #[clap(long, value_parser = parse_samples, default_value_t = 4)]
+    /// Multisampling (MSAA) samples: 0 to disable, 2, 4 or 8. Retried without if unsupported
+    pub msaa: u16,
+    #[clap(long, value_enum, default_value = "core")]
+    /// OpenGL profile to request. Falls back to any available profile if unsupported
+    pub gl_profile: GlProfile,
+    #[clap(long, value_parser, about)]
+    /// Wait for the display's vertical sync instead of capping the frame rate
+    pub vsync: bool,
+    #[clap(long, value_parser, about)]
+    /// Serve profile data for puffin_viewer on the default puffin port, and open the Profiler window
+    pub profile: bool,
+    #[clap(long, value_parser, about)]
+    /// Force reduced mode (point budget, no MSAA, square points), on by default with software renderers
+    pub reduced: bool,
+    #[clap(long, value_parser, about)]
+    /// Directory colour ramp (*.ramp) and point shader (*.glsl) plugins are loaded from [default: <config dir>/point-cloud-cutaway/plugins]
+    pub plugin_dir: Option<String>,
+    #[clap(long, value_parser, about)]
+    /// PDAL pipeline (JSON) to run on point cloud files before loading them, needs `pdal` on the PATH
+    pub pdal_pipeline: Option<String>,
+    #[clap(long, value_parser, about)]
+    /// Watch a folder, loading LAS and LAZ files as they appear or change, e.g. to see coverage build up while scanning
+    pub watch: Option<String>,
+    #[clap(long, value_parser, about)]
+    /// Reproject point clouds to this EPSG code as they load, e.g. 7855, to overlay scans delivered in different coordinate systems
+    pub reproject: Option<u16>,
+    #[clap(long, value_parser, about)]
+    /// Listen for a scanner streaming points on this address, e.g. 0.0.0.0:7878, for a live preview
+    pub listen: Option<String>,
+    #[clap(long, value_parser, about)]
+    /// Orbit the camera after this many seconds without input, for unattended displays
+    pub auto_orbit_after: Option<f32>,
+    #[clap(long, value_parser, about)]
+    /// Organisation settings file [default: <config dir>/point-cloud-cutaway/organisation.conf]
+    pub org_config: Option<String>,
+    #[clap(long, value_parser, about)]
+    /// Project settings file, overriding the organisation's [default: ./point-cloud-cutaway.conf]
+    pub config: Option<String>,
+    #[clap(flatten)]
+    pub headless: HeadlessArgs,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Render slices at regular elevations to numbered PNGs in the output directory, without opening a window
+    Sweep(SweepArgs),
+    /// Render the whole file looking straight down, a tile at a time, to one large georeferenced PNG (or an XYZ tile
+    /// pyramid) in the output directory, without opening a window
+    Ortho(OrthoArgs),
+    /// Run a Rhai script that loads files, renders slices and saves images, without opening a window
+    Script {
+        #[clap(value_parser)]
+        path: String,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SweepArgs {
+    #[clap(long, value_parser, allow_hyphen_values = true)]
+    /// Elevation of the first slice, in file units
+    pub from: f64,
+    #[clap(long, value_parser, allow_hyphen_values = true)]
+    /// Highest elevation to slice at, in file units
+    pub to: f64,
+    #[clap(long, value_parser, default_value_t = 1.0)]
+    /// Distance between slices, in file units
+    pub step: f64,
+    #[clap(long, value_parser, default_value_t = 1920)]
+    /// Width of the rendered slices, in pixels
+    pub width: u32,
+    #[clap(long, value_parser, default_value_t = 1080)]
+    /// Height of the rendered slices, in pixels
+    pub height: u32,
+    #[clap(long, value_parser)]
+    /// Also write every slice to one multi-page PDF at this path
+    pub pdf: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct OrthoArgs {
+    #[clap(long, value_parser, default_value_t = 0.01)]
+    /// Ground distance covered by a pixel, in file units
+    pub pixel_size: f64,
+    #[clap(long, value_parser, default_value_t = 2048)]
+    /// Side of the tiles rendered at a time, in pixels
+    pub tile_size: u32,
+    #[clap(long, value_parser)]
+    /// Write a {z}/{x}/{y}.png tile pyramid for web maps instead of one image
+    pub xyz: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct HeadlessArgs {
+    #[clap(long, value_parser, about)]
+    /// Render one cutaway and its processed slice to the output directory without opening a window, then exit
+    pub headless: bool,
+    #[clap(long, value_parser, allow_hyphen_values = true)]
+    /// Elevation --headless slices at, in file units
+    pub slice_height: Option<f64>,
+    #[clap(long, value_parser = parse_resolution, default_value = "1920x1080")]
+    /// Size of --headless renders, as WIDTHxHEIGHT pixels
+    pub resolution: (u32, u32),
+}
+
+/// Work a subcommand does once there's a context to render with
+type HeadlessRender<'a> = Box<dyn FnOnce(&glium::HeadlessRenderer) -> Result<(), String> + 'a>;
+
+/// Run the renderer a subcommand or --headless asks for on an offscreen context, then exit. Returns without doing
+/// anything when the viewer should open instead.
+pub fn run_command(args: &Args, config: &Config) {
+    let file = |name: &str| args.file.as_deref().unwrap_or_else(|| {
+        eprintln!("{} needs a point cloud file (--file)", name);
+        std::process::exit(2);
+    });
+    let output_dir = Path::new(&args.output_dir);
+
+    let render: HeadlessRender = match &args.command {
+        Some(Command::Sweep(sweep_args)) => {
+            let filename = file("sweep");
+            Box::new(move |renderer| sweep::run(renderer, filename, args.point_size, args.num_points, output_dir, sweep_args, config))
+        },
+        Some(Command::Ortho(ortho_args)) => {
+            let filename = file("ortho");
+            Box::new(move |renderer| sweep::ortho(renderer, filename, args.point_size, args.num_points, output_dir, ortho_args))
+        },
+        Some(Command::Script { path }) => Box::new(move |renderer| script::run(renderer, Path::new(path), args.point_size, config, &JobContext::detached())),
+        None if args.headless.headless => {
+            let filename = file("--headless");
+            Box::new(move |renderer| sweep::cutaway(renderer, filename, args.point_size, args.num_points, output_dir, &args.headless, config))
+        },
+        None => return,
+    };
+
+    let event_loop = glutin::event_loop::EventLoop::new();
+
+    if let Err(err) = context::create_headless(&event_loop, args.gl_profile).and_then(|renderer| render(&renderer)) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+
+    std::process::exit(0);
+}
+
+fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(|| format!("`{}` isn't WIDTHxHEIGHT", value))?;
+    let parse = |n: &str| n.trim().parse::<u32>().ok().filter(|n| *n > 0).ok_or_else(|| format!("`{}` isn't a size above 0", n));
+
+    Ok((parse(width)?, parse(height)?))
+}
+
+fn parse_samples(value: &str) -> Result<u16, String> {
+    let samples: u16 = value.parse().map_err(|_| format!("`{}` isn't a number", value))?;
+
+    if matches!(samples, 0 | 2 | 4 | 8) {
+        Ok(samples)
+    } else {
+        Err("must be 0, 2, 4 or 8".to_owned())
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use glam::DVec3;
+use rayon::prelude::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::{filter::PointFilter, loader::PointBatch, selection::SelectMode};
+
+/// Groups of points separated from each other by gaps, e.g. the buildings, trees and cars on a tile. Found as the
+/// connected voxels of a grid as wide as the gap, so points closer than the gap always share a cluster and points a
+/// little further apart sometimes do.
+pub struct Clusters {
+    voxel_size: f64,
+    /// Cluster of each voxel of the points, voxels of clusters that were too small are left out
+    voxels: HashMap<[i64; 3], u32>,
+    /// Points in each cluster, largest first
+    pub sizes: Vec<u64>,
+}
+
+impl Clusters {
+    /// Cluster `points` (file coordinates, e.g. from `loader::positions`), dropping clusters with fewer than
+    /// `min_points`
+    pub fn find(points: &[DVec3], gap: f64, min_points: u64) -> Clusters {
+        let voxel = |point: &DVec3| point.to_array().map(|v| (v / gap).floor() as i64);
+
+        let counts = points.par_iter()
+            .fold(HashMap::new, |mut counts: HashMap<[i64; 3], u64>, point| {
+                *counts.entry(voxel(point)).or_insert(0) += 1;
+                counts
+            })
+            .reduce(HashMap::new, |mut a, b| {
+                for (voxel, count) in b {
+                    *a.entry(voxel).or_insert(0) += count;
+                }
+                a
+            });
+
+        let keys: Vec<[i64; 3]> = counts.keys().copied().collect();
+        let index: HashMap<[i64; 3], usize> = keys.iter().enumerate().map(|(i, key)| (*key, i)).collect();
+
+        // Union-find over the voxels, joining each to the neighbours after it so every pair is only tried once
+        let mut parent: Vec<usize> = (0..keys.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        let offsets = (-1..=1).flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| [x, y, z])))
+            .filter(|offset| *offset > [0, 0, 0]);
+
+        for (i, key) in keys.iter().enumerate() {
+            for offset in offsets.clone() {
+                let neighbour = [key[0] + offset[0], key[1] + offset[1], key[2] + offset[2]];
+
+                if let Some(&j) = index.get(&neighbour) {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    if a != b {
+                        parent[a.max(b)] = a.min(b);
+                    }
+                }
+            }
+        }
+
+        let mut totals: HashMap<usize, u64> = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            *totals.entry(root(&mut parent, i)).or_insert(0) += counts[key];
+        }
+
+        let mut kept: Vec<(usize, u64)> = totals.into_iter().filter(|(_, size)| *size >= min_points).collect();
+        kept.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let clusters: HashMap<usize, u32> = kept.iter().enumerate().map(|(cluster, (root, _))| (*root, cluster as u32)).collect();
+        let voxels = keys.iter()
+            .enumerate()
+            .filter_map(|(i, key)| clusters.get(&root(&mut parent, i)).map(|cluster| (*key, *cluster)))
+            .collect();
+
+        Clusters {
+            voxel_size: gap,
+            voxels,
+            sizes: kept.into_iter().map(|(_, size)| size).collect(),
+        }
+    }
+
+    /// Index of the cluster `point` is in, None if it's in one that was too small or wasn't clustered
+    pub fn cluster(&self, point: &las::Point) -> Option<usize> {
+        let voxel = [point.x, point.y, point.z].map(|v| (v / self.voxel_size).floor() as i64);
+        self.voxels.get(&voxel).map(|cluster| *cluster as usize)
+    }
+
+    /// Select the points the filter shows in cluster `cluster`, for exporting or deleting
+    pub fn select(&self, batches: &mut [PointBatch], filter: &PointFilter, cluster: usize, mode: SelectMode) {
+        batches.par_iter_mut().for_each(|batch| {
+            for (point, selected) in batch.points.iter().zip(batch.selected.iter_mut()) {
+                let inside = filter.accepts(point) && self.cluster(point) == Some(cluster);
+
+                *selected = match mode {
+                    SelectMode::Replace => inside,
+                    SelectMode::Add => *selected || inside,
+                    SelectMode::Remove => *selected && !inside,
+                };
+            }
+        });
+    }
+}
@@ -0,0 +1,414 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{cluster::Clusters, geometry::DetectedPlane, loader::PointBatch, random::XorShift};
+
+/// Colours of detected planes, in detection order
+const PLANE_COLOURS: [[u8; 3]; 8] = [
+    [230, 25, 75], [60, 180, 75], [0, 130, 200], [245, 130, 48],
+    [145, 30, 180], [70, 240, 240], [240, 50, 230], [210, 245, 60],
+];
+const UNASSIGNED_COLOUR: [u8; 3] = [128, 128, 128];
+/// Fraction of points in voxels denser than the top of the density ramp, so a few very dense voxels (e.g. right
+/// under the scanner) don't wash out the rest
+const DENSITY_OUTLIERS: f64 = 0.05;
+/// Reference points searched for the nearest to each compared point, evenly sampled beyond this
+const MAX_REFERENCE_POINTS: usize = 5_000_000;
+/// Compared points sampled for the distance statistics
+const DISTANCE_SAMPLES: usize = 50_000;
+/// Colour of selected points, whatever the colour mode
+pub const SELECTION_COLOUR: [u8; 3] = [255, 0, 255];
+
+/// Where point colours come from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColourMode {
+    Rgb,
+    /// Elevation through the colour ramp with this index
+    Elevation(usize),
+    /// Intensity through the colour ramp with this index
+    Intensity(usize),
+    /// Points per voxel through the colour ramp with this index, see `DensityGrid`
+    Density(usize),
+    /// Distance to the nearest point of a reference file through the colour ramp with this index, see `CloudDistances`
+    Distance(usize),
+    /// Plugin shader with this index
+    Shader(usize),
+    /// Detected planes, points on no plane in grey
+    Planes,
+    /// LAS classification, see `classification_colour`
+    Classification,
+    /// Found clusters, points in none in grey
+    Clusters,
+}
+
+#[derive(Debug)]
+pub struct ColourRamp {
+    pub name: String,
+    /// Positions (0 to 1, ascending) and colours
+    stops: Vec<(f32, [u8; 3])>,
+}
+
+impl ColourRamp {
+    fn new(name: &str, stops: &[(f32, [u8; 3])]) -> ColourRamp {
+        ColourRamp {
+            name: name.to_owned(),
+            stops: stops.to_vec(),
+        }
+    }
+
+    /// Parse a ramp file: one `position #rrggbb` stop per line, `#` at the start of a line is a comment
+    pub fn parse(name: &str, text: &str) -> Result<ColourRamp, String> {
+        let mut stops = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = || format!("line {}: expected `position #rrggbb`", i + 1);
+
+            let (position, colour) = line.split_once(char::is_whitespace).ok_or_else(error)?;
+            let position: f32 = position.parse().map_err(|_| error())?;
+            let colour = colour.trim().strip_prefix('#').filter(|hex| hex.len() == 6).ok_or_else(error)?;
+            let channel = |i: usize| u8::from_str_radix(&colour[i..i + 2], 16).map_err(|_| error());
+
+            stops.push((position.clamp(0.0, 1.0), [channel(0)?, channel(2)?, channel(4)?]));
+        }
+
+        if stops.is_empty() {
+            return Err("no colour stops".to_owned());
+        }
+
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(ColourRamp {
+            name: name.to_owned(),
+            stops,
+        })
+    }
+
+    pub fn sample(&self, t: f32) -> [u8; 3] {
+        let t = if t.is_finite() { t.clamp(0.0, 1.0) } else { 0.0 };
+
+        let upper = self.stops.iter().position(|(position, _)| *position >= t).unwrap_or(self.stops.len() - 1);
+        let lower = upper.saturating_sub(1);
+
+        let ((p0, c0), (p1, c1)) = (self.stops[lower], self.stops[upper]);
+        let f = if p1 > p0 { ((t - p0) / (p1 - p0)).clamp(0.0, 1.0) } else { 1.0 };
+
+        [0, 1, 2].map(|i| (c0[i] as f32 + (c1[i] as f32 - c0[i] as f32) * f).round() as u8)
+    }
+}
+
+pub fn builtin_ramps() -> Vec<ColourRamp> {
+    vec![
+        ColourRamp::new("Rainbow", &[(0.0, [0, 0, 255]), (0.25, [0, 255, 255]), (0.5, [0, 255, 0]), (0.75, [255, 255, 0]), (1.0, [255, 0, 0])]),
+        ColourRamp::new("Greyscale", &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])]),
+        ColourRamp::new("Viridis", &[(0.0, [68, 1, 84]), (0.25, [59, 82, 139]), (0.5, [33, 145, 140]), (0.75, [94, 201, 98]), (1.0, [253, 231, 37])]),
+    ]
+}
+
+/// Shader snippet defining `vec3 point_colour(vec3 position, vec3 colour, float intensity)`,
+/// given the position relative to `u_centre`, RGB (0 to 1) and intensity (0 to 1) of a point. Positions are kept
+/// relative so they stay precise far from the file origin; `u_centre + position` is the file position, as precise
+/// as a float allows.
+pub struct PointShader {
+    pub name: String,
+    pub source: String,
+}
+
+#[derive(Default)]
+pub struct Plugins {
+    pub ramps: Vec<ColourRamp>,
+    pub shaders: Vec<PointShader>,
+    pub errors: Vec<String>,
+}
+
+/// Load `*.ramp` colour ramps and `*.glsl` point shaders from a directory, a missing directory is not an error
+pub fn load_plugins(directory: &Path) -> Plugins {
+    let mut plugins = Plugins::default();
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return plugins,
+    };
+
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_stem().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let extension = path.extension().map(|extension| extension.to_ascii_lowercase());
+
+        let text = match extension.as_ref().and_then(|extension| extension.to_str()) {
+            Some("ramp") | Some("glsl") => match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(err) => {
+                    plugins.errors.push(format!("{}: {}", path.display(), err));
+                    continue;
+                },
+            },
+            _ => continue,
+        };
+
+        if extension.as_ref().is_some_and(|extension| extension == "ramp") {
+            match ColourRamp::parse(&name, &text) {
+                Ok(ramp) => plugins.ramps.push(ramp),
+                Err(err) => plugins.errors.push(format!("{}: {}", path.display(), err)),
+            }
+        } else {
+            plugins.shaders.push(PointShader {
+                name,
+                source: text,
+            });
+        }
+    }
+
+    plugins
+}
+
+/// Point vertex shader with the default `point_colour` replaced by a plugin's
+pub fn vertex_shader_with(snippet: &str) -> String {
+    let source = include_str!("shaders/main.vert");
+
+    let start = source.find("// BEGIN POINT_COLOUR").expect("Missing point colour hook in main shader");
+    let end = source.find("// END POINT_COLOUR").expect("Missing point colour hook in main shader");
+
+    format!("{}{}\n{}", &source[..start], snippet, &source[end..])
+}
+
+/// Points in each voxel of a grid over the point cloud, to find under-scanned areas before cutting it away
+pub struct DensityGrid {
+    voxel_size: f64,
+    counts: HashMap<[i64; 3], u32>,
+    /// Points per voxel at the top of the ramp
+    pub full: u32,
+}
+
+impl DensityGrid {
+    /// Count the points of every batch into cubic voxels `voxel_size` wide, in file units
+    pub fn new(batches: &[PointBatch], voxel_size: f64) -> DensityGrid {
+        let voxel = |point: &las::Point| [point.x, point.y, point.z].map(|v| (v / voxel_size).floor() as i64);
+
+        let counts = batches.par_iter()
+            .map(|batch| {
+                let mut counts = HashMap::new();
+                for point in &batch.points {
+                    *counts.entry(voxel(point)).or_insert(0) += 1;
+                }
+                counts
+            })
+            .reduce(HashMap::new, |mut a, b| {
+                for (voxel, count) in b {
+                    *a.entry(voxel).or_insert(0) += count;
+                }
+                a
+            });
+
+        // Densest voxels first, until they hold the outlying fraction of the points
+        let mut sorted: Vec<u32> = counts.values().copied().collect();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+
+        let total: u64 = sorted.iter().map(|count| *count as u64).sum();
+        let mut outlying = 0;
+        let full = sorted.iter()
+            .find(|count| {
+                outlying += **count as u64;
+                outlying as f64 >= total as f64 * DENSITY_OUTLIERS
+            })
+            .copied()
+            .unwrap_or(1);
+
+        DensityGrid { voxel_size, counts, full: full.max(1) }
+    }
+
+    /// Points in the voxel `point` is in
+    pub fn count(&self, point: &las::Point) -> u32 {
+        let voxel = [point.x, point.y, point.z].map(|v| (v / self.voxel_size).floor() as i64);
+        self.counts.get(&voxel).copied().unwrap_or(0)
+    }
+
+    /// Density of the voxel `point` is in, from 0 to 1 at `full`. Logarithmic, scans thin out quickly away from the
+    /// scanner.
+    pub fn density(&self, point: &las::Point) -> f32 {
+        ((self.count(point) as f32).ln_1p() / (self.full as f32).ln_1p()).min(1.0)
+    }
+}
+
+/// Distances from the points of one file to the nearest point of a reference file (cloud-to-cloud), to check an
+/// as-built scan against a design or find what changed between two surveys
+pub struct CloudDistances {
+    pub reference: usize,
+    pub comparison: usize,
+    tree: kd_tree::KdTree<[f64; 3]>,
+    /// Distances of a sample of the compared points, ascending
+    sample: Vec<f64>,
+}
+
+impl CloudDistances {
+    pub fn new(batches: &[PointBatch], reference: usize, comparison: usize) -> Result<CloudDistances, String> {
+        let points = |file: usize| batches.iter().filter(move |batch| batch.file == file).flat_map(|batch| batch.points.iter());
+        let stride = |file: usize, count: usize| (points(file).count() / count).max(1);
+
+        let reference_points: Vec<[f64; 3]> = points(reference)
+            .step_by(stride(reference, MAX_REFERENCE_POINTS))
+            .map(|point| [point.x, point.y, point.z])
+            .collect();
+
+        if reference_points.is_empty() || points(comparison).next().is_none() {
+            return Err("Both files need points to compare".to_owned());
+        }
+
+        let tree = kd_tree::KdTree::build_by_ordered_float(reference_points);
+
+        let mut distances = CloudDistances { reference, comparison, tree, sample: vec![] };
+        let mut sample: Vec<f64> = points(comparison)
+            .step_by(stride(comparison, DISTANCE_SAMPLES))
+            .map(|point| distances.distance(point))
+            .collect();
+        sample.sort_unstable_by(f64::total_cmp);
+        distances.sample = sample;
+
+        Ok(distances)
+    }
+
+    /// Distance from `point` to the nearest reference point, in file units
+    pub fn distance(&self, point: &las::Point) -> f64 {
+        self.tree.nearest(&[point.x, point.y, point.z]).map_or(f64::INFINITY, |nearest| nearest.squared_distance.sqrt())
+    }
+
+    /// Distance this fraction (0 to 1) of the compared points are within, estimated from the sample
+    pub fn percentile(&self, fraction: f64) -> f64 {
+        let index = ((self.sample.len() - 1) as f64 * fraction.clamp(0.0, 1.0)).round() as usize;
+        self.sample[index]
+    }
+
+    /// Estimated fraction of the compared points within `threshold` of the reference
+    pub fn within(&self, threshold: f64) -> f64 {
+        self.sample.partition_point(|distance| *distance <= threshold) as f64 / self.sample.len() as f64
+    }
+}
+
+/// Colour of the detected plane with this index
+pub fn plane_colour(index: usize) -> [u8; 3] {
+    PLANE_COLOURS[index % PLANE_COLOURS.len()]
+}
+
+/// Random bright colour of the cluster with this index, the same each time
+pub fn cluster_colour(index: usize) -> [u8; 3] {
+    let mut random = XorShift::new(index as u64 + 1);
+    random.next_u64();
+
+    // Hue from the generator, fully saturated and bright so neighbouring clusters stand apart
+    let hue = (random.next_u64() % 360) as f32 / 60.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let [r, g, b] = match hue as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    };
+
+    [r, g, b].map(|channel: f32| (64.0 + channel * 191.0) as u8)
+}
+
+/// Usual colour of points with a LAS classification code, grey for codes without one
+pub fn classification_colour(code: u8) -> [u8; 3] {
+    match code {
+        2 => [170, 120, 60],
+        3 => [150, 220, 100],
+        4 => [60, 180, 60],
+        5 => [20, 110, 30],
+        6 => [220, 60, 50],
+        7 | 18 => [255, 0, 255],
+        9 => [40, 120, 230],
+        10 => [110, 80, 60],
+        11 => [90, 90, 90],
+        13..=16 => [255, 220, 0],
+        17 => [140, 140, 200],
+        _ => UNASSIGNED_COLOUR,
+    }
+}
+
+/// Colours points on the CPU for the ramp, plane, classification and cluster modes, RGB otherwise
+pub struct Colouring<'a> {
+    pub mode: ColourMode,
+    pub ramps: &'a [ColourRamp],
+    /// Elevation mapped to the ends of the ramp
+    pub elevation_range: (f64, f64),
+    pub planes: &'a [DetectedPlane],
+    /// Distance from a plane within which points belong to it
+    pub plane_threshold: f64,
+    /// Density mode points are at the bottom of the ramp without one
+    pub density: Option<&'a DensityGrid>,
+    /// Distance mode points of the compared file are at the bottom of the ramp without these, other files in grey
+    pub distances: Option<&'a CloudDistances>,
+    /// Distance at the top of the ramp, further points are clamped to it
+    pub distance_threshold: f64,
+    /// Cluster mode points are grey without these
+    pub clusters: Option<&'a Clusters>,
+}
+
+impl<'a> Colouring<'a> {
+    /// Colour of a point of the loaded file with index `file`
+    pub fn colour(&self, point: &las::Point, file: usize) -> [u8; 3] {
+        match self.mode {
+            ColourMode::Elevation(ramp) => {
+                let (min, max) = self.elevation_range;
+                self.ramps[ramp].sample(((point.z - min) / (max - min)) as f32)
+            },
+            ColourMode::Intensity(ramp) => self.ramps[ramp].sample(point.intensity as f32 / u16::MAX as f32),
+            ColourMode::Density(ramp) => self.ramps[ramp].sample(self.density.map_or(0.0, |grid| grid.density(point))),
+            ColourMode::Distance(ramp) => match self.distances {
+                Some(distances) if distances.comparison == file => {
+                    self.ramps[ramp].sample((distances.distance(point) / self.distance_threshold) as f32)
+                },
+                Some(_) => UNASSIGNED_COLOUR,
+                None => self.ramps[ramp].sample(0.0),
+            },
+            ColourMode::Planes => {
+                let p = glam::dvec3(point.x, point.y, point.z);
+
+                self.planes.iter()
+                    .position(|detected| detected.plane.distance(p) <= self.plane_threshold)
+                    .map_or(UNASSIGNED_COLOUR, plane_colour)
+            },
+            ColourMode::Classification => classification_colour(u8::from(point.classification)),
+            ColourMode::Clusters => self.clusters.and_then(|clusters| clusters.cluster(point)).map_or(UNASSIGNED_COLOUR, cluster_colour),
+            ColourMode::Rgb | ColourMode::Shader(_) => {
+                if let Some(colour) = point.color {
+                    [(colour.red / 256) as u8, (colour.green / 256) as u8, (colour.blue / 256) as u8]
+                } else {
+                    [u8::MAX; 3]
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sorts_and_clamps_stops() {
+        let ramp = ColourRamp::parse("heat", "# Cold to hot\n1.5 #FF0000\n\n0 #0000ff\n0.5 #00ff00\n").expect("Valid ramp");
+
+        assert_eq!(ramp.name, "heat");
+        assert_eq!(ramp.stops, vec![(0.0, [0, 0, 255]), (0.5, [0, 255, 0]), (1.0, [255, 0, 0])]);
+        assert_eq!(ramp.sample(0.25), [0, 128, 128]);
+        assert_eq!(ramp.sample(f32::NAN), [0, 0, 255]);
+    }
+
+    #[test]
+    fn parse_rejects_broken_ramps() {
+        assert_eq!(ColourRamp::parse("x", "0 #000000\n1 red").unwrap_err(), "line 2: expected `position #rrggbb`");
+        assert_eq!(ColourRamp::parse("x", "0 #00000g").unwrap_err(), "line 1: expected `position #rrggbb`");
+        assert_eq!(ColourRamp::parse("x", "# Nothing\n").unwrap_err(), "no colour stops");
+    }
+}
@@ -0,0 +1,166 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::camera::{Z_FAR, Z_NEAR};
+
+/// Project overrides picked up from the working directory when `--config` isn't given
+pub const PROJECT_FILE: &str = "point-cloud-cutaway.conf";
+/// Largest multiple captures can be supersampled by
+pub const MAX_SUPERSAMPLING: u32 = 4;
+
+/// Slice and export settings teams keep consistent between operators. Layered from the built-in defaults, then the
+/// organisation file, then the project file, each only overriding the settings it sets.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Thickness of the slice above the clipping plane, in file units
+    pub slice_thickness: f32,
+    /// Distance slice points are joined within, in on-screen point sizes
+    pub densify_radius: f32,
+    /// Slice pixels more opaque than this (0 to 254) are counted as points
+    pub alpha_threshold: u8,
+    /// Quick render file names, `{index}` is replaced with the next free 4 digit number
+    pub cutaway_template: String,
+    pub floor_plan_template: String,
+    /// Extra directory of `*.ramp` colour ramps and `*.glsl` point shaders
+    pub palette_dir: Option<PathBuf>,
+    /// Colour ramp to colour elevation with on start, RGB if unset
+    pub palette: Option<String>,
+    /// Cutaway captures are drawn at this multiple of their size (1 to 4) and scaled down, smoothing their edges
+    pub capture_supersampling: u32,
+    /// Bearing of north from the file's +Y axis, in degrees clockwise. 0 for grid north in projected coordinates.
+    pub north_offset: f32,
+    /// Interface language on start, English if unset
+    pub language: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            slice_thickness: 0.05,
+            densify_radius: 10.0,
+            alpha_threshold: 128,
+            cutaway_template: "cutaway_{index}.png".to_owned(),
+            floor_plan_template: "floor_plan_{index}.png".to_owned(),
+            palette_dir: None,
+            palette: None,
+            capture_supersampling: 1,
+            north_offset: 0.0,
+            language: None,
+        }
+    }
+}
+
+impl Config {
+    /// Layer the organisation file (if any) and then the project file over the defaults. Missing files are skipped,
+    /// problems with the others are returned alongside whatever could be read.
+    pub fn load(organisation: Option<&Path>, project: Option<&Path>) -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let mut errors = vec![];
+
+        for path in [organisation, project].into_iter().flatten() {
+            let text = match fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    errors.push(format!("{}: {}", path.display(), err));
+                    continue;
+                },
+            };
+
+            let base = path.parent().unwrap_or(Path::new("."));
+            errors.extend(config.apply(&text, base).into_iter().map(|err| format!("{}: {}", path.display(), err)));
+        }
+
+        (config, errors)
+    }
+
+    /// Apply `key = value` lines, `#` at the start of a line is a comment. Relative paths are relative to `base`.
+    fn apply(&mut self, text: &str, base: &Path) -> Vec<String> {
+        let mut errors = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    errors.push(format!("line {}: expected `key = value`", i + 1));
+                    continue;
+                },
+            };
+
+            let positive = |value: &str| value.parse::<f32>().ok().filter(|v| *v > 0.0).ok_or_else(|| format!("line {}: {} must be a number above 0", i + 1, key));
+            let template = |value: &str| Some(value.to_owned()).filter(|v| v.contains("{index}")).ok_or_else(|| format!("line {}: {} must contain {{index}}", i + 1, key));
+
+            let result = match key {
+                "slice_thickness" => positive(value).map(|v| self.slice_thickness = v),
+                "densify_radius" => positive(value).map(|v| self.densify_radius = v),
+                "alpha_threshold" => value.parse::<u8>().ok().filter(|v| *v < u8::MAX)
+                    .ok_or_else(|| format!("line {}: {} must be a whole number from 0 to 254", i + 1, key))
+                    .map(|v| self.alpha_threshold = v),
+                "cutaway_template" => template(value).map(|v| self.cutaway_template = v),
+                "floor_plan_template" => template(value).map(|v| self.floor_plan_template = v),
+                "palette_dir" => {
+                    self.palette_dir = Some(base.join(value));
+                    Ok(())
+                },
+                "capture_supersampling" => value.parse::<u32>().ok().filter(|v| (1..=MAX_SUPERSAMPLING).contains(v))
+                    .ok_or_else(|| format!("line {}: {} must be a whole number from 1 to {}", i + 1, key, MAX_SUPERSAMPLING))
+                    .map(|v| self.capture_supersampling = v),
+                "north_offset" => value.parse::<f32>().ok().filter(|v| v.is_finite())
+                    .ok_or_else(|| format!("line {}: {} must be a number of degrees", i + 1, key))
+                    .map(|v| self.north_offset = v.rem_euclid(360.0)),
+                "palette" => {
+                    self.palette = Some(value.to_owned());
+                    Ok(())
+                },
+                "language" => {
+                    self.language = Some(value.to_owned());
+                    Ok(())
+                },
+                _ => Err(format!("line {}: unknown setting `{}`", i + 1, key)),
+            };
+
+            if let Err(err) = result {
+                errors.push(err);
+            }
+        }
+
+        errors
+    }
+
+    /// Slice thickness in the units of the slice shaders, normalised window depth
+    pub fn slice_width(&self) -> f32 {
+        self.slice_thickness / (Z_FAR - Z_NEAR) / 2.0
+    }
+
+    /// Radius in pixels slice points are joined within
+    pub fn connect_radius(&self, point_size: f32, zoom: f32) -> i32 {
+        (f32::max(point_size * zoom, 1.0) * self.densify_radius) as i32
+    }
+}
+
+/// Quick render file name for `index`
+pub fn file_name(template: &str, index: u32) -> String {
+    template.replace("{index}", &format!("{:04}", index))
+}
+
+/// First index not used by any numbered quick render in the directory
+pub fn next_output_index(directory: &Path, config: &Config) -> u32 {
+    let mut index = 1;
+
+    while directory.join(file_name(&config.cutaway_template, index)).exists()
+        || directory.join(file_name(&config.floor_plan_template, index)).exists() {
+        index += 1;
+    }
+
+    index
+}
+
+/// Organisation wide settings, shared by everyone on the machine (or deployed by IT)
+pub fn organisation_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("point-cloud-cutaway").join("organisation.conf"))
+}
@@ -0,0 +1,80 @@
+//! OpenGL contexts for the viewer's window and the offscreen renderers, falling back to settings more drivers support
+
+use glium::glutin::{self, event_loop::EventLoopWindowTarget};
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GlProfile {
+    Core,
+    Compatibility,
+    Any,
+}
+
+/// Try the requested context settings first, then without multisampling, then with any profile
+pub fn create_display(wb: glutin::window::WindowBuilder, event_loop: &glutin::event_loop::EventLoop<()>, msaa: u16, profile: GlProfile, vsync: bool) -> Result<glium::Display, glium::backend::glutin::DisplayCreationError> {
+    let mut attempts = vec![(msaa, profile)];
+    for attempt in [(0, profile), (0, GlProfile::Any)] {
+        if !attempts.contains(&attempt) {
+            attempts.push(attempt);
+        }
+    }
+
+    let mut last_error = None;
+
+    for (i, (msaa, profile)) in attempts.into_iter().enumerate() {
+        if i > 0 {
+            eprintln!("Retrying with {}x MSAA and {:?} profile", msaa, profile);
+        }
+
+        match glium::Display::new(wb.clone(), context_builder(msaa, profile, vsync), event_loop) {
+            Ok(display) => return Ok(display),
+            Err(err) => {
+                eprintln!("Failed to create display ({}x MSAA, {:?} profile): {}", msaa, profile, err);
+                last_error = Some(err);
+            },
+        }
+    }
+
+    Err(last_error.expect("No context creation attempts made"))
+}
+
+/// Offscreen context for the command-line renderers, falling back to any profile like `create_display`
+pub fn create_headless(event_loop: &EventLoopWindowTarget<()>, profile: GlProfile) -> Result<glium::HeadlessRenderer, String> {
+    headless_context(event_loop, profile)
+        .and_then(|context| glium::HeadlessRenderer::new(context).map_err(|err| format!("Unable to create an OpenGL context: {}", err)))
+}
+
+/// Context for `create_headless`, not yet current so it can be sent to the thread that renders with it
+pub fn headless_context(event_loop: &EventLoopWindowTarget<()>, profile: GlProfile) -> Result<glutin::Context<glutin::NotCurrent>, String> {
+    let mut last_error = String::new();
+
+    for (profile, software) in [(profile, false), (GlProfile::Any, false), (GlProfile::Any, true)] {
+        // Mesa's software renderer, for servers without a GPU (or a working driver)
+        if software {
+            if std::env::var_os("LIBGL_ALWAYS_SOFTWARE").is_some() {
+                break;
+            }
+            std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+        }
+
+        // Everything is drawn into textures, so the context's own surface is never used
+        match context_builder(0, profile, false).build_headless(event_loop, glutin::dpi::PhysicalSize::new(1, 1)) {
+            Ok(context) => return Ok(context),
+            Err(err) => {
+                eprintln!("Failed to create headless context ({:?} profile{}): {}", profile, if software { ", software" } else { "" }, err);
+                last_error = err.to_string();
+            },
+        }
+    }
+
+    Err(format!("Unable to create an OpenGL context: {}", last_error))
+}
+
+fn context_builder(msaa: u16, profile: GlProfile, vsync: bool) -> glutin::ContextBuilder<'static, glutin::NotCurrent> {
+    let cb = glutin::ContextBuilder::new().with_multisampling(msaa).with_vsync(vsync);
+
+    match profile {
+        GlProfile::Core => cb.with_gl_profile(glutin::GlProfile::Core),
+        GlProfile::Compatibility => cb.with_gl_profile(glutin::GlProfile::Compatibility),
+        GlProfile::Any => cb,
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use glam::DVec2;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::raster::ElevationGrid;
+
+/// Lines of equal elevation
+#[derive(Clone, Debug)]
+pub struct Contour {
+    pub elevation: f64,
+    /// Index contours, every few intervals, are drawn heavier
+    pub major: bool,
+    /// Polylines in file X and Y, closed ones end where they start
+    pub lines: Vec<Vec<DVec2>>,
+}
+
+/// Contours of the grid every `interval`, with every `major_every`th a major one. Traced by marching squares between
+/// cell centres, leaving gaps around cells without points.
+pub fn contours(grid: &ElevationGrid, interval: f64, major_every: u32) -> Vec<Contour> {
+    let (min, max) = grid.range();
+    if interval <= 0.0 || min > max {
+        return vec![];
+    }
+
+    let first = (min / interval).ceil() as i64;
+    let last = (max / interval).floor() as i64;
+
+    (first..=last).collect::<Vec<_>>().par_iter()
+        .map(|&i| {
+            let elevation = i as f64 * interval;
+
+            Contour {
+                elevation,
+                major: major_every > 0 && i.rem_euclid(major_every as i64) == 0,
+                lines: join(trace(grid, elevation)),
+            }
+        })
+        .filter(|contour| !contour.lines.is_empty())
+        .collect()
+}
+
+/// Grid edge a contour crosses, by the cells at its ends, lower index first
+type Edge = (usize, usize);
+
+/// Segments of the contour at `elevation` in each square of four cell centres
+fn trace(grid: &ElevationGrid, elevation: f64) -> Vec<((Edge, DVec2), (Edge, DVec2))> {
+    let mut segments = vec![];
+    let index = |x: usize, y: usize| x + y * grid.width;
+
+    // Point on the edge between two cells where the elevation is crossed
+    let crossing = |a: (usize, usize), b: (usize, usize)| -> (Edge, DVec2) {
+        let (za, zb) = (grid.get(a.0, a.1), grid.get(b.0, b.1));
+        let t = ((elevation - za) / (zb - za)).clamp(0.0, 1.0);
+        let position = |(x, y): (usize, usize)| grid.origin + glam::dvec2(x as f64, y as f64) * grid.cell_size;
+        let (ia, ib) = (index(a.0, a.1), index(b.0, b.1));
+
+        ((ia.min(ib), ia.max(ib)), position(a).lerp(position(b), t))
+    };
+
+    for y in 0..grid.height.saturating_sub(1) {
+        for x in 0..grid.width.saturating_sub(1) {
+            // Counter-clockwise from the bottom left
+            let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+            let z = corners.map(|(x, y)| grid.get(x, y));
+
+            if z.iter().any(|z| z.is_nan()) {
+                continue;
+            }
+
+            let case = z.iter().enumerate().fold(0, |case, (i, z)| case | (((*z >= elevation) as usize) << i));
+            let edge = |i: usize| crossing(corners[i], corners[(i + 1) % 4]);
+
+            // Edges crossed, by the corners above the contour: 0 bottom, 1 right, 2 top, 3 left
+            let pairs: &[(usize, usize)] = match case {
+                0 | 15 => &[],
+                1 | 14 => &[(3, 0)],
+                2 | 13 => &[(0, 1)],
+                3 | 12 => &[(3, 1)],
+                4 | 11 => &[(1, 2)],
+                6 | 9 => &[(0, 2)],
+                7 | 8 => &[(3, 2)],
+                // Saddles, split by the mean of the corners
+                5 | 10 => {
+                    let centre_above = z.iter().sum::<f64>() / 4.0 >= elevation;
+                    if centre_above == (case == 5) { &[(3, 2), (0, 1)] } else { &[(3, 0), (1, 2)] }
+                },
+                _ => unreachable!(),
+            };
+
+            segments.extend(pairs.iter().map(|&(a, b)| (edge(a), edge(b))));
+        }
+    }
+
+    segments
+}
+
+/// Join segments sharing grid edges into polylines
+fn join(segments: Vec<((Edge, DVec2), (Edge, DVec2))>) -> Vec<Vec<DVec2>> {
+    let mut by_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, ((a, _), (b, _))) in segments.iter().enumerate() {
+        by_edge.entry(*a).or_default().push(i);
+        by_edge.entry(*b).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut lines = vec![];
+
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+
+        let ((first_edge, first), (last_edge, last)) = segments[start];
+        let mut line = std::collections::VecDeque::from([first, last]);
+
+        // Extend from the end, then from the start
+        for (mut edge, at_end) in [(last_edge, true), (first_edge, false)] {
+            while let Some(&next) = by_edge.get(&edge).and_then(|candidates| candidates.iter().find(|i| !used[**i])) {
+                used[next] = true;
+                let ((a, pa), (b, pb)) = segments[next];
+                let (point, other) = if a == edge { (pb, b) } else { (pa, a) };
+
+                if at_end { line.push_back(point) } else { line.push_front(point) }
+                edge = other;
+            }
+        }
+
+        lines.push(line.into_iter().collect());
+    }
+
+    lines
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeSet;
+
+use crate::locale::Language;
+
+/// Width floating sections open at
+const FLOATING_WIDTH: f32 = 280.0;
+
+/// Sections of the side panel, each collapsed into the panel or floating in its own window that can be moved, resized
+/// and collapsed. Right click a section's header to float it, close its window to put it back.
+#[derive(Default)]
+pub struct Dock {
+    floating: BTreeSet<&'static str>,
+}
+
+impl Dock {
+    /// Show the section called `name` in the panel `ui` is in, or in its window if it's floating. Docked sections are
+    /// followed by a separator. Names are in English and shown in `locale`.
+    pub fn section(&mut self, ctx: &egui::Context, ui: &mut egui::Ui, locale: &Language, name: &'static str, add_contents: impl FnOnce(&mut egui::Ui)) {
+        if self.floating.contains(name) {
+            let mut open = true;
+
+            egui::Window::new(locale.tr(name))
+                .id(egui::Id::new(("dock", name)))
+                .open(&mut open)
+                .default_width(FLOATING_WIDTH)
+                .resizable(true)
+                .vscroll(true)
+                .show(ctx, add_contents);
+
+            if !open {
+                self.floating.remove(name);
+            }
+        } else {
+            let response = egui::CollapsingHeader::new(locale.tr(name)).id_source(("dock", name)).show(ui, add_contents);
+
+            response.header_response.on_hover_text(locale.tr("Right click to float in a window")).context_menu(|ui| {
+                if ui.button(locale.tr("Float in Window")).clicked() {
+                    self.floating.insert(name);
+                    ui.close_menu();
+                }
+            });
+
+            ui.separator();
+        }
+    }
+
+    /// Button putting every floating section back in the panel, shown while any are floating
+    pub fn dock_all_button(&mut self, ui: &mut egui::Ui, locale: &Language) {
+        if !self.floating.is_empty() && ui.button(locale.format("Dock All Windows ({})", &[&self.floating.len()])).clicked() {
+            self.floating.clear();
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::fmt::Write;
+
+use glam::{DVec2, DVec3};
+
+use crate::{contour::Contour, georef::Georeference, plan::{CaptureTransform, FloorPlan, TextLabel}, vector};
+
+/// Maximum distance (in pixels) simplified outlines may stray from the traced pixel edges
+pub const SIMPLIFY_TOLERANCE: f64 = 0.5;
+
+/// Floor plan traced into outlines, in floor plan image coordinates
+pub struct VectorPlan {
+    pub width: u32,
+    pub height: u32,
+    /// Outlines of the wall pixels
+    pub walls: Vec<Vec<DVec2>>,
+    /// Colour and outlines of each identified room (outer boundary and any holes)
+    pub rooms: Vec<([u8; 3], Vec<Vec<DVec2>>)>,
+    /// Outlines of the pencil strokes and shapes, grouped by colour
+    pub annotations: Vec<([u8; 3], Vec<Vec<DVec2>>)>,
+    /// Text labels, written as text
+    pub labels: Vec<TextLabel>,
+}
+
+/// Trace the visible wall, room and edit layers, keeping the labels as they are
+pub fn vectorise(floor_plan: &FloorPlan) -> VectorPlan {
+    let (width, height) = floor_plan.dimensions();
+
+    let outlines = |pixels: Vec<(u32, u32)>, inside: &dyn Fn(u32, u32) -> bool| trace(pixels, width, height, inside, SIMPLIFY_TOLERANCE);
+
+    // Outlines traced and edited by hand are kept as they are
+    let walls = if floor_plan.walls.visible {
+        floor_plan.traced_walls.clone().unwrap_or_else(|| trace_walls(floor_plan, SIMPLIFY_TOLERANCE))
+    } else {
+        vec![]
+    };
+
+    let rooms = if floor_plan.rooms.visible {
+        floor_plan.room_regions().into_iter()
+            .map(|(colour, region)| (colour, room_outlines(floor_plan, region)))
+            .filter(|(_, room)| !room.is_empty())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let mut annotations = vec![];
+
+    if floor_plan.edits.visible {
+        let image = &floor_plan.edits.image;
+        let mut colours: Vec<[u8; 3]> = vec![];
+
+        for pixel in image.pixels() {
+            let [r, g, b, a] = pixel.0;
+
+            if a == 255 && !colours.contains(&[r, g, b]) {
+                colours.push([r, g, b]);
+            }
+        }
+
+        for colour in colours {
+            let [r, g, b] = colour;
+            let matches = |x: u32, y: u32| image.get_pixel(x, y).0 == [r, g, b, 255];
+            let pixels = image.enumerate_pixels().filter(|(x, y, _)| matches(*x, *y)).map(|(x, y, _)| (x, y)).collect();
+
+            annotations.push((colour, outlines(pixels, &matches)));
+        }
+    }
+
+    VectorPlan {
+        width,
+        height,
+        walls,
+        rooms,
+        annotations,
+        labels: floor_plan.visible_labels().to_vec(),
+    }
+}
+
+/// Outlines of one of the floor plan's room regions (outer boundary and any holes)
+pub fn room_outlines(floor_plan: &FloorPlan, region: Vec<(u32, u32)>) -> Vec<Vec<DVec2>> {
+    let (width, height) = floor_plan.dimensions();
+    let in_region: std::collections::HashSet<(u32, u32)> = region.iter().copied().collect();
+
+    trace(region, width, height, &|x, y| in_region.contains(&(x, y)), SIMPLIFY_TOLERANCE)
+}
+
+/// Outlines of the wall pixels, simplified to within `tolerance` pixels, whether or not the walls layer is visible
+pub fn trace_walls(floor_plan: &FloorPlan, tolerance: f64) -> Vec<Vec<DVec2>> {
+    let image = &floor_plan.walls.image;
+    let (width, height) = image.dimensions();
+    let pixels = image.enumerate_pixels().filter(|(_, _, pixel)| pixel.0[3] == 255).map(|(x, y, _)| (x, y)).collect();
+
+    trace(pixels, width, height, &|x, y| image.get_pixel(x, y).0[3] == 255, tolerance)
+}
+
+/// Simplified outlines of the `pixels` where `inside` holds, dropping any too small to enclose anything
+fn trace(pixels: Vec<(u32, u32)>, width: u32, height: u32, inside: &dyn Fn(u32, u32) -> bool, tolerance: f64) -> Vec<Vec<DVec2>> {
+    let inside = |x: i64, y: i64| x >= 0 && y >= 0 && x < width as i64 && y < height as i64 && inside(x as u32, y as u32);
+
+    vector::trace_outlines(pixels.into_iter(), inside).iter()
+        .map(|outline| vector::simplify(outline, tolerance))
+        .filter(|outline| outline.len() >= 3)
+        .collect()
+}
+
+/// SVG document of the plan. One user unit is one file unit (the document is sized assuming metres),
+/// with x and y running along the floor plan image axes. Where the top left corner is in the world is in the
+/// description.
+pub fn svg(plan: &VectorPlan, transform: &CaptureTransform, georeference: &Georeference) -> String {
+    let scale = transform.pixel_x.length();
+    let (width, height) = (plan.width as f64 * scale, plan.height as f64 * scale);
+
+    let path_data = |outlines: &[Vec<DVec2>]| {
+        let mut data = String::new();
+
+        for outline in outlines {
+            for (i, point) in outline.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                let _ = write!(data, "{}{:.4} {:.4} ", command, point.x * scale, point.y * scale);
+            }
+            data.push_str("Z ");
+        }
+
+        data.trim_end().to_owned()
+    };
+
+    let mut svg = String::new();
+
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.1}mm" height="{:.1}mm" viewBox="0 0 {:.4} {:.4}">"#,
+        width * 1000.0, height * 1000.0, width, height);
+    let _ = writeln!(svg, "  <title>Floor Plan</title>");
+    let direction = |axis: glam::DVec3| {
+        let axis = axis.normalize_or_zero();
+        format!("({:.4}, {:.4}, {:.4})", axis.x, axis.y, axis.z)
+    };
+    let _ = writeln!(svg, "  <desc>Top left corner at {}{}, x along {}, y along {}</desc>",
+        georeference.format(transform.origin),
+        georeference.crs_name().map_or(String::new(), |name| format!(" in {}", xml_escape(&name))),
+        direction(transform.pixel_x), direction(transform.pixel_y));
+
+    let _ = writeln!(svg, r#"  <g id="rooms" fill-opacity="0.25" fill-rule="evenodd" stroke="none">"#);
+    for (i, ([r, g, b], room)) in plan.rooms.iter().enumerate() {
+        let _ = writeln!(svg, r##"    <path id="room-{}" fill="#{:02x}{:02x}{:02x}" d="{}"/>"##, i + 1, r, g, b, path_data(room));
+    }
+    let _ = writeln!(svg, "  </g>");
+
+    let _ = writeln!(svg, r##"  <g id="walls" fill="#000000" fill-rule="evenodd" stroke="none">"##);
+    if !plan.walls.is_empty() {
+        let _ = writeln!(svg, r#"    <path d="{}"/>"#, path_data(&plan.walls));
+    }
+    let _ = writeln!(svg, "  </g>");
+
+    let _ = writeln!(svg, r#"  <g id="annotations" fill-rule="evenodd" stroke="none">"#);
+    for ([r, g, b], outlines) in &plan.annotations {
+        let _ = writeln!(svg, r##"    <path fill="#{:02x}{:02x}{:02x}" d="{}"/>"##, r, g, b, path_data(outlines));
+    }
+    let _ = writeln!(svg, "  </g>");
+
+    let _ = writeln!(svg, r#"  <g id="labels" font-family="Ubuntu, sans-serif" text-anchor="middle" dominant-baseline="central">"#);
+    for label in &plan.labels {
+        let [r, g, b] = label.colour;
+        let _ = writeln!(svg, r##"    <text x="{:.4}" y="{:.4}" font-size="{:.4}" fill="#{:02x}{:02x}{:02x}">{}</text>"##,
+            label.position.x as f64 * scale, label.position.y as f64 * scale, label.size as f64 * scale, r, g, b, xml_escape(&label.text));
+    }
+    let _ = writeln!(svg, "  </g>");
+
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// Text escaped for SVG content and attributes
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// DXF (R12) drawing of the plan in file coordinates, with walls, rooms, annotations and labels on separate layers.
+/// Coordinates are written to the precision the file stored them at.
+pub fn dxf(plan: &VectorPlan, transform: &CaptureTransform, georeference: &Georeference) -> String {
+    let mut dxf = String::new();
+    let decimals = georeference.decimals();
+
+    let mut group = |code: i32, value: &str| {
+        let _ = writeln!(dxf, "{}\n{}", code, value);
+    };
+
+    // (name, ACI colour)
+    dxf_tables(&mut group, &[("WALLS", 7), ("ROOMS", 5), ("ANNOTATIONS", 1), ("LABELS", 7)], georeference);
+
+    group(0, "SECTION");
+    group(2, "ENTITIES");
+
+    let outlines = plan.walls.iter().map(|outline| ("WALLS", outline))
+        .chain(plan.rooms.iter().flat_map(|(_, outlines)| outlines).map(|outline| ("ROOMS", outline)))
+        .chain(plan.annotations.iter().flat_map(|(_, outlines)| outlines).map(|outline| ("ANNOTATIONS", outline)));
+
+    for (layer, outline) in outlines {
+        dxf_polyline(&mut group, layer, outline.iter().map(|point| transform.pixel_to_world(*point)), true, decimals);
+    }
+
+    // Labels read along the image's x axis, centred on their position
+    let rotation = transform.pixel_x.y.atan2(transform.pixel_x.x).to_degrees();
+    for label in &plan.labels {
+        let centre = transform.pixel_to_world(label.position.as_dvec2());
+
+        group(0, "TEXT");
+        group(8, "LABELS");
+        for (x, y, z) in [(10, 20, 30), (11, 21, 31)] {
+            group(x, &format!("{:.*}", decimals, centre.x));
+            group(y, &format!("{:.*}", decimals, centre.y));
+            group(z, &format!("{:.*}", decimals, centre.z));
+        }
+        group(40, &format!("{:.4}", label.size as f64 * transform.pixel_x.length()));
+        group(1, &label.text.replace(['\r', '\n'], " "));
+        group(50, &format!("{:.4}", rotation));
+        group(72, "1");
+        group(73, "2");
+    }
+
+    group(0, "ENDSEC");
+    group(0, "EOF");
+
+    dxf
+}
+
+/// Header comment and the line type and layer tables, layers given by name and ACI colour
+fn dxf_tables(group: &mut impl FnMut(i32, &str), layers: &[(&str, i32)], georeference: &Georeference) {
+    if let Some(name) = georeference.crs_name() {
+        group(999, &format!("Coordinate system: {}", name));
+    }
+
+    group(0, "SECTION");
+    group(2, "TABLES");
+
+    group(0, "TABLE");
+    group(2, "LTYPE");
+    group(70, "1");
+    group(0, "LTYPE");
+    group(2, "CONTINUOUS");
+    group(70, "0");
+    group(3, "Solid line");
+    group(72, "65");
+    group(73, "0");
+    group(40, "0.0");
+    group(0, "ENDTAB");
+
+    group(0, "TABLE");
+    group(2, "LAYER");
+    group(70, &layers.len().to_string());
+    for &(name, colour) in layers {
+        group(0, "LAYER");
+        group(2, name);
+        group(70, "0");
+        group(62, &colour.to_string());
+        group(6, "CONTINUOUS");
+    }
+    group(0, "ENDTAB");
+
+    group(0, "ENDSEC");
+}
+
+/// 3D polyline through `points` (file coordinates) on `layer`
+fn dxf_polyline(group: &mut impl FnMut(i32, &str), layer: &str, points: impl Iterator<Item = DVec3>, closed: bool, decimals: usize) {
+    group(0, "POLYLINE");
+    group(8, layer);
+    group(66, "1");
+    group(10, "0.0");
+    group(20, "0.0");
+    group(30, "0.0");
+    group(70, if closed { "9" } else { "8" });
+
+    for world in points {
+        group(0, "VERTEX");
+        group(8, layer);
+        group(10, &format!("{:.*}", decimals, world.x));
+        group(20, &format!("{:.*}", decimals, world.y));
+        group(30, &format!("{:.*}", decimals, world.z));
+        group(70, "32");
+    }
+
+    group(0, "SEQEND");
+    group(8, layer);
+}
+
+/// Wavefront OBJ massing model of the walls layer, extruded from `floor` to `ceiling` (elevations in file units).
+/// Sides follow the traced wall outlines and the tops and bottoms are the wall pixels merged into rectangles.
+/// Vertices are relative to the centre of the plan, given in a comment, as modelling tools lose precision far from
+/// the origin. The capture must look straight down.
+pub fn extruded_walls_obj(floor_plan: &FloorPlan, transform: &CaptureTransform, floor: f64, ceiling: f64, georeference: &Georeference) -> Result<String, String> {
+    if !transform.is_top_down() {
+        return Err("Walls can only be extruded from a top down cutaway".to_owned());
+    }
+
+    let image = &floor_plan.walls.image;
+    let (width, height) = image.dimensions();
+    let is_wall = |x: i64, y: i64| x >= 0 && y >= 0 && x < width as i64 && y < height as i64 && image.get_pixel(x as u32, y as u32).0[3] == 255;
+
+    let wall_pixels: Vec<(u32, u32)> = image.enumerate_pixels().filter(|(_, _, pixel)| pixel.0[3] == 255).map(|(x, y, _)| (x, y)).collect();
+    if wall_pixels.is_empty() {
+        return Err("There are no walls to extrude".to_owned());
+    }
+
+    let centre = transform.pixel_to_world(glam::dvec2(width as f64, height as f64) / 2.0);
+    let local = |pixel: DVec2, elevation: f64| {
+        let world = transform.pixel_to_world(pixel);
+        glam::dvec3(world.x - centre.x, world.y - centre.y, elevation - centre.z)
+    };
+
+    // Image y runs down, so world y usually runs the other way and walls end up on the left of outlines. Everything
+    // is reversed when the capture keeps the image's handedness.
+    let same_handedness = transform.pixel_x.cross(transform.pixel_y).z > 0.0;
+
+    let mut obj = String::new();
+    let _ = writeln!(obj, "# Walls extruded from {:.3} to {:.3}", floor, ceiling);
+    let _ = writeln!(obj, "# Offset: {}{}", georeference.format(centre), georeference.crs_name().map_or(String::new(), |name| format!(" ({})", name)));
+    let _ = writeln!(obj, "o walls");
+
+    let mut vertices = 0;
+    let mut quad = |obj: &mut String, corners: [glam::DVec3; 4]| {
+        for corner in corners {
+            let _ = writeln!(obj, "v {:.4} {:.4} {:.4}", corner.x, corner.y, corner.z);
+        }
+        let _ = writeln!(obj, "f {} {} {} {}", vertices + 1, vertices + 2, vertices + 3, vertices + 4);
+        vertices += 4;
+    };
+
+    // Collinear points along the pixel edges are dropped, but not corners, so the sides meet the tops exactly
+    let outlines = vector::trace_outlines(wall_pixels.iter().copied(), is_wall);
+
+    for outline in outlines.iter().map(|outline| vector::simplify(outline, 0.0)) {
+        for (i, a) in outline.iter().enumerate() {
+            let b = outline[(i + 1) % outline.len()];
+            let (a, b) = if same_handedness { (b, *a) } else { (*a, b) };
+
+            quad(&mut obj, [local(a, floor), local(b, floor), local(b, ceiling), local(a, ceiling)]);
+        }
+    }
+
+    for (x0, y0, x1, y1) in pixel_rectangles(width, height, |x, y| image.get_pixel(x, y).0[3] == 255) {
+        let corners = [glam::dvec2(x0 as f64, y0 as f64), glam::dvec2(x1 as f64, y0 as f64), glam::dvec2(x1 as f64, y1 as f64), glam::dvec2(x0 as f64, y1 as f64)];
+        // Clockwise on screen, anticlockwise from above
+        let [a, b, c, d] = if same_handedness { [corners[3], corners[2], corners[1], corners[0]] } else { corners };
+
+        quad(&mut obj, [local(a, ceiling), local(b, ceiling), local(c, ceiling), local(d, ceiling)]);
+        quad(&mut obj, [local(d, floor), local(c, floor), local(b, floor), local(a, floor)]);
+    }
+
+    Ok(obj)
+}
+
+/// Pixels where `inside` holds merged into rectangles (left, top, right, bottom, exclusive), runs along each row
+/// joined with identical runs on the rows below
+fn pixel_rectangles(width: u32, height: u32, inside: impl Fn(u32, u32) -> bool) -> Vec<(u32, u32, u32, u32)> {
+    let mut rectangles = vec![];
+    // Runs still growing downwards, (left, right) to top
+    let mut open: std::collections::HashMap<(u32, u32), u32> = std::collections::HashMap::new();
+
+    for y in 0..=height {
+        let mut runs = vec![];
+        let mut x = 0;
+
+        while y < height && x < width {
+            if inside(x, y) {
+                let start = x;
+                while x < width && inside(x, y) {
+                    x += 1;
+                }
+                runs.push((start, x));
+            } else {
+                x += 1;
+            }
+        }
+
+        open.retain(|&(left, right), top| {
+            let continues = runs.contains(&(left, right));
+            if !continues {
+                rectangles.push((left, *top, right, y));
+            }
+            continues
+        });
+
+        for run in runs {
+            open.entry(run).or_insert(y);
+        }
+    }
+
+    rectangles
+}
+
+/// SVG document of contours seen from above, north up. One user unit is one file unit (the document is sized assuming
+/// metres). Where the top left corner is in the world is in the description, and each contour's elevation in its id.
+pub fn contours_svg(contours: &[Contour], georeference: &Georeference) -> String {
+    let (min, max) = contours.iter().flat_map(|contour| contour.lines.iter().flatten())
+        .fold((DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)), |(min, max), p| (min.min(*p), max.max(*p)));
+    let (min, max) = if min.x <= max.x { (min, max) } else { (DVec2::ZERO, DVec2::ZERO) };
+    let (width, height) = ((max - min).x, (max - min).y);
+    let decimals = georeference.decimals();
+
+    let mut svg = String::new();
+
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.1}mm" height="{:.1}mm" viewBox="0 0 {:.4} {:.4}">"#,
+        width * 1000.0, height * 1000.0, width, height);
+    let _ = writeln!(svg, "  <title>Contours</title>");
+    let _ = writeln!(svg, "  <desc>Top left corner at {}{}, x east, y south</desc>",
+        georeference.format(glam::dvec3(min.x, max.y, 0.0)),
+        georeference.crs_name().map_or(String::new(), |name| format!(" in {}", name.replace('&', "&amp;").replace('<', "&lt;"))));
+
+    let _ = writeln!(svg, r##"  <g id="contours" fill="none" stroke="#8b4513" stroke-linejoin="round">"##);
+    for contour in contours {
+        let mut data = String::new();
+        for line in &contour.lines {
+            for (i, point) in line.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                let _ = write!(data, "{}{:.4} {:.4} ", command, point.x - min.x, max.y - point.y);
+            }
+        }
+
+        let width = if contour.major { 0.1 } else { 0.04 };
+        let _ = writeln!(svg, r#"    <path id="contour-{:.*}" stroke-width="{}" d="{}"/>"#, decimals, contour.elevation, width, data.trim_end());
+    }
+    let _ = writeln!(svg, "  </g>");
+
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// DXF (R12) drawing of contours as 3D polylines at their elevations, index contours on their own layer
+pub fn contours_dxf(contours: &[Contour], georeference: &Georeference) -> String {
+    let mut dxf = String::new();
+    let decimals = georeference.decimals();
+
+    let mut group = |code: i32, value: &str| {
+        let _ = writeln!(dxf, "{}\n{}", code, value);
+    };
+
+    dxf_tables(&mut group, &[("CONTOURS_MAJOR", 1), ("CONTOURS_MINOR", 8)], georeference);
+
+    group(0, "SECTION");
+    group(2, "ENTITIES");
+
+    for contour in contours {
+        let layer = if contour.major { "CONTOURS_MAJOR" } else { "CONTOURS_MINOR" };
+
+        for line in &contour.lines {
+            let closed = line.len() > 2 && line.first() == line.last();
+            let points = if closed { &line[..line.len() - 1] } else { &line[..] };
+
+            dxf_polyline(&mut group, layer, points.iter().map(|p| p.extend(contour.elevation)), closed, decimals);
+        }
+    }
+
+    group(0, "ENDSEC");
+    group(0, "EOF");
+
+    dxf
+}
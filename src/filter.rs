@@ -0,0 +1,142 @@
+use std::{collections::{BTreeMap, HashSet}, ops::RangeInclusive};
+
+use crate::{footprint::FootprintClip, vegetation::VegetationFilter};
+
+/// Decides which of the loaded points are turned into vertices
+#[derive(Default)]
+pub struct PointFilter {
+    /// Point source IDs (scan stations) to hide
+    pub hidden_point_sources: HashSet<u16>,
+    /// LAS classification codes to hide, e.g. vegetation and noise
+    pub hidden_classifications: HashSet<u8>,
+    pub returns: Returns,
+    /// Points scanned further than this from nadir are hidden, in degrees either side
+    pub max_scan_angle: Option<f32>,
+    /// Only points acquired in this window of GPS time are shown, points without a time always are
+    pub gps_time: Option<RangeInclusive<f64>>,
+    /// Only points inside an imported building footprint are shown
+    pub footprint: Option<FootprintClip>,
+    /// Trees and bushes are hidden
+    pub vegetation: Option<VegetationFilter>,
+}
+
+impl PointFilter {
+    pub fn accepts(&self, point: &las::Point) -> bool {
+        !self.hidden_point_sources.contains(&point.point_source_id)
+            && !self.hidden_classifications.contains(&u8::from(point.classification))
+            && self.returns.accepts(point)
+            && self.max_scan_angle.is_none_or(|max| point.scan_angle.abs() <= max)
+            && self.gps_time.as_ref().zip(point.gps_time).is_none_or(|(window, time)| window.contains(&time))
+            && self.footprint.as_ref().is_none_or(|clip| clip.accepts(point))
+            && self.vegetation.as_ref().is_none_or(|vegetation| !vegetation.is_vegetation(point))
+    }
+
+    /// Stop hiding values no loaded point has any more, so they aren't hidden when they come back
+    pub fn retain_present(&mut self, counts: &AttributeCounts) {
+        self.hidden_point_sources.retain(|id| counts.point_sources.contains_key(id));
+        self.hidden_classifications.retain(|code| counts.classifications.contains_key(code));
+    }
+}
+
+/// Which returns of each laser pulse are kept. Points without return numbers (most terrestrial scans) count as the only
+/// return of their pulse.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Returns {
+    #[default]
+    All,
+    First,
+    /// Drops the tree canopy above buildings in aerial lidar
+    Last,
+    /// Pulses that came back once, hard surfaces
+    Single,
+}
+
+impl Returns {
+    pub const ALL: [Returns; 4] = [Returns::All, Returns::First, Returns::Last, Returns::Single];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Returns::All => "All Returns",
+            Returns::First => "First Returns Only",
+            Returns::Last => "Last Returns Only",
+            Returns::Single => "Single Returns Only",
+        }
+    }
+
+    pub fn accepts(&self, point: &las::Point) -> bool {
+        let returns = point.number_of_returns.max(1);
+        let number = point.return_number.clamp(1, returns);
+
+        match self {
+            Returns::All => true,
+            Returns::First => number == 1,
+            Returns::Last => number == returns,
+            Returns::Single => returns == 1,
+        }
+    }
+}
+
+/// Number of loaded points with each value of the attributes the filter can hide
+#[derive(Default, Clone, Debug)]
+pub struct AttributeCounts {
+    pub point_sources: BTreeMap<u16, u64>,
+    pub classifications: BTreeMap<u8, u64>,
+    /// Earliest and latest GPS time of the points that have one. Not narrowed when points are removed.
+    pub gps_time: Option<(f64, f64)>,
+}
+
+impl AttributeCounts {
+    pub fn add(&mut self, point: &las::Point) {
+        *self.point_sources.entry(point.point_source_id).or_insert(0) += 1;
+        *self.classifications.entry(u8::from(point.classification)).or_insert(0) += 1;
+
+        if let Some(time) = point.gps_time {
+            self.gps_time = Some(self.gps_time.map_or((time, time), |(start, end)| (start.min(time), end.max(time))));
+        }
+    }
+
+    /// Take away the points counted in `removed`, dropping values with none left
+    pub fn subtract(&mut self, removed: &AttributeCounts) {
+        fn subtract<K: Ord + Copy>(counts: &mut BTreeMap<K, u64>, removed: &BTreeMap<K, u64>) {
+            for (key, count) in removed {
+                let remaining = counts.get(key).map_or(0, |total| total.saturating_sub(*count));
+
+                if remaining > 0 {
+                    counts.insert(*key, remaining);
+                } else {
+                    counts.remove(key);
+                }
+            }
+        }
+
+        subtract(&mut self.point_sources, &removed.point_sources);
+        subtract(&mut self.classifications, &removed.classifications);
+    }
+}
+
+/// ASPRS name of a LAS classification code
+pub fn classification_name(code: u8) -> &'static str {
+    match code {
+        0 => "Never Classified",
+        1 => "Unclassified",
+        2 => "Ground",
+        3 => "Low Vegetation",
+        4 => "Medium Vegetation",
+        5 => "High Vegetation",
+        6 => "Building",
+        7 => "Low Noise",
+        8 => "Model Key Point",
+        9 => "Water",
+        10 => "Rail",
+        11 => "Road Surface",
+        12 => "Overlap",
+        13 => "Wire Guard",
+        14 => "Wire Conductor",
+        15 => "Transmission Tower",
+        16 => "Wire Connector",
+        17 => "Bridge Deck",
+        18 => "High Noise",
+        19..=63 => "Reserved",
+        _ => "User Defined",
+    }
+}
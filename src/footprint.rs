@@ -0,0 +1,172 @@
+use std::{fs, ops::RangeInclusive, path::Path};
+
+use glam::DVec2;
+use serde_json::Value;
+
+/// Outline of a building, or several, from a GIS layer. In the files' horizontal coordinates, which is only useful for
+/// georeferenced scans.
+#[derive(Clone, Debug)]
+pub struct Footprint {
+    /// Outer rings and holes alike, a point is inside when it's inside an odd number of them
+    rings: Vec<Vec<DVec2>>,
+    min: DVec2,
+    max: DVec2,
+}
+
+impl Footprint {
+    fn new(rings: Vec<Vec<DVec2>>) -> Result<Footprint, String> {
+        let rings: Vec<Vec<DVec2>> = rings.into_iter().filter(|ring| ring.len() >= 3).collect();
+
+        if rings.is_empty() {
+            return Err("no polygons".to_owned());
+        }
+
+        let (min, max) = rings.iter().flatten()
+            .fold((DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)), |(min, max), p| (min.min(*p), max.max(*p)));
+
+        Ok(Footprint { rings, min, max })
+    }
+
+    /// Polygons and multipolygons from GeoJSON (a geometry, feature or feature collection) or WKT
+    pub fn parse(text: &str) -> Result<Footprint, String> {
+        let text = text.trim();
+
+        if text.starts_with('{') {
+            let json: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+            let mut rings = vec![];
+            geojson_rings(&json, &mut rings)?;
+            Footprint::new(rings)
+        } else {
+            Footprint::new(wkt_rings(text)?)
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Footprint, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+        Footprint::parse(&text).map_err(|err| format!("Failed to parse {}: {}", path.display(), err))
+    }
+
+    pub fn ring_count(&self) -> usize {
+        self.rings.len()
+    }
+
+    /// Corners of the box around every ring
+    pub fn bounds(&self) -> (DVec2, DVec2) {
+        (self.min, self.max)
+    }
+
+    /// Even-odd test over every ring, so holes (courtyards) are left out
+    pub fn contains(&self, p: DVec2) -> bool {
+        if p.cmplt(self.min).any() || p.cmpgt(self.max).any() {
+            return false;
+        }
+
+        let mut inside = false;
+
+        for ring in &self.rings {
+            for (i, a) in ring.iter().enumerate() {
+                let b = ring[(i + 1) % ring.len()];
+
+                if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                    inside = !inside;
+                }
+            }
+        }
+
+        inside
+    }
+}
+
+/// Keeps the points inside a footprint, so neighbouring buildings and street clutter don't show up in the cutaway
+#[derive(Clone, Debug)]
+pub struct FootprintClip {
+    pub footprint: Footprint,
+    /// Points outside these elevations are clipped too, all heights are kept if None
+    pub elevation: Option<RangeInclusive<f64>>,
+}
+
+impl FootprintClip {
+    pub fn accepts(&self, point: &las::Point) -> bool {
+        self.elevation.as_ref().is_none_or(|elevation| elevation.contains(&point.z))
+            && self.footprint.contains(glam::dvec2(point.x, point.y))
+    }
+}
+
+fn geojson_rings(json: &Value, rings: &mut Vec<Vec<DVec2>>) -> Result<(), String> {
+    let polygon = |coordinates: &Value, rings: &mut Vec<Vec<DVec2>>| -> Result<(), String> {
+        for ring in coordinates.as_array().ok_or("polygon coordinates aren't an array")? {
+            rings.push(ring.as_array().ok_or("ring isn't an array")?.iter().map(geojson_position).collect::<Result<_, _>>()?);
+        }
+        Ok(())
+    };
+
+    match json["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in json["features"].as_array().ok_or("feature collection has no features")? {
+                geojson_rings(feature, rings)?;
+            }
+        },
+        Some("Feature") => geojson_rings(&json["geometry"], rings)?,
+        Some("GeometryCollection") => {
+            for geometry in json["geometries"].as_array().ok_or("geometry collection has no geometries")? {
+                geojson_rings(geometry, rings)?;
+            }
+        },
+        Some("Polygon") => polygon(&json["coordinates"], rings)?,
+        Some("MultiPolygon") => {
+            for coordinates in json["coordinates"].as_array().ok_or("multipolygon coordinates aren't an array")? {
+                polygon(coordinates, rings)?;
+            }
+        },
+        // Points and lines have no inside
+        Some(_) => {},
+        None => return Err("GeoJSON object has no type".to_owned()),
+    }
+
+    Ok(())
+}
+
+fn geojson_position(position: &Value) -> Result<DVec2, String> {
+    match position.as_array().map(|values| values.iter().map(Value::as_f64).collect::<Vec<_>>()).as_deref() {
+        Some([Some(x), Some(y), ..]) => Ok(glam::dvec2(*x, *y)),
+        _ => Err(format!("{} isn't a position", position)),
+    }
+}
+
+/// Rings of a WKT POLYGON or MULTIPOLYGON, or several: every innermost parenthesised list of coordinates
+fn wkt_rings(text: &str) -> Result<Vec<Vec<DVec2>>, String> {
+    let upper = text.to_ascii_uppercase();
+    if !upper.contains("POLYGON") {
+        return Err("expected GeoJSON or a WKT POLYGON or MULTIPOLYGON".to_owned());
+    }
+
+    let mut rings = vec![];
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => start = Some(i + 1),
+            ')' => {
+                if let Some(start) = start.take() {
+                    let ring = text[start..i].split(',')
+                        .map(|position| {
+                            let values: Vec<f64> = position.split_whitespace().map(str::parse).collect::<Result<_, _>>()
+                                .map_err(|_| format!("`{}` isn't a position", position.trim()))?;
+
+                            match values[..] {
+                                [x, y, ..] => Ok(glam::dvec2(x, y)),
+                                _ => Err(format!("`{}` isn't a position", position.trim())),
+                            }
+                        })
+                        .collect::<Result<_, String>>()?;
+
+                    rings.push(ring);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok(rings)
+}
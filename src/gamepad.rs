@@ -0,0 +1,88 @@
+use glam::{Vec2, Vec3};
+
+/// Stick positions closer to the centre than this are ignored, sticks rarely rest at exactly 0
+#[cfg(feature = "gamepad")]
+const DEAD_ZONE: f32 = 0.15;
+/// Look speed at full tilt, in mouse pixels per second
+#[cfg(feature = "gamepad")]
+const LOOK_SPEED: f32 = 1200.0;
+/// Zoom speed with a bumper held, in scroll wheel lines per second
+#[cfg(feature = "gamepad")]
+const ZOOM_SPEED: f32 = 10.0;
+
+/// Input from every connected gamepad this frame, in the terms the keyboard and mouse use
+#[derive(Clone, Copy, Default, Debug)]
+pub struct GamepadInput {
+    /// Right, up and forward, each from -1 to 1
+    pub movement: Vec3,
+    /// Added to the mouse movement
+    pub look: Vec2,
+    /// Added to the camera zoom
+    pub zoom: f32,
+    pub sprint: bool,
+}
+
+impl GamepadInput {
+    pub fn is_idle(&self) -> bool {
+        self.movement == Vec3::ZERO && self.look == Vec2::ZERO && self.zoom == 0.0 && !self.sprint
+    }
+}
+
+/// Left stick moves, right stick looks, triggers move down and up, bumpers zoom out and in, clicking the left stick
+/// moves faster. Always absent without the `gamepad` feature.
+pub struct GamepadManager {
+    #[cfg(feature = "gamepad")]
+    gilrs: gilrs::Gilrs,
+}
+
+impl GamepadManager {
+    #[cfg(feature = "gamepad")]
+    pub fn new() -> Option<GamepadManager> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(GamepadManager { gilrs }),
+            Err(err) => {
+                eprintln!("Gamepads unavailable: {}", err);
+                None
+            },
+        }
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn new() -> Option<GamepadManager> {
+        None
+    }
+
+    /// Current input, `frame_length` in seconds
+    #[cfg(feature = "gamepad")]
+    pub fn poll(&mut self, frame_length: f32) -> GamepadInput {
+        use gilrs::{Axis, Button};
+
+        // Gamepad state only updates as events are read
+        while self.gilrs.next_event().is_some() {}
+
+        let mut input = GamepadInput::default();
+
+        for (_, gamepad) in self.gilrs.gamepads() {
+            let axis = |axis| {
+                let value = gamepad.value(axis);
+                if value.abs() < DEAD_ZONE { 0.0 } else { value }
+            };
+            let button = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+
+            input.movement += glam::vec3(axis(Axis::LeftStickX), button(Button::RightTrigger2) - button(Button::LeftTrigger2), axis(Axis::LeftStickY));
+            // Stick up is positive, the mouse moving up is negative
+            input.look += glam::vec2(axis(Axis::RightStickX), -axis(Axis::RightStickY)) * LOOK_SPEED * frame_length;
+            input.zoom += (button(Button::RightTrigger) - button(Button::LeftTrigger)) * ZOOM_SPEED * frame_length;
+            input.sprint |= gamepad.is_pressed(Button::LeftThumb);
+        }
+
+        input.movement = input.movement.clamp_length_max(1.0);
+
+        input
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn poll(&mut self, _frame_length: f32) -> GamepadInput {
+        GamepadInput::default()
+    }
+}
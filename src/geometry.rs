@@ -0,0 +1,392 @@
+use glam::{DMat3, DVec2, DVec3};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{jobs::JobContext, random::XorShift};
+
+/// Random samples tried for each RANSAC plane
+const RANSAC_ITERATIONS: usize = 500;
+/// Normals within this of vertical (dot product) count as horizontal planes, within this of horizontal as walls
+const ORIENTATION_TOLERANCE: f64 = 0.95;
+/// Width of the elevation histogram bins levels are found in
+const LEVEL_BIN_SIZE: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub origin: DVec3,
+    pub normal: DVec3,
+}
+
+impl Plane {
+    pub fn distance(&self, p: DVec3) -> f64 {
+        (p - self.origin).dot(self.normal).abs()
+    }
+
+    /// Two orthonormal vectors spanning the plane
+    pub fn basis(&self) -> (DVec3, DVec3) {
+        let u = self.normal.any_orthonormal_vector();
+        let v = self.normal.cross(u);
+
+        (u, v)
+    }
+}
+
+/// Least squares plane through the points, normal is the direction of least variance
+pub fn fit_plane(points: &[DVec3]) -> Option<Plane> {
+    if points.len() < 3 {
+        return None;
+    }
+
+    let n = points.len() as f64;
+    let centroid = points.iter().fold(DVec3::ZERO, |acc, p| acc + *p) / n;
+
+    let mut covariance = DMat3::ZERO;
+    for p in points {
+        let d = *p - centroid;
+        covariance += DMat3::from_cols(d * d.x, d * d.y, d * d.z);
+    }
+    covariance *= 1.0 / n;
+
+    let (values, vectors) = symmetric_eigen(covariance);
+
+    let mut smallest = 0;
+    for i in 1..3 {
+        if values[i] < values[smallest] {
+            smallest = i;
+        }
+    }
+
+    let normal = vectors[smallest].normalize_or_zero();
+
+    if normal == DVec3::ZERO {
+        return None;
+    }
+
+    Some(Plane {
+        origin: centroid,
+        normal,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaneKind {
+    Floor,
+    Ceiling,
+    Wall,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DetectedPlane {
+    pub plane: Plane,
+    pub kind: PlaneKind,
+    /// Share of the sampled points lying on the plane
+    pub inlier_fraction: f64,
+}
+
+/// Sequential RANSAC: repeatedly find the plane with the most points within `threshold`, refit it to those points
+/// and remove them, until `max_planes` are found or the best plane has fewer than `min_fraction` of the points.
+/// File coordinates, z up. Run as a job, it gives up when the job is cancelled.
+pub fn detect_planes(points: &[DVec3], threshold: f64, max_planes: usize, min_fraction: f64, context: &JobContext) -> Result<Vec<DetectedPlane>, String> {
+    let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+    let mut remaining = points.to_vec();
+    let mut planes = vec![];
+
+    while planes.len() < max_planes && remaining.len() >= 3 {
+        let mut best: Option<(Plane, usize)> = None;
+
+        for _ in 0..RANSAC_ITERATIONS {
+            if context.is_cancelled() {
+                return Err("Plane detection was cancelled".to_owned());
+            }
+
+            let [a, b, c] = [0; 3].map(|_| remaining[rng.below(remaining.len())]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+
+            if normal == DVec3::ZERO {
+                continue;
+            }
+
+            let candidate = Plane {
+                origin: a,
+                normal,
+            };
+            let inliers = remaining.par_iter().filter(|p| candidate.distance(**p) <= threshold).count();
+
+            match best {
+                Some((_, count)) if count >= inliers => {},
+                _ => best = Some((candidate, inliers)),
+            }
+        }
+
+        let (candidate, count) = match best {
+            Some(best) => best,
+            None => break,
+        };
+
+        if (count as f64) < min_fraction * points.len() as f64 {
+            break;
+        }
+
+        let inliers: Vec<DVec3> = remaining.iter().copied().filter(|p| candidate.distance(*p) <= threshold).collect();
+        let plane = fit_plane(&inliers).unwrap_or(candidate);
+
+        remaining.retain(|p| plane.distance(*p) > threshold);
+
+        planes.push(DetectedPlane {
+            plane,
+            kind: classify_plane(&plane, &inliers, points),
+            inlier_fraction: inliers.len() as f64 / points.len() as f64,
+        });
+
+        context.progress().set(planes.len() as f32 / max_planes as f32);
+    }
+
+    Ok(planes)
+}
+
+/// Horizontal planes with more of the cloud just above them than below are floors
+fn classify_plane(plane: &Plane, inliers: &[DVec3], points: &[DVec3]) -> PlaneKind {
+    let vertical = plane.normal.z.abs();
+
+    if vertical < 1.0 - ORIENTATION_TOLERANCE {
+        return PlaneKind::Wall;
+    }
+    if vertical < ORIENTATION_TOLERANCE {
+        return PlaneKind::Other;
+    }
+
+    // Only look at points over the plane's own footprint
+    let (min, max) = inliers.iter().fold((DVec3::splat(f64::INFINITY), DVec3::splat(f64::NEG_INFINITY)), |(min, max), p| (min.min(*p), max.max(*p)));
+    let height = plane.origin.z;
+
+    let (above, below) = points.iter()
+        .filter(|p| p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y)
+        .fold((0, 0), |(above, below), p| {
+            let offset = p.z - height;
+
+            if (0.1..1.5).contains(&offset) {
+                (above + 1, below)
+            } else if (-1.5..-0.1).contains(&offset) {
+                (above, below + 1)
+            } else {
+                (above, below)
+            }
+        });
+
+    if above >= below {
+        PlaneKind::Floor
+    } else {
+        PlaneKind::Ceiling
+    }
+}
+
+/// Horizontal slab (floor or ceiling) found from the distribution of point elevations
+#[derive(Clone, Copy, Debug)]
+pub struct Level {
+    pub height: f64,
+    pub kind: PlaneKind,
+    /// Share of the points in the slab
+    pub fraction: f64,
+}
+
+/// Find floors and ceilings as peaks in a histogram of point elevations. Peaks need at least `min_fraction` of the points,
+/// must stand well above their surroundings and be at least `min_separation` apart. Sorted from lowest to highest.
+pub fn detect_levels(heights: &[f64], min_fraction: f64, min_separation: f64) -> Vec<Level> {
+    let (min, max) = heights.iter()
+        .filter(|z| z.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &z| (min.min(z), max.max(z)));
+
+    if min > max {
+        return vec![];
+    }
+
+    let num_bins = ((max - min) / LEVEL_BIN_SIZE) as usize + 1;
+    let bin = |z: f64| (((z - min) / LEVEL_BIN_SIZE) as usize).min(num_bins - 1);
+
+    let mut counts = vec![0_usize; num_bins];
+    let mut total = 0;
+
+    for &z in heights.iter().filter(|z| z.is_finite()) {
+        counts[bin(z)] += 1;
+        total += 1;
+    }
+
+    let count = |i: isize| if i >= 0 && (i as usize) < num_bins { counts[i as usize] as f64 } else { 0.0 };
+    let smoothed: Vec<f64> = (0..num_bins as isize).map(|i| (count(i - 1) + 2.0 * count(i) + count(i + 1)) / 4.0).collect();
+    let smooth = |i: isize| if i >= 0 && (i as usize) < num_bins { smoothed[i as usize] } else { 0.0 };
+
+    let window = (min_separation / LEVEL_BIN_SIZE).ceil() as isize;
+
+    let mut peaks: Vec<(isize, f64)> = (0..num_bins as isize)
+        .filter(|&i| smooth(i) >= smooth(i - 1) && smooth(i) > smooth(i + 1))
+        .filter(|&i| {
+            let fraction = (count(i - 1) + count(i) + count(i + 1)) / total as f64;
+            let background = (i - window..=i + window).map(smooth).sum::<f64>() / (2 * window + 1) as f64;
+
+            fraction >= min_fraction && smooth(i) >= 2.0 * background
+        })
+        .map(|i| (i, smooth(i)))
+        .collect();
+
+    // Keep the strongest of peaks closer than the separation
+    peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut kept: Vec<isize> = vec![];
+    for (i, _) in peaks {
+        if kept.iter().all(|&k| (k - i).abs() >= window) {
+            kept.push(i);
+        }
+    }
+
+    kept.sort_unstable();
+
+    let count_between = |from: f64, to: f64| if from > to || from > max || to < min {
+        0.0
+    } else {
+        (bin(from.max(min)) as isize..=bin(to.min(max)) as isize).map(count).sum::<f64>()
+    };
+
+    kept.iter().enumerate()
+        .map(|(n, &i)| {
+            // Mean elevation of the points in the peak's bins
+            let (low, high) = (min + (i - 1) as f64 * LEVEL_BIN_SIZE, min + (i + 2) as f64 * LEVEL_BIN_SIZE);
+            let (sum, slab) = heights.iter()
+                .filter(|&&z| z >= low && z < high)
+                .fold((0.0, 0), |(sum, slab), &z| (sum + z, slab + 1));
+            let height = sum / slab.max(1) as f64;
+
+            // Furniture and walls stand on floors, so there's more above a floor than below it.
+            // Only looks as far as the neighbouring levels, so the slab between storeys doesn't count.
+            let next = kept.get(n + 1).map_or(f64::INFINITY, |&j| min + j as f64 * LEVEL_BIN_SIZE);
+            let previous = n.checked_sub(1).map_or(f64::NEG_INFINITY, |m| min + (kept[m] + 1) as f64 * LEVEL_BIN_SIZE);
+
+            let above = count_between(height + 0.1, (height + 1.5).min(next - 0.1));
+            let below = count_between((height - 1.5).max(previous + 0.1), height - 0.1);
+
+            Level {
+                height,
+                kind: if above >= below { PlaneKind::Floor } else { PlaneKind::Ceiling },
+                fraction: slab as f64 / total as f64,
+            }
+        })
+        .collect()
+}
+
+/// Area of a closed polygon projected onto its best fit plane, along with the plane
+pub fn polygon_area(points: &[DVec3]) -> Option<(f64, Plane)> {
+    let plane = fit_plane(points)?;
+    let (u, v) = plane.basis();
+
+    let projected: Vec<DVec2> = points.iter().map(|p| glam::dvec2((*p - plane.origin).dot(u), (*p - plane.origin).dot(v))).collect();
+
+    Some((shoelace(&projected), plane))
+}
+
+/// Area of a closed polygon seen from above, ignoring elevation
+pub fn footprint_area(points: &[DVec3]) -> f64 {
+    let projected: Vec<DVec2> = points.iter().map(|p| p.truncate()).collect();
+
+    shoelace(&projected)
+}
+
+/// Unsigned area of a simple polygon
+fn shoelace(points: &[DVec2]) -> f64 {
+    let twice_area: f64 = points.iter().zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum();
+
+    twice_area.abs() / 2.0
+}
+
+/// Eigenvalues and eigenvectors of a symmetric 3x3 matrix (Jacobi rotation method)
+pub fn symmetric_eigen(m: DMat3) -> ([f64; 3], [DVec3; 3]) {
+    // Matrix is symmetric, so rows and columns are interchangeable
+    let mut a = m.to_cols_array_2d();
+    let mut v = DMat3::IDENTITY.to_cols_array_2d();
+
+    for _ in 0..32 {
+        let (p, q) = [(0, 1), (0, 2), (1, 2)].into_iter()
+            .max_by(|&(p0, q0), &(p1, q1)| a[p0][q0].abs().total_cmp(&a[p1][q1].abs()))
+            .expect("No off-diagonal elements");
+
+        if a[p][q].abs() < 1e-15 {
+            break;
+        }
+
+        let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+        let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+        let c = 1.0 / (t * t + 1.0).sqrt();
+        let s = t * c;
+
+        for row in a.iter_mut() {
+            let (akp, akq) = (row[p], row[q]);
+            row[p] = c * akp - s * akq;
+            row[q] = s * akp + c * akq;
+        }
+
+        let (row_p, row_q) = (a[p], a[q]);
+        a[p] = std::array::from_fn(|k| c * row_p[k] - s * row_q[k]);
+        a[q] = std::array::from_fn(|k| s * row_p[k] + c * row_q[k]);
+
+        for row in v.iter_mut() {
+            let (vkp, vkq) = (row[p], row[q]);
+            row[p] = c * vkp - s * vkq;
+            row[q] = s * vkp + c * vkq;
+        }
+    }
+
+    let values = [a[0][0], a[1][1], a[2][2]];
+    let vectors = [
+        DVec3::new(v[0][0], v[1][0], v[2][0]),
+        DVec3::new(v[0][1], v[1][1], v[2][1]),
+        DVec3::new(v[0][2], v[1][2], v[2][2]),
+    ];
+
+    (values, vectors)
+}
+
+/// Floor and ceiling of the storey a clipping plane at `elevation` cuts through, from detected floors and ceilings.
+/// Without them the floor is `cut_height` below the plane and the ceiling a typical storey above the floor.
+pub fn storey_heights(surfaces: &[(PlaneKind, f64)], elevation: f64, cut_height: f64) -> (f64, f64) {
+    const STOREY_HEIGHT: f64 = 2.7;
+
+    let heights = |kind: PlaneKind| surfaces.iter().filter(move |(k, _)| *k == kind).map(|(_, height)| *height);
+
+    let floor = heights(PlaneKind::Floor).filter(|height| *height <= elevation).reduce(f64::max)
+        .unwrap_or(elevation - cut_height);
+    let ceiling = heights(PlaneKind::Ceiling).filter(|height| *height >= elevation).reduce(f64::min)
+        .unwrap_or(floor + STOREY_HEIGHT);
+
+    (floor, ceiling)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_plane_through_level_points() {
+        let points: Vec<DVec3> = (0..5).flat_map(|x| (0..5).map(move |y| glam::dvec3(x as f64, y as f64, 2.0))).collect();
+
+        let plane = fit_plane(&points).expect("Points span a plane");
+
+        assert!(plane.normal.dot(DVec3::Z).abs() > 0.999);
+        assert!((plane.distance(glam::dvec3(1.0, 1.0, 5.0)) - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fit_plane_through_sloped_points() {
+        let points: Vec<DVec3> = (0..5).flat_map(|x| (0..5).map(move |y| glam::dvec3(x as f64, y as f64, x as f64))).collect();
+
+        let plane = fit_plane(&points).expect("Points span a plane");
+
+        assert!(plane.normal.dot(glam::dvec3(1.0, 0.0, -1.0).normalize()).abs() > 0.999);
+        assert!(points.iter().all(|p| plane.distance(*p) < 1e-9));
+    }
+
+    #[test]
+    fn fit_plane_needs_three_points() {
+        assert!(fit_plane(&[DVec3::ZERO, DVec3::X]).is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::{Path, PathBuf};
+
+use glam::{DVec2, DVec3};
+use las::Read;
+
+use crate::plan::CaptureTransform;
+
+/// LAS VLR user id for coordinate system records
+const PROJECTION_USER_ID: &str = "LASF_Projection";
+/// OGC WKT of the coordinate system, null terminated
+const WKT_RECORD_ID: u16 = 2112;
+/// GeoTIFF key directory, little endian u16s
+const GEO_KEY_DIRECTORY_RECORD_ID: u16 = 34735;
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const GEOGRAPHIC_TYPE_GEO_KEY: u16 = 2048;
+/// GeoTIFF code for a coordinate system defined by the other keys rather than an EPSG code
+const USER_DEFINED: u16 = 32767;
+
+/// Where a LAS file's coordinates are in the world. Points are kept in these coordinates; the viewer only moves them
+/// to the centre of the loaded files for rendering.
+#[derive(Clone, Debug)]
+pub struct Georeference {
+    /// Coordinate system from the file's WKT VLR
+    pub wkt: Option<String>,
+    /// EPSG code of the coordinate system from the file's GeoTIFF keys
+    pub epsg: Option<u16>,
+    /// Step between the coordinates the file can store
+    pub scale: DVec3,
+    /// Added to the stored coordinates
+    pub offset: DVec3,
+}
+
+impl Default for Georeference {
+    fn default() -> Georeference {
+        Georeference {
+            wkt: None,
+            epsg: None,
+            scale: DVec3::splat(0.001),
+            offset: DVec3::ZERO,
+        }
+    }
+}
+
+impl Georeference {
+    pub fn from_header(header: &las::Header) -> Georeference {
+        let transforms = header.transforms();
+        let projection = || header.all_vlrs().filter(|vlr| vlr.user_id.trim_end_matches('\0') == PROJECTION_USER_ID);
+
+        let wkt = projection()
+            .find(|vlr| vlr.record_id == WKT_RECORD_ID)
+            .map(|vlr| String::from_utf8_lossy(&vlr.data).trim_end_matches('\0').trim().to_owned())
+            .filter(|wkt| !wkt.is_empty());
+
+        let epsg = projection()
+            .find(|vlr| vlr.record_id == GEO_KEY_DIRECTORY_RECORD_ID)
+            .and_then(|vlr| geo_key_epsg(&vlr.data));
+
+        Georeference {
+            wkt,
+            epsg,
+            scale: glam::dvec3(transforms.x.scale, transforms.y.scale, transforms.z.scale),
+            offset: glam::dvec3(transforms.x.offset, transforms.y.offset, transforms.z.offset),
+        }
+    }
+
+    /// Georeference of the LAS file at `path`, reading only its header
+    pub fn read(path: &str) -> Result<Georeference, String> {
+        let reader = las::Reader::from_path(path).map_err(|err| format!("Failed to read the header of {}: {}", path, err))?;
+
+        Ok(Georeference::from_header(reader.header()))
+    }
+
+    /// Short name of the coordinate system, e.g. `EPSG:28355` or the name in the WKT
+    pub fn crs_name(&self) -> Option<String> {
+        if let Some(epsg) = self.epsg {
+            return Some(format!("EPSG:{}", epsg));
+        }
+
+        // PROJCS["GDA94 / MGA zone 55", ...
+        let wkt = self.wkt.as_ref()?;
+        let start = wkt.find('"')? + 1;
+        let end = start + wkt[start..].find('"')?;
+
+        Some(wkt[start..end].to_owned())
+    }
+
+    /// Decimal places the file stores coordinates to
+    pub fn decimals(&self) -> usize {
+        let finest = self.scale.min_element();
+
+        if finest > 0.0 && finest.is_finite() {
+            (-finest.log10()).ceil().clamp(0.0, 9.0) as usize
+        } else {
+            3
+        }
+    }
+
+    /// World coordinates to the file's precision
+    pub fn format(&self, position: DVec3) -> String {
+        let decimals = self.decimals();
+
+        format!("{:.*}, {:.*}, {:.*}", decimals, position.x, decimals, position.y, decimals, position.z)
+    }
+
+    /// Write the coordinate system next to an exported file as an ESRI style `.prj`, if the WKT is known. Returns the
+    /// file written.
+    pub fn write_prj(&self, exported: &Path) -> Result<Option<PathBuf>, String> {
+        let Some(wkt) = &self.wkt else {
+            return Ok(None);
+        };
+
+        let path = exported.with_extension("prj");
+        std::fs::write(&path, wkt).map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
+
+        Ok(Some(path))
+    }
+
+    /// Write a world file, `.prj` and GDAL `.aux.xml` beside an image captured with `transform`, so GIS software
+    /// (e.g. QGIS) places it on a map. Only for files with a coordinate system and captures looking straight down.
+    /// Returns the files written.
+    pub fn write_image_georeferencing(&self, image: &Path, transform: &CaptureTransform) -> Result<Vec<PathBuf>, String> {
+        let Some(srs) = self.wkt.clone().or_else(|| self.epsg.map(|epsg| format!("EPSG:{}", epsg))) else {
+            return Ok(vec![]);
+        };
+        let world = world_file(transform).ok_or_else(|| format!("{} isn't a top down view, it can't be georeferenced", image.display()))?;
+
+        let write = |path: PathBuf, contents: &str| {
+            std::fs::write(&path, contents).map_err(|err| format!("Failed to save {}: {}", path.display(), err)).map(|_| path)
+        };
+
+        let mut written = vec![write(world_file_path(image), &world)?];
+        written.extend(self.write_prj(image)?);
+
+        let srs = srs.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        let mut aux = image.as_os_str().to_owned();
+        aux.push(".aux.xml");
+        written.push(write(aux.into(), &format!("<PAMDataset>\n  <SRS>{}</SRS>\n</PAMDataset>\n", srs))?);
+
+        Ok(written)
+    }
+}
+
+/// Direction of north in file X and Y: grid north (+Y) turned clockwise by `north_offset` degrees, for scans in
+/// local coordinates or to correct to true north
+pub fn north(north_offset: f64) -> DVec2 {
+    let angle = north_offset.to_radians();
+
+    glam::dvec2(angle.sin(), angle.cos())
+}
+
+/// Compass bearing of a horizontal direction in file X and Y, in degrees clockwise from north
+pub fn bearing(direction: DVec2, north_offset: f64) -> f64 {
+    (direction.x.atan2(direction.y).to_degrees() - north_offset).rem_euclid(360.0)
+}
+
+/// Nearest of the eight compass points to a bearing
+pub fn compass_point(bearing: f64) -> &'static str {
+    ["N", "NE", "E", "SE", "S", "SW", "W", "NW"][(bearing.rem_euclid(360.0) / 45.0).round() as usize % 8]
+}
+
+/// ESRI world file of an image captured with `transform`, mapping pixel centres to world X and Y. None unless the
+/// capture looks straight down.
+pub fn world_file(transform: &CaptureTransform) -> Option<String> {
+    if !transform.is_top_down() {
+        return None;
+    }
+
+    let centre = transform.origin + (transform.pixel_x + transform.pixel_y) / 2.0;
+
+    // x scale, y rotation, x rotation, y scale, then the centre of the top left pixel
+    Some([transform.pixel_x.x, transform.pixel_x.y, transform.pixel_y.x, transform.pixel_y.y, centre.x, centre.y]
+        .map(|value| format!("{:.10}\n", value))
+        .concat())
+}
+
+/// World file beside an image, its extension's first and last letters and a w: `.pgw` for `.png`, `.tfw` for `.tiff`
+pub fn world_file_path(image: &Path) -> PathBuf {
+    let extension = image.extension().map_or(String::new(), |extension| extension.to_string_lossy().into_owned());
+
+    let world_extension = match (extension.chars().next(), extension.chars().last()) {
+        (Some(first), Some(last)) => format!("{}{}w", first, last),
+        _ => "wld".to_owned(),
+    };
+
+    image.with_extension(world_extension)
+}
+
+/// EPSG code of the projected (or failing that, geographic) coordinate system in a GeoTIFF key directory
+fn geo_key_epsg(data: &[u8]) -> Option<u16> {
+    let values: Vec<u16> = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+
+    // Header of version, revision, minor revision and key count, then keys of id, location, count and value
+    let keys = values.get(4..)?.chunks_exact(4).take(*values.get(3)? as usize);
+    // Location 0 means the value is stored in the key itself
+    let inline = |id: u16| keys.clone().find(|key| key[0] == id && key[1] == 0).map(|key| key[3]).filter(|code| *code != USER_DEFINED);
+
+    inline(PROJECTED_CS_TYPE_GEO_KEY).or_else(|| inline(GEOGRAPHIC_TYPE_GEO_KEY))
+}
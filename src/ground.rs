@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+
+use glam::{DVec2, DVec3};
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::jobs::JobContext;
+
+/// Cloth particles simulated at most, the cloth is made coarser than asked beyond this so huge clouds still finish
+const MAX_PARTICLES: usize = 1_000_000;
+/// Distance moved by gravity in one step, as in the original CSF implementation (0.2 gravity, 0.65 time step)
+const GRAVITY_STEP: f64 = 0.2 * 0.65 * 0.65;
+/// Fraction of its velocity a particle loses each step
+const DAMPING: f64 = 0.01;
+/// The cloth has settled when no particle moves further than this in a step, in file units
+const SETTLED: f64 = 0.005;
+
+/// Cloth simulation filter (CSF) settings, see `Cloth::settle`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClothSettings {
+    /// Spacing of the cloth particles, in file units. Coarser cloths span larger buildings.
+    pub resolution: f64,
+    /// How stiff the cloth is, 1 for steep terrain to 3 for flat terrain with large buildings, or more
+    pub rigidness: u32,
+    /// Points within this height of the cloth are ground, in file units
+    pub threshold: f64,
+    pub iterations: u32,
+}
+
+impl Default for ClothSettings {
+    fn default() -> ClothSettings {
+        ClothSettings {
+            resolution: 1.0,
+            rigidness: 3,
+            threshold: 0.5,
+            iterations: 500,
+        }
+    }
+}
+
+/// Lowest point under each particle of a cloth, what the cloth comes to rest against
+pub struct Surface {
+    origin: DVec2,
+    spacing: f64,
+    width: usize,
+    height: usize,
+    lowest: Vec<f64>,
+}
+
+impl Surface {
+    /// Grid of the lowest of `points` over `bounds`, with particles `resolution` apart or further when there would be
+    /// too many. Cells without points take the height of the nearest cell with some.
+    pub fn new(points: impl Iterator<Item = DVec3>, bounds: (DVec3, DVec3), resolution: f64) -> Result<Surface, String> {
+        let (min, max) = bounds;
+        let size = (max - min).truncate().max(DVec2::ZERO);
+
+        let cells = |spacing: f64| (size / spacing).floor().as_uvec2() + 1;
+        let count = cells(resolution);
+        let particles = count.x as f64 * count.y as f64;
+        let spacing = if particles > MAX_PARTICLES as f64 {
+            resolution * (particles / MAX_PARTICLES as f64).sqrt() * 1.01
+        } else {
+            resolution
+        };
+
+        let count = cells(spacing);
+        let (width, height) = (count.x as usize, count.y as usize);
+        let origin = min.truncate();
+
+        let mut lowest = vec![f64::NAN; width * height];
+        for point in points {
+            let cell = ((point.truncate() - origin) / spacing).round();
+            if cell.x < 0.0 || cell.y < 0.0 || cell.x >= width as f64 || cell.y >= height as f64 {
+                continue;
+            }
+
+            let i = cell.y as usize * width + cell.x as usize;
+            if lowest[i].is_nan() || point.z < lowest[i] {
+                lowest[i] = point.z;
+            }
+        }
+
+        // Breadth first out from the cells with points, so each empty cell gets its nearest
+        let mut queue: VecDeque<usize> = (0..lowest.len()).filter(|i| !lowest[*i].is_nan()).collect();
+        if queue.is_empty() {
+            return Err("There are no points to classify".to_owned());
+        }
+
+        while let Some(i) = queue.pop_front() {
+            let (x, y) = (i % width, i / width);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+
+            for neighbour in neighbours.into_iter().flatten() {
+                if lowest[neighbour].is_nan() {
+                    lowest[neighbour] = lowest[i];
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+
+        Ok(Surface { origin, spacing, width, height, lowest })
+    }
+
+    /// Particle spacing used, coarser than asked for with very large areas
+    pub fn spacing(&self) -> f64 {
+        self.spacing
+    }
+}
+
+/// Cloth that's come to rest against the underside of a point cloud, following the terrain and spanning buildings and
+/// trees, for telling ground points from the rest (Zhang et al. 2016)
+pub struct Cloth {
+    origin: DVec2,
+    spacing: f64,
+    width: usize,
+    height: usize,
+    heights: Vec<f64>,
+}
+
+impl Cloth {
+    /// Simulate the cloth rising against `surface` until it settles. This is the usual cloth dropped onto the upside
+    /// down cloud, without turning it over. Errors if the job is cancelled.
+    pub fn settle(surface: Surface, settings: &ClothSettings, context: &JobContext) -> Result<Cloth, String> {
+        let Surface { origin, spacing, width, height, lowest } = surface;
+
+        let start = lowest.iter().copied().fold(f64::INFINITY, f64::min) - spacing;
+        let mut heights = vec![start; lowest.len()];
+        let mut previous = heights.clone();
+        let mut movable = vec![true; lowest.len()];
+
+        for iteration in 0..settings.iterations {
+            if context.is_cancelled() {
+                return Err("Ground classification was cancelled".to_owned());
+            }
+
+            // Gravity, then stopping against the lowest point
+            heights.par_iter_mut()
+                .zip(previous.par_iter_mut())
+                .zip(movable.par_iter_mut())
+                .zip(&lowest)
+                .for_each(|(((height, previous), movable), lowest)| {
+                    if *movable {
+                        let current = *height;
+                        *height += (current - *previous) * (1.0 - DAMPING) + GRAVITY_STEP;
+                        *previous = current;
+
+                        if *height >= *lowest {
+                            *height = *lowest;
+                            *movable = false;
+                        }
+                    }
+                });
+
+            // Springs to the right and below, each evening out its particles. Going over them `rigidness` times
+            // spreads the pull of the particles that have stopped further into the cloth.
+            for _ in 0..settings.rigidness {
+                for y in 0..height {
+                    for x in 0..width {
+                        let a = y * width + x;
+
+                        for b in [(x + 1 < width).then_some(a + 1), (y + 1 < height).then_some(a + width)].into_iter().flatten() {
+                            let difference = heights[b] - heights[a];
+
+                            match (movable[a], movable[b]) {
+                                (true, true) => {
+                                    heights[a] += difference / 2.0;
+                                    heights[b] -= difference / 2.0;
+                                },
+                                (true, false) => heights[a] = heights[b],
+                                (false, true) => heights[b] = heights[a],
+                                (false, false) => {},
+                            }
+                        }
+                    }
+                }
+            }
+
+            context.progress().set((iteration + 1) as f32 / settings.iterations as f32);
+
+            let moved = heights.iter()
+                .zip(&previous)
+                .zip(&movable)
+                .filter(|(_, movable)| **movable)
+                .map(|((height, previous), _)| (height - previous).abs())
+                .fold(0.0, f64::max);
+
+            if moved < SETTLED {
+                break;
+            }
+        }
+
+        Ok(Cloth { origin, spacing, width, height, heights })
+    }
+
+    /// Height of the cloth at `position`, between the particles around it
+    pub fn height(&self, position: DVec2) -> f64 {
+        let cell = ((position - self.origin) / self.spacing).clamp(DVec2::ZERO, glam::dvec2((self.width - 1) as f64, (self.height - 1) as f64));
+        let (x0, y0) = (cell.x.floor() as usize, cell.y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let f = cell - glam::dvec2(x0 as f64, y0 as f64);
+
+        let at = |x: usize, y: usize| self.heights[y * self.width + x];
+        let bottom = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * f.x;
+        let top = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * f.x;
+
+        bottom + (top - bottom) * f.y
+    }
+
+    /// Whether a point at `position` is ground, within `threshold` of the cloth
+    pub fn is_ground(&self, position: DVec3, threshold: f64) -> bool {
+        (position.z - self.height(position.truncate())).abs() <= threshold
+    }
+}
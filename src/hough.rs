@@ -0,0 +1,211 @@
+use glam::Vec2;
+
+use crate::random::XorShift;
+
+/// Number of angle bins over 180 degrees
+const NUM_ANGLES: usize = 180;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Segment {
+    pub from: Vec2,
+    pub to: Vec2,
+}
+
+impl Segment {
+    pub fn distance_to(&self, p: Vec2) -> f32 {
+        let d = self.to - self.from;
+        let t = if d.length_squared() > 0.0 {
+            ((p - self.from).dot(d) / d.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        p.distance(self.from + d * t)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HoughParameters {
+    /// Votes a line needs before it's followed
+    pub threshold: u32,
+    /// Shortest segment kept, in pixels
+    pub min_length: f32,
+    /// Longest run of empty pixels bridged within a segment
+    pub max_gap: f32,
+}
+
+impl Default for HoughParameters {
+    fn default() -> Self {
+        HoughParameters {
+            threshold: 30,
+            min_length: 40.0,
+            max_gap: 8.0,
+        }
+    }
+}
+
+/// Progressive probabilistic Hough transform (Matas et al.), points are pixel positions in a `width` by `height` image
+pub fn detect_segments(width: u32, height: u32, points: &[(u32, u32)], parameters: &HoughParameters) -> Vec<Segment> {
+    let (width, height) = (width as usize, height as usize);
+    let max_rho = ((width * width + height * height) as f32).sqrt().ceil() as i32;
+    let num_rho = 2 * max_rho as usize + 1;
+
+    let trig: Vec<(f32, f32)> = (0..NUM_ANGLES)
+        .map(|n| {
+            let theta = n as f32 * std::f32::consts::PI / NUM_ANGLES as f32;
+            (theta.cos(), theta.sin())
+        })
+        .collect();
+
+    let rho_bin = |x: usize, y: usize, (cos, sin): (f32, f32)| (x as f32 * cos + y as f32 * sin).round() as i32 + max_rho;
+
+    let mut accumulator = vec![0_i32; NUM_ANGLES * num_rho];
+    let vote = |accumulator: &mut [i32], x: usize, y: usize, amount: i32| {
+        for (n, &t) in trig.iter().enumerate() {
+            accumulator[n * num_rho + rho_bin(x, y, t) as usize] += amount;
+        }
+    };
+
+    // Unprocessed points, and points that have voted
+    let mut mask = vec![false; width * height];
+    let mut voted = vec![false; width * height];
+
+    for &(x, y) in points {
+        if (x as usize) < width && (y as usize) < height {
+            mask[y as usize * width + x as usize] = true;
+        }
+    }
+
+    let mut order: Vec<(usize, usize)> = points.iter()
+        .map(|&(x, y)| (x as usize, y as usize))
+        .filter(|&(x, y)| x < width && y < height)
+        .collect();
+    XorShift::new(0x2545_f491_4f6c_dd1d).shuffle(&mut order);
+
+    let mut segments = vec![];
+
+    for (x, y) in order {
+        if !mask[y * width + x] {
+            continue;
+        }
+
+        vote(&mut accumulator, x, y, 1);
+        voted[y * width + x] = true;
+
+        let (best_n, best_votes) = (0..NUM_ANGLES)
+            .map(|n| (n, accumulator[n * num_rho + rho_bin(x, y, trig[n]) as usize]))
+            .max_by_key(|&(_, votes)| votes)
+            .expect("No angle bins");
+
+        if best_votes < parameters.threshold as i32 {
+            continue;
+        }
+
+        // Walk along the line in both directions, major axis stepping by one pixel
+        let (cos, sin) = trig[best_n];
+        let direction = {
+            let d = Vec2::new(-sin, cos);
+            d / d.x.abs().max(d.y.abs())
+        };
+        let start = Vec2::new(x as f32, y as f32);
+
+        let pixel_at = |p: Vec2| {
+            let (px, py) = (p.x.round(), p.y.round());
+
+            if px < 0.0 || py < 0.0 || px >= width as f32 || py >= height as f32 {
+                None
+            } else {
+                Some(py as usize * width + px as usize)
+            }
+        };
+
+        let mut ends = [start; 2];
+
+        for (end, sign) in ends.iter_mut().zip([1.0, -1.0]) {
+            let mut gap = 0.0;
+            let mut p = start;
+
+            while let Some(index) = pixel_at(p) {
+                if mask[index] {
+                    gap = 0.0;
+                    *end = p.round();
+                } else {
+                    gap += 1.0;
+
+                    if gap > parameters.max_gap {
+                        break;
+                    }
+                }
+
+                p += direction * sign;
+            }
+        }
+
+        let good_line = ends[0].distance(ends[1]) >= parameters.min_length;
+
+        // Remove the segment's points so they aren't reused, taking back their votes
+        for (end, sign) in ends.iter().zip([1.0, -1.0]) {
+            let mut p = start;
+
+            while let Some(index) = pixel_at(p) {
+                if mask[index] {
+                    if good_line && voted[index] {
+                        vote(&mut accumulator, index % width, index / width, -1);
+                        voted[index] = false;
+                    }
+                    mask[index] = false;
+                }
+
+                if p.round() == *end {
+                    break;
+                }
+
+                p += direction * sign;
+            }
+        }
+
+        if good_line {
+            segments.push(Segment {
+                from: ends[0],
+                to: ends[1],
+            });
+        }
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_to_clamps_to_the_ends() {
+        let segment = Segment {
+            from: Vec2::new(0.0, 0.0),
+            to: Vec2::new(10.0, 0.0),
+        };
+
+        assert_eq!(segment.distance_to(Vec2::new(5.0, 3.0)), 3.0);
+        assert_eq!(segment.distance_to(Vec2::new(13.0, 4.0)), 5.0);
+    }
+
+    #[test]
+    fn detect_segments_finds_a_line() {
+        let points: Vec<(u32, u32)> = (10..110).map(|x| (x, 20)).collect();
+
+        let segments = detect_segments(120, 40, &points, &HoughParameters::default());
+
+        assert_eq!(segments.len(), 1);
+        let Segment { from, to } = segments[0];
+        assert!(from.distance(to) >= 95.0);
+        assert!(segments[0].distance_to(Vec2::new(60.0, 20.0)) < 1.0);
+    }
+
+    #[test]
+    fn detect_segments_ignores_short_runs() {
+        let points: Vec<(u32, u32)> = (10..30).map(|x| (x, 20)).collect();
+
+        assert!(detect_segments(120, 40, &points, &HoughParameters::default()).is_empty());
+    }
+}
@@ -6,6 +6,12 @@ pub struct KeyboardManager {
     state: HashMap<VirtualKeyCode, bool>,
 }
 
+impl Default for KeyboardManager {
+    fn default() -> KeyboardManager {
+        KeyboardManager::new()
+    }
+}
+
 impl KeyboardManager {
     pub fn new() -> KeyboardManager {
         KeyboardManager{
@@ -39,6 +45,12 @@ pub struct MouseManager {
     new_frame: bool,
 }
 
+impl Default for MouseManager {
+    fn default() -> MouseManager {
+        MouseManager::new()
+    }
+}
+
 impl MouseManager {
     pub fn new() -> MouseManager {
         MouseManager {
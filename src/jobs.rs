@@ -0,0 +1,188 @@
+use std::{panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicBool, AtomicU8, Ordering}, mpsc::{self, Receiver, Sender, TryRecvError}, Arc, Mutex}, thread};
+
+use crate::slice::Progress;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Where a job is up to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobState {
+    /// Waiting for a free worker
+    Queued,
+    Running,
+    /// Finished, failed or cancelled
+    Done,
+}
+
+/// Name, progress and state of a job, shared between its handle, its worker and the queue's list
+#[derive(Debug)]
+pub struct JobStatus {
+    name: String,
+    progress: Progress,
+    cancelled: AtomicBool,
+    state: AtomicU8,
+}
+
+impl JobStatus {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Fraction done, as far as the job reports it
+    pub fn progress(&self) -> f32 {
+        self.progress.get()
+    }
+
+    pub fn state(&self) -> JobState {
+        match self.state.load(Ordering::Relaxed) {
+            0 => JobState::Queued,
+            1 => JobState::Running,
+            _ => JobState::Done,
+        }
+    }
+
+    /// Stop the job: queued jobs never start, running ones are told to stop and their result is dropped
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn set_state(&self, state: JobState) {
+        self.state.store(state as u8, Ordering::Relaxed);
+    }
+}
+
+/// What a running job sees of itself
+#[derive(Clone, Debug)]
+pub struct JobContext {
+    status: Arc<JobStatus>,
+}
+
+impl JobContext {
+    /// Context for running a job's work directly, e.g. from the command line. It's never cancelled.
+    pub fn detached() -> JobContext {
+        JobContext {
+            status: Arc::new(JobStatus {
+                name: String::new(),
+                progress: Progress::default(),
+                cancelled: AtomicBool::new(false),
+                state: AtomicU8::new(JobState::Running as u8),
+            }),
+        }
+    }
+
+    /// Set as the job goes, for its progress bar
+    pub fn progress(&self) -> &Progress {
+        &self.status.progress
+    }
+
+    /// Long jobs should check this now and then and give up early
+    pub fn is_cancelled(&self) -> bool {
+        self.status.is_cancelled()
+    }
+}
+
+/// Handle to a submitted job and its result. Dropping it cancels the job.
+pub struct Job<T> {
+    status: Arc<JobStatus>,
+    rx: Receiver<Result<T, String>>,
+}
+
+impl<T> Job<T> {
+    pub fn status(&self) -> &JobStatus {
+        &self.status
+    }
+
+    /// The job's result once it's done, None until then. Cancelled jobs, and jobs that panicked, are errors.
+    pub fn poll(&self) -> Option<Result<T, String>> {
+        match self.rx.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) if self.status.is_cancelled() => Some(Err(format!("{} was cancelled", self.status.name))),
+            Err(TryRecvError::Disconnected) => Some(Err(format!("{} stopped before finishing", self.status.name))),
+        }
+    }
+}
+
+impl<T> Drop for Job<T> {
+    fn drop(&mut self) {
+        self.status.cancel();
+    }
+}
+
+/// Runs long operations (processing slices, preprocessing files, exports) on a few worker threads, in the order
+/// they're submitted, keeping a list of them to show progress and cancel from
+pub struct JobQueue {
+    tx: Sender<Task>,
+    jobs: Vec<Arc<JobStatus>>,
+}
+
+impl JobQueue {
+    pub fn new(workers: usize) -> JobQueue {
+        let (tx, rx) = mpsc::channel::<Task>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        for _ in 0..workers.max(1) {
+            let rx = rx.clone();
+
+            thread::spawn(move || loop {
+                // Lock released before running, so the other workers can take the next task
+                let task = rx.lock().expect("Job queue lock poisoned").recv();
+
+                match task {
+                    Ok(task) => task(),
+                    Err(_) => break,
+                }
+            });
+        }
+
+        JobQueue { tx, jobs: vec![] }
+    }
+
+    /// Queue `run` to be run on a worker, returning its handle
+    pub fn submit<T: Send + 'static>(&mut self, name: impl Into<String>, run: impl FnOnce(&JobContext) -> Result<T, String> + Send + 'static) -> Job<T> {
+        let status = Arc::new(JobStatus {
+            name: name.into(),
+            progress: Progress::default(),
+            cancelled: AtomicBool::new(false),
+            state: AtomicU8::new(JobState::Queued as u8),
+        });
+        let (tx, rx) = mpsc::channel();
+        let context = JobContext { status: status.clone() };
+
+        let task = move || {
+            if !context.is_cancelled() {
+                context.status.set_state(JobState::Running);
+
+                // A panicking job drops its sender, so it's reported as stopped, and the worker carries on
+                if let Ok(result) = panic::catch_unwind(AssertUnwindSafe(|| run(&context))) {
+                    // Nothing is waiting for a cancelled job
+                    if !context.is_cancelled() {
+                        let _ = tx.send(result);
+                    }
+                }
+            }
+
+            context.status.set_state(JobState::Done);
+        };
+
+        self.tx.send(Box::new(task)).expect("Job workers stopped");
+        self.jobs.push(status.clone());
+
+        Job { status, rx }
+    }
+
+    /// Jobs queued or running, oldest first. Cancelled jobs are kept until they've stopped running.
+    pub fn active(&mut self) -> &[Arc<JobStatus>] {
+        self.jobs.retain(|job| match job.state() {
+            JobState::Queued => !job.is_cancelled(),
+            JobState::Running => true,
+            JobState::Done => false,
+        });
+
+        &self.jobs
+    }
+}
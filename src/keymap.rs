@@ -0,0 +1,187 @@
+use std::{fs, path::PathBuf};
+
+use glium::glutin::event::VirtualKeyCode;
+
+/// Something done with the keyboard that can be bound to another key
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Held to move faster
+    Sprint,
+    ToggleSlice,
+    QuickRender,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [Action::Forward, Action::Back, Action::Left, Action::Right, Action::Up, Action::Down,
+        Action::Sprint, Action::ToggleSlice, Action::QuickRender];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Forward => "Move Forward",
+            Action::Back => "Move Back",
+            Action::Left => "Move Left",
+            Action::Right => "Move Right",
+            Action::Up => "Move Up",
+            Action::Down => "Move Down",
+            Action::Sprint => "Move Faster",
+            Action::ToggleSlice => "Toggle Slice",
+            Action::QuickRender => "Quick Render",
+        }
+    }
+
+    /// Name in the keymap file
+    fn id(self) -> &'static str {
+        match self {
+            Action::Forward => "forward",
+            Action::Back => "back",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Sprint => "sprint",
+            Action::ToggleSlice => "toggle_slice",
+            Action::QuickRender => "quick_render",
+        }
+    }
+}
+
+/// Keys actions can be bound to. Escape, Return, Backspace and Delete keep their fixed meanings.
+const KEYS: &[VirtualKeyCode] = {
+    use VirtualKeyCode::*;
+
+    &[
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Space, Tab, LShift, RShift, LControl, RControl, LAlt, RAlt,
+        Up, Down, Left, Right, Home, End, PageUp, PageDown, Insert,
+        Comma, Period, Semicolon, Apostrophe, Slash, Backslash, LBracket, RBracket, Minus, Equals, Grave,
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    ]
+};
+
+/// Forward, left, back and right on common layouts, so the movement keys stay where WASD is on QWERTY
+pub const LAYOUTS: [(&str, [VirtualKeyCode; 4]); 3] = [
+    ("QWERTY", [VirtualKeyCode::W, VirtualKeyCode::A, VirtualKeyCode::S, VirtualKeyCode::D]),
+    ("AZERTY", [VirtualKeyCode::Z, VirtualKeyCode::Q, VirtualKeyCode::S, VirtualKeyCode::D]),
+    ("Dvorak", [VirtualKeyCode::Comma, VirtualKeyCode::A, VirtualKeyCode::O, VirtualKeyCode::E]),
+];
+
+/// Key bound to each action, saved in the config directory
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Keymap {
+    keys: [VirtualKeyCode; Action::ALL.len()],
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap {
+            keys: [
+                VirtualKeyCode::W,
+                VirtualKeyCode::S,
+                VirtualKeyCode::A,
+                VirtualKeyCode::D,
+                VirtualKeyCode::Space,
+                VirtualKeyCode::LControl,
+                VirtualKeyCode::LShift,
+                VirtualKeyCode::T,
+                VirtualKeyCode::F5,
+            ],
+        }
+    }
+}
+
+impl Keymap {
+    pub fn key(&self, action: Action) -> VirtualKeyCode {
+        self.keys[action as usize]
+    }
+
+    /// Action bound to `key`, if any
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| self.key(*action) == key)
+    }
+
+    /// Bind `key` to `action`, swapping with the action it was bound to so no key does two things.
+    /// Returns false for keys that can't be bound.
+    pub fn bind(&mut self, action: Action, key: VirtualKeyCode) -> bool {
+        if !KEYS.contains(&key) {
+            return false;
+        }
+
+        if let Some(other) = self.action(key) {
+            self.keys[other as usize] = self.key(action);
+        }
+        self.keys[action as usize] = key;
+
+        true
+    }
+
+    /// Move the movement keys to where WASD is on QWERTY for a layout from `LAYOUTS`
+    pub fn use_layout(&mut self, [forward, left, back, right]: [VirtualKeyCode; 4]) {
+        for (action, key) in [(Action::Forward, forward), (Action::Left, left), (Action::Back, back), (Action::Right, right)] {
+            self.bind(action, key);
+        }
+    }
+
+    /// Saved keymap, with any problems with the file
+    pub fn load() -> (Keymap, Vec<String>) {
+        let mut keymap = Keymap::default();
+        let mut errors = vec![];
+
+        let Some(text) = keymap_file().and_then(|path| fs::read_to_string(path).ok()) else {
+            return (keymap, errors);
+        };
+
+        for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let binding = line.split_once('=').and_then(|(id, name)| {
+                let action = Action::ALL.into_iter().find(|action| action.id() == id.trim())?;
+                let key = KEYS.iter().find(|key| key_name(**key) == name.trim())?;
+
+                Some((action, *key))
+            });
+
+            match binding {
+                Some((action, key)) => {
+                    keymap.bind(action, key);
+                },
+                None => errors.push(format!("line {}: expected `action = key`, got `{}`", i + 1, line)),
+            }
+        }
+
+        (keymap, errors)
+    }
+
+    pub fn save(&self) {
+        if let Some(file) = keymap_file() {
+            let text: String = Action::ALL.into_iter().map(|action| format!("{} = {}\n", action.id(), key_name(self.key(action)))).collect();
+
+            let result = file.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&file, text));
+
+            if let Err(err) = result {
+                eprintln!("Failed to save keymap to {}: {}", file.display(), err);
+            }
+        }
+    }
+}
+
+fn keymap_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("point-cloud-cutaway").join("keymap.txt"))
+}
+
+/// Name shown in the settings and written to the keymap file
+pub fn key_name(key: VirtualKeyCode) -> String {
+    let name = format!("{:?}", key);
+
+    // Key1 -> 1
+    match name.strip_prefix("Key") {
+        Some(digit) if !digit.is_empty() => digit.to_owned(),
+        _ => name,
+    }
+}
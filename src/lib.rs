@@ -0,0 +1,58 @@
+//! Loading, slicing and rendering point clouds, and tracing the slices into floor plans. [`viewer::Viewer`] is the
+//! interactive viewer, the `point-cloud-cutaway` binary opens it in a window.
+
+#[macro_use] extern crate glium;
+#[macro_use] extern crate maplit;
+
+pub mod camera;
+pub mod cluster;
+pub mod colour;
+pub mod config;
+pub mod context;
+pub mod contour;
+pub mod dock;
+pub mod export;
+pub mod filter;
+pub mod footprint;
+pub mod gamepad;
+pub mod geometry;
+pub mod georef;
+pub mod ground;
+pub mod hough;
+pub mod input;
+pub mod jobs;
+pub mod keymap;
+pub mod link;
+pub mod loader;
+pub mod minimap;
+pub mod ortho;
+pub mod locale;
+pub mod mesh;
+pub mod pdal;
+pub mod pdf;
+pub mod picking;
+pub mod plan;
+pub mod playback;
+pub mod profile;
+pub mod random;
+pub mod raster;
+pub mod recent;
+pub mod refine;
+pub mod registration;
+pub mod reproject;
+pub mod render;
+pub mod script;
+pub mod section;
+pub mod selection;
+pub mod slice;
+pub mod split;
+pub mod stages;
+pub mod stream;
+pub mod subset;
+pub mod transform;
+pub mod tutorial;
+pub mod vector;
+pub mod vegetation;
+pub mod viewer;
+pub mod watch;
+pub mod web;
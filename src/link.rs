@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use glam::{DVec3, Vec2};
+
+/// Start of links that open a file at a saved view
+const PREFIX: &str = "point-cloud-cutaway://open?";
+
+/// Camera placement, with the position in file coordinates so it doesn't depend on how the files were recentred
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    pub position: DVec3,
+    pub rotation: Vec2,
+    pub zoom: f32,
+}
+
+/// File to open, and where to look from once it has loaded
+#[derive(Clone, Debug)]
+pub struct Link {
+    pub path: String,
+    pub view: Option<View>,
+}
+
+/// Link opening `path` at `view`
+pub fn view_link(path: &str, view: &View) -> String {
+    let View { position, rotation, zoom } = view;
+
+    format!("{}file={}&view={:.3},{:.3},{:.3},{:.4},{:.4},{:.2}", PREFIX, encode(path),
+        position.x, position.y, position.z, rotation.x, rotation.y, zoom)
+}
+
+/// Pasted text as something to open: a view link, a file:// URL or the path of an existing file
+pub fn parse(text: &str) -> Option<Link> {
+    let text = text.lines().next()?.trim().trim_matches(|c| c == '"' || c == '\'');
+
+    if let Some(query) = text.strip_prefix(PREFIX) {
+        let mut path = None;
+        let mut view = None;
+
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some(("file", value)) => path = decode(value),
+                Some(("view", value)) => view = parse_view(value),
+                _ => {},
+            }
+        }
+
+        return path.map(|path| Link { path, view });
+    }
+
+    let path = match text.strip_prefix("file://") {
+        Some(url) => decode(url)?,
+        None => text.to_owned(),
+    };
+
+    Path::new(&path).is_file().then_some(Link { path, view: None })
+}
+
+fn parse_view(value: &str) -> Option<View> {
+    let numbers: Vec<f64> = value.split(',').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+
+    match numbers[..] {
+        [x, y, z, yaw, pitch, zoom] => Some(View {
+            position: glam::dvec3(x, y, z),
+            rotation: glam::vec2(yaw as f32, pitch as f32),
+            zoom: zoom as f32,
+        }),
+        _ => None,
+    }
+}
+
+/// Percent-encode everything but unreserved characters and path separators
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+fn decode(text: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_links_round_trip() {
+        let view = View {
+            position: glam::dvec3(512_345.678, 6_123_456.789, 12.5),
+            rotation: glam::vec2(1.25, -0.5),
+            zoom: -32.0,
+        };
+
+        let link = parse(&view_link("/scans/level 2 (east).laz", &view)).expect("Link to a file");
+        let parsed = link.view.expect("Link with a view");
+
+        assert_eq!(link.path, "/scans/level 2 (east).laz");
+        assert!(parsed.position.distance(view.position) < 1e-3);
+        assert_eq!(parsed.rotation, view.rotation);
+        assert_eq!(parsed.zoom, view.zoom);
+    }
+
+    #[test]
+    fn parse_skips_broken_views() {
+        let link = parse("point-cloud-cutaway://open?file=scan.laz&view=1,2,three").expect("Link to a file");
+
+        assert_eq!(link.path, "scan.laz");
+        assert!(link.view.is_none());
+        assert!(parse("point-cloud-cutaway://open?view=1,2,3,0,0,1").is_none());
+        assert!(parse("point-cloud-cutaway://open?file=%4").is_none());
+    }
+
+    #[test]
+    fn parse_opens_existing_files() {
+        let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+        assert_eq!(parse(&format!("\"{}\"\nsecond line", manifest)).map(|link| link.path), Some(manifest.to_owned()));
+        assert_eq!(parse(&format!("file://{}", encode(manifest))).map(|link| link.path), Some(manifest.to_owned()));
+        assert!(parse(concat!(env!("CARGO_MANIFEST_DIR"), "/missing.laz")).is_none());
+    }
+}
@@ -0,0 +1,467 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use glam::DVec3;
+use las::{Reader, Read};
+
+use crate::{filter::PointFilter, georef::Georeference, jobs::JobContext, reproject::Reprojection};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+pub const BATCH_SIZE: u64 = 500_000;
+/// LAS VLR user id and record id of the descriptions of each point's extra bytes
+const EXTRA_BYTES_USER_ID: &str = "LASF_Spec";
+const EXTRA_BYTES_RECORD_ID: u16 = 4;
+const EXTRA_BYTES_DESCRIPTOR_SIZE: usize = 192;
+
+/// Points from one loader batch. Vertex positions are relative to the batch's own origin,
+/// so precision doesn't depend on how far the batch is from the shared rendering origin.
+pub struct PointBatch {
+    pub points: Vec<las::Point>,
+    /// Local origin of the batch, in file coordinates
+    pub origin: DVec3,
+    /// Index into the list of loaded files
+    pub file: usize,
+    /// Whether each point is selected
+    pub selected: Vec<bool>,
+}
+
+impl PointBatch {
+    pub fn new(points: Vec<las::Point>, origin: DVec3, file: usize) -> PointBatch {
+        PointBatch {
+            selected: vec![false; points.len()],
+            points,
+            origin,
+            file,
+        }
+    }
+}
+
+pub struct LoadedFile {
+    pub path: String,
+    /// Bounds of the valid points loaded from the file so far
+    pub bounds: Option<(DVec3, DVec3)>,
+    /// Loaded from the output of the PDAL pipeline rather than the file itself
+    pub preprocessed: bool,
+    pub georeference: Georeference,
+    /// Named values in each point's extra bytes, in order
+    pub extra_bytes: Vec<ExtraBytes>,
+}
+
+/// A value stored in each point's extra bytes, described by the file's extra bytes VLR
+#[derive(Clone, Debug)]
+pub struct ExtraBytes {
+    pub name: String,
+    /// LAS data type, 1 to 10 are u8, i8, u16, i16, u32, i32, u64, i64, f32 and f64, 11 to 30 pairs and triples of
+    /// them, 0 undocumented bytes
+    pub data_type: u8,
+    /// Bytes taken in each point
+    pub size: usize,
+    pub scale: Option<f64>,
+    pub offset: Option<f64>,
+}
+
+impl ExtraBytes {
+    pub fn from_header(header: &las::Header) -> Vec<ExtraBytes> {
+        let Some(vlr) = header.all_vlrs().find(|vlr| vlr.user_id.trim_end_matches('\0') == EXTRA_BYTES_USER_ID && vlr.record_id == EXTRA_BYTES_RECORD_ID) else {
+            return vec![];
+        };
+
+        vlr.data.chunks_exact(EXTRA_BYTES_DESCRIPTOR_SIZE).map(|descriptor| {
+            let data_type = descriptor[2];
+            let options = descriptor[3];
+            let f64_at = |start: usize| f64::from_le_bytes(descriptor[start..start + 8].try_into().expect("Descriptor field is 8 bytes"));
+
+            let size = match data_type {
+                0 => options as usize,
+                1..=30 => {
+                    let (base, count) = element_type(data_type);
+                    element_size(base) * count
+                },
+                _ => 0,
+            };
+
+            ExtraBytes {
+                name: String::from_utf8_lossy(&descriptor[4..36]).trim_end_matches('\0').trim().to_owned(),
+                data_type,
+                size,
+                scale: (options & 0b1000 != 0).then(|| f64_at(112)),
+                offset: (options & 0b10000 != 0).then(|| f64_at(136)),
+            }
+        }).collect()
+    }
+
+    /// Extra bytes of the LAS file at `path`, reading only its header
+    pub fn read(path: &str) -> Result<Vec<ExtraBytes>, String> {
+        let reader = Reader::from_path(path).map_err(|err| format!("Failed to read the header of {}: {}", path, err))?;
+
+        Ok(ExtraBytes::from_header(reader.header()))
+    }
+
+    /// This value in `bytes`, the extra bytes from its start. Scaled and offset if the file says to, undocumented
+    /// bytes are shown in hex.
+    pub fn format(&self, bytes: &[u8]) -> String {
+        let Some(bytes) = bytes.get(..self.size) else {
+            return "Missing".to_owned();
+        };
+
+        if self.data_type == 0 || self.data_type > 30 {
+            return hex(bytes);
+        }
+
+        let (base, count) = element_type(self.data_type);
+        let size = element_size(base);
+
+        (0..count).map(|i| {
+            let value = element_value(base, &bytes[i * size..(i + 1) * size]);
+            let value = value * self.scale.unwrap_or(1.0) + self.offset.unwrap_or(0.0);
+
+            value.to_string()
+        }).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Header of a LAS file, for checking a delivery without other tools
+pub struct FileInfo {
+    pub version: String,
+    pub point_format: String,
+    pub points: u64,
+    /// Points with each return number, from the first
+    pub points_by_return: Vec<u64>,
+    pub bounds: (DVec3, DVec3),
+    pub georeference: Georeference,
+    pub system_identifier: String,
+    pub generating_software: String,
+    /// Day the file was created, as the header gives it
+    pub created: Option<String>,
+    pub file_source_id: u16,
+    pub guid: String,
+    pub vlrs: Vec<VlrInfo>,
+}
+
+/// Variable length record of a LAS file, without its data
+pub struct VlrInfo {
+    pub user_id: String,
+    pub record_id: u16,
+    pub description: String,
+    /// Bytes of data
+    pub size: usize,
+    /// Extended VLR, stored after the points
+    pub extended: bool,
+}
+
+impl VlrInfo {
+    /// Records describing the coordinate system
+    pub fn is_crs(&self) -> bool {
+        self.user_id == "LASF_Projection"
+    }
+}
+
+impl FileInfo {
+    /// Header of the LAS file at `path`, reading only the header
+    pub fn read(path: &str) -> Result<FileInfo, String> {
+        let reader = Reader::from_path(path).map_err(|err| format!("Failed to read the header of {}: {}", path, err))?;
+        let header = reader.header();
+
+        let format = header.point_format();
+        let mut point_format = format.to_u8().map_or_else(|_| format.to_string(), |n| n.to_string());
+        for (has, name) in [(format.is_compressed, "compressed"), (format.extra_bytes > 0, "extra bytes")] {
+            if has {
+                point_format.push_str(&format!(", {}", name));
+            }
+        }
+
+        let vlr = |vlr: &las::Vlr, extended: bool| VlrInfo {
+            user_id: vlr.user_id.trim_end_matches('\0').to_owned(),
+            record_id: vlr.record_id,
+            description: vlr.description.trim_end_matches('\0').to_owned(),
+            size: vlr.data.len(),
+            extended,
+        };
+
+        let bounds = header.bounds();
+
+        let mut points_by_return: Vec<u64> = (1..=15).map(|n| header.number_of_points_by_return(n).unwrap_or(0)).collect();
+        while points_by_return.last() == Some(&0) {
+            points_by_return.pop();
+        }
+
+        Ok(FileInfo {
+            version: header.version().to_string(),
+            point_format,
+            points: header.number_of_points(),
+            points_by_return,
+            bounds: (glam::dvec3(bounds.min.x, bounds.min.y, bounds.min.z), glam::dvec3(bounds.max.x, bounds.max.y, bounds.max.z)),
+            georeference: Georeference::from_header(header),
+            system_identifier: header.system_identifier().trim_end_matches('\0').to_owned(),
+            generating_software: header.generating_software().trim_end_matches('\0').to_owned(),
+            created: header.date().map(|date| date.format("%Y-%m-%d").to_string()),
+            file_source_id: header.file_source_id(),
+            guid: header.guid().to_string(),
+            vlrs: header.vlrs().iter().map(|record| vlr(record, false))
+                .chain(header.evlrs().iter().map(|record| vlr(record, true)))
+                .collect(),
+        })
+    }
+}
+
+/// Bytes in hex, e.g. `0a ff`
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+/// Scalar type (1 to 10) and number of them in an extra bytes data type
+fn element_type(data_type: u8) -> (u8, usize) {
+    match data_type {
+        1..=10 => (data_type, 1),
+        11..=20 => (data_type - 10, 2),
+        _ => (data_type - 20, 3),
+    }
+}
+
+fn element_size(base: u8) -> usize {
+    match base {
+        1 | 2 => 1,
+        3 | 4 => 2,
+        5 | 6 | 9 => 4,
+        _ => 8,
+    }
+}
+
+fn element_value(base: u8, bytes: &[u8]) -> f64 {
+    macro_rules! read {
+        ($t:ty) => { <$t>::from_le_bytes(bytes.try_into().expect("Element is the size of its type")) as f64 };
+    }
+
+    match base {
+        1 => read!(u8),
+        2 => read!(i8),
+        3 => read!(u16),
+        4 => read!(i16),
+        5 => read!(u32),
+        6 => read!(i32),
+        7 => read!(u64),
+        8 => read!(i64),
+        9 => read!(f32),
+        _ => read!(f64),
+    }
+}
+
+/// File picked to load, from the file dialog or command line
+pub struct LoadRequest {
+    pub path: String,
+    /// Add to the loaded files instead of replacing them
+    pub append: bool,
+    /// PDAL pipeline output to load in place of `path`, deleted once dropped
+    pub preprocessed: Option<tempfile::TempPath>,
+}
+
+pub enum LoaderMessage {
+    Batch(Vec<las::Point>),
+    /// Sent once the whole file has been read
+    Finished {
+        /// Points dropped for having NaN or infinite coordinates, or for being outside the coordinate system they
+        /// were reprojected to
+        invalid_points: u64,
+        /// Why reading stopped before the end of the file, the points before it are still sent
+        error: Option<String>,
+    },
+}
+
+/// Open `filename` to read its points, moved to another coordinate system as they're read if `reprojection` is
+/// given. Returns the number of points to expect, the centre of the file's bounds, where the points are sent and the
+/// reader that sends them, to run on the job queue.
+pub fn load_point_cloud(filename: &str, num_points: u64, reprojection: Option<Reprojection>) -> Result<(u64, DVec3, Receiver<LoaderMessage>, PointReader), String> {
+    let reader = Reader::from_path(filename).map_err(|err| format!("Couldn't open {}: {}", filename, err))?;
+
+    // let colour_format_options = ["Solid White", "8-Bit Colour", "16-Bit Colour"];
+    // let mut colour_format: i32 = if reader.header().point_format().has_color {
+    //     2
+    // } else {
+    //     0
+    // };
+    
+    // May be invalid if the file contains invalid points, recomputed from the valid points once loaded
+    let centre = {
+        let bounds = reader.header().bounds();
+
+        let centre = glam::dvec3(
+            (bounds.min.x + bounds.max.x) / 2.0,
+            (bounds.min.y + bounds.max.y) / 2.0,
+            (bounds.min.z + bounds.max.z) / 2.0,
+        );
+
+        match &reprojection {
+            Some(reprojection) => reprojection.position(centre).unwrap_or(DVec3::NAN),
+            None => centre,
+        }
+    };
+    
+    let total_points = reader.header().number_of_points();
+    let n = if num_points == 0 {
+        total_points
+    } else {
+        num_points
+    };
+
+    if n < total_points {
+        println!("Loading {} of {} points", n, total_points);
+    } else {
+        println!("Loading {} points", n);
+    }
+    
+    let (tx, rx) = mpsc::channel();
+
+    let point_reader = PointReader {
+        reader,
+        filename: filename.to_owned(),
+        num_points: n,
+        reprojection,
+        tx,
+    };
+
+    return Ok((n, centre, rx, point_reader));
+}
+
+/// Sends the points of a file opened with `load_point_cloud` in batches, then `Finished`
+pub struct PointReader {
+    reader: Reader<'static>,
+    filename: String,
+    num_points: u64,
+    reprojection: Option<Reprojection>,
+    tx: Sender<LoaderMessage>,
+}
+
+impl PointReader {
+    /// Read the points, stopping early if the job is cancelled or the receiver is dropped. Errors reading the file are
+    /// sent with `Finished`.
+    pub fn read(mut self, context: &JobContext) -> Result<(), String> {
+        puffin::profile_scope!("load_file");
+
+        let n = self.num_points;
+
+        // let mut i = 0;
+        let mut points_processed = 0;
+        let mut invalid_points = 0;
+        
+        // let mut last_progress = 0;
+
+        let mut batch = vec![];
+        let mut batch_number = 0;
+        let mut error = None;
+
+        while let Some(point) = self.reader.read() {
+            let mut point = match point {
+                Ok(point) => point,
+                Err(err) => {
+                    error = Some(format!("Stopped reading {} after {} points: {}", self.filename, points_processed, err));
+                    break;
+                },
+            };
+
+            // Skip invalid coordinates, these would corrupt the bounds and the view
+            if !(point.x.is_finite() && point.y.is_finite() && point.z.is_finite()) {
+                invalid_points += 1;
+                continue;
+            }
+
+            if let Some(reprojection) = &self.reprojection {
+                match reprojection.position(glam::dvec3(point.x, point.y, point.z)) {
+                    Some(position) => (point.x, point.y, point.z) = (position.x, position.y, position.z),
+                    None => {
+                        invalid_points += 1;
+                        continue;
+                    },
+                }
+            }
+
+            batch.push(point);
+
+            // i += 1;
+            points_processed += 1;
+
+            if points_processed % BATCH_SIZE == 0 {
+                if context.is_cancelled() {
+                    batch.clear();
+                    error = Some(self.cancelled(points_processed));
+                    break;
+                }
+
+                puffin::profile_scope!("send_batch");
+                // The receiver is dropped when another file is opened in its place
+                if self.tx.send(LoaderMessage::Batch(batch)).is_err() {
+                    return Err(self.cancelled(points_processed));
+                }
+                batch = vec![];
+                batch_number += 1;
+                println!("Loaded Batch {}/{}", batch_number, n / BATCH_SIZE + 1);
+
+                context.progress().set(points_processed as f32 / n as f32);
+            }
+
+            if points_processed >= n {
+                break;
+            }
+        }
+
+        if !batch.is_empty() && self.tx.send(LoaderMessage::Batch(batch)).is_err() {
+            return Err(self.cancelled(points_processed));
+        }
+
+        if invalid_points > 0 {
+            eprintln!("Dropped {} points with invalid coordinates", invalid_points);
+        }
+
+        if let Some(error) = &error {
+            eprintln!("{}", error);
+        }
+
+        if self.tx.send(LoaderMessage::Finished { invalid_points, error }).is_err() {
+            return Err(self.cancelled(points_processed));
+        }
+
+        println!("Points Loaded");
+
+        Ok(())
+    }
+
+    fn cancelled(&self, points_processed: u64) -> String {
+        format!("Stopped reading {} after {} points: cancelled", self.filename, points_processed)
+    }
+}
+
+/// File coordinates of the points `filter` shows, copied out for a job to work on while the batches change
+pub fn positions(batches: &[PointBatch], filter: &PointFilter) -> Vec<DVec3> {
+    batches.par_iter()
+        .flat_map_iter(|batch| batch.points.iter().filter(|point| filter.accepts(point)).map(|point| glam::dvec3(point.x, point.y, point.z)))
+        .collect()
+}
+
+/// Axis aligned bounds of the points, None if empty
+pub fn bounds(points: &[las::Point]) -> Option<(DVec3, DVec3)> {
+    points.par_iter()
+        .map(|point| {
+            let p = glam::dvec3(point.x, point.y, point.z);
+            (p, p)
+        })
+        .reduce_with(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+}
+
+pub fn union_bounds(a: Option<(DVec3, DVec3)>, b: Option<(DVec3, DVec3)>) -> Option<(DVec3, DVec3)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.max(b.1))),
+        _ => a.or(b),
+    }
+}
+
+/// Elevation range of all the loaded files
+pub fn elevation_range(files: &[LoadedFile]) -> (f64, f64) {
+    files.iter()
+        .fold(None, |bounds, file| union_bounds(bounds, file.bounds))
+        .map_or((0.0, 1.0), |(min, max)| (min.z, max.z))
+}
+
+/// Coordinate system floor plans are exported in, the first loaded file's. Files loaded together are assumed to share
+/// one.
+pub fn plan_georeference(files: &[LoadedFile]) -> Georeference {
+    files.first().map(|file| file.georeference.clone()).unwrap_or_default()
+}
+
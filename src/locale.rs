@@ -0,0 +1,143 @@
+use std::{collections::HashMap, fmt::Display, fs, path::Path};
+
+/// Interface text in one language. Text is looked up by its English wording, English is the text itself and anything
+/// a language doesn't translate is shown in English.
+#[derive(Clone, Debug)]
+pub struct Language {
+    pub name: String,
+    translations: HashMap<String, String>,
+}
+
+impl Language {
+    pub fn english() -> Language {
+        Language {
+            name: "English".to_owned(),
+            translations: HashMap::new(),
+        }
+    }
+
+    /// Parse `English text = translation` lines, `#` at the start of a line is a comment. `\n` is a line break and
+    /// `\=` an equals sign in either side.
+    pub fn parse(name: &str, text: &str) -> Result<Language, String> {
+        let mut translations = HashMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let separator = separator(line).ok_or_else(|| format!("line {}: expected `English text = translation`", i + 1))?;
+            let (english, translation) = (unescape(line[..separator].trim()), unescape(line[separator + 1..].trim()));
+
+            if english.is_empty() || translation.is_empty() {
+                return Err(format!("line {}: text and translation can't be empty", i + 1));
+            }
+
+            translations.insert(english, translation);
+        }
+
+        Ok(Language {
+            name: name.to_owned(),
+            translations,
+        })
+    }
+
+    /// `text` in this language
+    pub fn tr<'a>(&'a self, text: &'a str) -> &'a str {
+        self.translations.get(text).map_or(text, String::as_str)
+    }
+
+    /// `text` in this language with each `{}` replaced by the next of `args`
+    pub fn format(&self, text: &str, args: &[&dyn Display]) -> String {
+        let mut parts = self.tr(text).split("{}");
+        let mut formatted = parts.next().unwrap_or_default().to_owned();
+
+        for (i, part) in parts.enumerate() {
+            if let Some(arg) = args.get(i) {
+                formatted.push_str(&arg.to_string());
+            }
+            formatted.push_str(part);
+        }
+
+        formatted
+    }
+}
+
+/// Load the `*.lang` files in a directory, named after the files. A missing directory is not an error, problems with
+/// the files are returned alongside the languages that could be read.
+pub fn load_languages(directory: &Path) -> (Vec<Language>, Vec<String>) {
+    let mut languages = vec![];
+    let mut errors = vec![];
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return (languages, errors),
+    };
+
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("lang")))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_stem().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+
+        match fs::read_to_string(&path).map_err(|err| err.to_string()).and_then(|text| Language::parse(&name, &text)) {
+            Ok(language) => languages.push(language),
+            Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    (languages, errors)
+}
+
+/// Byte index of the first `=` not escaped with `\`
+fn separator(line: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '=' if !escaped => return Some(i),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+
+    None
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\n", "\n").replace("\\=", "=")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_translations() {
+        let language = Language::parse("Deutsch", "# Comment\n\nLoad = Laden\n  Points: {} = Punkte: {}  \nA \\= B = A \\= B\\nC\n").expect("Valid file");
+
+        assert_eq!(language.name, "Deutsch");
+        assert_eq!(language.tr("Load"), "Laden");
+        assert_eq!(language.tr("Save"), "Save");
+        assert_eq!(language.tr("A = B"), "A = B\nC");
+        assert_eq!(language.format("Points: {}", &[&12]), "Punkte: 12");
+    }
+
+    #[test]
+    fn parse_rejects_broken_lines() {
+        assert_eq!(Language::parse("x", "Load = Laden\nSave").unwrap_err(), "line 2: expected `English text = translation`");
+        assert_eq!(Language::parse("x", "Load =").unwrap_err(), "line 1: text and translation can't be empty");
+    }
+
+    #[test]
+    fn format_fills_in_order() {
+        let english = Language::english();
+
+        assert_eq!(english.format("{} of {}", &[&1, &"two"]), "1 of two");
+        assert_eq!(english.format("{} and {}", &[&1]), "1 and ");
+    }
+}
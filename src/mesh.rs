@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use glam::DVec3;
+
+use crate::{render::Vertex, geometry};
+
+/// Nodes in the height field grid, caps memory use on very small cell sizes
+const MAX_GRID_NODES: usize = 4_000_000;
+
+pub struct MeshPreview {
+    /// Vertex positions are relative to this point, in file coordinates
+    pub origin: DVec3,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub num_points: usize,
+    /// Root mean square distance of the points from the best-fit plane
+    pub rms_deviation: f64,
+    pub max_deviation: f64,
+}
+
+/// Quick surface preview of a region: fits a plane to the points, grids them
+/// as a height field over that plane and greedily triangulates the occupied cells.
+/// Vertices are coloured by their distance from the plane, green (flat) to red (>= tolerance).
+pub fn triangulate(points: &[DVec3], cell_size: f64, tolerance: f64) -> Option<MeshPreview> {
+    let plane = geometry::fit_plane(points)?;
+    let (u, v) = plane.basis();
+
+    let projected: Vec<_> = points.iter().map(|p| {
+        let d = *p - plane.origin;
+        (d.dot(u), d.dot(v), d.dot(plane.normal))
+    }).collect();
+
+    let (min_u, min_v, max_u, max_v) = projected.iter().fold(
+        (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        |(min_u, min_v, max_u, max_v), (pu, pv, _)| (min_u.min(*pu), min_v.min(*pv), max_u.max(*pu), max_v.max(*pv)),
+    );
+
+    let width = ((max_u - min_u) / cell_size).round() as usize + 1;
+    let height = ((max_v - min_v) / cell_size).round() as usize + 1;
+
+    if width * height > MAX_GRID_NODES {
+        return None;
+    }
+
+    // Average offset from the plane at each grid node
+    let mut nodes: HashMap<(usize, usize), (f64, u32)> = hashmap!{};
+
+    for (pu, pv, h) in &projected {
+        let ix = ((pu - min_u) / cell_size).round() as usize;
+        let iy = ((pv - min_v) / cell_size).round() as usize;
+
+        let node = nodes.entry((ix, iy)).or_insert((0.0, 0));
+        node.0 += h;
+        node.1 += 1;
+    }
+
+    let mut vertices = vec![];
+    let mut vertex_indices = HashMap::new();
+
+    for (&(ix, iy), &(sum, count)) in &nodes {
+        let h = sum / count as f64;
+        let position = u * (min_u + ix as f64 * cell_size)
+            + v * (min_v + iy as f64 * cell_size)
+            + plane.normal * h;
+
+        vertex_indices.insert((ix, iy), vertices.len() as u32);
+        vertices.push(Vertex {
+            position: [position.x as f32, position.y as f32, position.z as f32],
+            colour: deviation_colour(h.abs() / tolerance),
+            intensity: 0.0,
+        });
+    }
+
+    let mut indices = vec![];
+
+    for iy in 0..height.saturating_sub(1) {
+        for ix in 0..width.saturating_sub(1) {
+            let corners: Vec<u32> = [(ix, iy), (ix + 1, iy), (ix + 1, iy + 1), (ix, iy + 1)].iter()
+                .filter_map(|node| vertex_indices.get(node).copied())
+                .collect();
+
+            match corners.len() {
+                4 => indices.extend_from_slice(&[corners[0], corners[1], corners[2], corners[0], corners[2], corners[3]]),
+                3 => indices.extend_from_slice(&corners),
+                _ => {},
+            }
+        }
+    }
+
+    let (sum_squares, max_deviation) = projected.iter()
+        .fold((0.0, 0.0_f64), |(sum, max), (_, _, h)| (sum + h * h, max.max(h.abs())));
+
+    Some(MeshPreview {
+        origin: plane.origin,
+        vertices,
+        indices,
+        num_points: points.len(),
+        rms_deviation: (sum_squares / points.len() as f64).sqrt(),
+        max_deviation,
+    })
+}
+
+/// Green -> yellow -> red as t goes from 0 to 1
+fn deviation_colour(t: f64) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+
+    if t < 0.5 {
+        [(t * 2.0 * 255.0) as u8, 255, 0]
+    } else {
+        [255, ((1.0 - t) * 2.0 * 255.0) as u8, 0]
+    }
+}
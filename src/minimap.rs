@@ -0,0 +1,83 @@
+use glam::{DVec2, DVec3};
+use crate::{loader::PointBatch, recent};
+
+/// Width and height of the minimap, in pixels
+const MINIMAP_SIZE: u32 = 192;
+/// Points drawn into the minimap, taken evenly from the loaded points
+const MINIMAP_POINTS: usize = 250_000;
+/// The minimap is redrawn once the camera moves this far up or down, in file units
+const REDRAW_HEIGHT: f64 = 0.25;
+
+/// Top-down thumbnail of the loaded points in a corner of the 3D view, with the camera marked on it
+pub struct Minimap {
+    texture: egui::TextureHandle,
+    bounds: (DVec3, DVec3),
+    /// Points above this were left out, so the floor the camera is on isn't hidden under the ones above
+    ceiling: Option<f64>,
+}
+
+impl Minimap {
+    pub fn new(ctx: &egui::Context, batches: &[PointBatch], bounds: (DVec3, DVec3), ceiling: Option<f64>) -> Minimap {
+        let total: usize = batches.iter().map(|batch| batch.points.len()).sum();
+        let stride = (total / MINIMAP_POINTS).max(1);
+
+        let points = batches.iter()
+            .flat_map(|batch| batch.points.iter())
+            .step_by(stride)
+            .filter(|point| ceiling.is_none_or(|ceiling| point.z <= ceiling));
+
+        let image = recent::render_thumbnail(points, bounds, MINIMAP_SIZE);
+        let image = egui::ColorImage::from_rgba_unmultiplied([image.width() as usize, image.height() as usize], image.as_raw());
+
+        Minimap {
+            texture: ctx.load_texture("minimap", image, egui::TextureFilter::Linear),
+            bounds,
+            ceiling,
+        }
+    }
+
+    /// Points above the camera are left out while it's inside the point cloud
+    pub fn ceiling_for(bounds: (DVec3, DVec3), camera: DVec3) -> Option<f64> {
+        (camera.z < bounds.1.z).then_some(camera.z)
+    }
+
+    /// Whether the minimap should be redrawn for the loaded points' `bounds` and a camera at `camera`
+    pub fn is_stale(&self, bounds: (DVec3, DVec3), camera: DVec3) -> bool {
+        let ceiling = Minimap::ceiling_for(bounds, camera);
+
+        let moved = match (self.ceiling, ceiling) {
+            (Some(old), Some(new)) => (old - new).abs() > REDRAW_HEIGHT,
+            (None, None) => false,
+            _ => true,
+        };
+
+        moved || self.bounds != bounds
+    }
+
+    /// Draw the minimap in the top right corner with the camera at `camera` (file coordinates) looking along
+    /// `heading`, east and north
+    pub fn show(&self, ctx: &egui::Context, camera: DVec3, heading: DVec2) {
+        egui::Area::new("minimap").anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0]).interactable(false).show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                let size = egui::Vec2::splat(MINIMAP_SIZE as f32);
+                let response = ui.image(self.texture.id(), size);
+
+                let to_screen = |position: DVec2| {
+                    let pixel = recent::thumbnail_pixel(position, self.bounds, MINIMAP_SIZE).as_vec2();
+                    response.rect.min + egui::vec2(pixel.x, pixel.y)
+                };
+
+                let position = to_screen(camera.truncate());
+                // Kept inside the map when the camera is outside the point cloud
+                let position = response.rect.clamp(position);
+                let direction = heading.normalize_or_zero().as_vec2();
+
+                let painter = ui.painter_at(response.rect);
+                let stroke = egui::Stroke::new(2.0, egui::Color32::RED);
+
+                painter.line_segment([position, position + egui::vec2(direction.x, -direction.y) * 16.0], stroke);
+                painter.circle(position, 4.0, egui::Color32::RED, egui::Stroke::new(1.0, egui::Color32::WHITE));
+            });
+        });
+    }
+}
@@ -0,0 +1,205 @@
+use std::{fs::{self, File}, io::{BufWriter, Write}, path::Path};
+
+use glam::{DVec2, DVec3};
+use glium::{backend::Facade, framebuffer::{DepthRenderBuffer, SimpleFrameBuffer}, Surface};
+use image::{imageops, RgbaImage};
+
+use crate::{camera::{self, Z_NEAR}, plan::CaptureTransform, render, slice::{self, Scene}};
+
+/// Side of the tiles of an XYZ pyramid, in pixels
+pub const XYZ_TILE_SIZE: u32 = 256;
+/// Widest margin rendered around a tile, in pixels, see `TileRenderer::render`
+const MAX_GUTTER: u32 = 256;
+
+/// Renders a scene straight down, without a clipping plane, a tile at a time, for orthoimages too big to render at once
+pub struct TileRenderer<'a, F: Facade> {
+    facade: &'a F,
+    scene: &'a Scene,
+    program: glium::Program,
+    tile_size: u32,
+}
+
+impl<'a, F: Facade> TileRenderer<'a, F> {
+    pub fn new(facade: &'a F, scene: &'a Scene, tile_size: u32) -> Result<TileRenderer<'a, F>, String> {
+        if tile_size == 0 {
+            return Err("Tiles must be at least 1 pixel wide".to_owned());
+        }
+
+        Ok(TileRenderer {
+            facade,
+            scene,
+            program: render::point_program(facade),
+            tile_size,
+        })
+    }
+
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Tile with its top left corner at `top_left` in file X and Y, `pixel_size` file units per pixel, transparent
+    /// where there are no points. Rendered with a margin that's cropped off, so points straddling the edges of
+    /// neighbouring tiles are drawn whole in both.
+    pub fn render(&self, top_left: DVec2, pixel_size: f64, point_size: f32) -> Result<RgbaImage, String> {
+        let gutter = ((point_size as f64 / pixel_size / 2.0).ceil() as u32 + 1).min(MAX_GUTTER);
+        let size = self.tile_size + gutter * 2;
+
+        let texture = render::capture_texture(self.facade, size, size);
+        let depth = DepthRenderBuffer::new(self.facade, glium::texture::DepthFormat::F32, size, size).map_err(|err| format!("Failed to create tile depth buffer: {}", err))?;
+        let mut buffer = SimpleFrameBuffer::with_depth_buffer(self.facade, &texture, &depth).map_err(|err| format!("Failed to create tile buffer: {}", err))?;
+        buffer.clear_color_and_depth((1.0, 1.0, 1.0, 0.0), 1.0);
+
+        let (min, max) = self.scene.bounds();
+        let half = self.tile_size as f64 * pixel_size / 2.0;
+        let centre = glam::dvec3(top_left.x + half, top_left.y - half, min.z);
+
+        let zoom = (size as f64 * pixel_size) as f32;
+        let projection = camera::projection(zoom, size, size);
+        // Camera just above the highest point, looking down through all of them
+        let modelview = slice::top_down_modelview(max.z + Z_NEAR as f64, centre);
+
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::Points);
+        let draw_params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for (vertex_buffer, batch) in self.scene.vertex_buffers().iter().zip(self.scene.batches()) {
+            let modelview = modelview * glam::Mat4::from_translation((batch.origin - centre).as_vec3());
+
+            let uniforms = uniform! {
+                u_modelview: modelview.to_cols_array_2d(),
+                u_projection: projection.to_cols_array_2d(),
+                u_clipping: false,
+                u_slice: false,
+                u_slice_width: 0.0_f32,
+                u_zoom: size as f32 / zoom,
+                u_size: point_size,
+                u_round_points: true,
+                u_origin: (batch.origin - centre).as_vec3().to_array(),
+                u_centre: centre.as_vec3().to_array(),
+            };
+
+            buffer.draw(vertex_buffer, indices, &self.program, &uniforms, &draw_params).map_err(|err| format!("Failed to draw tile: {}", err))?;
+        }
+
+        Ok(imageops::crop_imm(&render::read_image(&texture), gutter, gutter, self.tile_size, self.tile_size).to_image())
+    }
+}
+
+/// Corner of the whole site the tiles are laid out from, north west, and its size in tiles across and down
+fn layout(scene: &Scene, tile_size: u32, pixel_size: f64) -> (DVec2, u32, u32) {
+    let (min, max) = scene.bounds();
+    let tile = tile_size as f64 * pixel_size;
+
+    (glam::dvec2(min.x, max.y), ((max.x - min.x) / tile).floor() as u32 + 1, ((max.y - min.y) / tile).floor() as u32 + 1)
+}
+
+/// Render the whole scene to one PNG `pixel_size` file units per pixel, a row of tiles at a time, so only a row is
+/// held in memory. `progress` is called with the rows of tiles done and the total. Returns the image's transform, for
+/// georeferencing.
+pub fn write_orthoimage<F: Facade>(renderer: &TileRenderer<F>, path: &Path, pixel_size: f64, point_size: f32, mut progress: impl FnMut(u32, u32)) -> Result<CaptureTransform, String> {
+    let tile_size = renderer.tile_size;
+    let (top_left, columns, rows) = layout(renderer.scene, tile_size, pixel_size);
+    let (width, height) = (columns * tile_size, rows * tile_size);
+
+    let error = |err: png::EncodingError| format!("Failed to write {}: {}", path.display(), err);
+
+    let file = File::create(path).map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(error)?;
+    let mut stream = writer.stream_writer().map_err(error)?;
+
+    let tile_width = tile_size as f64 * pixel_size;
+
+    for row in 0..rows {
+        let tiles = (0..columns)
+            .map(|column| renderer.render(top_left + glam::dvec2(column as f64, -(row as f64)) * tile_width, pixel_size, point_size))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for y in 0..tile_size {
+            for tile in &tiles {
+                let start = (y * tile_size * 4) as usize;
+                stream.write_all(&tile.as_raw()[start..start + tile_size as usize * 4]).map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+            }
+        }
+
+        progress(row + 1, rows);
+    }
+
+    stream.finish().map_err(error)?;
+
+    Ok(CaptureTransform {
+        origin: top_left.extend(renderer.scene.bounds().0.z),
+        pixel_x: DVec3::X * pixel_size,
+        pixel_y: DVec3::NEG_Y * pixel_size,
+    })
+}
+
+/// Render the whole scene to a `{z}/{x}/{y}.png` tile pyramid in `dir`, for web map viewers (e.g. Leaflet with
+/// `CRS.Simple`). Tiles are in the files' own coordinates, not Web Mercator: level 0 is one tile over the whole site,
+/// north west corner first, and each level halves the pixel size down to at most `pixel_size`. Tiles without points
+/// are left out. `progress` is called with each level as it's finished. Returns the deepest level.
+pub fn write_xyz_tiles<F: Facade>(renderer: &TileRenderer<F>, dir: &Path, pixel_size: f64, point_size: f32, mut progress: impl FnMut(u32)) -> Result<u32, String> {
+    if renderer.tile_size != XYZ_TILE_SIZE {
+        return Err(format!("XYZ tiles are {} pixels wide", XYZ_TILE_SIZE));
+    }
+
+    let (top_left, columns, rows) = layout(renderer.scene, XYZ_TILE_SIZE, pixel_size);
+    let max_zoom = columns.max(rows).next_power_of_two().trailing_zeros();
+    let tile_width = XYZ_TILE_SIZE as f64 * pixel_size;
+
+    let tile_path = |z: u32, x: u32, y: u32| dir.join(z.to_string()).join(x.to_string()).join(format!("{}.png", y));
+    let save = |image: &RgbaImage, z: u32, x: u32, y: u32| -> Result<(), String> {
+        let path = tile_path(z, x, y);
+        fs::create_dir_all(path.parent().expect("Tile path has no parent")).map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+        image.save(&path).map_err(|err| format!("Failed to save {}: {}", path.display(), err))
+    };
+    let empty = |image: &RgbaImage| image.pixels().all(|pixel| pixel.0[3] == 0);
+
+    // Deepest level from the points
+    for y in 0..rows {
+        for x in 0..columns {
+            let tile = renderer.render(top_left + glam::dvec2(x as f64, -(y as f64)) * tile_width, pixel_size, point_size)?;
+
+            if !empty(&tile) {
+                save(&tile, max_zoom, x, y)?;
+            }
+        }
+    }
+    progress(max_zoom);
+
+    // Each level above from the four tiles under each of its tiles
+    for z in (0..max_zoom).rev() {
+        let count = 1 << z;
+
+        for y in 0..count {
+            for x in 0..count {
+                // Same clear colour as rendered tiles, so averaging into empty space doesn't darken edges
+                let mut parent = RgbaImage::from_pixel(XYZ_TILE_SIZE * 2, XYZ_TILE_SIZE * 2, image::Rgba([255, 255, 255, 0]));
+                let mut found = false;
+
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    if let Ok(child) = image::open(tile_path(z + 1, x * 2 + dx, y * 2 + dy)) {
+                        imageops::replace(&mut parent, &child.to_rgba8(), (dx * XYZ_TILE_SIZE) as i64, (dy * XYZ_TILE_SIZE) as i64);
+                        found = true;
+                    }
+                }
+
+                if found {
+                    save(&render::downsample(&parent, 2), z, x, y)?;
+                }
+            }
+        }
+
+        progress(z);
+    }
+
+    Ok(max_zoom)
+}
@@ -0,0 +1,73 @@
+use std::{fs, io::Write, path::Path, process::{Command, Stdio}};
+
+use serde_json::{json, Value};
+use tempfile::TempPath;
+
+/// Run a PDAL pipeline (e.g. ground classification, outlier removal) over `input`, writing the result to a temporary
+/// LAS file that's deleted once dropped. The pipeline's own readers and writers are replaced, so one pipeline can be
+/// used on any file.
+pub fn preprocess(pipeline: &Path, input: &str) -> Result<TempPath, String> {
+    let text = fs::read_to_string(pipeline).map_err(|err| format!("Failed to read {}: {}", pipeline.display(), err))?;
+    let filters = filter_stages(&text).map_err(|err| format!("{}: {}", pipeline.display(), err))?;
+
+    let output = tempfile::Builder::new()
+        .prefix("pdal-")
+        .suffix(".las")
+        .tempfile()
+        .map_err(|err| format!("Failed to create PDAL output file: {}", err))?
+        .into_temp_path();
+
+    let mut stages = vec![json!(input)];
+    stages.extend(filters);
+    stages.push(json!({
+        "type": "writers.las",
+        "filename": output.to_string_lossy(),
+        // Keep the input's point format, scale and offset
+        "forward": "all",
+    }));
+
+    let mut child = Command::new("pdal")
+        .args(["pipeline", "--stdin"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Failed to run PDAL ({}), is it installed and on the PATH?", err))?;
+
+    child.stdin.take().expect("PDAL stdin not piped")
+        .write_all(json!({ "pipeline": stages }).to_string().as_bytes())
+        .map_err(|err| format!("Failed to send pipeline to PDAL: {}", err))?;
+
+    let result = child.wait_with_output().map_err(|err| format!("Failed to run PDAL: {}", err))?;
+
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(format!("PDAL pipeline failed ({}): {}", result.status, stderr.trim()));
+    }
+
+    Ok(output)
+}
+
+/// Stages of a pipeline file other than readers and writers
+fn filter_stages(text: &str) -> Result<Vec<Value>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+
+    let stages = match value {
+        Value::Array(stages) => stages,
+        Value::Object(mut object) => match object.remove("pipeline") {
+            Some(Value::Array(stages)) => stages,
+            _ => return Err("expected a \"pipeline\" array".to_owned()),
+        },
+        _ => return Err("expected a pipeline array or object".to_owned()),
+    };
+
+    Ok(stages.into_iter()
+        .filter(|stage| {
+            // Bare strings and stages without a type are file names, i.e. readers or writers
+            match stage.get("type").and_then(Value::as_str) {
+                Some(kind) => !kind.starts_with("readers.") && !kind.starts_with("writers."),
+                None => false,
+            }
+        })
+        .collect())
+}
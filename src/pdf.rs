@@ -0,0 +1,148 @@
+use std::{fmt::Write as _, io::{self, Write}};
+
+use flate2::{write::ZlibEncoder, Compression};
+use image::RgbaImage;
+
+/// A4 landscape, in points
+const PAGE_WIDTH: f64 = 842.0;
+const PAGE_HEIGHT: f64 = 595.0;
+const MARGIN: f64 = 36.0;
+const CAPTION_SIZE: f64 = 12.0;
+
+struct Page {
+    width: u32,
+    height: u32,
+    /// Deflated RGB pixels
+    pixels: Vec<u8>,
+    caption: String,
+    text: Vec<PageText>,
+}
+
+/// Text written over a page's image as PDF text, so it stays sharp and can be searched and copied
+pub struct PageText {
+    pub text: String,
+    /// Left end of the baseline, in image pixels
+    pub position: glam::Vec2,
+    /// In image pixels
+    pub size: f32,
+    pub colour: [u8; 3],
+}
+
+/// Multi-page PDF with one captioned image per page (and any text over it), scaled to fit an A4 landscape page
+#[derive(Default)]
+pub struct Document {
+    pages: Vec<Page>,
+}
+
+impl Document {
+    pub fn new() -> Document {
+        Document::default()
+    }
+
+    /// Compresses the image straight away, so pages don't hold on to the full size image
+    pub fn add_page(&mut self, image: &RgbaImage, caption: &str) -> io::Result<()> {
+        self.add_page_with_text(image, caption, vec![])
+    }
+
+    /// Page with `text` over the image, see `add_page`
+    pub fn add_page_with_text(&mut self, image: &RgbaImage, caption: &str, text: Vec<PageText>) -> io::Result<()> {
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+
+        for pixel in image.pixels() {
+            encoder.write_all(&pixel.0[..3])?;
+        }
+
+        self.pages.push(Page {
+            width: image.width(),
+            height: image.height(),
+            pixels: encoder.finish()?,
+            caption: caption.to_owned(),
+            text,
+        });
+
+        Ok(())
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        // Objects 1 to 3 are the catalog, page tree and font, then each page has a page, content stream and image
+        let page_object = |i: usize| 4 + 3 * i;
+
+        let mut pdf: Vec<u8> = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = vec![];
+
+        let mut object = |pdf: &mut Vec<u8>, body: &[u8]| {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+            pdf.extend_from_slice(body);
+            pdf.extend_from_slice(b"\nendobj\n");
+        };
+
+        let stream = |dictionary: &str, data: &[u8]| {
+            let mut body = format!("<< {} /Length {} >>\nstream\n", dictionary, data.len()).into_bytes();
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\nendstream");
+            body
+        };
+
+        let kids: Vec<String> = (0..self.pages.len()).map(|i| format!("{} 0 R", page_object(i))).collect();
+
+        object(&mut pdf, b"<< /Type /Catalog /Pages 2 0 R >>");
+        object(&mut pdf, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), self.pages.len()).as_bytes());
+        object(&mut pdf, b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>");
+
+        for (i, page) in self.pages.iter().enumerate() {
+            // Fit the image below the caption, keeping its aspect ratio
+            let (available_width, available_height) = (PAGE_WIDTH - 2.0 * MARGIN, PAGE_HEIGHT - 3.0 * MARGIN);
+            let scale = f64::min(available_width / page.width as f64, available_height / page.height as f64);
+            let (width, height) = (page.width as f64 * scale, page.height as f64 * scale);
+            let (x, y) = ((PAGE_WIDTH - width) / 2.0, MARGIN + (available_height - height) / 2.0);
+
+            let mut contents = String::new();
+            let _ = writeln!(contents, "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im0 Do Q", width, height, x, y);
+            let _ = writeln!(contents, "BT /F1 {} Tf {:.2} {:.2} Td ({}) Tj ET", CAPTION_SIZE, MARGIN, PAGE_HEIGHT - MARGIN - CAPTION_SIZE, escape(&page.caption));
+
+            // Image rows run down, the page's y up
+            for text in &page.text {
+                let [r, g, b] = text.colour.map(|channel| channel as f64 / 255.0);
+                let (text_x, text_y) = (x + text.position.x as f64 * scale, y + height - text.position.y as f64 * scale);
+
+                let _ = writeln!(contents, "q {:.3} {:.3} {:.3} rg BT /F1 {:.2} Tf {:.2} {:.2} Td ({}) Tj ET Q",
+                    r, g, b, text.size as f64 * scale, text_x, text_y, escape(&text.text));
+            }
+
+            object(&mut pdf, format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R >> /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, page_object(i) + 2, page_object(i) + 1,
+            ).as_bytes());
+            object(&mut pdf, &stream("", contents.as_bytes()));
+            object(&mut pdf, &stream(&format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode",
+                page.width, page.height,
+            ), &page.pixels));
+        }
+
+        let xref = pdf.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1);
+        for offset in &offsets {
+            let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(trailer, "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", offsets.len() + 1, xref);
+        pdf.extend_from_slice(trailer.as_bytes());
+
+        out.write_all(&pdf)
+    }
+}
+
+/// Escape a string for a PDF literal, dropping characters the standard fonts can't show
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in text.chars().filter(|c| c.is_ascii() && !c.is_ascii_control()) {
+        if matches!(c, '(' | ')' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
@@ -0,0 +1,48 @@
+use glam::DVec3;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{filter::PointFilter, loader::PointBatch};
+
+/// Line of sight through the view, in file coordinates
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: DVec3,
+    pub direction: DVec3,
+}
+
+impl Ray {
+    /// Ray through a window position (physical pixels), starting on the near (clipping) plane.
+    /// `mvp` maps file coordinates relative to `centre` to clip space.
+    pub fn from_screen(position: glam::Vec2, window_size: glam::Vec2, mvp: glam::Mat4, centre: DVec3) -> Ray {
+        let inverse = mvp.as_dmat4().inverse();
+        let ndc = (position / window_size).as_dvec2() * glam::dvec2(2.0, -2.0) + glam::dvec2(-1.0, 1.0);
+
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+
+        Ray {
+            origin: near + centre,
+            direction: (far - near).normalize_or_zero(),
+        }
+    }
+}
+
+/// Point within `radius` of the ray that's closest to its origin, i.e. the visible point under the cursor, and the
+/// index of the file it's from
+pub fn pick(batches: &[PointBatch], filter: &PointFilter, ray: &Ray, radius: f64) -> Option<(las::Point, usize)> {
+    batches.par_iter()
+        .flat_map_iter(|batch| batch.points.iter().map(|point| (point, batch.file)))
+        .filter(|(point, _)| filter.accepts(point))
+        .filter_map(|(point, file)| {
+            let offset = glam::dvec3(point.x, point.y, point.z) - ray.origin;
+            let depth = offset.dot(ray.direction);
+
+            if depth >= 0.0 && (offset - ray.direction * depth).length_squared() <= radius * radius {
+                Some((depth, point, file))
+            } else {
+                None
+            }
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, point, file)| (point.clone(), file))
+}
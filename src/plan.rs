@@ -0,0 +1,1097 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use glam::{DVec2, DVec3};
+use image::{DynamicImage, GrayImage, ImageError, ImageResult, Luma, Rgba, RgbaImage};
+use rusttype::{Font, Scale};
+
+use crate::{hough, vector};
+
+/// Colour of erased (empty) floor plan pixels
+pub const ERASED: Rgba<u8> = Rgba([255, 255, 255, 0]);
+
+/// Most grid lines of each axis drawn across a floor plan, closer spacings are left out rather than filling the image
+pub const MAX_GRID_LINES: usize = 500;
+
+const GRID_COLOUR: Rgba<u8> = Rgba([128, 128, 128, 255]);
+const LABEL_COLOUR: Rgba<u8> = Rgba([64, 64, 64, 255]);
+const LABEL_SIZE: f32 = 14.0;
+/// Largest vertical step per pixel, relative to its length, of a capture treated as looking straight down
+const TOP_DOWN_TOLERANCE: f64 = 1.0e-4;
+
+/// Maps floor plan image pixels to file coordinates on the clipping plane, recorded when the cutaway is captured
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureTransform {
+    /// File coordinates of the top left corner of the image
+    pub origin: DVec3,
+    /// Step in file coordinates for one pixel to the right
+    pub pixel_x: DVec3,
+    /// Step in file coordinates for one pixel down
+    pub pixel_y: DVec3,
+}
+
+impl CaptureTransform {
+    /// `mvp` maps file coordinates relative to `centre` to clip space
+    pub fn new(mvp: glam::Mat4, width: u32, height: u32, centre: DVec3) -> CaptureTransform {
+        let inverse = mvp.as_dmat4().inverse();
+
+        // Clipping plane lies at normalised depth 0
+        let unproject = |x: f64, y: f64| {
+            inverse.project_point3(DVec3::new(x / width as f64 * 2.0 - 1.0, 1.0 - y / height as f64 * 2.0, 0.0))
+        };
+
+        let origin = unproject(0.0, 0.0);
+
+        CaptureTransform {
+            origin: origin + centre,
+            pixel_x: unproject(1.0, 0.0) - origin,
+            pixel_y: unproject(0.0, 1.0) - origin,
+        }
+    }
+
+    pub fn pixel_to_world(&self, pixel: DVec2) -> DVec3 {
+        self.origin + self.pixel_x * pixel.x + self.pixel_y * pixel.y
+    }
+
+    /// Normal of the clipping plane
+    pub fn normal(&self) -> DVec3 {
+        self.pixel_x.cross(self.pixel_y).normalize_or_zero()
+    }
+
+    /// Area covered by one pixel on the clipping plane, in square file units
+    pub fn pixel_area(&self) -> f64 {
+        self.pixel_x.cross(self.pixel_y).length()
+    }
+
+    /// Whether the capture looks straight down, so its pixels lie flat in the world
+    pub fn is_top_down(&self) -> bool {
+        let flat = |step: DVec3| step.length() > 0.0 && step.z.abs() <= step.length() * TOP_DOWN_TOLERANCE;
+
+        flat(self.pixel_x) && flat(self.pixel_y)
+    }
+}
+
+/// Room filled on the rooms layer
+#[derive(Clone, Copy, Debug)]
+pub struct Room {
+    pub colour: [u8; 3],
+    /// Number of pixels filled
+    pub pixels: usize,
+    /// Mean pixel position, in floor plan image coordinates
+    pub centre: glam::Vec2,
+}
+
+/// Text placed on the floor plan, such as a room name. Kept as text rather than pixels so vector exports write it as
+/// text, shown and exported with the edits layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextLabel {
+    pub text: String,
+    /// Centre of the text, in floor plan image coordinates
+    pub position: glam::Vec2,
+    /// Height of a line of the text, in floor plan pixels
+    pub size: f32,
+    pub colour: [u8; 3],
+}
+
+impl TextLabel {
+    /// Top left corner and size of the text
+    pub fn bounds(&self, font: &Font) -> (glam::Vec2, glam::Vec2) {
+        let (width, _) = imageproc::drawing::text_size(Scale::uniform(self.size), font, &self.text);
+        let size = glam::vec2(width as f32, self.size);
+
+        (self.position - size / 2.0, size)
+    }
+
+    /// Left end of the text's baseline, where PDF and CAD text is placed from
+    pub fn baseline(&self, font: &Font) -> glam::Vec2 {
+        let (min, _) = self.bounds(font);
+        min + glam::vec2(0.0, font.v_metrics(Scale::uniform(self.size)).ascent)
+    }
+}
+
+/// Fill colour of the first room (left click), later rooms get `room_colour`s of their own
+pub const ROOM: Rgba<u8> = Rgba([0, 0, 255, 0]);
+/// Room fill colour for walls and the exterior (right click)
+pub const EXTERIOR: Rgba<u8> = Rgba([255, 0, 0, 0]);
+/// Hue between successive room colours, in degrees (the golden angle), so rooms filled one after another differ
+const ROOM_HUE_STEP: f32 = 137.5;
+
+/// Fill colour of the room with this index, `ROOM` first. Never the exterior's red or erased white.
+pub fn room_colour(index: usize) -> Rgba<u8> {
+    if index == 0 {
+        return ROOM;
+    }
+
+    let hue = (240.0 + index as f32 * ROOM_HUE_STEP) % 360.0 / 60.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let [r, g, b] = match hue as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    };
+
+    let [r, g, b] = [r, g, b].map(|channel: f32| (40.0 + channel * 200.0) as u8);
+    Rgba([r, g, b, 0])
+}
+
+/// Colour and pixels of a region filled as a room
+pub type RoomRegion = ([u8; 3], Vec<(u32, u32)>);
+
+/// Whether a rooms layer pixel is filled as a room, rather than the exterior or nothing
+pub fn is_room(pixel: Rgba<u8>) -> bool {
+    pixel.0[3] == 0 && pixel != EXTERIOR && pixel != ERASED
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LayerKind {
+    /// Rendered cutaway of the point cloud
+    Cutaway,
+    /// Slice points and the lines automatically connecting them
+    Walls,
+    /// Pencil strokes and shapes
+    Edits,
+    /// Room identification colours
+    Rooms,
+}
+
+impl LayerKind {
+    /// Bottom to top
+    pub const ALL: [LayerKind; 4] = [LayerKind::Cutaway, LayerKind::Walls, LayerKind::Edits, LayerKind::Rooms];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayerKind::Cutaway => "Cutaway",
+            LayerKind::Walls => "Walls",
+            LayerKind::Edits => "Edits",
+            LayerKind::Rooms => "Rooms",
+        }
+    }
+}
+
+pub struct Layer {
+    pub image: RgbaImage,
+    pub visible: bool,
+    /// Display opacity, exports only honour visibility
+    pub opacity: f32,
+}
+
+impl Layer {
+    fn new(image: RgbaImage) -> Layer {
+        Layer {
+            image,
+            visible: true,
+            opacity: 1.0,
+        }
+    }
+
+    /// Opacity the layer is drawn with on screen
+    pub fn display_opacity(&self) -> f32 {
+        if self.visible {
+            self.opacity
+        } else {
+            0.0
+        }
+    }
+}
+
+/// How room fills treat slightly varied colours and small gaps in walls
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FillSettings {
+    /// Largest difference in any channel between pixels filled together. Walls and strokes this close to opaque stop
+    /// the fill as well, so anti-aliased edges don't leak.
+    pub tolerance: u8,
+    /// Gaps in walls and strokes up to this wide, in pixels, are bridged while filling
+    pub gap: u32,
+}
+
+/// Layers of a floor plan being edited, all the same size as the captured cutaway
+pub struct FloorPlan {
+    pub cutaway: Layer,
+    /// Slice as rendered, before its points were joined into walls. Only shown, to compare against, hidden at first.
+    pub raw_slice: Layer,
+    pub walls: Layer,
+    pub edits: Layer,
+    pub rooms: Layer,
+    pub labels: Vec<TextLabel>,
+    /// Wall outlines traced from the walls layer and edited by hand, exported in place of tracing the layer again.
+    /// None until they're traced.
+    pub traced_walls: Option<Vec<Vec<DVec2>>>,
+}
+
+impl FloorPlan {
+    pub fn new(cutaway: RgbaImage, raw_slice: RgbaImage, walls: RgbaImage) -> FloorPlan {
+        let blank = RgbaImage::from_pixel(cutaway.width(), cutaway.height(), ERASED);
+
+        FloorPlan {
+            cutaway: Layer::new(cutaway),
+            raw_slice: Layer {
+                visible: false,
+                ..Layer::new(raw_slice)
+            },
+            walls: Layer::new(walls),
+            edits: Layer::new(blank.clone()),
+            rooms: Layer::new(blank),
+            labels: vec![],
+            traced_walls: None,
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.cutaway.image.dimensions()
+    }
+
+    pub fn layer(&self, kind: LayerKind) -> &Layer {
+        match kind {
+            LayerKind::Cutaway => &self.cutaway,
+            LayerKind::Walls => &self.walls,
+            LayerKind::Edits => &self.edits,
+            LayerKind::Rooms => &self.rooms,
+        }
+    }
+
+    pub fn layer_mut(&mut self, kind: LayerKind) -> &mut Layer {
+        match kind {
+            LayerKind::Cutaway => &mut self.cutaway,
+            LayerKind::Walls => &mut self.walls,
+            LayerKind::Edits => &mut self.edits,
+            LayerKind::Rooms => &mut self.rooms,
+        }
+    }
+
+    /// Walls and strokes stop room fills, whether or not their layers are visible. Those within `tolerance` of opaque
+    /// count too.
+    fn is_barrier(&self, x: u32, y: u32, tolerance: u8) -> bool {
+        let opaque = 255 - tolerance;
+        self.walls.image.get_pixel(x, y).0[3] >= opaque || self.edits.image.get_pixel(x, y).0[3] >= opaque
+    }
+
+    /// Flood fill the room containing `start` on the rooms layer
+    pub fn fill_room(&mut self, start: (u32, u32), colour: Rgba<u8>, settings: &FillSettings) {
+        let (width, height) = self.dimensions();
+
+        if start.0 >= width || start.1 >= height || self.is_barrier(start.0, start.1, settings.tolerance) {
+            return;
+        }
+
+        let start_colour = *self.rooms.image.get_pixel(start.0, start.1);
+
+        if start_colour == colour {
+            return;
+        }
+
+        let index = |x: u32, y: u32| y as usize * width as usize + x as usize;
+        let similar = |pixel: &Rgba<u8>| pixel.0.iter().zip(start_colour.0).all(|(a, b)| a.abs_diff(b) <= settings.tolerance);
+
+        // Pixels the fill may cover, ignoring gaps
+        let mut open = vec![false; width as usize * height as usize];
+        for (x, y, pixel) in self.rooms.image.enumerate_pixels() {
+            open[index(x, y)] = similar(pixel) && !self.is_barrier(x, y, settings.tolerance);
+        }
+
+        // Walls grown by half the gap close it, unless that would cover where the fill starts
+        let radius = settings.gap.div_ceil(2).min(u8::MAX as u32) as u8;
+        let mut closed = open.clone();
+        if radius > 0 {
+            let mut barriers = GrayImage::from_fn(width, height, |x, y| Luma([if self.is_barrier(x, y, settings.tolerance) { 255 } else { 0 }]));
+            imageproc::morphology::dilate_mut(&mut barriers, imageproc::distance_transform::Norm::LInf, radius);
+
+            for (x, y, pixel) in barriers.enumerate_pixels() {
+                closed[index(x, y)] &= pixel.0[0] == 0;
+            }
+        }
+        if !closed[index(start.0, start.1)] {
+            closed = open.clone();
+        }
+
+        let neighbours = |(x, y): (u32, u32)| {
+            [
+                (x > 0).then(|| (x - 1, y)),
+                (y > 0).then(|| (x, y - 1)),
+                (x + 1 < width).then(|| (x + 1, y)),
+                (y + 1 < height).then(|| (x, y + 1)),
+            ].into_iter().flatten()
+        };
+
+        let mut filled = vec![false; open.len()];
+        let mut frontier = vec![start];
+        filled[index(start.0, start.1)] = true;
+
+        while let Some(point) = frontier.pop() {
+            self.rooms.image.put_pixel(point.0, point.1, colour);
+
+            for (x, y) in neighbours(point) {
+                if closed[index(x, y)] && !filled[index(x, y)] {
+                    filled[index(x, y)] = true;
+                    frontier.push((x, y));
+                }
+            }
+        }
+
+        // Grow back up to the walls the gaps were closed with, going no further into the gaps than that
+        if radius > 0 {
+            let mut frontier: Vec<(u32, u32)> = self.rooms.image.enumerate_pixels()
+                .filter(|(x, y, _)| filled[index(*x, *y)])
+                .map(|(x, y, _)| (x, y))
+                .collect();
+
+            for _ in 0..radius {
+                let mut next = vec![];
+
+                for point in frontier {
+                    for (x, y) in neighbours(point) {
+                        if open[index(x, y)] && !filled[index(x, y)] {
+                            filled[index(x, y)] = true;
+                            self.rooms.image.put_pixel(x, y, colour);
+                            next.push((x, y));
+                        }
+                    }
+                }
+
+                frontier = next;
+            }
+        }
+    }
+
+    /// Colours rooms are filled with, in the order they're first found going down the image
+    pub fn room_colours(&self) -> Vec<Rgba<u8>> {
+        let mut colours = vec![];
+
+        for pixel in self.rooms.image.pixels() {
+            if is_room(*pixel) && !colours.contains(pixel) {
+                colours.push(*pixel);
+            }
+        }
+
+        colours
+    }
+
+    /// Colour for a new room, the first of the `room_colour`s no room has yet
+    pub fn next_room_colour(&self) -> Rgba<u8> {
+        let used = self.room_colours();
+
+        (0..).map(room_colour).find(|colour| !used.contains(colour)).expect("Ran out of room colours")
+    }
+
+    /// Colour and pixels of each separate region filled as a room, in the order they're found going down the image.
+    /// Touching rooms of different colours are separate rooms.
+    pub fn room_regions(&self) -> Vec<RoomRegion> {
+        let (width, height) = self.dimensions();
+
+        let mut regions: Vec<RoomRegion> = self.room_colours().into_iter()
+            .flat_map(|colour| {
+                let [r, g, b, _] = colour.0;
+                vector::connected_regions(width, height, |x, y| *self.rooms.image.get_pixel(x, y) == colour)
+                    .into_iter()
+                    .map(move |region| ([r, g, b], region))
+            })
+            .collect();
+
+        // Each region starts at its first pixel going down the image
+        regions.sort_by_key(|(_, region)| (region[0].1, region[0].0));
+
+        regions
+    }
+
+    pub fn rooms(&self) -> Vec<Room> {
+        self.room_regions().iter()
+            .map(|(colour, region)| {
+                let sum = region.iter().fold(glam::DVec2::ZERO, |sum, &(x, y)| sum + glam::dvec2(x as f64, y as f64));
+
+                Room {
+                    colour: *colour,
+                    pixels: region.len(),
+                    centre: (sum / region.len() as f64 + glam::DVec2::splat(0.5)).as_vec2(),
+                }
+            })
+            .collect()
+    }
+
+    /// Visible cutaway layer, or white if it's hidden
+    pub fn background(&self) -> RgbaImage {
+        if self.cutaway.visible {
+            self.cutaway.image.clone()
+        } else {
+            let (width, height) = self.dimensions();
+            RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]))
+        }
+    }
+
+    /// Visible layers above the cutaway combined into one image: opaque walls and strokes over alpha 0 room colours
+    pub fn merged(&self) -> RgbaImage {
+        let (width, height) = self.dimensions();
+
+        let mut base = if self.rooms.visible {
+            self.rooms.image.clone()
+        } else {
+            RgbaImage::from_pixel(width, height, ERASED)
+        };
+
+        for layer in [&self.walls, &self.edits] {
+            if !layer.visible {
+                continue;
+            }
+
+            for (x, y, pixel) in layer.image.enumerate_pixels() {
+                if pixel.0[3] == 255 {
+                    base.put_pixel(x, y, *pixel);
+                }
+            }
+        }
+
+        base
+    }
+
+    /// Labels to show and export, none while the edits layer is hidden
+    pub fn visible_labels(&self) -> &[TextLabel] {
+        if self.edits.visible {
+            &self.labels
+        } else {
+            &[]
+        }
+    }
+
+    /// Topmost visible label covering `position` (floor plan image coordinates)
+    pub fn label_at(&self, position: glam::Vec2, font: &Font) -> Option<usize> {
+        self.visible_labels().iter().rposition(|label| {
+            let (min, size) = label.bounds(font);
+            position.cmpge(min).all() && position.cmple(min + size).all()
+        })
+    }
+
+    /// Burn the visible labels into a raster export
+    pub fn draw_labels(&self, image: &mut RgbaImage, font: &Font) {
+        for label in self.visible_labels() {
+            let (min, _) = label.bounds(font);
+            let [r, g, b] = label.colour;
+
+            imageproc::drawing::draw_text_mut(image, Rgba([r, g, b, 255]), min.x.round() as i32, min.y.round() as i32, Scale::uniform(label.size), font, &label.text);
+        }
+    }
+}
+
+/// Font used to label exported images (egui's built-in proportional font)
+pub fn label_font() -> Font<'static> {
+    let fonts = egui::FontDefinitions::default();
+    let data = fonts.font_data.get("Ubuntu-Light").expect("Missing built-in egui font");
+
+    Font::try_from_vec(data.font.to_vec()).expect("Failed to parse built-in egui font")
+}
+
+/// Cutaway render with the opaque floor plan pixels (walls and strokes) drawn over it
+pub fn annotated_cutaway(cutaway: &RgbaImage, plan: &RgbaImage) -> RgbaImage {
+    let mut base = cutaway.clone();
+
+    for (x, y, pixel) in plan.enumerate_pixels() {
+        if pixel.0[3] == 255 {
+            base.put_pixel(x, y, *pixel);
+        }
+    }
+
+    base
+}
+
+/// Floor plan (walls, annotations and room colours) on a white background
+pub fn flatten(plan: &RgbaImage) -> RgbaImage {
+    let mut base = plan.clone();
+
+    for pixel in base.pixels_mut() {
+        pixel.0[3] = 255;
+    }
+
+    base
+}
+
+/// Pixel formats floor plans are exported in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColourDepth {
+    /// Colour on a white background
+    Colour,
+    /// Colour with the empty background left transparent
+    Transparent,
+    /// 8-bit greyscale on a white background
+    Grey,
+    /// 1-bit, walls and strokes black on white, for tracing in CAD
+    Binary,
+}
+
+impl ColourDepth {
+    pub const ALL: [ColourDepth; 4] = [ColourDepth::Colour, ColourDepth::Transparent, ColourDepth::Grey, ColourDepth::Binary];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColourDepth::Colour => "RGB",
+            ColourDepth::Transparent => "RGBA",
+            ColourDepth::Grey => "Grey",
+            ColourDepth::Binary => "1-bit",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ColourDepth::Colour => "Colour on a white background",
+            ColourDepth::Transparent => "Colour with a transparent background",
+            ColourDepth::Grey => "8-bit greyscale on a white background",
+            ColourDepth::Binary => "1-bit black and white, room colours left out",
+        }
+    }
+}
+
+/// Pixels at least this light are white in binary exports, keeping grid lines and labels
+const BINARY_THRESHOLD: u8 = 192;
+
+/// Convert a floor plan (empty pixels transparent, rooms alpha 0) to `depth`
+pub fn convert(plan: &RgbaImage, depth: ColourDepth) -> DynamicImage {
+    match depth {
+        ColourDepth::Colour => DynamicImage::ImageRgba8(flatten(plan)),
+        ColourDepth::Transparent => {
+            let mut base = plan.clone();
+
+            // Room colours are stored with alpha 0
+            for pixel in base.pixels_mut().filter(|pixel| **pixel != ERASED && pixel.0[3] == 0) {
+                pixel.0[3] = 255;
+            }
+
+            DynamicImage::ImageRgba8(base)
+        },
+        ColourDepth::Grey => DynamicImage::ImageLuma8(DynamicImage::ImageRgba8(flatten(plan)).into_luma8()),
+        ColourDepth::Binary => {
+            let grey = DynamicImage::ImageRgba8(plan.clone()).into_luma8();
+
+            DynamicImage::ImageLuma8(GrayImage::from_fn(plan.width(), plan.height(), |x, y| {
+                let ink = plan.get_pixel(x, y).0[3] >= 128 && grey.get_pixel(x, y).0[0] < BINARY_THRESHOLD;
+                Luma([if ink { 0 } else { 255 }])
+            }))
+        },
+    }
+}
+
+/// Save a floor plan in `depth`, packing binary PNGs to one bit per pixel
+pub fn save_image(plan: &RgbaImage, depth: ColourDepth, path: &Path) -> ImageResult<()> {
+    let image = convert(plan, depth);
+    let is_png = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+
+    if depth == ColourDepth::Binary && is_png {
+        save_binary_png(&image.into_luma8(), path).map_err(|err| {
+            ImageError::Encoding(image::error::EncodingError::new(image::ImageFormat::Png.into(), err))
+        })
+    } else {
+        image.save(path)
+    }
+}
+
+fn save_binary_png(image: &GrayImage, path: &Path) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), image.width(), image.height());
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+
+    // Rows are padded to whole bytes, most significant bit first
+    let row_bytes = (image.width() as usize).div_ceil(8);
+    let mut data = vec![0_u8; row_bytes * image.height() as usize];
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel.0[0] >= 128 {
+            data[y as usize * row_bytes + x as usize / 8] |= 0x80 >> (x % 8);
+        }
+    }
+
+    encoder.write_header()?.write_image_data(&data)
+}
+
+/// Convert a window position (physical pixels) to floor plan image coordinates
+pub fn screen_to_image(position: glam::Vec2, window_size: glam::Vec2, drawing_mvp: glam::Mat4, image_size: glam::Vec2) -> glam::Vec2 {
+    let ndc = position / window_size * 2.0 - glam::Vec2::ONE;
+    let p = drawing_mvp.inverse().transform_point3(glam::vec3(ndc.x, ndc.y, 0.0));
+
+    (glam::vec2(p.x, p.y) / 2.0 + glam::Vec2::splat(0.5)) * image_size
+}
+
+/// Convert floor plan image coordinates to a window position (physical pixels)
+pub fn image_to_screen(position: glam::Vec2, window_size: glam::Vec2, drawing_mvp: glam::Mat4, image_size: glam::Vec2) -> glam::Vec2 {
+    let model = position / image_size * 2.0 - glam::Vec2::ONE;
+    let p = drawing_mvp.transform_point3(glam::vec3(model.x, model.y, 0.0));
+
+    (glam::vec2(p.x, p.y) + glam::Vec2::ONE) / 2.0 * window_size
+}
+
+/// Part of the traced wall outlines under the cursor, as the outline and the index of the vertex (or the vertex the
+/// edge starts at)
+pub enum OutlinePick {
+    Vertex(usize, usize),
+    Edge(usize, usize),
+}
+
+/// Index of the proposed wall closest to a window position, if it's within a few pixels on screen
+pub fn pick_wall_proposal(proposals: &[hough::Segment], position: glam::Vec2, window_size: glam::Vec2, drawing_mvp: glam::Mat4, image_size: glam::Vec2) -> Option<usize> {
+    const PICK_DISTANCE: f32 = 8.0;
+
+    let pixel_scale = (image_to_screen(glam::vec2(1.0, 0.0), window_size, drawing_mvp, image_size)
+        - image_to_screen(glam::Vec2::ZERO, window_size, drawing_mvp, image_size)).length();
+    let pos = screen_to_image(position, window_size, drawing_mvp, image_size) - glam::Vec2::splat(0.5);
+
+    proposals.iter().enumerate()
+        .map(|(i, segment)| (i, segment.distance_to(pos)))
+        .filter(|(_, distance)| distance * pixel_scale <= PICK_DISTANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+/// Vertex of the traced wall outlines near the cursor, or failing that the edge near it
+pub fn pick_outline(outlines: &[Vec<glam::DVec2>], position: glam::Vec2, window_size: glam::Vec2, drawing_mvp: glam::Mat4, image_size: glam::Vec2) -> Option<OutlinePick> {
+    const PICK_DISTANCE: f32 = 8.0;
+
+    let pixel_scale = (image_to_screen(glam::vec2(1.0, 0.0), window_size, drawing_mvp, image_size)
+        - image_to_screen(glam::Vec2::ZERO, window_size, drawing_mvp, image_size)).length() as f64;
+    let pos = screen_to_image(position, window_size, drawing_mvp, image_size).as_dvec2();
+    let reach = PICK_DISTANCE as f64 / pixel_scale;
+
+    let nearest = |distance: &dyn Fn(&[glam::DVec2], usize) -> f64| {
+        outlines.iter().enumerate()
+            .flat_map(|(outline, points)| (0..points.len()).map(move |i| (outline, i, distance(points, i))))
+            .filter(|(_, _, distance)| *distance <= reach)
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+    };
+
+    if let Some((outline, i, _)) = nearest(&|points, i| points[i].distance(pos)) {
+        return Some(OutlinePick::Vertex(outline, i));
+    }
+
+    nearest(&|points, i| vector::segment_distance(pos, points[i], points[(i + 1) % points.len()]))
+        .map(|(outline, i, _)| OutlinePick::Edge(outline, i))
+}
+
+/// Paint a filled circle of the given diameter
+pub fn stamp(image: &mut RgbaImage, centre: (i32, i32), size: f32, colour: Rgba<u8>) {
+    let radius = size / 2.0;
+    let extent = radius.ceil() as i32;
+
+    for dy in -extent..=extent {
+        for dx in -extent..=extent {
+            if (dx * dx + dy * dy) as f32 > radius * radius {
+                continue;
+            }
+
+            let (x, y) = (centre.0 + dx, centre.1 + dy);
+
+            if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+                continue;
+            }
+
+            image.put_pixel(x as u32, y as u32, colour);
+        }
+    }
+}
+
+/// Paint a straight line with a round brush of the given diameter
+pub fn draw_line(image: &mut RgbaImage, from: glam::Vec2, to: glam::Vec2, size: f32, colour: Rgba<u8>) {
+    for point in line_drawing::Bresenham::new((from.x as i32, from.y as i32), (to.x as i32, to.y as i32)) {
+        stamp(image, point, size, colour);
+    }
+}
+
+/// Paint a closed polygon outline, optionally filling its interior
+pub fn draw_polygon(image: &mut RgbaImage, points: &[glam::Vec2], size: f32, colour: Rgba<u8>, filled: bool) {
+    if filled {
+        fill_polygon(image, points, colour);
+    }
+
+    for (i, &from) in points.iter().enumerate() {
+        draw_line(image, from, points[(i + 1) % points.len()], size, colour);
+    }
+}
+
+/// Even-odd scanline fill, sampled at pixel centres
+pub fn fill_polygon(image: &mut RgbaImage, points: &[glam::Vec2], colour: Rgba<u8>) {
+    if points.len() < 3 {
+        return;
+    }
+
+    let min_y = points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min).floor().max(0.0) as u32;
+    let max_y = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max).ceil().min(image.height() as f32) as u32;
+
+    let mut crossings = vec![];
+
+    for y in min_y..max_y {
+        let yc = y as f32 + 0.5;
+
+        crossings.clear();
+        for (i, &a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+
+            if (a.y <= yc) != (b.y <= yc) {
+                crossings.push(a.x + (yc - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+        crossings.sort_by(f32::total_cmp);
+
+        for span in crossings.chunks_exact(2) {
+            let start = (span[0] - 0.5).ceil().max(0.0) as u32;
+            let end = (span[1] - 0.5).floor().min(image.width() as f32 - 1.0);
+
+            if end < 0.0 {
+                continue;
+            }
+
+            for x in start..=end as u32 {
+                image.put_pixel(x, y, colour);
+            }
+        }
+    }
+}
+
+/// Corners of the axis aligned rectangle spanned by two opposite corners
+pub fn rectangle(a: glam::Vec2, b: glam::Vec2) -> [glam::Vec2; 4] {
+    [a, glam::vec2(b.x, a.y), b, glam::vec2(a.x, b.y)]
+}
+
+/// Snap `position` to the horizontal or vertical line through `anchor`, whichever is closer
+pub fn constrain_axis(anchor: glam::Vec2, position: glam::Vec2) -> glam::Vec2 {
+    let delta = position - anchor;
+
+    if delta.x.abs() > delta.y.abs() {
+        glam::vec2(position.x, anchor.y)
+    } else {
+        glam::vec2(anchor.x, position.y)
+    }
+}
+
+/// Segments arcs of plan symbols are drawn with, per quarter turn
+const ARC_SEGMENTS: usize = 12;
+
+/// Architectural plan symbols the stamp tool places
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Symbol {
+    Door,
+    DoubleDoor,
+    SlidingDoor,
+    Window,
+    Stairs,
+}
+
+impl Symbol {
+    pub const ALL: [Symbol; 5] = [Symbol::Door, Symbol::DoubleDoor, Symbol::SlidingDoor, Symbol::Window, Symbol::Stairs];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Symbol::Door => "Door",
+            Symbol::DoubleDoor => "Double Door",
+            Symbol::SlidingDoor => "Sliding Door",
+            Symbol::Window => "Window",
+            Symbol::Stairs => "Stairs",
+        }
+    }
+
+    /// Lines of the symbol for an opening one unit wide along x centred on the origin, doors swinging towards -y and
+    /// stairs climbing towards -y
+    fn lines(&self) -> Vec<Vec<glam::Vec2>> {
+        let arc = |centre: glam::Vec2, radius: f32, from: f32, to: f32| -> Vec<glam::Vec2> {
+            (0..=ARC_SEGMENTS)
+                .map(|i| {
+                    let angle = from + (to - from) * i as f32 / ARC_SEGMENTS as f32;
+                    centre + glam::Vec2::from_angle(angle) * radius
+                })
+                .collect()
+        };
+        let closed = |corners: [glam::Vec2; 4]| vec![corners[0], corners[1], corners[2], corners[3], corners[0]];
+        let quarter = std::f32::consts::FRAC_PI_2;
+
+        match self {
+            // Leaf open at right angles to the opening, and the path of its edge
+            Symbol::Door => vec![
+                vec![glam::vec2(-0.5, 0.0), glam::vec2(-0.5, -1.0)],
+                arc(glam::vec2(-0.5, 0.0), 1.0, 0.0, -quarter),
+            ],
+            Symbol::DoubleDoor => vec![
+                vec![glam::vec2(-0.5, 0.0), glam::vec2(-0.5, -0.5)],
+                arc(glam::vec2(-0.5, 0.0), 0.5, 0.0, -quarter),
+                vec![glam::vec2(0.5, 0.0), glam::vec2(0.5, -0.5)],
+                arc(glam::vec2(0.5, 0.0), 0.5, -2.0 * quarter, -quarter),
+            ],
+            // Two panels overlapping in the middle
+            Symbol::SlidingDoor => vec![
+                closed(rectangle(glam::vec2(-0.5, -0.08), glam::vec2(0.08, 0.0))),
+                closed(rectangle(glam::vec2(-0.08, 0.0), glam::vec2(0.5, 0.08))),
+            ],
+            // Frame across the wall with the glazing down the middle
+            Symbol::Window => vec![
+                closed(rectangle(glam::vec2(-0.5, -0.1), glam::vec2(0.5, 0.1))),
+                vec![glam::vec2(-0.5, 0.0), glam::vec2(0.5, 0.0)],
+            ],
+            // Flight twice as long as it's wide, with treads and an arrow up it
+            Symbol::Stairs => {
+                let mut lines = vec![closed(rectangle(glam::vec2(-0.5, -1.0), glam::vec2(0.5, 1.0)))];
+                lines.extend((1..10).map(|i| {
+                    let y = -1.0 + i as f32 * 0.2;
+                    vec![glam::vec2(-0.5, y), glam::vec2(0.5, y)]
+                }));
+                lines.push(vec![glam::vec2(0.0, 0.9), glam::vec2(0.0, -0.9)]);
+                lines.push(vec![glam::vec2(-0.15, -0.7), glam::vec2(0.0, -0.9), glam::vec2(0.15, -0.7)]);
+                lines
+            },
+        }
+    }
+
+    /// Lines of the symbol `size` pixels wide centred on `centre`, turned clockwise by `rotation` degrees
+    pub fn placed(&self, centre: glam::Vec2, size: f32, rotation: f32) -> Vec<Vec<glam::Vec2>> {
+        let turn = glam::Vec2::from_angle(rotation.to_radians());
+
+        self.lines().into_iter()
+            .map(|line| line.into_iter().map(|p| centre + turn.rotate(p * size)).collect())
+            .collect()
+    }
+}
+
+/// Draw a symbol onto a layer, see `Symbol::placed`
+pub fn draw_symbol(image: &mut RgbaImage, symbol: Symbol, centre: glam::Vec2, size: f32, rotation: f32, width: f32, colour: Rgba<u8>) {
+    for line in symbol.placed(centre, size, rotation) {
+        for segment in line.windows(2) {
+            draw_line(image, segment[0], segment[1], width, colour);
+        }
+    }
+}
+
+/// File coordinate grid line across a floor plan image
+#[derive(Clone, Copy, Debug)]
+pub struct GridLine {
+    /// 0, 1 or 2 for lines of constant X, Y or Z
+    pub axis: usize,
+    pub value: f64,
+    /// Ends of the line where it crosses the image edges, in pixels
+    pub from: DVec2,
+    pub to: DVec2,
+}
+
+/// Grid lines every `spacing` file units across an image `size` pixels captured with `transform`, for the axes lying
+/// in the clipping plane. Axes with more than `max_lines` lines across the image are left out.
+pub fn grid_lines(transform: &CaptureTransform, size: (u32, u32), spacing: f64, max_lines: usize) -> Vec<GridLine> {
+    let (width, height) = (size.0 as f64, size.1 as f64);
+    let normal = transform.normal();
+    let mut lines = vec![];
+
+    for axis in (0..3).filter(|&axis| normal[axis].abs() < 0.9) {
+        // Value along the axis is origin + gradient . pixel
+        let origin = transform.origin[axis];
+        let gradient = DVec2::new(transform.pixel_x[axis], transform.pixel_y[axis]);
+
+        let corners = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)].map(|(x, y)| origin + gradient.dot(DVec2::new(x, y)));
+        let lowest = (corners.iter().copied().fold(f64::INFINITY, f64::min) / spacing).ceil() as i64;
+        let highest = (corners.iter().copied().fold(f64::NEG_INFINITY, f64::max) / spacing).floor() as i64;
+
+        if highest < lowest || (highest - lowest) as usize > max_lines {
+            continue;
+        }
+
+        for i in lowest..=highest {
+            let value = i as f64 * spacing;
+            let target = value - origin;
+
+            // Where the line crosses each edge of the image
+            let mut crossings = vec![];
+            if gradient.y != 0.0 {
+                crossings.extend([0.0, width].map(|x| DVec2::new(x, (target - gradient.x * x) / gradient.y)).into_iter().filter(|p| (0.0..=height).contains(&p.y)));
+            }
+            if gradient.x != 0.0 {
+                crossings.extend([0.0, height].map(|y| DVec2::new((target - gradient.y * y) / gradient.x, y)).into_iter().filter(|p| (0.0..=width).contains(&p.x)));
+            }
+
+            let Some(&from) = crossings.first() else {
+                continue;
+            };
+            let to = crossings.iter().copied().max_by(|a, b| a.distance_squared(from).total_cmp(&b.distance_squared(from))).unwrap_or(from);
+
+            lines.push(GridLine { axis, value, from, to });
+        }
+    }
+
+    lines
+}
+
+/// Decimal places to label grid lines `spacing` apart with
+pub fn grid_decimals(spacing: f64) -> usize {
+    if spacing >= 1.0 {
+        0
+    } else {
+        (-spacing.log10()).ceil() as usize
+    }
+}
+
+/// Burn a labelled grid of file coordinate lines (every `spacing` units) into an exported image. Like the grid shown
+/// over the floor plan, axes with more than `MAX_GRID_LINES` lines are left out, and labels that would overlap one
+/// already drawn are skipped.
+pub fn draw_grid(image: &mut RgbaImage, transform: &CaptureTransform, spacing: f64, font: &Font) {
+    let lines = grid_lines(transform, image.dimensions(), spacing, MAX_GRID_LINES);
+
+    for line in &lines {
+        imageproc::drawing::draw_line_segment_mut(image, (line.from.x as f32, line.from.y as f32), (line.to.x as f32, line.to.y as f32), GRID_COLOUR);
+    }
+
+    let decimals = grid_decimals(spacing);
+    let scale = Scale::uniform(LABEL_SIZE);
+    let mut labelled: Vec<imageproc::rect::Rect> = vec![];
+
+    for line in &lines {
+        // Label the end nearest the top left
+        let end = if line.from.x + line.from.y <= line.to.x + line.to.y { line.from } else { line.to };
+        let label = format!("{} {:.*}", ["X", "Y", "Z"][line.axis], decimals, line.value);
+        let (width, height) = imageproc::drawing::text_size(scale, font, &label);
+        let area = imageproc::rect::Rect::at(end.x as i32 + 3, end.y as i32 + 2).of_size(width.max(1) as u32, height.max(1) as u32);
+
+        if labelled.iter().all(|other| other.intersect(area).is_none()) {
+            imageproc::drawing::draw_text_mut(image, LABEL_COLOUR, area.left(), area.top(), scale, font, &label);
+            labelled.push(area);
+        }
+    }
+}
+
+/// Longest round length (1, 2 or 5 times a power of ten) that's no longer than `max`
+pub fn scale_bar_length(max: f64) -> f64 {
+    let power = 10_f64.powf(max.log10().floor());
+
+    [5.0, 2.0, 1.0].into_iter()
+        .map(|step| step * power)
+        .find(|&length| length <= max)
+        .unwrap_or(power)
+}
+
+/// Length in file units (assumed to be metres), in mm, cm, m or km
+pub fn format_length(metres: f64) -> String {
+    let (value, unit) = match metres.abs() {
+        length if length >= 1000.0 => (metres / 1000.0, "km"),
+        length if length >= 1.0 => (metres, "m"),
+        length if length >= 0.01 => (metres * 100.0, "cm"),
+        _ => (metres * 1000.0, "mm"),
+    };
+
+    // Up to three significant figures, without trailing zeros
+    let decimals = (2 - value.abs().log10().floor().max(0.0) as i32).max(0) as usize;
+    let text = format!("{:.*}", decimals, value);
+    let text = if text.contains('.') { text.trim_end_matches('0').trim_end_matches('.') } else { &text };
+
+    format!("{} {}", text, unit)
+}
+
+/// Scale bar in the bottom left corner, about a fifth of the image wide, labelled with its length and the size of a pixel
+pub fn draw_scale_bar(image: &mut RgbaImage, transform: &CaptureTransform, font: &Font) {
+    let metres_per_pixel = transform.pixel_x.length();
+
+    if !(metres_per_pixel.is_finite() && metres_per_pixel > 0.0) {
+        return;
+    }
+
+    let length = scale_bar_length(metres_per_pixel * image.width() as f64 / 5.0);
+    let bar_width = (length / metres_per_pixel).round() as i32;
+    let label = format!("{}    1 px = {}", format_length(length), format_length(metres_per_pixel));
+
+    let scale = Scale::uniform(LABEL_SIZE);
+    let (label_width, _) = imageproc::drawing::text_size(scale, font, &label);
+
+    // Box behind the bar and label so it reads over the cutaway
+    let (x, y) = (10, image.height() as i32 - 44);
+    let background = imageproc::rect::Rect::at(x - 6, y - 6).of_size((bar_width.max(label_width) + 12) as u32, 40);
+    imageproc::drawing::draw_filled_rect_mut(image, background, Rgba([255, 255, 255, 255]));
+
+    imageproc::drawing::draw_text_mut(image, LABEL_COLOUR, x, y, scale, font, &label);
+
+    // Alternating black and white quarters, outlined
+    let bar_y = y + LABEL_SIZE as i32 + 4;
+    for i in 0..4 {
+        let from = x + bar_width * i / 4;
+        let to = x + bar_width * (i + 1) / 4;
+        let colour = if i % 2 == 0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) };
+
+        imageproc::drawing::draw_filled_rect_mut(image, imageproc::rect::Rect::at(from, bar_y).of_size((to - from).max(1) as u32, 8), colour);
+    }
+    imageproc::drawing::draw_hollow_rect_mut(image, imageproc::rect::Rect::at(x, bar_y).of_size(bar_width.max(1) as u32, 8), Rgba([0, 0, 0, 255]));
+}
+
+/// Direction of `north` (in file X and Y) in image pixels, down being +y. None unless the capture looks straight down.
+pub fn north_in_image(transform: &CaptureTransform, north: DVec2) -> Option<DVec2> {
+    if !transform.is_top_down() {
+        return None;
+    }
+
+    let pixels = glam::DMat2::from_cols(transform.pixel_x.truncate(), transform.pixel_y.truncate());
+
+    if pixels.determinant() == 0.0 {
+        return None;
+    }
+
+    Some((pixels.inverse() * north).normalize_or_zero())
+}
+
+/// North arrow in the top right corner, pointing along `north` (in file X and Y). Nothing is drawn unless the capture
+/// looks straight down.
+pub fn draw_north_arrow(image: &mut RgbaImage, transform: &CaptureTransform, north: DVec2, font: &Font) {
+    let Some(direction) = north_in_image(transform, north) else {
+        return;
+    };
+
+    let direction = direction.as_vec2();
+    let side = direction.perp();
+    let (radius, half_box) = (18.0, 38.0);
+    let centre = glam::vec2(image.width() as f32 - half_box - 10.0, half_box + 10.0);
+
+    let background = imageproc::rect::Rect::at((centre.x - half_box) as i32, (centre.y - half_box) as i32).of_size(half_box as u32 * 2, half_box as u32 * 2);
+    imageproc::drawing::draw_filled_rect_mut(image, background, Rgba([255, 255, 255, 255]));
+
+    // Half filled arrowhead, notched at the tail
+    let tip = centre + direction * radius;
+    let notch = centre - direction * radius * 0.5;
+    let left = centre - direction * radius + side * radius * 0.6;
+    let right = centre - direction * radius - side * radius * 0.6;
+
+    fill_polygon(image, &[tip, left, notch], Rgba([0, 0, 0, 255]));
+    draw_polygon(image, &[tip, left, notch, right], 2.0, Rgba([0, 0, 0, 255]), false);
+
+    let scale = Scale::uniform(LABEL_SIZE);
+    let (width, height) = imageproc::drawing::text_size(scale, font, "N");
+    let label = centre + direction * (radius + 10.0);
+    imageproc::drawing::draw_text_mut(image, LABEL_COLOUR, label.x as i32 - width / 2, label.y as i32 - height / 2, scale, font, "N");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Top down capture of `size` pixels, `metres_per_pixel` apart
+    fn top_down(size: u32, metres_per_pixel: f64) -> (RgbaImage, CaptureTransform) {
+        let transform = CaptureTransform {
+            origin: DVec3::new(0.0, 0.0, 0.0),
+            pixel_x: DVec3::new(metres_per_pixel, 0.0, 0.0),
+            pixel_y: DVec3::new(0.0, -metres_per_pixel, 0.0),
+        };
+
+        (RgbaImage::from_pixel(size, size, Rgba([255, 255, 255, 255])), transform)
+    }
+
+    fn grid_pixels(image: &RgbaImage) -> usize {
+        image.pixels().filter(|pixel| **pixel != Rgba([255, 255, 255, 255])).count()
+    }
+
+    #[test]
+    fn grid_lines_cross_the_image() {
+        let (_, transform) = top_down(100, 0.1);
+        let lines = grid_lines(&transform, (100, 100), 1.0, MAX_GRID_LINES);
+
+        // X from 0 to 10 and Y from -10 to 0, 11 lines each
+        assert_eq!(lines.iter().filter(|line| line.axis == 0).count(), 11);
+        assert_eq!(lines.iter().filter(|line| line.axis == 1).count(), 11);
+        assert!(lines.iter().all(|line| line.from.distance(line.to) > 99.0));
+    }
+
+    #[test]
+    fn draw_grid_leaves_out_dense_axes() {
+        let font = label_font();
+
+        let (mut image, transform) = top_down(1000, 0.1);
+        draw_grid(&mut image, &transform, 1.0, &font);
+        let drawn = grid_pixels(&image);
+        assert!(drawn > 0 && drawn < (image.width() * image.height()) as usize / 2);
+
+        // A line every pixel would cover the whole image
+        let (mut image, transform) = top_down(1000, 0.01);
+        draw_grid(&mut image, &transform, 0.01, &font);
+        assert_eq!(grid_pixels(&image), 0);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{ops::RangeInclusive, time::Instant};
+
+/// Least time between rebuilding the vertex buffers while playing, in seconds. Each rebuild filters every point.
+const REBUILD_INTERVAL: f32 = 0.1;
+
+/// Reveals the points of a mobile scan in the order they were acquired, by a window of GPS time moving along the scan
+pub struct Playback {
+    pub enabled: bool,
+    pub playing: bool,
+    /// Latest GPS time shown, in the files' GPS time units (seconds)
+    pub time: f64,
+    /// Length of the window of time shown before `time`, everything since the start of the scan if None
+    pub window: Option<f64>,
+    /// Scan seconds played each second
+    pub speed: f64,
+    /// When the filter was last changed by playing
+    last_rebuild: Instant,
+}
+
+impl Default for Playback {
+    fn default() -> Playback {
+        Playback {
+            enabled: false,
+            playing: false,
+            time: 0.0,
+            window: None,
+            speed: 10.0,
+            last_rebuild: Instant::now(),
+        }
+    }
+}
+
+impl Playback {
+    /// Window of GPS time the filter should show, None when playback is off
+    pub fn filter(&self) -> Option<RangeInclusive<f64>> {
+        self.enabled.then(|| self.window.map_or(f64::NEG_INFINITY, |window| self.time - window)..=self.time)
+    }
+
+    /// Move the time on by `frame_length` seconds while playing, stopping at the end of `span`. Returns whether the
+    /// filter should be updated, at most every `REBUILD_INTERVAL`.
+    pub fn advance(&mut self, span: (f64, f64), frame_length: f32) -> bool {
+        if !(self.enabled && self.playing) {
+            return false;
+        }
+
+        self.time = (self.time + self.speed * frame_length as f64).min(span.1);
+
+        if self.time >= span.1 {
+            self.playing = false;
+        } else if self.last_rebuild.elapsed().as_secs_f32() < REBUILD_INTERVAL {
+            return false;
+        }
+
+        self.last_rebuild = Instant::now();
+        true
+    }
+}
@@ -0,0 +1,89 @@
+use egui::plot::{Line, Plot, PlotPoints, Points};
+use crate::{locale::Language, section::{Section, SectionPoint}};
+
+/// Points plotted at most, taken evenly from the points in the profile
+const PROFILE_POINTS: usize = 200_000;
+
+/// Height against distance along a line, of the points either side of it, e.g. to check a floor is flat
+pub struct Profile {
+    /// Distance along the line and elevation
+    points: Vec<[f64; 2]>,
+    length: f64,
+    /// Least squares line through the points, elevation at the start and rise per unit along
+    fit: Option<(f64, f64)>,
+    /// Root mean square and largest distance of the points above or below the fitted line
+    deviation: (f64, f64),
+    total: usize,
+    /// Same scale along and up, otherwise small changes in height are stretched to fill the plot
+    pub true_scale: bool,
+}
+
+impl Profile {
+    pub fn new(section: &Section, points: &[SectionPoint]) -> Profile {
+        let fit = fit_line(points);
+        let deviation = fit.map_or((0.0, 0.0), |(start, rise)| {
+            let (sum, max) = points.iter()
+                .map(|point| (point.elevation - (start + rise * point.chainage)).abs())
+                .fold((0.0, 0.0_f64), |(sum, max), distance| (sum + distance * distance, max.max(distance)));
+
+            ((sum / points.len() as f64).sqrt(), max)
+        });
+
+        let stride = (points.len() / PROFILE_POINTS).max(1);
+
+        Profile {
+            points: points.iter().step_by(stride).map(|point| [point.chainage, point.elevation]).collect(),
+            length: section.length(),
+            fit,
+            deviation,
+            total: points.len(),
+            true_scale: false,
+        }
+    }
+
+    /// Window with the plot and how far the points stray from a straight line. Closed by clearing `open`.
+    pub fn show(&mut self, ctx: &egui::Context, locale: &Language, open: &mut bool) {
+        egui::Window::new(locale.tr("Profile")).open(open).default_size([640.0, 360.0]).resizable(true).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(locale.format("{} m long, {} points", &[&format!("{:.2}", self.length), &self.total]));
+                ui.checkbox(&mut self.true_scale, locale.tr("True Scale"));
+            });
+
+            if let Some((_, rise)) = self.fit {
+                let (rms, max) = self.deviation;
+                ui.label(locale.format("Slope {}%, deviation from a straight line {} mm RMS, {} mm at most", &[&format!("{:.2}", rise * 100.0), &format!("{:.1}", rms * 1000.0), &format!("{:.1}", max * 1000.0)]))
+                    .on_hover_text(locale.tr("Least squares line through every point in the profile"));
+            }
+
+            let mut plot = Plot::new("profile").x_axis_formatter(|x, _| format!("{:.2} m", x)).y_axis_formatter(|y, _| format!("{:.2}", y));
+            if self.true_scale {
+                plot = plot.data_aspect(1.0);
+            }
+
+            plot.show(ui, |plot_ui| {
+                plot_ui.points(Points::new(PlotPoints::from(self.points.clone())).color(egui::Color32::from_rgb(0, 130, 200)).radius(1.0).name(locale.tr("Points")));
+
+                if let Some((start, rise)) = self.fit {
+                    let line = vec![[0.0, start], [self.length, start + rise * self.length]];
+                    plot_ui.line(Line::new(PlotPoints::from(line)).color(egui::Color32::from_rgb(230, 25, 75)).name(locale.tr("Best Fit")));
+                }
+            });
+        });
+    }
+}
+
+/// Least squares elevation against chainage, None with fewer than two distinct chainages
+fn fit_line(points: &[SectionPoint]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let (sx, sz) = points.iter().fold((0.0, 0.0), |(sx, sz), point| (sx + point.chainage, sz + point.elevation));
+    let (mx, mz) = (sx / n, sz / n);
+    let (sxx, sxz) = points.iter().fold((0.0, 0.0), |(sxx, sxz), point| {
+        let dx = point.chainage - mx;
+        (sxx + dx * dx, sxz + dx * (point.elevation - mz))
+    });
+
+    (sxx > 0.0).then(|| {
+        let rise = sxz / sxx;
+        (mz - rise * mx, rise)
+    })
+}
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, fs::File, io::BufWriter, path::Path};
+
+use glam::{DVec2, DVec3};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use tiff::{encoder::{colortype::Gray32Float, TiffEncoder}, tags::Tag};
+
+use crate::georef::Georeference;
+
+/// Cells in a side of a grid at most. Larger cells are used for larger areas.
+const MAX_GRID_SIZE: usize = 4096;
+/// Written for cells without points in GeoTIFFs
+const NO_DATA: f32 = -9999.0;
+/// Sun the hillshade is lit from, degrees clockwise from north and above the horizon
+const SUN_AZIMUTH: f64 = 315.0;
+const SUN_ALTITUDE: f64 = 45.0;
+
+/// GeoTIFF keys
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+
+/// Which elevation of the points in a cell it takes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Statistic {
+    /// Lowest, a terrain model (DEM) when only ground points are shown
+    Min,
+    /// Highest, the surface model (DSM) with roofs and canopy
+    Max,
+    Mean,
+}
+
+impl Statistic {
+    pub const ALL: [Statistic; 3] = [Statistic::Min, Statistic::Max, Statistic::Mean];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Statistic::Min => "Lowest (DEM)",
+            Statistic::Max => "Highest (DSM)",
+            Statistic::Mean => "Mean",
+        }
+    }
+}
+
+/// Elevation of the points in each cell of a horizontal grid. Cells without points are NaN.
+pub struct ElevationGrid {
+    /// File X and Y of the centre of the first cell, the south west corner. Rows run north.
+    pub origin: DVec2,
+    pub cell_size: f64,
+    pub width: usize,
+    pub height: usize,
+    elevations: Vec<f64>,
+}
+
+impl ElevationGrid {
+    /// Grid `points` (file coordinates, e.g. from `loader::positions`), at least `cell_size` wide. Filter to ground points
+    /// first for terrain.
+    pub fn new(points: &[DVec3], cell_size: f64, statistic: Statistic) -> Option<ElevationGrid> {
+        let (min, max) = points.iter()
+            .fold((DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)), |(min, max), point| {
+                let p = glam::dvec2(point.x, point.y);
+                (min.min(p), max.max(p))
+            });
+
+        if min.x > max.x {
+            return None;
+        }
+
+        let cell_size = cell_size.max((max - min).max_element() / MAX_GRID_SIZE as f64);
+        let width = ((max.x - min.x) / cell_size) as usize + 1;
+        let height = ((max.y - min.y) / cell_size) as usize + 1;
+        let cell = |point: &DVec3| {
+            let x = ((point.x - min.x) / cell_size) as usize;
+            let y = ((point.y - min.y) / cell_size) as usize;
+            x.min(width - 1) + y.min(height - 1) * width
+        };
+
+        // (lowest, highest, sum, count)
+        let merge = |a: (f64, f64, f64, u32), b: (f64, f64, f64, u32)| (a.0.min(b.0), a.1.max(b.1), a.2 + b.2, a.3 + b.3);
+        let empty = (f64::INFINITY, f64::NEG_INFINITY, 0.0, 0);
+
+        let cells = points.par_iter()
+            .fold(HashMap::new, |mut cells: HashMap<usize, (f64, f64, f64, u32)>, point| {
+                let entry = cells.entry(cell(point)).or_insert(empty);
+                *entry = merge(*entry, (point.z, point.z, point.z, 1));
+                cells
+            })
+            .reduce(HashMap::new, |mut a, b| {
+                for (cell, value) in b {
+                    let entry = a.entry(cell).or_insert(empty);
+                    *entry = merge(*entry, value);
+                }
+                a
+            });
+
+        let mut elevations = vec![f64::NAN; width * height];
+        for (cell, (lowest, highest, sum, count)) in cells {
+            elevations[cell] = match statistic {
+                Statistic::Min => lowest,
+                Statistic::Max => highest,
+                Statistic::Mean => sum / count as f64,
+            };
+        }
+
+        Some(ElevationGrid {
+            origin: min + cell_size / 2.0,
+            cell_size,
+            width,
+            height,
+            elevations,
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f64 {
+        self.elevations[x + y * self.width]
+    }
+
+    /// Lowest and highest cell
+    pub fn range(&self) -> (f64, f64) {
+        self.elevations.iter()
+            .filter(|z| z.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), z| (min.min(*z), max.max(*z)))
+    }
+
+    /// Corners of the grid's outer edges, south west and north east
+    pub fn bounds(&self) -> (DVec2, DVec2) {
+        let half = DVec2::splat(self.cell_size / 2.0);
+        let size = glam::dvec2(self.width as f64, self.height as f64) * self.cell_size;
+
+        (self.origin - half, self.origin - half + size)
+    }
+
+    /// Grey shaded relief lit from the north west, north up, transparent where there are no points
+    pub fn hillshade(&self) -> RgbaImage {
+        let (azimuth, altitude) = (SUN_AZIMUTH.to_radians(), SUN_ALTITUDE.to_radians());
+        // Towards the sun, east, north and up
+        let sun = glam::dvec3(azimuth.sin() * altitude.cos(), azimuth.cos() * altitude.cos(), altitude.sin());
+
+        // Neighbours off the edge or without points fall back to the cell itself, flat that way
+        let at = |x: usize, y: usize, dx: isize, dy: isize| {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            let inside = nx >= 0 && ny >= 0 && (nx as usize) < self.width && (ny as usize) < self.height;
+            let z = if inside { self.get(nx as usize, ny as usize) } else { f64::NAN };
+            if z.is_nan() { self.get(x, y) } else { z }
+        };
+
+        RgbaImage::from_fn(self.width as u32, self.height as u32, |px, py| {
+            let (x, y) = (px as usize, self.height - 1 - py as usize);
+
+            if self.get(x, y).is_nan() {
+                return Rgba([0, 0, 0, 0]);
+            }
+
+            let dz_dx = (at(x, y, 1, 0) - at(x, y, -1, 0)) / (2.0 * self.cell_size);
+            let dz_dy = (at(x, y, 0, 1) - at(x, y, 0, -1)) / (2.0 * self.cell_size);
+            let normal = glam::dvec3(-dz_dx, -dz_dy, 1.0).normalize();
+            let shade = (normal.dot(sun).max(0.0) * 255.0) as u8;
+
+            Rgba([shade, shade, shade, 255])
+        })
+    }
+
+    /// Single band 32-bit float GeoTIFF, north up, with the EPSG code of the coordinate system if known. A `.prj` is
+    /// written beside it for coordinate systems only given as WKT.
+    pub fn write_geotiff(&self, path: &Path, georeference: &Georeference) -> Result<(), String> {
+        let error = |err: tiff::TiffError| format!("Failed to write {}: {}", path.display(), err);
+
+        let file = File::create(path).map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+        let mut encoder = TiffEncoder::new(BufWriter::new(file)).map_err(error)?;
+        let mut image = encoder.new_image::<Gray32Float>(self.width as u32, self.height as u32).map_err(error)?;
+
+        let (min, max) = self.bounds();
+        let mut keys = vec![1, 1, 0, 2, GT_MODEL_TYPE_GEO_KEY, 0, 1, MODEL_TYPE_PROJECTED, GT_RASTER_TYPE_GEO_KEY, 0, 1, RASTER_PIXEL_IS_AREA];
+        if let Some(epsg) = georeference.epsg {
+            keys[3] += 1;
+            keys.extend([PROJECTED_CS_TYPE_GEO_KEY, 0, 1, epsg]);
+        }
+
+        let directory = image.encoder();
+        directory.write_tag(Tag::ModelPixelScaleTag, &[self.cell_size, self.cell_size, 0.0][..]).map_err(error)?;
+        directory.write_tag(Tag::ModelTiepointTag, &[0.0, 0.0, 0.0, min.x, max.y, 0.0][..]).map_err(error)?;
+        directory.write_tag(Tag::GeoKeyDirectoryTag, &keys[..]).map_err(error)?;
+        directory.write_tag(Tag::GdalNodata, &*NO_DATA.to_string()).map_err(error)?;
+
+        // Rows from the north
+        let data: Vec<f32> = (0..self.height).rev()
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.get(x, y))
+            .map(|z| if z.is_nan() { NO_DATA } else { z as f32 })
+            .collect();
+        image.write_data(&data).map_err(error)?;
+
+        if georeference.epsg.is_none() {
+            georeference.write_prj(path)?;
+        }
+
+        Ok(())
+    }
+}
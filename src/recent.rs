@@ -0,0 +1,115 @@
+use std::{collections::hash_map::DefaultHasher, fs, hash::{Hash, Hasher}, path::{Path, PathBuf}};
+
+use glam::DVec3;
+use image::{Rgba, RgbaImage};
+
+/// Number of recently opened files remembered
+const MAX_RECENT: usize = 12;
+/// Width and height of thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 128;
+/// Points drawn into a thumbnail, taken evenly from the loaded points
+pub const THUMBNAIL_POINTS: usize = 250_000;
+
+fn config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("point-cloud-cutaway").join("recent.txt"))
+}
+
+/// Recently opened files, most recent first
+pub fn load() -> Vec<String> {
+    config_file()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map_or(vec![], |text| text.lines().filter(|line| !line.is_empty()).map(str::to_owned).collect())
+}
+
+/// Move `path` to the front of the list and save it
+pub fn add(recent: &mut Vec<String>, path: &str) {
+    recent.retain(|other| other != path);
+    recent.insert(0, path.to_owned());
+    recent.truncate(MAX_RECENT);
+
+    if let Some(file) = config_file() {
+        let result = file.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&file, recent.join("\n")));
+
+        if let Err(err) = result {
+            eprintln!("Failed to save recent files to {}: {}", file.display(), err);
+        }
+    }
+}
+
+/// Cached thumbnail location, named after the file's path, size and modification time so edited files get new ones
+fn thumbnail_file(path: &str) -> Option<PathBuf> {
+    let metadata = fs::metadata(path).ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    metadata.len().hash(&mut hasher);
+    metadata.modified().ok().hash(&mut hasher);
+
+    dirs::cache_dir().map(|dir| dir.join("point-cloud-cutaway").join("thumbnails").join(format!("{:016x}.png", hasher.finish())))
+}
+
+pub fn load_thumbnail(path: &str) -> Option<RgbaImage> {
+    image::open(thumbnail_file(path)?).ok().map(|image| image.into_rgba8())
+}
+
+pub fn has_thumbnail(path: &str) -> bool {
+    thumbnail_file(path).is_some_and(|file| file.exists())
+}
+
+pub fn save_thumbnail(path: &str, thumbnail: &RgbaImage) {
+    let file = match thumbnail_file(path) {
+        Some(file) => file,
+        None => return,
+    };
+
+    let result = file.parent().map_or(Ok(()), fs::create_dir_all).map_err(image::ImageError::IoError)
+        .and_then(|_| thumbnail.save(&file));
+
+    if let Err(err) = result {
+        eprintln!("Failed to save thumbnail to {}: {}", file.display(), err);
+    }
+}
+
+/// Top-down view of the points, fitted to a square `size` pixels wide with a transparent background. The highest
+/// point in each pixel is drawn, in its own colour or shaded by elevation if it has none.
+pub fn render_thumbnail<'a>(points: impl Iterator<Item = &'a las::Point>, (min, max): (DVec3, DVec3), size: u32) -> RgbaImage {
+    let mut image = RgbaImage::new(size, size);
+    let mut heights = vec![f64::NEG_INFINITY; (size * size) as usize];
+
+    for point in points {
+        let p = thumbnail_pixel(glam::dvec2(point.x, point.y), (min, max), size);
+        let (x, y) = ((p.x as u32).min(size - 1), (p.y as u32).min(size - 1));
+        let index = (y * size + x) as usize;
+
+        if point.z <= heights[index] {
+            continue;
+        }
+
+        heights[index] = point.z;
+
+        let colour = match point.color {
+            Some(colour) => [(colour.red / 256) as u8, (colour.green / 256) as u8, (colour.blue / 256) as u8],
+            None => [(((point.z - min.z) / (max.z - min.z).max(f64::EPSILON)) * 200.0 + 55.0) as u8; 3],
+        };
+
+        image.put_pixel(x, y, Rgba([colour[0], colour[1], colour[2], 255]));
+    }
+
+    image
+}
+
+/// Where a file position lands in a thumbnail of `bounds` `size` pixels wide, north up
+pub fn thumbnail_pixel(position: glam::DVec2, (min, max): (DVec3, DVec3), size: u32) -> glam::DVec2 {
+    let extent = (max - min).truncate().max_element().max(f64::EPSILON);
+    // Centre the footprint
+    let offset = (glam::DVec2::splat(extent) - (max - min).truncate()) / 2.0;
+
+    let p = (position - min.truncate() + offset) / extent * size as f64;
+    glam::dvec2(p.x, size as f64 - p.y)
+}
+
+/// File name for display
+pub fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map_or(path.to_owned(), |name| name.to_string_lossy().into_owned())
+}
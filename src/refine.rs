@@ -0,0 +1,90 @@
+use std::ops::Range;
+
+use glium::{backend::Facade, framebuffer::SimpleFrameBuffer, texture::{DepthTexture2d, Texture2d}, Surface};
+use crate::render;
+
+/// Points accumulated over several frames while the view holds still, so large clouds stay interactive while moving
+/// and fill in to full density once stopped. Vertex buffers are shuffled, so each part drawn is an even sample.
+pub struct Refinement<K> {
+    colour: Texture2d,
+    depth: DepthTexture2d,
+    /// View the accumulated points were drawn with
+    view: Option<K>,
+    /// Fraction of every vertex buffer drawn so far
+    drawn: f32,
+}
+
+impl<K: PartialEq> Refinement<K> {
+    pub fn new<F: Facade>(facade: &F, width: u32, height: u32) -> Result<Refinement<K>, String> {
+        let colour = render::capture_texture(facade, width, height);
+        let depth = DepthTexture2d::empty_with_format(facade, glium::texture::DepthFormat::F32, glium::texture::MipmapsOption::NoMipmap, width, height)
+            .map_err(|err| format!("Failed to create refinement depth texture: {}", err))?;
+
+        Ok(Refinement {
+            colour,
+            depth,
+            view: None,
+            drawn: 0.0,
+        })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.colour.dimensions()
+    }
+
+    /// Fraction of the points accumulated
+    pub fn progress(&self) -> f32 {
+        self.drawn
+    }
+
+    /// Part of every vertex buffer to draw this frame as a fraction of its length, and the framebuffer to draw it into.
+    /// Starts again when `view` differs from the last frame's. None once every point has been drawn.
+    pub fn next<F: Facade>(&mut self, facade: &F, view: K, step: f32) -> Result<Option<(Range<f32>, SimpleFrameBuffer<'_>)>, String> {
+        let restart = self.view.as_ref() != Some(&view);
+
+        if restart {
+            self.view = Some(view);
+            self.drawn = 0.0;
+        } else if self.drawn >= 1.0 {
+            return Ok(None);
+        }
+
+        let mut framebuffer = SimpleFrameBuffer::with_depth_buffer(facade, &self.colour, &self.depth)
+            .map_err(|err| format!("Failed to create refinement buffer: {}", err))?;
+
+        if restart {
+            framebuffer.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+        }
+
+        let range = self.drawn..(self.drawn + step).min(1.0);
+        self.drawn = range.end;
+
+        Ok(Some((range, framebuffer)))
+    }
+
+    /// Draw the accumulated points over `target`, with their depths so later geometry is hidden behind them
+    pub fn composite<S: Surface>(&self, target: &mut S, program: &glium::Program, quad: &glium::VertexBuffer<render::Vertex>) -> Result<(), glium::DrawError> {
+        let uniforms = uniform! {
+            u_colour: self.colour.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest).minify_filter(glium::uniforms::MinifySamplerFilter::Nearest),
+            u_depth: self.depth.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest).minify_filter(glium::uniforms::MinifySamplerFilter::Nearest),
+        };
+
+        let draw_params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLessOrEqual,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        target.draw(quad, glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList), program, &uniforms, &draw_params)
+    }
+}
+
+/// Indices `range` (fractions of its length) covers in a buffer of `len` vertices
+pub fn vertex_range(range: &Range<f32>, len: usize) -> Range<usize> {
+    let index = |fraction: f32| ((len as f64 * fraction as f64).round() as usize).min(len);
+
+    index(range.start)..index(range.end)
+}
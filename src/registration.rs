@@ -0,0 +1,254 @@
+use glam::{DMat4, DQuat, DVec2, DVec3};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{jobs::JobContext, loader::PointBatch};
+
+/// Points sampled from the cloud being moved, and from the reference it's aligned to
+const MOVING_SAMPLES: usize = 50_000;
+const REFERENCE_SAMPLES: usize = 250_000;
+/// Iterations of the coarse candidates, and of the fine registration of the best of them
+const COARSE_ITERATIONS: usize = 10;
+const MAX_ITERATIONS: usize = 60;
+/// Registration stops once the error improves by less than this fraction in an iteration
+const CONVERGENCE: f64 = 1e-5;
+/// Pairs further apart than this many times the median distance are left out, as parts only one cloud covers
+const OUTLIER_FACTOR: f64 = 3.0;
+
+/// Transform aligning one cloud to another
+#[derive(Clone, Copy, Debug)]
+pub struct Registration {
+    /// Moves the aligned cloud's file coordinates onto the reference
+    pub matrix: DMat4,
+    /// Root mean square distance between matched points, in file units
+    pub rmse: f64,
+    pub iterations: usize,
+    /// Share of the sampled points matched to the reference, roughly how much the clouds overlap
+    pub overlap: f64,
+}
+
+/// Samples of the points of file `reference` and file `moving` to `register`, taken up front so registering can run on a
+/// worker
+pub fn samples(batches: &[PointBatch], reference: usize, moving: usize) -> (Vec<DVec3>, Vec<DVec3>) {
+    (sample(batches, reference, REFERENCE_SAMPLES), sample(batches, moving, MOVING_SAMPLES))
+}
+
+/// Align the `source` points to the `target` points by point-to-point ICP. With `coarse`, also tries turning the source
+/// about Z to line up its main horizontal direction with the target's, for clouds that aren't already roughly aligned.
+/// Scans are assumed to be levelled. Fails once `context` is cancelled.
+pub fn register(target: Vec<DVec3>, source: Vec<DVec3>, coarse: bool, context: &JobContext) -> Result<Registration, String> {
+    if target.len() < 3 || source.len() < 3 {
+        return Err("Both files need points to register".to_owned());
+    }
+
+    // Relative to the reference's centroid, so georeferenced coordinates don't lose precision
+    let origin = centroid(&target);
+    let target: Vec<[f64; 3]> = target.into_iter().map(|p| (p - origin).to_array()).collect();
+    let source: Vec<DVec3> = source.into_iter().map(|p| p - origin).collect();
+    let tree = kd_tree::KdTree::build_by_ordered_float(target);
+
+    // Where the cloud is now, and with coarse alignment its centroid moved onto the reference's and turned about it
+    let mut candidates = vec![DMat4::IDENTITY];
+    if coarse {
+        let (source_centre, target_centre) = (centroid(&source), centroid_of(tree.items()));
+        let turn = heading(tree.items().iter().map(|p| DVec3::from(*p)), target_centre) - heading(source.iter().copied(), source_centre);
+
+        candidates.extend([0.0, turn, turn + std::f64::consts::PI].map(|angle| {
+            DMat4::from_translation(target_centre) * DMat4::from_rotation_z(angle) * DMat4::from_translation(-source_centre)
+        }));
+    }
+
+    let mut best: Option<Registration> = None;
+    for start in candidates {
+        let result = icp(&tree, &source, start, COARSE_ITERATIONS, context)?;
+        if best.is_none_or(|best| result.rmse < best.rmse) {
+            best = Some(result);
+        }
+    }
+    let best = best.expect("There is always a candidate");
+
+    let mut result = icp(&tree, &source, best.matrix, MAX_ITERATIONS, context)?;
+    result.matrix = DMat4::from_translation(origin) * result.matrix * DMat4::from_translation(-origin);
+    result.iterations += if coarse { COARSE_ITERATIONS } else { 0 };
+
+    Ok(result)
+}
+
+/// Refine `matrix` by iterative closest points
+fn icp(tree: &kd_tree::KdTree<[f64; 3]>, source: &[DVec3], mut matrix: DMat4, max_iterations: usize, context: &JobContext) -> Result<Registration, String> {
+    let mut previous = f64::INFINITY;
+    let mut result = Registration {
+        matrix,
+        rmse: f64::INFINITY,
+        iterations: 0,
+        overlap: 0.0,
+    };
+
+    for iteration in 1..=max_iterations {
+        if context.is_cancelled() {
+            return Err("Registration was cancelled".to_owned());
+        }
+
+        let mut pairs: Vec<(DVec3, DVec3, f64)> = source.par_iter().filter_map(|p| {
+            let moved = matrix.transform_point3(*p);
+            tree.nearest(&moved.to_array()).map(|nearest| (moved, DVec3::from(*nearest.item), nearest.squared_distance))
+        }).collect();
+
+        if pairs.len() < 3 {
+            break;
+        }
+
+        let mut distances: Vec<f64> = pairs.iter().map(|(_, _, distance)| *distance).collect();
+        let middle = distances.len() / 2;
+        let median = *distances.select_nth_unstable_by(middle, f64::total_cmp).1;
+        let limit = median * OUTLIER_FACTOR * OUTLIER_FACTOR;
+        pairs.retain(|(_, _, distance)| *distance <= limit);
+
+        let rmse = (pairs.iter().map(|(_, _, distance)| distance).sum::<f64>() / pairs.len() as f64).sqrt();
+        result = Registration {
+            matrix,
+            rmse,
+            iterations: iteration,
+            overlap: pairs.len() as f64 / source.len() as f64,
+        };
+
+        if previous.is_finite() && previous - rmse <= previous * CONVERGENCE {
+            break;
+        }
+        previous = rmse;
+
+        matrix = best_fit(&pairs) * matrix;
+    }
+
+    Ok(result)
+}
+
+/// Rigid transform best moving the first of each pair onto the second (Horn's quaternion method)
+fn best_fit(pairs: &[(DVec3, DVec3, f64)]) -> DMat4 {
+    let from = centroid_of_iter(pairs.iter().map(|(a, _, _)| *a), pairs.len());
+    let to = centroid_of_iter(pairs.iter().map(|(_, b, _)| *b), pairs.len());
+
+    let mut s = [[0.0; 3]; 3];
+    for (a, b, _) in pairs {
+        let (a, b) = ((*a - from).to_array(), (*b - to).to_array());
+        for (i, row) in s.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value += a[i] * b[j];
+            }
+        }
+    }
+
+    let [[sxx, sxy, sxz], [syx, syy, syz], [szx, szy, szz]] = s;
+    let n = [
+        [sxx + syy + szz, syz - szy, szx - sxz, sxy - syx],
+        [syz - szy, sxx - syy - szz, sxy + syx, szx + sxz],
+        [szx - sxz, sxy + syx, -sxx + syy - szz, syz + szy],
+        [sxy - syx, szx + sxz, syz + szy, -sxx - syy + szz],
+    ];
+
+    // Largest eigenvector by power iteration, shifted so every eigenvalue is positive
+    let shift = n.iter().flatten().map(|v| v * v).sum::<f64>().sqrt();
+    let mut q = [1.0, 1e-3, 1e-3, 1e-3];
+    for _ in 0..100 {
+        let next: [f64; 4] = std::array::from_fn(|i| (0..4).map(|j| n[i][j] * q[j]).sum::<f64>() + shift * q[i]);
+        let length = next.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if length == 0.0 {
+            break;
+        }
+        q = next.map(|v| v / length);
+    }
+
+    let rotation = DQuat::from_xyzw(q[1], q[2], q[3], q[0]).normalize();
+
+    DMat4::from_translation(to) * DMat4::from_quat(rotation) * DMat4::from_translation(-from)
+}
+
+/// Evenly spaced points of one file, at most `count`
+fn sample(batches: &[PointBatch], file: usize, count: usize) -> Vec<DVec3> {
+    let total: usize = batches.iter().filter(|batch| batch.file == file).map(|batch| batch.points.len()).sum();
+    let stride = (total / count).max(1);
+
+    batches.iter().filter(|batch| batch.file == file)
+        .flat_map(|batch| batch.points.iter())
+        .step_by(stride)
+        .map(|point| glam::dvec3(point.x, point.y, point.z))
+        .collect()
+}
+
+fn centroid(points: &[DVec3]) -> DVec3 {
+    centroid_of_iter(points.iter().copied(), points.len())
+}
+
+fn centroid_of(points: &[[f64; 3]]) -> DVec3 {
+    centroid_of_iter(points.iter().map(|p| DVec3::from(*p)), points.len())
+}
+
+fn centroid_of_iter(points: impl Iterator<Item = DVec3>, count: usize) -> DVec3 {
+    points.fold(DVec3::ZERO, |sum, p| sum + p) / count.max(1) as f64
+}
+
+/// Angle of the main horizontal direction of the points, from the X axis
+fn heading(points: impl Iterator<Item = DVec3>, centre: DVec3) -> f64 {
+    let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+
+    for p in points {
+        let d = (p - centre).truncate();
+        xx += d.x * d.x;
+        xy += d.x * d.y;
+        yy += d.y * d.y;
+    }
+
+    let direction = DVec2::new(xx - yy, 2.0 * xy);
+    direction.y.atan2(direction.x) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points scattered through an uneven box, from a fixed seed so the tests are repeatable
+    fn scattered_points(count: usize) -> Vec<DVec3> {
+        let mut state: u64 = 12345;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1_u64 << 53) as f64
+        };
+
+        (0..count).map(|_| DVec3::new(next() * 8.0, next() * 5.0, next() * 3.0 + next() * next())).collect()
+    }
+
+    fn known_transform() -> DMat4 {
+        DMat4::from_translation(DVec3::new(0.3, -0.2, 0.1)) * DMat4::from_rotation_z(4_f64.to_radians()) * DMat4::from_rotation_x(1_f64.to_radians())
+    }
+
+    fn assert_matrix_eq(a: DMat4, b: DMat4, tolerance: f64) {
+        assert!(a.abs_diff_eq(b, tolerance), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn best_fit_matches_exact_pairs() {
+        let transform = known_transform();
+        let pairs: Vec<(DVec3, DVec3, f64)> = scattered_points(100).into_iter().map(|p| (p, transform.transform_point3(p), 0.0)).collect();
+
+        assert_matrix_eq(best_fit(&pairs), transform, 1e-9);
+    }
+
+    #[test]
+    fn register_recovers_a_known_transform() {
+        let target = scattered_points(5000);
+        let transform = known_transform();
+        let source: Vec<DVec3> = target.iter().map(|p| transform.transform_point3(*p)).collect();
+
+        let result = register(target, source, false, &JobContext::detached()).expect("Registration failed");
+
+        assert!(result.rmse < 1e-6, "RMSE {}", result.rmse);
+        assert!(result.overlap > 0.9, "Overlap {}", result.overlap);
+        assert_matrix_eq(result.matrix * transform, DMat4::IDENTITY, 1e-6);
+    }
+
+    #[test]
+    fn register_needs_points() {
+        let points = scattered_points(2);
+
+        assert!(register(points.clone(), points, false, &JobContext::detached()).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use glium::{backend::Facade, program::ProgramCreationInput, texture::Texture2d, CapabilitiesSource, Program};
+use image::RgbaImage;
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::{colour, contour::Contour, filter::PointFilter, loader::PointBatch, random::XorShift};
+
+/// Batches are shuffled the same way every time they're built
+const VERTEX_SHUFFLE_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+/// Sky blue
+const DEFAULT_BACKGROUND: [u8; 3] = [135, 206, 235];
+/// Background of cutaway captures, neutral so exported plans aren't tinted by the 3D view's background
+pub const CAPTURE_CLEAR_COLOUR: (f32, f32, f32, f32) = (1.0, 1.0, 1.0, 1.0);
+
+/// Most lines drawn across the ground grid in each direction, the spacing is widened tenfold until it fits
+const MAX_GRID_LINES: f64 = 1000.0;
+const GRID_COLOUR: [u8; 3] = [96, 96, 96];
+/// Every tenth line
+const MAJOR_GRID_COLOUR: [u8; 3] = [32, 32, 32];
+const CONTOUR_COLOUR: [u8; 3] = [205, 133, 63];
+/// Index contours
+const MAJOR_CONTOUR_COLOUR: [u8; 3] = [139, 69, 19];
+
+/// Distant points fading into the background, for a sense of depth
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fog {
+    Off,
+    /// Faded from none at `start` to fully at `end`, in file units from the clipping plane
+    Linear { start: f32, end: f32 },
+    /// Faded by `1 - e^(-density * distance)`
+    Exponential { density: f32 },
+}
+
+impl Fog {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fog::Off => "Off",
+            Fog::Linear { .. } => "Linear",
+            Fog::Exponential { .. } => "Exponential",
+        }
+    }
+
+    /// `u_fog` and `u_fog_parameters` of the point shader
+    pub fn uniforms(&self) -> (i32, [f32; 2]) {
+        match *self {
+            Fog::Off => (0, [0.0; 2]),
+            Fog::Linear { start, end } => (1, [start, end]),
+            Fog::Exponential { density } => (2, [density, 0.0]),
+        }
+    }
+}
+
+/// Background behind the points in the 3D view
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Background {
+    Solid([u8; 3]),
+    /// Blended from the top of the window to the bottom
+    Gradient { top: [u8; 3], bottom: [u8; 3] },
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Solid(DEFAULT_BACKGROUND)
+    }
+}
+
+impl Background {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Background::Solid(_) => "Solid Colour",
+            Background::Gradient { .. } => "Gradient",
+        }
+    }
+
+    /// Colour to clear to, the gradient is drawn over it
+    pub fn clear_colour(&self) -> (f32, f32, f32, f32) {
+        let [r, g, b] = match self {
+            Background::Solid(colour) => *colour,
+            Background::Gradient { bottom, .. } => *bottom,
+        };
+
+        (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
+    }
+
+    /// The gradient's top and bottom colours, None for a solid colour
+    pub fn gradient(&self) -> Option<([u8; 3], [u8; 3])> {
+        match self {
+            Background::Solid(_) => None,
+            Background::Gradient { top, bottom } => Some((*top, *bottom)),
+        }
+    }
+
+    /// Top and bottom colours, the same for a solid colour
+    pub fn colours(&self) -> ([u8; 3], [u8; 3]) {
+        match *self {
+            Background::Solid(colour) => (colour, colour),
+            Background::Gradient { top, bottom } => (top, bottom),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub colour: [u8; 3],
+    /// 0 to 1
+    pub intensity: f32,
+}
+
+implement_vertex!(Vertex, position, colour, intensity/*, size*/);
+
+/// Swaps y and z, so file z (elevation) is up when rendering
+pub const COORDINATE_SYSTEM: glam::Mat4 = glam::Mat4::from_cols(
+    glam::Vec4::new(1.0, 0.0, 0.0, 0.0),
+    glam::Vec4::new(0.0, 0.0, 1.0, 0.0),
+    glam::Vec4::new(0.0, 1.0, 0.0, 0.0),
+    glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
+);
+
+/// Program for drawing points normally, with the default point colours
+pub fn point_program<F: Facade>(facade: &F) -> Program {
+    program(facade, include_str!("shaders/main.vert"), include_str!("shaders/main.frag")).expect("Failed to parse main shader.")
+}
+
+/// Program drawing a background gradient over a fullscreen quad, behind everything else
+pub fn background_program<F: Facade>(facade: &F) -> Program {
+    program(facade, include_str!("shaders/background.vert"), include_str!("shaders/background.frag")).expect("Failed to parse background shader.")
+}
+
+/// Program drawing points accumulated over several frames over the screen, with their depths
+pub fn refine_program<F: Facade>(facade: &F) -> Program {
+    program(facade, include_str!("shaders/refine.vert"), include_str!("shaders/refine.frag")).expect("Failed to parse refinement shader.")
+}
+
+/// Program drawing ground grid lines in their vertex colours
+pub fn grid_program<F: Facade>(facade: &F) -> Program {
+    program(facade, include_str!("shaders/grid.vert"), include_str!("shaders/grid.frag")).expect("Failed to parse grid shader.")
+}
+
+/// Program drawing a raster image flat over the ground, stretched over the fullscreen quad
+pub fn raster_program<F: Facade>(facade: &F) -> Program {
+    program(facade, include_str!("shaders/raster.vert"), include_str!("shaders/raster.frag")).expect("Failed to parse raster shader.")
+}
+
+/// Program drawing only the points on the clipping plane, one pixel each
+pub fn slice_program<F: Facade>(facade: &F) -> Program {
+    program(facade, include_str!("shaders/single_pixel.vert"), include_str!("shaders/single_pixel.frag")).expect("Failed to parse slice shader.")
+}
+
+pub fn program<F: Facade>(facade: &F, vertex_shader: &str, fragment_shader: &str) -> Result<Program, glium::program::ProgramCreationError> {
+    Program::new(facade, ProgramCreationInput::SourceCode {
+        vertex_shader,
+        fragment_shader,
+        uses_point_size: true,
+        tessellation_control_shader: None,
+        tessellation_evaluation_shader: None,
+        geometry_shader: None,
+        transform_feedback_varyings: None,
+        outputs_srgb: true,
+    })
+}
+
+/// Empty RGBA texture to render captures into
+pub fn capture_texture<F: Facade>(facade: &F, width: u32, height: u32) -> Texture2d {
+    Texture2d::empty_with_format(facade,
+        glium::texture::UncompressedFloatFormat::U8U8U8U8,
+        glium::texture::MipmapsOption::NoMipmap, width, height).expect("Failed to create capture texture")
+}
+
+/// Supersampling factor, at most `factor`, that keeps a `width` by `height` capture within the largest texture the GL
+/// allows
+pub fn supersampling<F: Facade>(facade: &F, width: u32, height: u32, factor: u32) -> u32 {
+    let max_size = facade.get_context().get_capabilities().max_texture_size.max(1) as u32;
+
+    factor.min(max_size / width.max(height).max(1)).max(1)
+}
+
+/// Average each `factor` by `factor` block of a supersampled capture into one pixel
+pub fn downsample(image: &RgbaImage, factor: u32) -> RgbaImage {
+    if factor <= 1 {
+        return image.clone();
+    }
+
+    let samples = factor * factor;
+
+    RgbaImage::from_fn(image.width() / factor, image.height() / factor, |x, y| {
+        let mut sum = [0_u32; 4];
+
+        for dy in 0..factor {
+            for dx in 0..factor {
+                let pixel = image.get_pixel(x * factor + dx, y * factor + dy);
+                for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                    *total += channel as u32;
+                }
+            }
+        }
+
+        image::Rgba(sum.map(|total| ((total + samples / 2) / samples) as u8))
+    })
+}
+
+/// Read a rendered texture back, flipped so the first row is the top of the image
+pub fn read_image(texture: &Texture2d) -> RgbaImage {
+    let raw: glium::texture::RawImage2d<u8> = texture.read();
+    let mut image = RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned()).expect("Failed to parse capture texture");
+    image::imageops::flip_vertical_in_place(&mut image);
+
+    image
+}
+
+/// Vertices of the points in a batch the filter accepts, relative to the batch origin. Shuffled, so any part of the
+/// buffer is an even sample of the batch.
+pub fn batch_vertices(batch: &PointBatch, filter: &PointFilter, colouring: &colour::Colouring) -> Vec<Vertex> {
+    let mut vertices: Vec<Vertex> = batch.points.par_iter().zip(batch.selected.par_iter()).filter(|(point, _)| filter.accepts(point)).map(|(point, selected)| {
+        Vertex {
+            position: [(point.x - batch.origin.x) as f32, (point.y - batch.origin.y) as f32, (point.z - batch.origin.z) as f32],
+            colour: if *selected { colour::SELECTION_COLOUR } else { colouring.colour(point, batch.file) },
+            intensity: point.intensity as f32 / u16::MAX as f32,
+        }
+    }).collect();
+
+    XorShift::new(VERTEX_SHUFFLE_SEED).shuffle(&mut vertices);
+
+    vertices
+}
+
+/// Line list of a horizontal grid at `elevation` covering `min` to `max` (file X and Y), relative to `centre`. Lines
+/// lie on multiples of `spacing` in file coordinates, so they stay on whole metres wherever the files are.
+pub fn ground_grid(min: glam::DVec2, max: glam::DVec2, elevation: f64, spacing: f64, centre: glam::DVec3) -> Vec<Vertex> {
+    let mut spacing = spacing;
+    while ((max - min) / spacing).max_element() > MAX_GRID_LINES {
+        spacing *= 10.0;
+    }
+
+    let first = (min / spacing).floor().as_ivec2();
+    let last = (max / spacing).ceil().as_ivec2();
+    let vertex = |x: f64, y: f64, major: bool| Vertex {
+        position: (glam::dvec3(x, y, elevation) - centre).as_vec3().to_array(),
+        colour: if major { MAJOR_GRID_COLOUR } else { GRID_COLOUR },
+        intensity: 0.0,
+    };
+
+    let (from, to) = (first.as_dvec2() * spacing, last.as_dvec2() * spacing);
+    let mut vertices = vec![];
+
+    for i in first.x..=last.x {
+        let x = i as f64 * spacing;
+        vertices.extend([vertex(x, from.y, i % 10 == 0), vertex(x, to.y, i % 10 == 0)]);
+    }
+    for i in first.y..=last.y {
+        let y = i as f64 * spacing;
+        vertices.extend([vertex(from.x, y, i % 10 == 0), vertex(to.x, y, i % 10 == 0)]);
+    }
+
+    vertices
+}
+
+/// Line list of the contours at their elevations, relative to `centre`
+pub fn contour_lines(contours: &[Contour], centre: glam::DVec3) -> Vec<Vertex> {
+    let mut vertices = vec![];
+
+    for contour in contours {
+        let colour = if contour.major { MAJOR_CONTOUR_COLOUR } else { CONTOUR_COLOUR };
+
+        for segment in contour.lines.iter().flat_map(|line| line.windows(2)) {
+            vertices.extend(segment.iter().map(|p| Vertex {
+                position: (p.extend(contour.elevation) - centre).as_vec3().to_array(),
+                colour,
+                intensity: 0.0,
+            }));
+        }
+    }
+
+    vertices
+}
+
+/// Mesa's llvmpipe/softpipe, SwiftShader and Windows' fallback renderer
+pub fn is_software_renderer(renderer: &str) -> bool {
+    let renderer = renderer.to_lowercase();
+
+    ["llvmpipe", "softpipe", "swrast", "software rasterizer", "swiftshader", "gdi generic", "basic render"].iter()
+        .any(|name| renderer.contains(name))
+}
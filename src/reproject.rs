@@ -0,0 +1,86 @@
+use glam::DVec3;
+use proj4rs::Proj;
+
+use crate::georef::Georeference;
+
+/// Step between stored coordinates of clouds reprojected to latitude and longitude, in degrees, about a centimetre
+const LATLONG_SCALE: f64 = 1.0e-7;
+
+/// Moves points from a file's coordinate system to another as they're loaded, so scans delivered in different
+/// coordinate systems line up. Both are looked up by EPSG code; datum shifts needing grid files aren't applied.
+pub struct Reprojection {
+    from: Proj,
+    to: Proj,
+    target: u16,
+}
+
+impl Reprojection {
+    /// From the coordinate system of a file with `source` to EPSG `target`, None if it's already in it
+    pub fn new(source: &Georeference, target: u16) -> Result<Option<Reprojection>, String> {
+        let from = source.epsg.ok_or("the file has no EPSG code to reproject from")?;
+        if from == target {
+            return Ok(None);
+        }
+
+        let proj = |code: u16| Proj::from_epsg_code(code).map_err(|err| format!("EPSG:{} isn't usable: {}", code, err));
+
+        Ok(Some(Reprojection {
+            from: proj(from)?,
+            to: proj(target)?,
+            target,
+        }))
+    }
+
+    pub fn target(&self) -> u16 {
+        self.target
+    }
+
+    /// Position in the target coordinate system, None if it's outside where either is defined
+    pub fn position(&self, position: DVec3) -> Option<DVec3> {
+        // Latitude and longitude are in degrees in files, radians to proj
+        let mut point = if self.from.is_latlong() {
+            (position.x.to_radians(), position.y.to_radians(), position.z)
+        } else {
+            (position.x, position.y, position.z)
+        };
+
+        proj4rs::transform::transform(&self.from, &self.to, &mut point).ok()?;
+
+        let position = if self.to.is_latlong() {
+            glam::dvec3(point.0.to_degrees(), point.1.to_degrees(), point.2)
+        } else {
+            glam::dvec3(point.0, point.1, point.2)
+        };
+
+        position.is_finite().then_some(position)
+    }
+
+    /// Georeference of a file with `source` once reprojected, for exports and the coordinates shown
+    pub fn georeference(&self, source: &Georeference) -> Georeference {
+        let scale = if self.to.is_latlong() {
+            DVec3::splat(LATLONG_SCALE)
+        } else if self.from.is_latlong() {
+            Georeference::default().scale
+        } else {
+            source.scale
+        };
+
+        Georeference {
+            wkt: crs_definitions::from_code(self.target).map(|definition| definition.wkt.to_owned()),
+            epsg: Some(self.target),
+            scale,
+            offset: DVec3::ZERO,
+        }
+    }
+}
+
+/// Name of the coordinate system with EPSG `code`, None if it isn't known
+pub fn crs_name(code: u16) -> Option<String> {
+    let definition = crs_definitions::from_code(code)?;
+
+    // PROJCRS["GDA2020 / MGA zone 55", ...
+    let start = definition.wkt.find('"')? + 1;
+    let end = start + definition.wkt[start..].find('"')?;
+
+    Some(definition.wkt[start..end].to_owned())
+}
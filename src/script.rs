@@ -0,0 +1,177 @@
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use glium::backend::{Context, Facade};
+use crate::{config::Config, geometry, georef, hough, jobs::JobContext, plan, slice::{self, ConnectPoints, Scene, SliceImages, Slicer}};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, FLOAT, INT};
+
+/// Points sampled for level detection, like the viewer's Floor Levels
+const LEVEL_SAMPLE_SIZE: usize = 2_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// What the script has loaded and rendered. Scripts load their own copy of a file, separate from the viewer's.
+struct State {
+    context: Rc<Context>,
+    config: Config,
+    point_size: f32,
+    scene: Option<Scene>,
+    clip_height: f64,
+    resolution: (u32, u32),
+    images: Option<SliceImages>,
+}
+
+impl State {
+    fn scene(&self) -> ScriptResult<&Scene> {
+        self.scene.as_ref().ok_or_else(|| "load a file first".into())
+    }
+
+    fn images(&self) -> ScriptResult<&SliceImages> {
+        self.images.as_ref().ok_or_else(|| "render_slice first".into())
+    }
+}
+
+/// Run a Rhai script rendering with `facade`. Scripts get:
+/// - `load(path)`, replacing the file loaded before
+/// - `set_clip_height(elevation)` in file units and `set_resolution(width, height)` in pixels
+/// - `render_slice()` at the clip height
+/// - `save_image(path)` (cutaway, slice, scale bar and north arrow), `save_cutaway(path)` and `save_floor_plan(path)`
+/// - `detect_floors()` and `detect_ceilings()`, elevations from lowest to highest
+/// - `detect_walls()`, straight walls in the last slice as maps of `x1`, `y1`, `x2`, `y2` and `length` in file units
+///
+/// The script is stopped between operations once `context` is cancelled.
+pub fn run<F: Facade>(facade: &F, path: &Path, point_size: f32, config: &Config, context: &JobContext) -> Result<(), String> {
+    let state = Rc::new(RefCell::new(State {
+        context: facade.get_context().clone(),
+        config: config.clone(),
+        point_size,
+        scene: None,
+        clip_height: 0.0,
+        resolution: (1920, 1080),
+        images: None,
+    }));
+
+    let mut engine = Engine::new();
+
+    let job = context.clone();
+    engine.on_progress(move |_| job.is_cancelled().then(|| "cancelled".into()));
+
+    let s = state.clone();
+    engine.register_fn("load", move |path: &str| -> ScriptResult<()> {
+        let state = &mut *s.borrow_mut();
+
+        state.images = None;
+        state.scene = None;
+        state.scene = Some(Scene::load(&state.context, path, 0)?);
+
+        println!("Loaded {}", path);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("set_clip_height", move |elevation: FLOAT| s.borrow_mut().clip_height = elevation);
+    let s = state.clone();
+    engine.register_fn("set_clip_height", move |elevation: INT| s.borrow_mut().clip_height = elevation as f64);
+
+    let s = state.clone();
+    engine.register_fn("set_resolution", move |width: INT, height: INT| -> ScriptResult<()> {
+        let size = |n: INT| u32::try_from(n).ok().filter(|n| *n > 0).ok_or_else(|| format!("{} isn't a size above 0", n));
+
+        s.borrow_mut().resolution = (size(width)?, size(height)?);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("render_slice", move || -> ScriptResult<()> {
+        let state = &mut *s.borrow_mut();
+        let (width, height) = state.resolution;
+
+        let images = {
+            let slicer = Slicer::new(&state.context, state.scene()?, width, height)?;
+            slicer.render(state.clip_height, state.point_size, &state.config, &ConnectPoints)?
+        };
+
+        state.images = Some(images);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("save_image", move |path: &str| -> ScriptResult<()> {
+        let state = s.borrow();
+        let images = state.images()?;
+
+        let mut image = plan::annotated_cutaway(&images.cutaway, &images.slice);
+        let font = plan::label_font();
+        plan::draw_scale_bar(&mut image, &images.transform, &font);
+        plan::draw_north_arrow(&mut image, &images.transform, georef::north(state.config.north_offset as f64), &font);
+
+        save(&image, path)
+    });
+
+    let s = state.clone();
+    engine.register_fn("save_cutaway", move |path: &str| -> ScriptResult<()> {
+        save(&s.borrow().images()?.cutaway, path)
+    });
+
+    let s = state.clone();
+    engine.register_fn("save_floor_plan", move |path: &str| -> ScriptResult<()> {
+        save(&s.borrow().images()?.slice, path)
+    });
+
+    let s = state.clone();
+    engine.register_fn("detect_floors", move || -> ScriptResult<Array> {
+        detect_levels(s.borrow().scene()?, geometry::PlaneKind::Floor)
+    });
+
+    let s = state.clone();
+    engine.register_fn("detect_ceilings", move || -> ScriptResult<Array> {
+        detect_levels(s.borrow().scene()?, geometry::PlaneKind::Ceiling)
+    });
+
+    let s = state;
+    engine.register_fn("detect_walls", move || -> ScriptResult<Array> {
+        let state = s.borrow();
+        let images = state.images()?;
+
+        let points = slice::slice_pixels(&images.raw, state.config.alpha_threshold);
+        let segments = hough::detect_segments(images.raw.width(), images.raw.height(), &points, &hough::HoughParameters::default());
+
+        Ok(segments.iter().map(|segment| {
+            let from = images.transform.pixel_to_world(segment.from.as_dvec2());
+            let to = images.transform.pixel_to_world(segment.to.as_dvec2());
+
+            let mut wall = Map::new();
+            wall.insert("x1".into(), Dynamic::from_float(from.x));
+            wall.insert("y1".into(), Dynamic::from_float(from.y));
+            wall.insert("x2".into(), Dynamic::from_float(to.x));
+            wall.insert("y2".into(), Dynamic::from_float(to.y));
+            wall.insert("length".into(), Dynamic::from_float(from.distance(to)));
+
+            Dynamic::from_map(wall)
+        }).collect())
+    });
+
+    engine.run_file(path.to_path_buf()).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn save(image: &image::RgbaImage, path: &str) -> ScriptResult<()> {
+    image.save(path).map_err(|err| format!("Failed to save {}: {}", path, err))?;
+
+    println!("Saved {}", path);
+    Ok(())
+}
+
+fn detect_levels(scene: &Scene, kind: geometry::PlaneKind) -> ScriptResult<Array> {
+    let total: usize = scene.batches().iter().map(|batch| batch.points.len()).sum();
+    let stride = (total / LEVEL_SAMPLE_SIZE).max(1);
+
+    let heights: Vec<f64> = scene.batches().iter()
+        .flat_map(|batch| batch.points.iter())
+        .step_by(stride)
+        .map(|point| point.z)
+        .collect();
+
+    Ok(geometry::detect_levels(&heights, 0.01, 0.2).into_iter()
+        .filter(|level| level.kind == kind)
+        .map(|level| Dynamic::from_float(level.height))
+        .collect())
+}
@@ -0,0 +1,117 @@
+use glam::DVec2;
+use image::{Rgba, RgbaImage};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{colour::Colouring, filter::PointFilter, loader::PointBatch};
+
+/// Longest side of a cross-section image, in pixels. Coarser pixels are used for long or tall sections.
+const MAX_SECTION_SIZE: u32 = 8192;
+const BEND_COLOUR: Rgba<u8> = Rgba([200, 200, 200, 255]);
+
+/// Vertical section through the points along a path on the ground, unrolled flat, e.g. along a corridor or up a stair
+#[derive(Clone, Debug, Default)]
+pub struct Section {
+    /// Path in file X and Y
+    pub path: Vec<DVec2>,
+    /// Points up to this far either side of the path are in the section, in file units
+    pub half_width: f64,
+}
+
+/// A point of a section, unrolled
+#[derive(Clone, Copy, Debug)]
+pub struct SectionPoint {
+    /// Distance along the path
+    pub chainage: f64,
+    pub elevation: f64,
+    /// Distance from the path, left of it is negative
+    pub offset: f64,
+    pub colour: [u8; 3],
+}
+
+impl Section {
+    pub fn length(&self) -> f64 {
+        self.path.windows(2).map(|segment| segment[0].distance(segment[1])).sum()
+    }
+
+    /// Distance along the path and from it of the nearest point on the path to `p`, None if that's further than
+    /// `half_width` or past either end
+    pub fn locate(&self, p: DVec2) -> Option<(f64, f64)> {
+        let mut start = 0.0;
+        let mut nearest: Option<(f64, f64)> = None;
+
+        for segment in self.path.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            let length = a.distance(b);
+
+            if length > 0.0 {
+                let direction = (b - a) / length;
+                let along = (p - a).dot(direction);
+                let offset = direction.perp_dot(p - a);
+
+                if (0.0..=length).contains(&along) && offset.abs() <= self.half_width && nearest.is_none_or(|(_, nearest)| offset.abs() < nearest.abs()) {
+                    nearest = Some((start + along, -offset));
+                }
+            }
+
+            start += length;
+        }
+
+        nearest
+    }
+
+    /// Points the filter shows within the section, coloured as in the 3D view
+    pub fn points(&self, batches: &[PointBatch], filter: &PointFilter, colouring: &Colouring) -> Vec<SectionPoint> {
+        batches.par_iter()
+            .flat_map_iter(|batch| batch.points.iter().filter_map(move |point| {
+                let (chainage, offset) = self.locate(glam::dvec2(point.x, point.y))?;
+
+                filter.accepts(point).then(|| SectionPoint {
+                    chainage,
+                    elevation: point.z,
+                    offset,
+                    colour: colouring.colour(point, batch.file),
+                })
+            }))
+            .collect()
+    }
+
+    /// Unrolled image of the section's points, chainage to the right and elevation up, with the bends in the path
+    /// marked. `pixel_size` is in file units, larger if the image would be too big. Returns the image, the pixel size
+    /// used and the elevation of the bottom edge, None without points.
+    pub fn render(&self, points: &[SectionPoint], pixel_size: f64) -> Option<(RgbaImage, f64, f64)> {
+        let (bottom, top) = points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(bottom, top), point| (bottom.min(point.elevation), top.max(point.elevation)));
+        if bottom > top {
+            return None;
+        }
+
+        let size = DVec2::new(self.length(), top - bottom);
+        let pixel_size = pixel_size.max(size.max_element() / MAX_SECTION_SIZE as f64);
+        let (width, height) = ((size.x / pixel_size).ceil() as u32 + 1, (size.y / pixel_size).ceil() as u32 + 1);
+
+        let mut image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+
+        let mut chainage = 0.0;
+        for segment in self.path.windows(2).take(self.path.len().saturating_sub(2)) {
+            chainage += segment[0].distance(segment[1]);
+            let x = ((chainage / pixel_size) as u32).min(width - 1);
+
+            for y in 0..height {
+                image.put_pixel(x, y, BEND_COLOUR);
+            }
+        }
+
+        // Furthest from the path first, so the points nearest the cut are on top
+        let mut sorted: Vec<&SectionPoint> = points.iter().collect();
+        sorted.sort_unstable_by(|a, b| b.offset.abs().total_cmp(&a.offset.abs()));
+
+        for point in sorted {
+            let x = ((point.chainage / pixel_size) as u32).min(width - 1);
+            let y = height - 1 - (((point.elevation - bottom) / pixel_size) as u32).min(height - 1);
+            let [r, g, b] = point.colour;
+
+            image.put_pixel(x, y, Rgba([r, g, b, 255]));
+        }
+
+        Some((image, pixel_size, bottom))
+    }
+}
@@ -0,0 +1,114 @@
+use glam::{DVec3, Mat4, Vec2};
+use rayon::prelude::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::{filter::{AttributeCounts, PointFilter}, loader::PointBatch};
+
+/// Shape dragged out on screen to select points
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SelectTool {
+    Box,
+    Lasso,
+}
+
+/// How a new selection combines with the points already selected
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SelectMode {
+    Replace,
+    Add,
+    Remove,
+}
+
+/// Select the visible points whose projection falls inside `outline` (window pixels), leaving out points cut away by
+/// the clipping plane. `mvp` maps file coordinates relative to `centre` to clip space.
+pub fn select(batches: &mut [PointBatch], filter: &PointFilter, outline: &[Vec2], window_size: Vec2, mvp: Mat4, centre: DVec3, mode: SelectMode) {
+    if outline.len() < 3 {
+        return;
+    }
+
+    let (min, max) = outline.iter().fold((Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)), |(min, max), p| (min.min(*p), max.max(*p)));
+
+    batches.par_iter_mut().for_each(|batch| {
+        // Relative to the batch origin like the vertices, for precision
+        let mvp = mvp * Mat4::from_translation((batch.origin - centre).as_vec3());
+
+        for (point, selected) in batch.points.iter().zip(batch.selected.iter_mut()) {
+            let inside = filter.accepts(point) && {
+                let ndc = mvp.project_point3((glam::dvec3(point.x, point.y, point.z) - batch.origin).as_vec3());
+                let p = (ndc.truncate() * glam::vec2(0.5, -0.5) + 0.5) * window_size;
+
+                (0.0..=1.0).contains(&ndc.z) && p.cmpge(min).all() && p.cmple(max).all() && contains(outline, p)
+            };
+
+            *selected = match mode {
+                SelectMode::Replace => inside,
+                SelectMode::Add => *selected || inside,
+                SelectMode::Remove => *selected && !inside,
+            };
+        }
+    });
+}
+
+/// Number of selected points
+pub fn count(batches: &[PointBatch]) -> usize {
+    batches.iter().map(|batch| batch.selected.iter().filter(|selected| **selected).count()).sum()
+}
+
+/// File coordinates of the selected points the filter shows, e.g. to mesh one surface
+pub fn positions(batches: &[PointBatch], filter: &PointFilter) -> Vec<DVec3> {
+    batches.par_iter()
+        .flat_map_iter(|batch| {
+            batch.points.iter().zip(&batch.selected)
+                .filter(|(point, selected)| **selected && filter.accepts(point))
+                .map(|(point, _)| glam::dvec3(point.x, point.y, point.z))
+        })
+        .collect()
+}
+
+pub fn clear(batches: &mut [PointBatch]) {
+    for batch in batches {
+        batch.selected.fill(false);
+    }
+}
+
+/// Remove the selected points, e.g. people walking past, tripods or noise, so they don't show up in the cutaway.
+/// Returns the indices of the batches that changed and the counts of the points removed.
+pub fn delete(batches: &mut [PointBatch]) -> (Vec<usize>, AttributeCounts) {
+    let mut changed = vec![];
+    let mut removed = AttributeCounts::default();
+
+    for (i, batch) in batches.iter_mut().enumerate() {
+        if !batch.selected.contains(&true) {
+            continue;
+        }
+
+        let points = std::mem::take(&mut batch.points);
+
+        for (point, selected) in points.into_iter().zip(&batch.selected) {
+            if *selected {
+                removed.add(&point);
+            } else {
+                batch.points.push(point);
+            }
+        }
+
+        batch.selected = vec![false; batch.points.len()];
+        changed.push(i);
+    }
+
+    (changed, removed)
+}
+
+/// Even-odd test, so self-intersecting lassos behave like a fill would
+fn contains(polygon: &[Vec2], p: Vec2) -> bool {
+    let mut inside = false;
+
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
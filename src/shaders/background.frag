@@ -0,0 +1,12 @@
+#version 140
+
+in float v_height;
+
+out vec4 color;
+
+uniform vec3 u_top;
+uniform vec3 u_bottom;
+
+void main() {
+    color = vec4(mix(u_bottom, u_top, v_height) / 255.0, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 140
+
+in vec3 position;
+
+out float v_height;
+
+void main() {
+    // 0 at the bottom of the window, 1 at the top
+    v_height = position.y * 0.5 + 0.5;
+
+    gl_Position = vec4(position.xy, 1.0, 1.0);
+}
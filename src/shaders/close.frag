@@ -0,0 +1,38 @@
+#version 140
+
+out vec4 color;
+
+uniform sampler2D u_slice;
+uniform int u_radius;
+// Minimum alpha of a point, pixels at or below it are empty
+uniform float u_threshold;
+// Keep pixels with every neighbour set (erode) rather than set pixels with any neighbour set (dilate)
+uniform bool u_erode;
+
+bool is_set(ivec2 p, ivec2 size) {
+    // Outside the image neither grows walls nor eats into them
+    if (any(lessThan(p, ivec2(0))) || any(greaterThanEqual(p, size))) {
+        return u_erode;
+    }
+
+    return texelFetch(u_slice, p, 0).a > u_threshold;
+}
+
+void main() {
+    ivec2 size = textureSize(u_slice, 0);
+    ivec2 centre = ivec2(gl_FragCoord.xy);
+
+    // Dilating looks for a set neighbour, eroding for an unset one
+    bool found = false;
+
+    for (int y = -u_radius; y <= u_radius && !found; y++) {
+        for (int x = -u_radius; x <= u_radius; x++) {
+            if (x * x + y * y <= u_radius * u_radius && is_set(centre + ivec2(x, y), size) != u_erode) {
+                found = true;
+                break;
+            }
+        }
+    }
+
+    color = found != u_erode ? vec4(0.0, 0.0, 0.0, 1.0) : vec4(1.0, 1.0, 1.0, 0.0);
+}
@@ -5,19 +5,60 @@ in vec3 v_position;
 out vec4 color;
 
 uniform sampler2D u_cutaway;
-uniform sampler2D u_cutaway_slice;
-uniform sampler2D u_cutaway_slice_processed;
+uniform sampler2D u_raw_slice;
+uniform sampler2D u_walls;
+uniform sampler2D u_edits;
+uniform sampler2D u_rooms;
+
+uniform float u_cutaway_opacity;
+uniform float u_raw_slice_opacity;
+uniform float u_walls_opacity;
+uniform float u_edits_opacity;
+uniform float u_rooms_opacity;
+
+// Cutaway adjustments for tracing over dark renders, 0, 1 and 0 leave it as it is
+uniform float u_brightness;
+uniform float u_contrast;
+uniform float u_desaturate;
+
+// Raw slice left of this fraction of the width and processed slice right of it, below 0 when not comparing
+uniform float u_compare_split;
+// Wall pixels without a raw slice point more opaque than the threshold were added joining the points up
+uniform bool u_highlight_added;
+uniform float u_alpha_threshold;
 
 void main() {
     vec2 tex_coords = (v_position.xy + vec2(1.0, 1.0)) / 2.0;
 
-    // color = vec4(tex_coords, 1.0, 1.0);
+    vec3 cutaway = texture(u_cutaway, tex_coords).rgb;
+    cutaway = mix(cutaway, vec3(dot(cutaway, vec3(0.2126, 0.7152, 0.0722))), u_desaturate);
+    cutaway = clamp((cutaway - 0.5) * u_contrast + 0.5 + u_brightness, 0.0, 1.0);
+
+    vec3 colour = mix(vec3(1.0), cutaway, u_cutaway_opacity);
+
+    // Room colours tint the cutaway, empty (white) pixels fade it
+    colour = mix(colour, texture(u_rooms, tex_coords).rgb, 0.5 * u_rooms_opacity);
+
+    float raw_slice_opacity = u_raw_slice_opacity;
+    float walls_opacity = u_walls_opacity;
+    if (u_compare_split >= 0.0) {
+        bool raw_side = tex_coords.x < u_compare_split;
+        raw_slice_opacity = raw_side ? 1.0 : 0.0;
+        walls_opacity = raw_side ? 0.0 : 1.0;
+    }
+
+    // Slice points as the scanner saw them, under the walls traced from them
+    vec4 raw_colour = texture(u_raw_slice, tex_coords);
+    colour = mix(colour, raw_colour.rgb, raw_colour.a * raw_slice_opacity);
 
-    vec4 cutaway_colour = texture(u_cutaway, tex_coords);
-    vec4 slice_colour = texture(u_cutaway_slice, tex_coords);
+    vec4 walls_colour = texture(u_walls, tex_coords);
+    if (u_highlight_added && raw_colour.a <= u_alpha_threshold) {
+        walls_colour.rgb = vec3(1.0, 0.45, 0.0);
+    }
+    colour = mix(colour, walls_colour.rgb, walls_colour.a * walls_opacity);
 
-    float slice_factor = slice_colour.a / 2 + 0.5;
-    float cutaway_factor = 1.0 - slice_factor;
+    vec4 edits_colour = texture(u_edits, tex_coords);
+    colour = mix(colour, edits_colour.rgb, edits_colour.a * u_edits_opacity);
 
-    color = vec4(cutaway_colour.rgb * cutaway_factor + slice_colour.rgb * slice_factor, 1.0);
+    color = vec4(colour, 1.0);
 }
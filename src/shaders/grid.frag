@@ -0,0 +1,11 @@
+#version 140
+
+in vec3 v_colour;
+
+out vec4 color;
+
+uniform float u_opacity;
+
+void main() {
+    color = vec4(v_colour / 255.0, u_opacity);
+}
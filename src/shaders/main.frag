@@ -7,6 +7,16 @@ out vec4 color;
 uniform bool u_clipping;
 uniform bool u_slice;
 uniform float u_slice_width;
+uniform bool u_round_points;
+// 0 for no fog, 1 linear between the parameters' distances, 2 exponential with the first parameter's density
+uniform int u_fog;
+uniform vec2 u_fog_parameters;
+// Far plane distance from the near plane, in file units
+uniform float u_depth_range;
+// Background the points fade into, a gradient up the window
+uniform vec3 u_fog_top;
+uniform vec3 u_fog_bottom;
+uniform float u_window_height;
 
 void main() {
     float z = gl_FragCoord.z;
@@ -18,8 +28,8 @@ void main() {
         discard;
     }
     vec2 pos = gl_PointCoord - vec2(0.5);
-    // Shape of point
-    if (dot(pos, pos) > 0.25) {
+    // Shape of point, square in reduced mode
+    if (u_round_points && dot(pos, pos) > 0.25) {
         discard;
     }
 
@@ -32,5 +42,19 @@ void main() {
     //     color = vec4(v_colour / c, 1.0);
     // }
 
-    color = vec4(v_colour / 256.0, 1.0);
+    vec3 colour = v_colour / 256.0;
+
+    if (u_fog != 0) {
+        // From the clipping plane (or the camera, without clipping)
+        float distance = max(z - clipping_dist, 0.0) * 2.0 * u_depth_range;
+
+        float fog = u_fog == 1
+            ? clamp((distance - u_fog_parameters.x) / max(u_fog_parameters.y - u_fog_parameters.x, 0.001), 0.0, 1.0)
+            : 1.0 - exp(-u_fog_parameters.x * distance);
+
+        vec3 background = mix(u_fog_bottom, u_fog_top, gl_FragCoord.y / u_window_height) / 255.0;
+        colour = mix(colour, background, fog);
+    }
+
+    color = vec4(colour, 1.0);
 }
@@ -2,6 +2,7 @@
 
 in vec3 position;
 in vec3 colour;
+in float intensity;
 // in float size;
 
 out vec3 v_colour;
@@ -10,9 +11,19 @@ uniform mat4 u_modelview;
 uniform mat4 u_projection;
 uniform float u_zoom;
 uniform float u_size;
+// Batch origin relative to the centre, vertex positions are relative to it
+uniform vec3 u_origin;
+// File coordinates of the centre, only as precise as a float
+uniform vec3 u_centre;
+
+// BEGIN POINT_COLOUR
+vec3 point_colour(vec3 position, vec3 colour, float intensity) {
+    return colour;
+}
+// END POINT_COLOUR
 
 void main() {
-    v_colour = colour;
+    v_colour = point_colour(u_origin + position, colour / 256.0, intensity) * 256.0;
 
     vec4 pos = u_modelview * vec4(position, 1.0);
     
@@ -0,0 +1,14 @@
+#version 140
+
+in vec3 v_colour;
+in vec3 v_position;
+
+out vec4 color;
+
+void main() {
+    // Flat shading, face normal from screen space derivatives
+    vec3 normal = normalize(cross(dFdx(v_position), dFdy(v_position)));
+    float light = 0.4 + 0.6 * abs(normal.z);
+
+    color = vec4(v_colour / 256.0 * light, 1.0);
+}
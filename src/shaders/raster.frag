@@ -0,0 +1,19 @@
+#version 140
+
+in vec2 v_tex_coords;
+
+out vec4 color;
+
+uniform sampler2D u_raster;
+uniform float u_opacity;
+
+void main() {
+    vec4 colour = texture(u_raster, v_tex_coords);
+
+    // Cells without points
+    if (colour.a == 0.0) {
+        discard;
+    }
+
+    color = vec4(colour.rgb, colour.a * u_opacity);
+}
@@ -0,0 +1,20 @@
+#version 140
+
+in vec3 position;
+
+out vec2 v_tex_coords;
+
+uniform mat4 u_modelview;
+uniform mat4 u_projection;
+// Corners of the raster and its height, relative to the centre of the view
+uniform vec2 u_min;
+uniform vec2 u_max;
+uniform float u_elevation;
+
+void main() {
+    v_tex_coords = (position.xy + vec2(1.0, 1.0)) / 2.0;
+
+    vec2 ground = mix(u_min, u_max, v_tex_coords);
+
+    gl_Position = u_projection * u_modelview * vec4(ground, u_elevation, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 140
+
+in vec2 v_uv;
+
+out vec4 color;
+
+uniform sampler2D u_colour;
+uniform sampler2D u_depth;
+
+void main() {
+    vec4 colour = texture(u_colour, v_uv);
+
+    // Nothing drawn here yet
+    if (colour.a == 0.0) {
+        discard;
+    }
+
+    color = colour;
+    gl_FragDepth = texture(u_depth, v_uv).r;
+}
@@ -0,0 +1,558 @@
+use std::{sync::{atomic::{AtomicU32, Ordering}, Arc}, thread};
+
+use glam::DVec3;
+use glium::{backend::Facade, framebuffer::{DepthRenderBuffer, SimpleFrameBuffer}, Surface, Texture2d, VertexBuffer};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+use imageproc::{distance_transform::Norm, morphology};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{camera::{self, Z_NEAR}, colour, config::Config, filter, jobs::JobContext, loader::{self, LoaderMessage, PointBatch}, locale::Language, plan, render::{self, Vertex}, transform::Transform};
+
+/// Space left around the point cloud's footprint, as a fraction of its size
+const FIT_MARGIN: f32 = 0.05;
+
+/// Slice pixels searched for neighbours at a time before their lines are drawn, bounding the lines held in memory
+const CONNECT_CHUNK_SIZE: usize = 4096;
+
+/// Turns a raw slice, opaque where points cross the clipping plane, into the image floor plans are traced from.
+/// Implement this and register it with `PostProcessors::register` to try other ways of joining up walls, the viewer
+/// offers the post-processors it's created with (see `viewer::Viewer::new`).
+pub trait SlicePostProcessor: Send + Sync {
+    /// Shown when choosing how slices are processed
+    fn name(&self) -> &str;
+
+    /// Fails once the job processing the slice is cancelled, see `JobContext::is_cancelled`
+    fn process(&self, slice: &RgbaImage, context: &ProcessContext) -> Result<RgbaImage, String>;
+
+    /// Controls for the post-processor's own parameters, shown while it's chosen, labelled in `locale`
+    fn settings(&mut self, _ui: &mut egui::Ui, _locale: &Language) {}
+}
+
+/// How the slice being processed was rendered
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessContext<'a> {
+    /// Gaps narrower than this are expected to be closed, in pixels, from the point size and zoom
+    pub connect_radius: i32,
+    /// Pixels more opaque than this are points, see `slice_pixels`
+    pub alpha_threshold: u8,
+    /// Job processing the slice, its progress is set as processing goes so the viewer can show how far along a slice
+    /// is, and processing stops early once it's cancelled
+    pub job: &'a JobContext,
+}
+
+/// Fraction of a slice processed so far, shared with the thread processing it
+#[derive(Default, Debug)]
+pub struct Progress(AtomicU32);
+
+impl Progress {
+    pub fn set(&self, fraction: f32) {
+        self.0.store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// Joins every slice pixel to its neighbours with straight lines, see `connect_slice`
+pub struct ConnectPoints;
+
+impl SlicePostProcessor for ConnectPoints {
+    fn name(&self) -> &str {
+        "Connect Nearby Points"
+    }
+
+    fn process(&self, slice: &RgbaImage, context: &ProcessContext) -> Result<RgbaImage, String> {
+        let mut image = slice.clone();
+        connect_slice(&mut image, context.connect_radius, context.alpha_threshold, context.job)?;
+
+        Ok(image)
+    }
+}
+
+/// Closes gaps by dilating and then eroding the slice, much faster than joining points but rounds off tight corners
+pub struct MorphologicalClosing {
+    /// In pixels, the connect radius if unset
+    pub radius: Option<u8>,
+    /// Times the slice is dilated before being eroded as many times, each widening the gaps closed by the radius
+    pub iterations: u8,
+}
+
+impl Default for MorphologicalClosing {
+    fn default() -> MorphologicalClosing {
+        MorphologicalClosing {
+            radius: None,
+            iterations: 1,
+        }
+    }
+}
+
+impl SlicePostProcessor for MorphologicalClosing {
+    fn name(&self) -> &str {
+        "Morphological Closing"
+    }
+
+    fn process(&self, slice: &RgbaImage, context: &ProcessContext) -> Result<RgbaImage, String> {
+        let radius = self.radius.unwrap_or(context.connect_radius.clamp(0, u8::MAX as i32) as u8);
+        let mut mask = GrayImage::from_fn(slice.width(), slice.height(), |x, y| Luma([if slice.get_pixel(x, y).0[3] > context.alpha_threshold { 255 } else { 0 }]));
+
+        let passes = self.iterations as f32 * 2.0;
+
+        for i in 0..self.iterations * 2 {
+            if context.job.is_cancelled() {
+                return Err("Closing the slice was cancelled".to_owned());
+            }
+
+            if i < self.iterations {
+                morphology::dilate_mut(&mut mask, Norm::L1, radius);
+            } else {
+                morphology::erode_mut(&mut mask, Norm::L1, radius);
+            }
+            context.job.progress().set((i + 1) as f32 / passes);
+        }
+
+        Ok(RgbaImage::from_fn(mask.width(), mask.height(), |x, y| {
+            if mask.get_pixel(x, y).0[0] > 0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 0]) }
+        }))
+    }
+
+    fn settings(&mut self, ui: &mut egui::Ui, locale: &Language) {
+        ui.horizontal(|ui| {
+            let mut auto = self.radius.is_none();
+
+            if ui.checkbox(&mut auto, locale.tr("Auto Radius")).on_hover_text(locale.tr("Use the connect radius, from the point size and zoom")).changed() {
+                self.radius = if auto { None } else { Some(5) };
+            }
+            if let Some(radius) = &mut self.radius {
+                ui.add(egui::DragValue::new(radius).clamp_range(1..=64).suffix(" px"));
+            }
+        });
+        ui.add(egui::Slider::new(&mut self.iterations, 1..=8).text(locale.tr("Iterations")));
+    }
+}
+
+/// Slice post-processors to choose from, the first is the default
+pub struct PostProcessors {
+    processors: Vec<Arc<dyn SlicePostProcessor>>,
+}
+
+impl Default for PostProcessors {
+    fn default() -> PostProcessors {
+        PostProcessors {
+            processors: vec![Arc::new(ConnectPoints), Arc::new(MorphologicalClosing::default())],
+        }
+    }
+}
+
+impl PostProcessors {
+    /// Add a post-processor, returning its index
+    pub fn register(&mut self, processor: Box<dyn SlicePostProcessor>) -> usize {
+        self.processors.push(processor.into());
+        self.processors.len() - 1
+    }
+
+    /// Post-processor at `index`, or the default if there isn't one
+    pub fn get(&self, index: usize) -> &dyn SlicePostProcessor {
+        self.shared(index).as_ref()
+    }
+
+    /// Post-processor at `index` to hand to a processing thread
+    pub fn shared(&self, index: usize) -> &Arc<dyn SlicePostProcessor> {
+        self.processors.get(index).unwrap_or(&self.processors[0])
+    }
+
+    /// None while a processing thread is still using the post-processor
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut (dyn SlicePostProcessor + 'static)> {
+        let index = if index < self.processors.len() { index } else { 0 };
+        Arc::get_mut(&mut self.processors[index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn SlicePostProcessor> {
+        self.processors.iter().map(|processor| processor.as_ref())
+    }
+}
+
+/// Closes gaps in a slice on the GPU by dilating and then eroding it by the connect radius. Much faster than
+/// `connect_slice` on large captures, but also rounds off concave corners tighter than the radius.
+pub struct GpuClosing {
+    program: glium::Program,
+    quad: VertexBuffer<Vertex>,
+}
+
+impl GpuClosing {
+    pub fn new<F: Facade>(facade: &F) -> GpuClosing {
+        let corner = |x: f32, y: f32| Vertex { position: [x, y, 0.0], colour: [0, 0, 0], intensity: 0.0 };
+
+        GpuClosing {
+            program: render::program(facade, include_str!("shaders/slice_pass.vert"), include_str!("shaders/close.frag")).expect("Failed to parse closing shader."),
+            quad: VertexBuffer::new(facade, &[
+                corner(-1.0, -1.0), corner(-1.0, 1.0), corner(1.0, 1.0),
+                corner(-1.0, -1.0), corner(1.0, 1.0), corner(1.0, -1.0),
+            ]).expect("Failed to create slice pass quad."),
+        }
+    }
+
+    /// Processed copy of the slice rendered into `raw`, opaque black where there are walls. Pixels more opaque than
+    /// `alpha_threshold` are points.
+    pub fn process<F: Facade>(&self, facade: &F, raw: &Texture2d, radius: i32, alpha_threshold: u8) -> Result<Texture2d, String> {
+        let (width, height) = raw.dimensions();
+        let dilated = render::capture_texture(facade, width, height);
+        let closed = render::capture_texture(facade, width, height);
+
+        self.pass(facade, raw, &dilated, radius, alpha_threshold, false)?;
+        // The dilated slice is fully opaque or clear
+        self.pass(facade, &dilated, &closed, radius, 0, true)?;
+
+        Ok(closed)
+    }
+
+    fn pass<F: Facade>(&self, facade: &F, input: &Texture2d, output: &Texture2d, radius: i32, alpha_threshold: u8, erode: bool) -> Result<(), String> {
+        let mut buffer = SimpleFrameBuffer::new(facade, output).map_err(|err| format!("Failed to create slice pass buffer: {}", err))?;
+        buffer.clear_color(1.0, 1.0, 1.0, 0.0);
+
+        let uniforms = uniform! {
+            u_slice: input.sampled()
+                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+                .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest),
+            u_radius: radius.max(0),
+            u_threshold: alpha_threshold as f32 / 255.0,
+            u_erode: erode,
+        };
+
+        buffer.draw(&self.quad, glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList), &self.program, &uniforms, &Default::default())
+            .map_err(|err| format!("Failed to draw slice pass: {}", err))
+    }
+}
+
+/// Pixels of a rendered slice that points landed on, those more opaque than `alpha_threshold`. Lower thresholds keep
+/// the faint edges of points, higher ones drop stray points caught at the edge of the slice.
+pub fn slice_pixels(image: &RgbaImage, alpha_threshold: u8) -> Vec<(u32, u32)> {
+    image.enumerate_pixels().filter(|(_, _, colour)| colour.0[3] > alpha_threshold).map(|(x, y, _)| (x, y)).collect()
+}
+
+/// Join each pixel of a rendered slice to the others within `radius` pixels with black lines, closing the gaps
+/// between scanned points. Neighbours are searched for in parallel. Returns the slice's pixels from before they were
+/// joined, or an error once `job` is cancelled.
+pub fn connect_slice(image: &mut RgbaImage, radius: i32, alpha_threshold: u8, job: &JobContext) -> Result<Vec<(u32, u32)>, String> {
+    let found_points = slice_pixels(image, alpha_threshold);
+    let points: Vec<[i32; 2]> = found_points.iter().map(|(x, y)| [*x as i32, *y as i32]).collect();
+    let kdtree = kd_tree::KdTree::build(points);
+
+    for (i, chunk) in kdtree.chunks(CONNECT_CHUNK_SIZE).enumerate() {
+        if job.is_cancelled() {
+            return Err("Connecting the slice was cancelled".to_owned());
+        }
+
+        // One line per pair of points is enough to close the gap between them
+        let lines: Vec<([i32; 2], [i32; 2])> = chunk.par_iter()
+            .flat_map_iter(|point| {
+                kdtree.within_radius(point, radius).into_iter()
+                    .filter(move |close_point| *close_point > point)
+                    .map(move |close_point| (*point, *close_point))
+            })
+            .collect();
+
+        for ([x, y], [cx, cy]) in lines {
+            for (lx, ly) in line_drawing::Bresenham::new((x, y), (cx, cy)) {
+                image.put_pixel(lx as u32, ly as u32, image::Rgba([0, 0, 0, 255]));
+            }
+        }
+
+        job.progress().set(((i + 1) * CONNECT_CHUNK_SIZE) as f32 / kdtree.len() as f32);
+    }
+
+    Ok(found_points)
+}
+
+/// Every valid point in a file, uploaded for rendering
+pub struct Scene {
+    batches: Vec<PointBatch>,
+    vertex_buffers: Vec<VertexBuffer<Vertex>>,
+    min: DVec3,
+    max: DVec3,
+}
+
+impl Scene {
+    pub fn load<F: Facade>(facade: &F, filename: &str, num_points: u64) -> Result<Scene, String> {
+        let (_, _, rx, reader) = loader::load_point_cloud(filename, num_points, None)?;
+        thread::spawn(move || reader.read(&JobContext::detached()));
+
+        let mut batches = vec![];
+        let mut bounds = None;
+
+        for message in rx {
+            match message {
+                LoaderMessage::Batch(points) => {
+                    if let Some((min, max)) = loader::bounds(&points) {
+                        bounds = loader::union_bounds(bounds, Some((min, max)));
+
+                        batches.push(PointBatch::new(points, (min + max) / 2.0, 0));
+                    }
+                },
+                LoaderMessage::Finished { error: Some(error), .. } => return Err(error),
+                LoaderMessage::Finished { .. } => break,
+            }
+        }
+
+        let (min, max) = bounds.ok_or_else(|| format!("{} has no valid points", filename))?;
+
+        let colouring = colour::Colouring {
+            mode: colour::ColourMode::Rgb,
+            ramps: &[],
+            elevation_range: (min.z, max.z),
+            planes: &[],
+            plane_threshold: 0.0,
+            density: None,
+            distances: None,
+            distance_threshold: 1.0,
+            clusters: None,
+        };
+        let vertex_buffers = batches.iter()
+            .map(|batch| glium::VertexBuffer::new(facade, &render::batch_vertices(batch, &filter::PointFilter::default(), &colouring)).expect("Failed to create point vertex buffer."))
+            .collect();
+
+        Ok(Scene { batches, vertex_buffers, min, max })
+    }
+
+    pub fn centre(&self) -> DVec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn batches(&self) -> &[PointBatch] {
+        &self.batches
+    }
+
+    pub fn vertex_buffers(&self) -> &[VertexBuffer<Vertex>] {
+        &self.vertex_buffers
+    }
+
+    /// Corners of the box around every point
+    pub fn bounds(&self) -> (DVec3, DVec3) {
+        (self.min, self.max)
+    }
+}
+
+/// Zoom fitting a footprint `size` wide (file X and Y) to a `width` by `height` image, with a margin
+pub fn fit_zoom(size: DVec3, width: u32, height: u32) -> f32 {
+    let aspect = height as f32 / width as f32;
+    let size = size.as_vec3();
+
+    f32::max(size.x, size.y / aspect).max(f32::EPSILON) * (1.0 + FIT_MARGIN)
+}
+
+/// Modelview looking straight down over `centre`, north up, with the clipping plane at `elevation`. Points are
+/// relative to `centre`.
+pub fn top_down_modelview(elevation: f64, centre: DVec3) -> glam::Mat4 {
+    let rotation = glam::Quat::from_euler(glam::EulerRot::YXZ, 0.0, std::f32::consts::FRAC_PI_2, 0.0);
+
+    // Clipping plane sits at the near plane
+    let camera_position = glam::vec3(0.0, (elevation - centre.z) as f32 + Z_NEAR, 0.0);
+    let view = glam::Mat4::from_rotation_translation(rotation, camera_position).inverse();
+
+    view * render::COORDINATE_SYSTEM
+}
+
+/// Where a cutaway's slice was drawn from: points just beyond the clipping plane, within the slice width, and inside
+/// the view. Tests points the way the slice shader does, so exported slice points match the slice image.
+#[derive(Clone, Copy, Debug)]
+pub struct SliceBand {
+    /// Projection and modelview the cutaway was rendered with
+    pub view_projection: glam::Mat4,
+    /// Transform previewed on the points when it was rendered
+    pub transform: Transform,
+    pub centre: DVec3,
+    /// Thickness in the units of the slice shaders, see `Config::slice_width`
+    pub width: f32,
+}
+
+impl SliceBand {
+    /// Whether each point of the batch falls in the band
+    pub fn contains(&self, batch: &PointBatch) -> Vec<bool> {
+        let mvp = self.view_projection * self.transform.batch_model(batch.origin, self.centre);
+
+        batch.points.par_iter().map(|point| {
+            let ndc = mvp.project_point3((glam::dvec3(point.x, point.y, point.z) - batch.origin).as_vec3());
+            // Window depth, 0.5 at the clipping plane
+            let depth = ndc.z * 0.5 + 0.5;
+
+            depth > 0.5 && depth < 0.5 + self.width && ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0
+        }).collect()
+    }
+}
+
+/// Images of one slice
+pub struct SliceImages {
+    pub cutaway: RgbaImage,
+    /// Points on the clipping plane, before post-processing
+    pub raw: RgbaImage,
+    pub slice: RgbaImage,
+    /// File coordinates of the images' pixels
+    pub transform: plan::CaptureTransform,
+}
+
+/// Draws top down slices of a scene, fitted to the image
+pub struct Slicer<'a, F: Facade> {
+    facade: &'a F,
+    scene: &'a Scene,
+    point_program: glium::Program,
+    slice_program: glium::Program,
+    cutaway_texture: Texture2d,
+    slice_texture: Texture2d,
+    depth: DepthRenderBuffer,
+    projection: glam::Mat4,
+    zoom: f32,
+    width: u32,
+    height: u32,
+}
+
+impl<'a, F: Facade> Slicer<'a, F> {
+    pub fn new(facade: &'a F, scene: &'a Scene, width: u32, height: u32) -> Result<Slicer<'a, F>, String> {
+        if width == 0 || height == 0 {
+            return Err("The image must be at least 1 pixel wide and high".to_owned());
+        }
+
+        let zoom = fit_zoom(scene.max - scene.min, width, height);
+        let projection = camera::projection(zoom, width, height);
+
+        Ok(Slicer {
+            facade,
+            scene,
+            point_program: render::point_program(facade),
+            slice_program: render::slice_program(facade),
+            cutaway_texture: render::capture_texture(facade, width, height),
+            slice_texture: render::capture_texture(facade, width, height),
+            depth: DepthRenderBuffer::new(facade, glium::texture::DepthFormat::F32, width, height).map_err(|err| format!("Failed to create cutaway depth buffer: {}", err))?,
+            projection,
+            zoom,
+            width,
+            height,
+        })
+    }
+
+    /// Cutaway and slice at `elevation`
+    pub fn render(&self, elevation: f64, point_size: f32, config: &Config, processor: &dyn SlicePostProcessor) -> Result<SliceImages, String> {
+        let centre = self.scene.centre();
+        let modelview = top_down_modelview(elevation, centre);
+
+        // Larger cutaway to scale down, the slice stays at full size for processing
+        let supersampling = render::supersampling(self.facade, self.width, self.height, config.capture_supersampling);
+        let supersampled = if supersampling > 1 {
+            let (width, height) = (self.width * supersampling, self.height * supersampling);
+            let depth = DepthRenderBuffer::new(self.facade, glium::texture::DepthFormat::F32, width, height).map_err(|err| format!("Failed to create cutaway depth buffer: {}", err))?;
+
+            Some((render::capture_texture(self.facade, width, height), depth))
+        } else {
+            None
+        };
+        let (cutaway_texture, depth) = supersampled.as_ref().map_or((&self.cutaway_texture, &self.depth), |(texture, depth)| (texture, depth));
+
+        let mut cutaway_buffer = SimpleFrameBuffer::with_depth_buffer(self.facade, cutaway_texture, depth).map_err(|err| format!("Failed to create cutaway buffer: {}", err))?;
+        let mut slice_buffer = SimpleFrameBuffer::new(self.facade, &self.slice_texture).map_err(|err| format!("Failed to create slice buffer: {}", err))?;
+
+        cutaway_buffer.clear_color_and_depth(render::CAPTURE_CLEAR_COLOUR, 1.0);
+        slice_buffer.clear_color(1.0, 1.0, 1.0, 0.0);
+
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::Points);
+
+        for (vertex_buffer, batch) in self.scene.vertex_buffers.iter().zip(&self.scene.batches) {
+            let modelview = modelview * glam::Mat4::from_translation((batch.origin - centre).as_vec3());
+
+            let uniforms = uniform! {
+                u_modelview: modelview.to_cols_array_2d(),
+                u_projection: self.projection.to_cols_array_2d(),
+                u_clipping: true,
+                u_slice: false,
+                u_slice_width: config.slice_width(),
+                u_zoom: self.width as f32 / self.zoom,
+                u_size: point_size,
+                u_round_points: true,
+                u_origin: (batch.origin - centre).as_vec3().to_array(),
+                u_centre: centre.as_vec3().to_array(),
+            };
+
+            let draw_params = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::DepthTest::IfLess,
+                    write: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+
+            let cutaway_uniforms = uniform! {
+                u_modelview: modelview.to_cols_array_2d(),
+                u_projection: self.projection.to_cols_array_2d(),
+                u_clipping: true,
+                u_slice: false,
+                u_slice_width: config.slice_width(),
+                u_zoom: (self.width * supersampling) as f32 / self.zoom,
+                u_size: point_size,
+                u_round_points: true,
+                u_origin: (batch.origin - centre).as_vec3().to_array(),
+                u_centre: centre.as_vec3().to_array(),
+            };
+
+            cutaway_buffer.draw(vertex_buffer, indices, &self.point_program, &cutaway_uniforms, &draw_params).expect("Failed to draw to cutaway buffer.");
+            slice_buffer.draw(vertex_buffer, indices, &self.slice_program, &uniforms, &Default::default()).expect("Failed to draw to cutaway slice buffer.");
+        }
+
+        let cutaway = render::downsample(&render::read_image(cutaway_texture), supersampling);
+        let raw = render::read_image(&self.slice_texture);
+        let slice = processor.process(&raw, &ProcessContext {
+            connect_radius: config.connect_radius(point_size, self.zoom),
+            alpha_threshold: config.alpha_threshold,
+            job: &JobContext::detached(),
+        })?;
+
+        let transform = plan::CaptureTransform::new(self.projection * modelview, self.width, self.height, centre);
+
+        Ok(SliceImages { cutaway, raw, slice, transform })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slice_with(points: &[(u32, u32)]) -> RgbaImage {
+        let mut image = RgbaImage::new(20, 20);
+
+        for &(x, y) in points {
+            image.put_pixel(x, y, image::Rgba([200, 0, 0, 255]));
+        }
+
+        image
+    }
+
+    #[test]
+    fn slice_pixels_drops_faint_pixels() {
+        let mut image = slice_with(&[(1, 1), (5, 5)]);
+        image.put_pixel(3, 3, image::Rgba([0, 0, 0, 10]));
+
+        assert_eq!(slice_pixels(&image, 20), vec![(1, 1), (5, 5)]);
+        assert_eq!(slice_pixels(&image, 0), vec![(1, 1), (3, 3), (5, 5)]);
+    }
+
+    #[test]
+    fn connect_slice_joins_points_within_radius() {
+        let mut image = slice_with(&[(2, 10), (6, 10), (17, 10)]);
+        let job = JobContext::detached();
+
+        let found = connect_slice(&mut image, 5, 0, &job).expect("Not cancelled");
+
+        assert_eq!(found, vec![(2, 10), (6, 10), (17, 10)]);
+        assert!((2..=6).all(|x| image.get_pixel(x, 10).0 == [0, 0, 0, 255]));
+        // Too far from the others to be joined
+        assert!((7..17).all(|x| image.get_pixel(x, 10).0[3] == 0));
+        assert_eq!(image.get_pixel(17, 10).0, [200, 0, 0, 255]);
+        assert_eq!(job.progress().get(), 1.0);
+    }
+}
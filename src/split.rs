@@ -0,0 +1,159 @@
+use glam::{DVec2, DVec3};
+use glium::{backend::Facade, framebuffer::{DepthRenderBuffer, SimpleFrameBuffer}, texture::Texture2d, Program, Surface, VertexBuffer};
+use crate::{camera, loader::PointBatch, locale::Language, plan, render::{self, Vertex}, slice};
+
+/// Width and height of the live slice, in pixels
+const LIVE_SLICE_SIZE: u32 = 1024;
+
+/// What the live slice is drawn from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LiveView {
+    /// Elevation of the clipping plane, in file coordinates
+    pub elevation: f64,
+    /// Bounds of the loaded files, the slice is fitted to them
+    pub bounds: (DVec3, DVec3),
+    pub point_size: f32,
+    pub slice_width: f32,
+    /// Version of the vertex buffers
+    pub points_version: u64,
+}
+
+/// Top down cutaway and slice at the clip height, shown beside the 3D view and drawn again whenever the clip height
+/// or points change
+pub struct LiveSlice {
+    cutaway: Texture2d,
+    slice: Texture2d,
+    depth: DepthRenderBuffer,
+    texture: Option<egui::TextureHandle>,
+    view: Option<LiveView>,
+    /// Where the slice's pixels are in the world
+    transform: Option<plan::CaptureTransform>,
+}
+
+impl LiveSlice {
+    pub fn new<F: Facade>(facade: &F) -> Result<LiveSlice, String> {
+        Ok(LiveSlice {
+            cutaway: render::capture_texture(facade, LIVE_SLICE_SIZE, LIVE_SLICE_SIZE),
+            slice: render::capture_texture(facade, LIVE_SLICE_SIZE, LIVE_SLICE_SIZE),
+            depth: DepthRenderBuffer::new(facade, glium::texture::DepthFormat::F32, LIVE_SLICE_SIZE, LIVE_SLICE_SIZE)
+                .map_err(|err| format!("Failed to create live slice depth buffer: {}", err))?,
+            texture: None,
+            view: None,
+            transform: None,
+        })
+    }
+
+    pub fn is_stale(&self, view: &LiveView) -> bool {
+        self.view.as_ref() != Some(view)
+    }
+
+    /// Draw the slice for `view` from the 3D view's vertex buffers, with the point and slice programs
+    pub fn draw<F: Facade>(&mut self, facade: &F, ctx: &egui::Context, (vertex_buffers, batches): (&[VertexBuffer<Vertex>], &[PointBatch]),
+        (point_program, slice_program): (&Program, &Program), view: LiveView) -> Result<(), String> {
+        let (min, max) = view.bounds;
+        let centre = (min + max) / 2.0;
+        let zoom = slice::fit_zoom(max - min, LIVE_SLICE_SIZE, LIVE_SLICE_SIZE);
+        let projection = camera::projection(zoom, LIVE_SLICE_SIZE, LIVE_SLICE_SIZE);
+        let modelview = slice::top_down_modelview(view.elevation, centre);
+
+        let mut cutaway_buffer = SimpleFrameBuffer::with_depth_buffer(facade, &self.cutaway, &self.depth).map_err(|err| format!("Failed to create live cutaway buffer: {}", err))?;
+        let mut slice_buffer = SimpleFrameBuffer::new(facade, &self.slice).map_err(|err| format!("Failed to create live slice buffer: {}", err))?;
+
+        cutaway_buffer.clear_color_and_depth(render::CAPTURE_CLEAR_COLOUR, 1.0);
+        slice_buffer.clear_color(1.0, 1.0, 1.0, 0.0);
+
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::Points);
+
+        for (vertex_buffer, batch) in vertex_buffers.iter().zip(batches) {
+            let modelview = modelview * glam::Mat4::from_translation((batch.origin - centre).as_vec3());
+
+            let uniforms = uniform! {
+                u_modelview: modelview.to_cols_array_2d(),
+                u_projection: projection.to_cols_array_2d(),
+                u_clipping: true,
+                u_slice: false,
+                u_slice_width: view.slice_width,
+                u_zoom: LIVE_SLICE_SIZE as f32 / zoom,
+                u_size: view.point_size,
+                u_round_points: true,
+                u_origin: (batch.origin - centre).as_vec3().to_array(),
+                u_centre: centre.as_vec3().to_array(),
+            };
+
+            let draw_params = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::DepthTest::IfLess,
+                    write: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+
+            cutaway_buffer.draw(vertex_buffer, indices, point_program, &uniforms, &draw_params).map_err(|err| format!("Failed to draw live cutaway: {}", err))?;
+            slice_buffer.draw(vertex_buffer, indices, slice_program, &uniforms, &Default::default()).map_err(|err| format!("Failed to draw live slice: {}", err))?;
+        }
+
+        let image = plan::annotated_cutaway(&render::read_image(&self.cutaway), &render::read_image(&self.slice));
+        let image = egui::ColorImage::from_rgba_unmultiplied([image.width() as usize, image.height() as usize], image.as_raw());
+
+        match &mut self.texture {
+            Some(texture) => texture.set(image, egui::TextureFilter::Linear),
+            None => self.texture = Some(ctx.load_texture("live_slice", image, egui::TextureFilter::Linear)),
+        }
+        self.view = Some(view);
+        self.transform = Some(plan::CaptureTransform::new(projection * modelview, LIVE_SLICE_SIZE, LIVE_SLICE_SIZE, centre));
+
+        Ok(())
+    }
+
+    /// Slice fitted to the space left in `ui`, with its elevation and a cross-section `path` (file X and Y) drawn over
+    /// it. While `drawing`, left clicks add to the path. Returns true when it's finished with a right click.
+    pub fn show(&self, ui: &mut egui::Ui, locale: &Language, format_elevation: impl Fn(f64) -> String, path: &mut Vec<DVec2>, drawing: bool) -> bool {
+        let (Some(texture), Some(view), Some(transform)) = (&self.texture, &self.view, &self.transform) else {
+            ui.label(locale.tr("Drawing slice..."));
+            return false;
+        };
+
+        ui.label(locale.format("Slice at {}", &[&format_elevation(view.elevation)]));
+
+        let side = ui.available_width().min(ui.available_height()).max(1.0);
+        let sense = if drawing { egui::Sense::click() } else { egui::Sense::hover() };
+        let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(side), sense);
+
+        let painter = ui.painter_at(rect);
+        painter.add(egui::Shape::image(texture.id(), rect, egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)), egui::Color32::WHITE));
+
+        let scale = LIVE_SLICE_SIZE as f64 / side as f64;
+        let to_world = |position: egui::Pos2| {
+            let pixel = glam::dvec2((position.x - rect.min.x) as f64, (position.y - rect.min.y) as f64) * scale;
+            transform.pixel_to_world(pixel).truncate()
+        };
+        let pixels = glam::DMat2::from_cols(transform.pixel_x.truncate(), transform.pixel_y.truncate()).inverse();
+        let to_screen = |position: DVec2| {
+            let pixel = pixels * (position - transform.origin.truncate()) / scale;
+            rect.min + egui::vec2(pixel.x as f32, pixel.y as f32)
+        };
+
+        let mut finished = false;
+        if drawing {
+            if response.clicked() {
+                if let Some(position) = response.interact_pointer_pos() {
+                    path.push(to_world(position));
+                }
+            }
+            finished = response.secondary_clicked() && path.len() >= 2;
+        }
+
+        let stroke = egui::Stroke::new(2.0, egui::Color32::RED);
+        let mut points: Vec<egui::Pos2> = path.iter().map(|p| to_screen(*p)).collect();
+        if drawing && !finished {
+            points.extend(response.hover_pos().filter(|_| !path.is_empty()));
+        }
+        painter.add(egui::Shape::line(points.clone(), stroke));
+        for point in points.iter().take(path.len()) {
+            painter.circle_filled(*point, 3.0, egui::Color32::RED);
+        }
+
+        finished
+    }
+}
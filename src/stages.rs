@@ -0,0 +1,74 @@
+use std::{fmt::Write as _, fs, path::{Path, PathBuf}};
+
+use image::{Rgba, RgbaImage};
+
+use crate::{export, hough::{self, HoughParameters}, plan};
+
+/// Intermediate images of one cutaway render, from the raw capture to the traced walls
+pub struct Stages<'a> {
+    pub cutaway: &'a RgbaImage,
+    /// Points on the clipping plane, one pixel each
+    pub slice: &'a RgbaImage,
+    pub slice_points: &'a [(u32, u32)],
+    /// Slice after nearby points are joined up
+    pub connected: &'a RgbaImage,
+    pub connect_radius: i32,
+    pub alpha_threshold: u8,
+    pub hough_parameters: &'a HoughParameters,
+}
+
+/// Save every stage to a new numbered folder in `directory`, so wall extraction can be tuned on a tricky dataset.
+/// Returns the folder.
+pub fn dump(directory: &Path, stages: &Stages) -> Result<PathBuf, String> {
+    let folder = (1..u32::MAX)
+        .map(|i| directory.join(format!("stages_{:04}", i)))
+        .find(|folder| !folder.exists())
+        .ok_or_else(|| format!("No free stage folder names left in {}", directory.display()))?;
+
+    fs::create_dir_all(&folder).map_err(|err| format!("Failed to create {}: {}", folder.display(), err))?;
+
+    let (width, height) = stages.slice.dimensions();
+
+    let mut proposals = plan::flatten(stages.connected);
+    let segments = hough::detect_segments(width, height, stages.slice_points, stages.hough_parameters);
+    for segment in &segments {
+        plan::draw_line(&mut proposals, segment.from, segment.to, 2.0, Rgba([255, 140, 0, 255]));
+    }
+
+    // Wall outlines as they'd be exported, over the cutaway
+    let vector_plan = export::vectorise(&plan::FloorPlan::new(stages.cutaway.clone(), stages.slice.clone(), stages.connected.clone()));
+    let mut vectorised = stages.cutaway.clone();
+    for outline in &vector_plan.walls {
+        let outline: Vec<glam::Vec2> = outline.iter().map(|p| p.as_vec2()).collect();
+        plan::draw_polygon(&mut vectorised, &outline, 1.0, Rgba([255, 0, 0, 255]), false);
+    }
+
+    let images = [
+        ("1_points.png", stages.cutaway),
+        ("2_slice.png", &plan::flatten(stages.slice)),
+        ("3_connected.png", &plan::flatten(stages.connected)),
+        ("4_wall_proposals.png", &proposals),
+        ("5_vectorised.png", &vectorised),
+    ];
+
+    for (name, image) in images {
+        let path = folder.join(name);
+        image.save(&path).map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
+    }
+
+    let mut parameters = String::new();
+    let _ = writeln!(parameters, "size: {}x{}", width, height);
+    let _ = writeln!(parameters, "slice points: {}", stages.slice_points.len());
+    let _ = writeln!(parameters, "connect radius: {} px", stages.connect_radius);
+    let _ = writeln!(parameters, "alpha threshold: {}", stages.alpha_threshold);
+    let _ = writeln!(parameters, "hough threshold: {}", stages.hough_parameters.threshold);
+    let _ = writeln!(parameters, "hough min length: {} px", stages.hough_parameters.min_length);
+    let _ = writeln!(parameters, "hough max gap: {} px", stages.hough_parameters.max_gap);
+    let _ = writeln!(parameters, "wall proposals: {}", segments.len());
+    let _ = writeln!(parameters, "wall outlines: {}", vector_plan.walls.len());
+
+    let path = folder.join("parameters.txt");
+    fs::write(&path, parameters).map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
+
+    Ok(folder)
+}
@@ -0,0 +1,434 @@
+//! Points streamed over the network as they're scanned, e.g. from a SLAM backpack scanner, for a live preview.
+//!
+//! The viewer listens, the scanner connects over plain TCP or a WebSocket (told apart by the first bytes) and sends
+//! messages, all little endian:
+//!
+//! - `u8` kind, 1 for points or 2 at the end of a scan
+//! - points: `u32` count, then per point `f64` x, y and z, `u16` intensity, `u16` red, green and blue and `u8`
+//!   classification, 33 bytes each
+//!
+//! Over TCP each message is preceded by its length as a `u32`. Over a WebSocket each is one binary message. Scanners
+//! can reconnect and keep sending, each connection's points are added to the last.
+
+use std::{io::{self, BufRead, BufReader, Read, Write}, net::{SocketAddr, TcpListener, TcpStream}, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, mpsc::{self, Receiver, Sender, TryRecvError}, Arc}, thread, time::{Duration, Instant}};
+
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+use crate::loader::LoaderMessage;
+
+/// Points gathered before they're passed on as a batch, scanners send many small messages
+const STREAM_BATCH_SIZE: usize = 50_000;
+/// Longest points are held back gathering a batch
+const STREAM_BATCH_TIME: Duration = Duration::from_millis(250);
+/// How often the listener checks whether it's been stopped while no scanner is connected
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// Largest message accepted, anything bigger is taken as a broken connection
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+const POINT_SIZE: usize = 33;
+
+const KIND_POINTS: u8 = 1;
+const KIND_END: u8 = 2;
+
+/// Appended to the client's key in the WebSocket handshake, from RFC 6455
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Listens for a scanner streaming points, sending them on as loader messages: batches as they arrive and `Finished`
+/// at the end of each scan or connection. Stops listening when dropped.
+pub struct PointStream {
+    address: SocketAddr,
+    rx: Receiver<LoaderMessage>,
+    stop: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+    received: Arc<AtomicU64>,
+}
+
+impl PointStream {
+    /// Listen on `address`, e.g. `0.0.0.0:7070`
+    pub fn listen(address: &str) -> Result<PointStream, String> {
+        let listener = TcpListener::bind(address).map_err(|err| format!("Failed to listen on {}: {}", address, err))?;
+        listener.set_nonblocking(true).map_err(|err| format!("Failed to listen on {}: {}", address, err))?;
+        let address = listener.local_addr().map_err(|err| format!("Failed to listen on {}: {}", address, err))?;
+
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let connected = Arc::new(AtomicBool::new(false));
+        let received = Arc::new(AtomicU64::new(0));
+
+        let (thread_stop, thread_connected, thread_received) = (stop.clone(), connected.clone(), received.clone());
+
+        thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((socket, peer)) => {
+                        println!("Streaming points from {}", peer);
+                        thread_connected.store(true, Ordering::Relaxed);
+
+                        let mut sender = BatchSender { tx: &tx, batch: vec![], started: Instant::now(), received: &thread_received };
+                        if let Err(err) = receive(socket, &mut sender, &thread_stop) {
+                            eprintln!("Point stream from {} stopped: {}", peer, err);
+                        }
+
+                        thread_connected.store(false, Ordering::Relaxed);
+                        if sender.finish().is_err() {
+                            break;
+                        }
+                    },
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                    Err(err) => {
+                        eprintln!("Stopped listening for points: {}", err);
+                        break;
+                    },
+                }
+            }
+        });
+
+        Ok(PointStream { address, rx, stop, connected, received })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Whether a scanner is connected
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Points received over every connection so far
+    pub fn points_received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    pub fn try_recv(&self) -> Result<LoaderMessage, TryRecvError> {
+        self.rx.try_recv()
+    }
+}
+
+impl Drop for PointStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Gathers streamed points into batches for the main thread
+struct BatchSender<'a> {
+    tx: &'a Sender<LoaderMessage>,
+    batch: Vec<las::Point>,
+    started: Instant,
+    received: &'a AtomicU64,
+}
+
+impl BatchSender<'_> {
+    /// Fails once nothing is receiving, the stream was dropped
+    fn push(&mut self, points: Vec<las::Point>) -> Result<(), String> {
+        if self.batch.is_empty() {
+            self.started = Instant::now();
+        }
+
+        self.received.fetch_add(points.len() as u64, Ordering::Relaxed);
+        self.batch.extend(points);
+
+        if self.batch.len() >= STREAM_BATCH_SIZE || self.started.elapsed() >= STREAM_BATCH_TIME {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        if !self.batch.is_empty() {
+            self.tx.send(LoaderMessage::Batch(std::mem::take(&mut self.batch))).map_err(|_| "the viewer stopped listening".to_owned())?;
+        }
+
+        Ok(())
+    }
+
+    /// Send what's left and mark the end of a scan
+    fn finish(&mut self) -> Result<(), String> {
+        self.flush()?;
+        self.tx.send(LoaderMessage::Finished { invalid_points: 0, error: None }).map_err(|_| "the viewer stopped listening".to_owned())
+    }
+}
+
+/// Read messages from a connection until it closes or the stream is stopped
+fn receive(socket: TcpStream, sender: &mut BatchSender, stop: &AtomicBool) -> Result<(), String> {
+    socket.set_nonblocking(false).map_err(|err| err.to_string())?;
+    // Wakes up now and then to flush a partial batch and check for being stopped
+    socket.set_read_timeout(Some(STREAM_BATCH_TIME)).map_err(|err| err.to_string())?;
+
+    let mut reader = BufReader::new(socket.try_clone().map_err(|err| err.to_string())?);
+    let websocket = wait(|| reader.fill_buf().map(|buffer| buffer.starts_with(b"GET ")), sender, stop)?;
+
+    if websocket {
+        accept_websocket(&mut reader, &socket)?;
+    }
+
+    loop {
+        let message = if websocket {
+            read_websocket_message(&mut reader, &socket, sender, stop)?
+        } else {
+            read_framed_message(&mut reader, sender, stop)?
+        };
+
+        let Some(message) = message else {
+            return Ok(());
+        };
+
+        match message.first() {
+            Some(&KIND_POINTS) => sender.push(parse_points(&message[1..])?)?,
+            Some(&KIND_END) => sender.finish()?,
+            Some(kind) => return Err(format!("unknown message kind {}", kind)),
+            None => return Err("empty message".to_owned()),
+        }
+    }
+}
+
+/// Run a read, retrying when it times out, after passing on any points held back too long
+fn wait<T>(mut read: impl FnMut() -> io::Result<T>, sender: &mut BatchSender, stop: &AtomicBool) -> Result<T, String> {
+    loop {
+        match read() {
+            Ok(value) => return Ok(value),
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                if stop.load(Ordering::Relaxed) {
+                    return Err("stopped listening".to_owned());
+                }
+                sender.flush()?;
+            },
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+}
+
+/// Fill `buffer`, None if the connection closed before the first byte
+fn read_exact(reader: &mut impl Read, buffer: &mut [u8], sender: &mut BatchSender, stop: &AtomicBool) -> Result<Option<()>, String> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        let read = wait(|| reader.read(&mut buffer[filled..]), sender, stop)?;
+
+        if read == 0 {
+            return if filled == 0 { Ok(None) } else { Err("connection closed partway through a message".to_owned()) };
+        }
+        filled += read;
+    }
+
+    Ok(Some(()))
+}
+
+fn read_framed_message(reader: &mut impl Read, sender: &mut BatchSender, stop: &AtomicBool) -> Result<Option<Vec<u8>>, String> {
+    let mut length = [0; 4];
+    if read_exact(reader, &mut length, sender, stop)?.is_none() {
+        return Ok(None);
+    }
+
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(format!("message of {} bytes is too long", length));
+    }
+
+    let mut message = vec![0; length];
+    read_exact(reader, &mut message, sender, stop)?.ok_or("connection closed partway through a message")?;
+
+    Ok(Some(message))
+}
+
+/// Answer the WebSocket opening handshake
+fn accept_websocket(reader: &mut impl BufRead, mut socket: &TcpStream) -> Result<(), String> {
+    let mut key = None;
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|err| err.to_string())?;
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    let key = key.ok_or("WebSocket request has no key")?;
+    let accept = base64::engine::general_purpose::STANDARD.encode(Sha1::digest(format!("{}{}", key, WEBSOCKET_GUID)));
+
+    write!(socket, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept)
+        .map_err(|err| err.to_string())
+}
+
+/// Next binary message, joining fragments and answering pings. None once the client closes.
+fn read_websocket_message(reader: &mut impl Read, mut socket: &TcpStream, sender: &mut BatchSender, stop: &AtomicBool) -> Result<Option<Vec<u8>>, String> {
+    let mut message = vec![];
+
+    loop {
+        let mut header = [0; 2];
+        if read_exact(reader, &mut header, sender, stop)?.is_none() {
+            return Ok(None);
+        }
+
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        let masked = header[1] & 0x80 != 0;
+
+        let mut read_bytes = |count: usize| -> Result<Vec<u8>, String> {
+            let mut bytes = vec![0; count];
+            read_exact(reader, &mut bytes, sender, stop)?.ok_or("connection closed partway through a frame")?;
+            Ok(bytes)
+        };
+
+        let length = match header[1] & 0x7f {
+            126 => u16::from_be_bytes(read_bytes(2)?.try_into().expect("Read 2 bytes")) as usize,
+            127 => {
+                let length = u64::from_be_bytes(read_bytes(8)?.try_into().expect("Read 8 bytes"));
+                usize::try_from(length).map_err(|_| format!("frame of {} bytes is too long", length))?
+            },
+            length => length as usize,
+        };
+        message.len().checked_add(length).filter(|n| *n <= MAX_MESSAGE_SIZE)
+            .ok_or_else(|| format!("frame of {} bytes makes the message too long", length))?;
+
+        let mask = if masked { Some(read_bytes(4)?) } else { None };
+        let mut payload = read_bytes(length)?;
+        if let Some(mask) = mask {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+
+        match opcode {
+            // Continuation, text (taken as binary) and binary
+            0..=2 => {
+                message.extend(payload);
+                if fin {
+                    return Ok(Some(message));
+                }
+            },
+            // Close
+            8 => {
+                let _ = socket.write_all(&[0x88, 0]);
+                return Ok(None);
+            },
+            // Ping, answered with a pong carrying the same data
+            9 if payload.len() < 126 => {
+                socket.write_all(&[0x8a, payload.len() as u8]).and_then(|_| socket.write_all(&payload)).map_err(|err| err.to_string())?;
+            },
+            _ => {},
+        }
+    }
+}
+
+fn parse_points(data: &[u8]) -> Result<Vec<las::Point>, String> {
+    let count = data.get(..4).map(|count| u32::from_le_bytes(count.try_into().expect("Took 4 bytes")) as usize).ok_or("points message has no count")?;
+    let points = &data[4..];
+
+    if points.len() != count * POINT_SIZE {
+        return Err(format!("points message should have {} bytes of points, it has {}", count * POINT_SIZE, points.len()));
+    }
+
+    Ok(points.chunks_exact(POINT_SIZE)
+        .map(|point| {
+            let f64_at = |i: usize| f64::from_le_bytes(point[i..i + 8].try_into().expect("Took 8 bytes"));
+            let u16_at = |i: usize| u16::from_le_bytes(point[i..i + 2].try_into().expect("Took 2 bytes"));
+
+            las::Point {
+                x: f64_at(0),
+                y: f64_at(8),
+                z: f64_at(16),
+                intensity: u16_at(24),
+                color: Some(las::Color::new(u16_at(26), u16_at(28), u16_at(30))),
+                classification: las::point::Classification::new(point[32]).unwrap_or_default(),
+                ..Default::default()
+            }
+        })
+        // Invalid coordinates would corrupt the bounds and the view
+        .filter(|point| point.x.is_finite() && point.y.is_finite() && point.z.is_finite())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point_bytes(x: f64, y: f64, z: f64) -> Vec<u8> {
+        let mut bytes = vec![];
+        for coordinate in [x, y, z] {
+            bytes.extend(coordinate.to_le_bytes());
+        }
+        for value in [100_u16, 1, 2, 3] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.push(2);
+
+        bytes
+    }
+
+    fn message(points: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = (points.len() as u32).to_le_bytes().to_vec();
+        data.extend(points.concat());
+
+        data
+    }
+
+    #[test]
+    fn parse_points_reads_every_field() {
+        let points = parse_points(&message(&[point_bytes(1.5, -2.0, 300.25)])).expect("Valid message");
+
+        assert_eq!(points.len(), 1);
+        let point = &points[0];
+        assert_eq!((point.x, point.y, point.z), (1.5, -2.0, 300.25));
+        assert_eq!(point.intensity, 100);
+        assert_eq!(point.color, Some(las::Color::new(1, 2, 3)));
+        assert_eq!(point.classification, las::point::Classification::Ground);
+    }
+
+    #[test]
+    fn parse_points_drops_invalid_coordinates() {
+        let points = parse_points(&message(&[point_bytes(f64::NAN, 0.0, 0.0), point_bytes(1.0, 2.0, 3.0)])).expect("Valid message");
+
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].x, 1.0);
+    }
+
+    fn read_frames(mut frames: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+        let socket = TcpStream::connect(listener.local_addr().expect("Listening")).expect("Failed to connect");
+        let (tx, _rx) = mpsc::channel();
+        let received = AtomicU64::new(0);
+        let mut sender = BatchSender {
+            tx: &tx,
+            batch: vec![],
+            started: Instant::now(),
+            received: &received,
+        };
+
+        read_websocket_message(&mut frames, &socket, &mut sender, &AtomicBool::new(false))
+    }
+
+    #[test]
+    fn websocket_messages_join_fragments() {
+        let frames = [0x02, 2, 1, 2, 0x80, 0x82, 0, 0, 0, 0, 3, 4];
+
+        assert_eq!(read_frames(&frames), Ok(Some(vec![1, 2, 3, 4])));
+    }
+
+    #[test]
+    fn websocket_messages_reject_huge_lengths() {
+        let overflowing = [0x02, 2, 1, 2, 0x80, 127, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let oversized = [0x82, 127, 0, 0, 0, 0, 0x04, 0, 0, 1];
+
+        assert_eq!(read_frames(&overflowing), Err(format!("frame of {} bytes makes the message too long", usize::MAX)));
+        assert_eq!(read_frames(&oversized), Err("frame of 67108865 bytes makes the message too long".to_owned()));
+    }
+
+    #[test]
+    fn parse_points_checks_the_length() {
+        let mut data = message(&[point_bytes(0.0, 0.0, 0.0)]);
+        data.pop();
+
+        assert_eq!(parse_points(&data).unwrap_err(), "points message should have 33 bytes of points, it has 32");
+        assert_eq!(parse_points(&[1, 0]).unwrap_err(), "points message has no count");
+    }
+}
@@ -0,0 +1,119 @@
+use std::{fs, path::Path};
+
+use las::{point::Format, Read, Write};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{filter::PointFilter, loader::PointBatch, slice::SliceBand};
+
+/// Write the selected points to a new LAS file (LAZ if the extension is .laz), keeping the header of the file they
+/// came from: scale, offset, point format and VLRs such as the coordinate system. Selections spanning several files
+/// use the header of the first. Returns the number of points written.
+pub fn export_selection(batches: &[PointBatch], files: &[String], path: &Path) -> Result<usize, String> {
+    let kept: Vec<&[bool]> = batches.iter().map(|batch| batch.selected.as_slice()).collect();
+
+    let source = source_file(batches, &kept, files).ok_or_else(|| "No points are selected".to_owned())?;
+
+    write_las(batches, &kept, source, path)
+}
+
+/// Write the points the filter shows within a cutaway's slice band, so vectorising tools get the geometry behind the
+/// slice image rather than a raster. LAS or LAZ as for `export_selection`, or PLY with a .ply extension. Returns the
+/// number of points written.
+pub fn export_slice(batches: &[PointBatch], filter: &PointFilter, band: &SliceBand, files: &[String], path: &Path) -> Result<usize, String> {
+    let kept: Vec<Vec<bool>> = batches.par_iter()
+        .map(|batch| band.contains(batch).into_iter().zip(&batch.points).map(|(inside, point)| inside && filter.accepts(point)).collect())
+        .collect();
+    let kept: Vec<&[bool]> = kept.iter().map(Vec::as_slice).collect();
+
+    let source = source_file(batches, &kept, files).ok_or_else(|| "No points are in the slice".to_owned())?;
+
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ply")) {
+        write_ply(batches, &kept, path)
+    } else {
+        write_las(batches, &kept, source, path)
+    }
+}
+
+/// File the first kept point came from, its header is reused. `kept` has a slice of flags for each batch.
+fn source_file<'a>(batches: &[PointBatch], kept: &[&[bool]], files: &'a [String]) -> Option<&'a String> {
+    batches.iter().zip(kept)
+        .find(|(_, kept)| kept.contains(&true))
+        .map(|(batch, _)| &files[batch.file])
+}
+
+fn write_las(batches: &[PointBatch], kept: &[&[bool]], source: &str, path: &Path) -> Result<usize, String> {
+    let header = las::Reader::from_path(source)
+        .map_err(|err| format!("Failed to read the header of {}: {}", source, err))?
+        .header()
+        .clone();
+
+    let mut builder = las::Builder::from(header);
+    // Waveform packets point into data that isn't copied
+    builder.point_format.has_waveform = false;
+    let header = builder.into_header().map_err(|err| format!("Unable to reuse the header of {}: {}", source, err))?;
+    let format = *header.point_format();
+
+    let mut writer = las::Writer::from_path(path, header).map_err(|err| format!("Failed to create {}: {}", path.display(), err))?;
+    let mut written = 0;
+
+    for point in kept_points(batches, kept) {
+        writer.write(conform(point.clone(), &format)).map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        written += 1;
+    }
+
+    writer.close().map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+
+    Ok(written)
+}
+
+/// Binary PLY with double precision positions, so georeferenced coordinates survive, and colour, intensity and
+/// classification
+fn write_ply(batches: &[PointBatch], kept: &[&[bool]], path: &Path) -> Result<usize, String> {
+    let count = kept.iter().map(|kept| kept.iter().filter(|kept| **kept).count()).sum::<usize>();
+
+    let header = format!(
+        "ply\nformat binary_little_endian 1.0\ncomment Exported by point-cloud-cutaway\nelement vertex {}\n\
+        property double x\nproperty double y\nproperty double z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        property ushort intensity\nproperty uchar classification\nend_header\n",
+        count,
+    );
+
+    let mut bytes = header.into_bytes();
+    bytes.reserve(count * 30);
+
+    for point in kept_points(batches, kept) {
+        for v in [point.x, point.y, point.z] {
+            bytes.extend(v.to_le_bytes());
+        }
+        let colour = point.color.map_or([u8::MAX; 3], |colour| [colour.red, colour.green, colour.blue].map(|c| (c / 256) as u8));
+        bytes.extend(colour);
+        bytes.extend(point.intensity.to_le_bytes());
+        bytes.push(u8::from(point.classification));
+    }
+
+    fs::write(path, bytes).map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+
+    Ok(count)
+}
+
+fn kept_points<'a>(batches: &'a [PointBatch], kept: &'a [&[bool]]) -> impl Iterator<Item = &'a las::Point> {
+    batches.iter().zip(kept).flat_map(|(batch, kept)| batch.points.iter().zip(kept.iter()).filter(|(_, kept)| **kept).map(|(point, _)| point))
+}
+
+/// Add or drop attributes so the point fits `format`, e.g. points from another file or a PDAL pipeline's output
+fn conform(mut point: las::Point, format: &Format) -> las::Point {
+    if format.has_gps_time != point.gps_time.is_some() {
+        point.gps_time = format.has_gps_time.then_some(0.0);
+    }
+    if format.has_color != point.color.is_some() {
+        point.color = format.has_color.then(las::Color::default);
+    }
+    if format.has_nir != point.nir.is_some() {
+        point.nir = format.has_nir.then_some(0);
+    }
+    point.waveform = None;
+    point.extra_bytes.resize(format.extra_bytes as usize, 0);
+
+    point
+}
@@ -0,0 +1,114 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use glium::backend::Facade;
+use point_cloud_cutaway::{config::{self, Config}, georef, ortho::{self, TileRenderer}, pdf, plan, slice::{ConnectPoints, Scene, Slicer}};
+
+use crate::cli::{HeadlessArgs, OrthoArgs, SweepArgs};
+
+/// Render a slice every `step` between two elevations, looking straight down at the whole file,
+/// writing each as a numbered PNG (and optionally all of them to one PDF)
+pub fn run<F: Facade>(facade: &F, filename: &str, point_size: f32, num_points: u64, output_dir: &Path, args: &SweepArgs, config: &Config) -> Result<(), String> {
+    if args.step.is_nan() || args.step <= 0.0 {
+        return Err("--step must be greater than 0".to_owned());
+    }
+    if args.to < args.from {
+        return Err("--to must not be below --from".to_owned());
+    }
+
+    let scene = Scene::load(facade, filename, num_points)?;
+    let slicer = Slicer::new(facade, &scene, args.width, args.height)?;
+
+    std::fs::create_dir_all(output_dir).map_err(|err| format!("Failed to create {}: {}", output_dir.display(), err))?;
+
+    let font = plan::label_font();
+    let mut document = args.pdf.as_ref().map(|_| pdf::Document::new());
+    let count = ((args.to - args.from) / args.step + 1.0e-9).floor() as usize + 1;
+
+    for i in 0..count {
+        let elevation = args.from + i as f64 * args.step;
+
+        let images = slicer.render(elevation, point_size, config, &ConnectPoints)?;
+
+        let mut image = plan::annotated_cutaway(&images.cutaway, &images.slice);
+        plan::draw_scale_bar(&mut image, &images.transform, &font);
+        plan::draw_north_arrow(&mut image, &images.transform, georef::north(config.north_offset as f64), &font);
+        let path = output_dir.join(format!("slice_{:04}.png", i + 1));
+
+        image.save(&path).map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
+        println!("Saved {} (elevation {:.3})", path.display(), elevation);
+
+        if let Some(document) = &mut document {
+            document.add_page(&image, &format!("{} - elevation {:.3}", filename, elevation))
+                .map_err(|err| format!("Failed to compress page {}: {}", i + 1, err))?;
+        }
+    }
+
+    if let (Some(document), Some(path)) = (&document, &args.pdf) {
+        let mut file = File::create(path).map(BufWriter::new).map_err(|err| format!("Failed to create {}: {}", path, err))?;
+        document.write(&mut file).map_err(|err| format!("Failed to write {}: {}", path, err))?;
+
+        println!("Saved {}", path);
+    }
+
+    Ok(())
+}
+
+/// Render one slice of the whole file, saving the cutaway and the processed (connected) slice to the output directory
+/// under the next free quick render names
+pub fn cutaway<F: Facade>(facade: &F, filename: &str, point_size: f32, num_points: u64, output_dir: &Path, args: &HeadlessArgs, config: &Config) -> Result<(), String> {
+    let elevation = args.slice_height.ok_or_else(|| "--headless needs the elevation to slice at (--slice-height)".to_owned())?;
+    let (width, height) = args.resolution;
+
+    let scene = Scene::load(facade, filename, num_points)?;
+    let slicer = Slicer::new(facade, &scene, width, height)?;
+
+    let images = slicer.render(elevation, point_size, config, &ConnectPoints)?;
+
+    std::fs::create_dir_all(output_dir).map_err(|err| format!("Failed to create {}: {}", output_dir.display(), err))?;
+
+    let index = config::next_output_index(output_dir, config);
+
+    for (image, template) in [(&images.cutaway, &config.cutaway_template), (&images.slice, &config.floor_plan_template)] {
+        let path = output_dir.join(config::file_name(template, index));
+
+        image.save(&path).map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
+        println!("Saved {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Render the whole file straight down at `--pixel-size`, tile by tile, to `ortho.png` with its world file and
+/// coordinate system, or with `--xyz` to a tile pyramid in `tiles/`
+pub fn ortho<F: Facade>(facade: &F, filename: &str, point_size: f32, num_points: u64, output_dir: &Path, args: &OrthoArgs) -> Result<(), String> {
+    if args.pixel_size.is_nan() || args.pixel_size <= 0.0 {
+        return Err("--pixel-size must be greater than 0".to_owned());
+    }
+
+    let scene = Scene::load(facade, filename, num_points)?;
+    let tile_size = if args.xyz { ortho::XYZ_TILE_SIZE } else { args.tile_size };
+    let renderer = TileRenderer::new(facade, &scene, tile_size)?;
+
+    std::fs::create_dir_all(output_dir).map_err(|err| format!("Failed to create {}: {}", output_dir.display(), err))?;
+
+    if args.xyz {
+        let dir = output_dir.join("tiles");
+        let max_zoom = ortho::write_xyz_tiles(&renderer, &dir, args.pixel_size, point_size, |zoom| println!("Rendered zoom level {}", zoom))?;
+
+        println!("Saved tiles for zoom levels 0 to {} to {}", max_zoom, dir.display());
+    } else {
+        let path = output_dir.join("ortho.png");
+        let transform = ortho::write_orthoimage(&renderer, &path, args.pixel_size, point_size, |row, rows| println!("Rendered row {} of {}", row, rows))?;
+
+        println!("Saved {}", path.display());
+
+        // Only LAS and LAZ files carry a coordinate system
+        if let Ok(georeference) = georef::Georeference::read(filename) {
+            for written in georeference.write_image_georeferencing(&path, &transform)? {
+                println!("Saved {}", written.display());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use glam::{DMat4, DQuat, DVec3, EulerRot, Mat4};
+use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::loader::PointBatch;
+
+/// Moves, rotates and scales the loaded points, e.g. to level a scan or square its walls to the axes before slicing.
+/// Rotation and scale are about a pivot, the centre the points are rendered around.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    /// In file units
+    pub translation: DVec3,
+    /// Degrees about the file's X, Y and Z axes, applied in that order
+    pub rotation: DVec3,
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            translation: DVec3::ZERO,
+            rotation: DVec3::ZERO,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn is_identity(&self) -> bool {
+        *self == Transform::default()
+    }
+
+    pub fn quat(&self) -> DQuat {
+        let [x, y, z] = self.rotation.to_array().map(f64::to_radians);
+
+        DQuat::from_euler(EulerRot::ZYX, z, y, x)
+    }
+
+    fn set_quat(&mut self, quat: DQuat) {
+        let (z, y, x) = quat.to_euler(EulerRot::ZYX);
+
+        self.rotation = glam::dvec3(x, y, z).to_array().map(f64::to_degrees).into();
+    }
+
+    /// File coordinates to transformed file coordinates
+    pub fn matrix(&self, pivot: DVec3) -> DMat4 {
+        DMat4::from_translation(pivot + self.translation)
+            * DMat4::from_scale_rotation_translation(DVec3::splat(self.scale), self.quat(), DVec3::ZERO)
+            * DMat4::from_translation(-pivot)
+    }
+
+    /// Model matrix of a batch at `origin`, from its points to transformed coordinates relative to `pivot`
+    pub fn batch_model(&self, origin: DVec3, pivot: DVec3) -> Mat4 {
+        (DMat4::from_translation(-pivot) * self.matrix(pivot) * DMat4::from_translation(origin)).as_mat4()
+    }
+
+    /// Tilt so a floor with `normal` (untransformed) is horizontal, keeping the heading
+    pub fn level(&mut self, normal: DVec3) {
+        let normal = if normal.z < 0.0 { -normal } else { normal };
+        let heading = DQuat::from_rotation_z(self.rotation.z.to_radians());
+
+        self.set_quat(heading * DQuat::from_rotation_arc(normal.normalize(), DVec3::Z));
+    }
+
+    /// Turn so a wall with `normal` (untransformed) lines up with the nearest of the X and Y axes
+    pub fn square(&mut self, normal: DVec3) {
+        let normal = self.quat() * normal;
+        let angle = normal.y.atan2(normal.x);
+        let quarter = std::f64::consts::FRAC_PI_2;
+        let offset = angle - (angle / quarter).round() * quarter;
+
+        self.set_quat(DQuat::from_rotation_z(-offset) * self.quat());
+    }
+}
+
+/// Move every point by `matrix`, and each batch's origin with them
+pub fn apply<'a>(batches: impl IntoIterator<Item = &'a mut PointBatch>, matrix: DMat4) {
+    for batch in batches {
+        batch.points.par_iter_mut().for_each(|point| {
+            let p = matrix.transform_point3(glam::dvec3(point.x, point.y, point.z));
+
+            point.x = p.x;
+            point.y = p.y;
+            point.z = p.z;
+        });
+
+        batch.origin = matrix.transform_point3(batch.origin);
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fs, path::PathBuf};
+
+/// Steps of the guided walkthrough, from a point cloud to an exported floor plan
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Step {
+    Load,
+    PositionClip,
+    Render,
+    Trace,
+    IdentifyRooms,
+    Export,
+}
+
+impl Step {
+    pub const ALL: [Step; 6] = [Step::Load, Step::PositionClip, Step::Render, Step::Trace, Step::IdentifyRooms, Step::Export];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Step::Load => "Load a Point Cloud",
+            Step::PositionClip => "Position the Clipping Plane",
+            Step::Render => "Render the Cutaway",
+            Step::Trace => "Trace the Walls",
+            Step::IdentifyRooms => "Identify the Rooms",
+            Step::Export => "Export the Floor Plan",
+        }
+    }
+
+    pub fn instructions(self) -> &'static str {
+        match self {
+            Step::Load => "Open a LAS or LAZ scan of a building with the highlighted button, or paste its path into the \
+                window (Ctrl+V).",
+            Step::PositionClip => "Show the cutaway to cut away everything in front of the camera's clipping plane. \
+                Fly down into the building with the movement keys until the plane is about a metre above the floor, or \
+                detect the floor levels and pick one to snap to.",
+            Step::Render => "Render the slice through the walls at the clipping plane. The points it cuts are joined \
+                into walls and open as a floor plan to draw on.",
+            Step::Trace => "Fix the walls with the highlighted tools: the pencil and shapes add walls, the eraser \
+                removes scan noise, and proposed walls can be accepted or rejected.",
+            Step::IdentifyRooms => "Fill each room with the room tool, left clicking inside it. Right click walls and \
+                the outside to fill them as the exterior. Rooms and their areas are listed as they're filled.",
+            Step::Export => "Save the finished plan as an image, or export it to SVG, DXF or PDF for drawing and CAD \
+                software.",
+        }
+    }
+
+    /// Position in the walkthrough, from 0
+    pub fn index(self) -> usize {
+        Step::ALL.iter().position(|step| *step == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Option<Step> {
+        Step::ALL.get(self.index() + 1).copied()
+    }
+
+    pub fn previous(self) -> Option<Step> {
+        self.index().checked_sub(1).map(|i| Step::ALL[i])
+    }
+}
+
+/// Left once the walkthrough is finished or closed, so it only opens by itself the first time
+fn seen_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("point-cloud-cutaway").join("walkthrough_seen"))
+}
+
+/// Whether the walkthrough has been seen before. Without a config directory to remember it in, it has.
+pub fn seen() -> bool {
+    seen_file().is_none_or(|file| file.exists())
+}
+
+pub fn mark_seen() {
+    if let Some(file) = seen_file() {
+        let result = file.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&file, ""));
+
+        if let Err(err) = result {
+            eprintln!("Failed to save {}: {}", file.display(), err);
+        }
+    }
+}
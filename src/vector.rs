@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use glam::{DVec2, IVec2};
+
+/// Closed outlines along the pixel edges of the region where `inside` holds, in image coordinates.
+/// `pixels` must cover the region. Outer boundaries run clockwise on screen, holes anticlockwise.
+pub fn trace_outlines(pixels: impl Iterator<Item = (u32, u32)>, inside: impl Fn(i64, i64) -> bool) -> Vec<Vec<DVec2>> {
+    // Directed boundary edges, interior on the right (y down)
+    let mut edges: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+    let mut add_edge = |from: IVec2, to: IVec2| edges.entry(from).or_default().push(to);
+
+    for (x, y) in pixels {
+        let (xi, yi) = (x as i64, y as i64);
+        let p = IVec2::new(x as i32, y as i32);
+
+        if !inside(xi, yi - 1) {
+            add_edge(p, p + IVec2::X);
+        }
+        if !inside(xi + 1, yi) {
+            add_edge(p + IVec2::X, p + IVec2::ONE);
+        }
+        if !inside(xi, yi + 1) {
+            add_edge(p + IVec2::ONE, p + IVec2::Y);
+        }
+        if !inside(xi - 1, yi) {
+            add_edge(p + IVec2::Y, p);
+        }
+    }
+
+    let mut outlines = vec![];
+
+    // Every vertex has as many edges in as out, so following edges always returns to the start
+    while let Some(&start) = edges.keys().next() {
+        let mut outline = vec![start.as_dvec2()];
+        let mut current = start;
+
+        loop {
+            let next = {
+                let outgoing = edges.get_mut(&current).expect("Unmatched boundary edge");
+                let next = outgoing.pop().expect("Unmatched boundary edge");
+
+                if outgoing.is_empty() {
+                    edges.remove(&current);
+                }
+
+                next
+            };
+
+            if next == start {
+                break;
+            }
+
+            outline.push(next.as_dvec2());
+            current = next;
+        }
+
+        outlines.push(outline);
+    }
+
+    outlines
+}
+
+/// 4-connected regions of the pixels where `inside` holds
+pub fn connected_regions(width: u32, height: u32, inside: impl Fn(u32, u32) -> bool) -> Vec<Vec<(u32, u32)>> {
+    let mut visited = vec![false; width as usize * height as usize];
+    let index = |x: u32, y: u32| y as usize * width as usize + x as usize;
+
+    let mut regions = vec![];
+
+    for y in 0..height {
+        for x in 0..width {
+            if visited[index(x, y)] || !inside(x, y) {
+                continue;
+            }
+
+            let mut region = vec![];
+            let mut stack = vec![(x, y)];
+            visited[index(x, y)] = true;
+
+            while let Some((px, py)) = stack.pop() {
+                region.push((px, py));
+
+                let neighbours = [
+                    (px.wrapping_sub(1), py),
+                    (px + 1, py),
+                    (px, py.wrapping_sub(1)),
+                    (px, py + 1),
+                ];
+
+                for (nx, ny) in neighbours {
+                    if nx < width && ny < height && !visited[index(nx, ny)] && inside(nx, ny) {
+                        visited[index(nx, ny)] = true;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+
+            regions.push(region);
+        }
+    }
+
+    regions
+}
+
+/// Douglas-Peucker simplification of a closed outline
+pub fn simplify(outline: &[DVec2], tolerance: f64) -> Vec<DVec2> {
+    if outline.len() < 4 {
+        return outline.to_vec();
+    }
+
+    let mut closed = outline.to_vec();
+    closed.push(outline[0]);
+
+    let mut keep = vec![false; closed.len()];
+    keep[0] = true;
+    keep[closed.len() - 1] = true;
+
+    let mut stack = vec![(0, closed.len() - 1)];
+
+    while let Some((first, last)) = stack.pop() {
+        let (a, b) = (closed[first], closed[last]);
+
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(closed[i], a, b)))
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                stack.push((first, i));
+                stack.push((i, last));
+            }
+        }
+    }
+
+    closed.pop();
+
+    closed.into_iter().zip(keep).filter(|(_, keep)| *keep).map(|(p, _)| p).collect()
+}
+
+/// Distance from `p` to the segment from `a` to `b`
+pub fn segment_distance(p: DVec2, a: DVec2, b: DVec2) -> f64 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    p.distance(a + ab * t)
+}
+
+/// Direction of the two dominant, perpendicular axes of a set of closed outlines, the other being its perpendicular.
+/// Edges vote with their length, angles taken four times over so edges along either axis agree.
+pub fn dominant_direction(outlines: &[Vec<DVec2>]) -> DVec2 {
+    let mut sum = DVec2::ZERO;
+
+    for outline in outlines {
+        for (i, a) in outline.iter().enumerate() {
+            let edge = outline[(i + 1) % outline.len()] - *a;
+            let angle = edge.y.atan2(edge.x);
+
+            sum += DVec2::new((4.0 * angle).cos(), (4.0 * angle).sin()) * edge.length();
+        }
+    }
+
+    let angle = sum.y.atan2(sum.x) / 4.0;
+    DVec2::new(angle.cos(), angle.sin())
+}
+
+/// Clean up closed outlines traced from a noisy scan. Vertices of any outlines closer than `join_distance` are joined,
+/// then edges within `tolerance` radians of the dominant axes are straightened onto them, runs of edges along the
+/// same axis becoming one, so walls meet at right angles. Edges further off keep their direction.
+pub fn regularise(outlines: &[Vec<DVec2>], tolerance: f64, join_distance: f64) -> Vec<Vec<DVec2>> {
+    let axis = dominant_direction(outlines);
+    let joined = join_vertices(outlines, join_distance);
+
+    joined.iter()
+        .map(|outline| {
+            // Edges shorter than the join distance are noise along a wall
+            let mut points: Vec<DVec2> = vec![];
+            for p in outline {
+                if points.last().is_none_or(|last| last.distance(*p) >= join_distance.max(f64::EPSILON)) {
+                    points.push(*p);
+                }
+            }
+            while points.len() > 1 && points[0].distance(points[points.len() - 1]) < join_distance.max(f64::EPSILON) {
+                points.pop();
+            }
+
+            straighten(&points, axis, tolerance)
+        })
+        .filter(|outline| outline.len() >= 3)
+        .collect()
+}
+
+/// Move vertices within `distance` of each other, in the same or different outlines, to where they are on average
+fn join_vertices(outlines: &[Vec<DVec2>], distance: f64) -> Vec<Vec<DVec2>> {
+    if distance <= 0.0 {
+        return outlines.to_vec();
+    }
+
+    let vertices: Vec<DVec2> = outlines.iter().flatten().copied().collect();
+    let cell = |p: DVec2| (p / distance).floor().as_ivec2();
+
+    let mut grid: HashMap<IVec2, Vec<usize>> = HashMap::new();
+    for (i, p) in vertices.iter().enumerate() {
+        grid.entry(cell(*p)).or_default().push(i);
+    }
+
+    // Union-find over the vertices near each other
+    let mut parent: Vec<usize> = (0..vertices.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for (i, p) in vertices.iter().enumerate() {
+        let centre = cell(*p);
+
+        for offset in (-1..=1).flat_map(|x| (-1..=1).map(move |y| IVec2::new(x, y))) {
+            for &j in grid.get(&(centre + offset)).into_iter().flatten() {
+                if j > i && p.distance(vertices[j]) < distance {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    if a != b {
+                        parent[a.max(b)] = a.min(b);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut sums: HashMap<usize, (DVec2, f64)> = HashMap::new();
+    for (i, p) in vertices.iter().enumerate() {
+        let sum = sums.entry(root(&mut parent, i)).or_insert((DVec2::ZERO, 0.0));
+        sum.0 += *p;
+        sum.1 += 1.0;
+    }
+
+    let mut i = 0;
+    outlines.iter()
+        .map(|outline| {
+            outline.iter()
+                .map(|_| {
+                    let (sum, count) = sums[&root(&mut parent, i)];
+                    i += 1;
+                    sum / count
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Outline with its edges near `axis` or its perpendicular put on them, see `regularise`
+fn straighten(outline: &[DVec2], axis: DVec2, tolerance: f64) -> Vec<DVec2> {
+    let n = outline.len();
+    if n < 3 {
+        return outline.to_vec();
+    }
+
+    let edge = |i: usize| (outline[i % n], outline[(i + 1) % n]);
+
+    // 0 along the axis, 1 across it, None for neither
+    let classes: Vec<Option<u8>> = (0..n)
+        .map(|i| {
+            let (a, b) = edge(i);
+            let along = (b - a).normalize_or_zero().dot(axis).abs().clamp(0.0, 1.0).acos();
+
+            if along <= tolerance {
+                Some(0)
+            } else if std::f64::consts::FRAC_PI_2 - along <= tolerance {
+                Some(1)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Start at an edge that begins a run, so runs don't wrap around the end
+    let Some(start) = (0..n).find(|&i| classes[i].is_none() || classes[i] != classes[(i + n - 1) % n]) else {
+        return outline.to_vec();
+    };
+
+    struct Run {
+        point: DVec2,
+        direction: DVec2,
+        length: f64,
+        /// Traced vertex the run ends at
+        end: usize,
+    }
+
+    // A run of edges along one axis becomes one line along it, through their middle. Edges along neither stay put.
+    let mut runs: Vec<Run> = vec![];
+    let mut i = start;
+    while i < start + n {
+        let class = classes[i % n];
+        let (mut sum, mut length) = (DVec2::ZERO, 0.0);
+        let first = i;
+
+        loop {
+            let (a, b) = edge(i);
+            sum += (a + b) / 2.0 * a.distance(b);
+            length += a.distance(b);
+            i += 1;
+
+            if class.is_none() || i >= start + n || classes[i % n] != class {
+                break;
+            }
+        }
+
+        let (a, b) = edge(first);
+        let (point, direction) = match class {
+            Some(0) => (sum / length.max(f64::EPSILON), axis),
+            Some(_) => (sum / length.max(f64::EPSILON), axis.perp()),
+            None => (a, (b - a).normalize_or_zero()),
+        };
+
+        runs.push(Run { point, direction, length, end: i % n });
+    }
+
+    // Corners where each line meets the next. Where they're near parallel, or would meet far from the traced corner,
+    // the traced corner is kept.
+    runs.iter()
+        .enumerate()
+        .map(|(k, run)| {
+            let next = &runs[(k + 1) % runs.len()];
+            let traced = outline[run.end];
+
+            let cross = run.direction.perp_dot(next.direction);
+            if cross.abs() < 1.0e-3 {
+                return traced;
+            }
+
+            let corner = run.point + run.direction * (next.point - run.point).perp_dot(next.direction) / cross;
+            if corner.distance(traced) > run.length.min(next.length) / 2.0 {
+                traced
+            } else {
+                corner
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Twice the signed area, positive for outlines running clockwise on screen
+    fn signed_area(outline: &[DVec2]) -> f64 {
+        (0..outline.len()).map(|i| outline[i].perp_dot(outline[(i + 1) % outline.len()])).sum()
+    }
+
+    fn square(size: u32, hole: bool) -> Vec<Vec<DVec2>> {
+        let inside = move |x: i64, y: i64| {
+            let within = (0..size as i64).contains(&x) && (0..size as i64).contains(&y);
+            let centre = hole && x == size as i64 / 2 && y == size as i64 / 2;
+
+            within && !centre
+        };
+        let pixels = (0..size).flat_map(|y| (0..size).map(move |x| (x, y))).filter(move |&(x, y)| inside(x as i64, y as i64));
+
+        trace_outlines(pixels, inside)
+    }
+
+    #[test]
+    fn trace_outlines_follows_pixel_edges() {
+        let outlines = square(2, false);
+
+        assert_eq!(outlines.len(), 1);
+        assert_eq!(outlines[0].len(), 8);
+        assert_eq!(signed_area(&outlines[0]), 8.0);
+    }
+
+    #[test]
+    fn trace_outlines_winds_holes_the_other_way() {
+        let mut outlines = square(3, true);
+        outlines.sort_by_key(|outline| std::cmp::Reverse(outline.len()));
+
+        assert_eq!(outlines.len(), 2);
+        assert_eq!(signed_area(&outlines[0]), 18.0);
+        assert_eq!(signed_area(&outlines[1]), -2.0);
+    }
+
+    #[test]
+    fn simplify_keeps_corners() {
+        let outline: Vec<DVec2> = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 1.0), (2.0, 2.0), (1.0, 2.0), (0.0, 2.0), (0.0, 1.0)]
+            .into_iter()
+            .map(|(x, y)| glam::dvec2(x, y))
+            .collect();
+
+        assert_eq!(simplify(&outline, 0.1), vec![outline[0], outline[2], outline[4], outline[6]]);
+    }
+
+    #[test]
+    fn simplify_keeps_detail_beyond_tolerance() {
+        let notched = [
+            glam::dvec2(0.0, 0.0),
+            glam::dvec2(5.0, 0.0),
+            glam::dvec2(5.0, 3.0),
+            glam::dvec2(10.0, 3.0),
+            glam::dvec2(10.0, 10.0),
+            glam::dvec2(0.0, 10.0),
+        ];
+
+        assert_eq!(simplify(&notched, 0.5).len(), 6);
+        assert_eq!(simplify(&notched, 2.0).len(), 4);
+        assert_eq!(simplify(&notched[..3], 2.0), notched[..3].to_vec());
+    }
+}
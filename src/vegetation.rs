@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use glam::{DMat3, DVec3};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{geometry, loader::PointBatch};
+
+/// Width of the voxels roughness is measured over, in file units
+const ROUGHNESS_VOXEL: f64 = 0.5;
+/// Voxels with fewer points than this are taken as smooth, too few to tell
+const MIN_VOXEL_POINTS: u32 = 6;
+pub const DEFAULT_GREENNESS: f32 = 0.08;
+pub const DEFAULT_ROUGHNESS: f32 = 0.08;
+
+/// Sums of the positions in a voxel, relative to its corner to keep precision
+#[derive(Clone, Copy, Default)]
+struct Moments {
+    count: u32,
+    sum: DVec3,
+    /// xx, yy, zz, xy, xz, yz
+    products: [f64; 6],
+}
+
+impl Moments {
+    fn add(&mut self, p: DVec3) {
+        self.count += 1;
+        self.sum += p;
+        for (product, value) in self.products.iter_mut().zip([p.x * p.x, p.y * p.y, p.z * p.z, p.x * p.y, p.x * p.z, p.y * p.z]) {
+            *product += value;
+        }
+    }
+
+    fn merge(&mut self, other: &Moments) {
+        self.count += other.count;
+        self.sum += other.sum;
+        for (product, value) in self.products.iter_mut().zip(other.products) {
+            *product += value;
+        }
+    }
+
+    /// Surface variation: smallest eigenvalue of the covariance over their sum, 0 on a plane up to 1/3 for scatter
+    fn roughness(&self) -> f32 {
+        let n = self.count as f64;
+        let mean = self.sum / n;
+        let [xx, yy, zz, xy, xz, yz] = self.products.map(|product| product / n);
+
+        let covariance = DMat3::from_cols_array(&[
+            xx - mean.x * mean.x, xy - mean.x * mean.y, xz - mean.x * mean.z,
+            xy - mean.x * mean.y, yy - mean.y * mean.y, yz - mean.y * mean.z,
+            xz - mean.x * mean.z, yz - mean.y * mean.z, zz - mean.z * mean.z,
+        ]);
+        let (values, _) = geometry::symmetric_eigen(covariance);
+        let total: f64 = values.iter().map(|value| value.max(0.0)).sum();
+
+        if total > 0.0 {
+            (values.iter().copied().fold(f64::INFINITY, f64::min).max(0.0) / total) as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Roughness of the points in each voxel of a grid over the point cloud. Foliage scatters points through a voxel,
+/// walls, roofs and the ground lay them on a plane.
+pub struct RoughnessGrid {
+    roughness: HashMap<[i64; 3], f32>,
+    /// Points it was made from, to tell when they've changed
+    points: usize,
+}
+
+impl RoughnessGrid {
+    pub fn new(batches: &[PointBatch]) -> RoughnessGrid {
+        let voxel = |p: DVec3| (p / ROUGHNESS_VOXEL).floor();
+
+        let moments = batches.par_iter()
+            .map(|batch| {
+                let mut moments: HashMap<[i64; 3], Moments> = HashMap::new();
+                for point in &batch.points {
+                    let p = glam::dvec3(point.x, point.y, point.z);
+                    let corner = voxel(p);
+                    moments.entry(corner.to_array().map(|v| v as i64)).or_default().add(p - corner * ROUGHNESS_VOXEL);
+                }
+                moments
+            })
+            .reduce(HashMap::new, |mut a, b| {
+                for (voxel, moments) in b {
+                    a.entry(voxel).or_default().merge(&moments);
+                }
+                a
+            });
+
+        let roughness = moments.into_iter()
+            .filter(|(_, moments)| moments.count >= MIN_VOXEL_POINTS)
+            .map(|(voxel, moments)| (voxel, moments.roughness()))
+            .collect();
+
+        RoughnessGrid {
+            roughness,
+            points: batches.iter().map(|batch| batch.points.len()).sum(),
+        }
+    }
+
+    /// Roughness of the voxel `point` is in, 0 where there are too few points to tell
+    pub fn roughness(&self, point: &las::Point) -> f32 {
+        let voxel = [point.x, point.y, point.z].map(|v| (v / ROUGHNESS_VOXEL).floor() as i64);
+        self.roughness.get(&voxel).copied().unwrap_or(0.0)
+    }
+}
+
+/// Visible-band stand-in for NDVI (green leaf index), -1 to 1 and above 0 for green
+pub fn greenness(colour: las::Color) -> f32 {
+    let [r, g, b] = [colour.red, colour.green, colour.blue].map(|channel| channel as f32);
+    let total = 2.0 * g + r + b;
+
+    if total > 0.0 {
+        (2.0 * g - r - b) / total
+    } else {
+        0.0
+    }
+}
+
+/// Heuristics for trees and bushes around a building, to hide them from the view and slices of scans that aren't
+/// classified. A point is vegetation when it's an early return, or when it's green and rough. With only one of
+/// colour and roughness used, or points without colour, the other decides alone.
+pub struct VegetationFilter {
+    /// Returns before the last of a pulse that came back more than once, the canopy above what the pulse hit last
+    pub early_returns: bool,
+    /// Points greener than this, see `greenness`
+    pub greenness: Option<f32>,
+    /// Points in voxels rougher than this, see `RoughnessGrid`
+    pub roughness: Option<f32>,
+    grid: Option<RoughnessGrid>,
+}
+
+impl Default for VegetationFilter {
+    fn default() -> VegetationFilter {
+        VegetationFilter {
+            early_returns: true,
+            greenness: Some(DEFAULT_GREENNESS),
+            roughness: Some(DEFAULT_ROUGHNESS),
+            grid: None,
+        }
+    }
+}
+
+impl VegetationFilter {
+    /// Measure roughness again if it's used and `batches` aren't the points it was measured on. Points are judged
+    /// without roughness until it is.
+    pub fn update_roughness(&mut self, batches: &[PointBatch]) {
+        if self.roughness.is_none() {
+            self.grid = None;
+            return;
+        }
+
+        let points: usize = batches.iter().map(|batch| batch.points.len()).sum();
+        if self.grid.as_ref().is_none_or(|grid| grid.points != points) {
+            self.grid = Some(RoughnessGrid::new(batches));
+        }
+    }
+
+    pub fn is_vegetation(&self, point: &las::Point) -> bool {
+        if self.early_returns && point.number_of_returns > 1 && point.return_number < point.number_of_returns {
+            return true;
+        }
+
+        let green = self.greenness.zip(point.color).map(|(threshold, colour)| greenness(colour) > threshold);
+        let rough = self.roughness.zip(self.grid.as_ref()).map(|(threshold, grid)| grid.roughness(point) > threshold);
+
+        match (green, rough) {
+            (Some(green), Some(rough)) => green && rough,
+            (Some(only), None) | (None, Some(only)) => only,
+            (None, None) => false,
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::time::Instant;
+
+use glium::glutin::{self, event::{VirtualKeyCode, MouseButton, ElementState}, dpi::PhysicalPosition};
+
+use crate::{gamepad, keymap};
+
+use super::{Viewer, DEFAULT_FPS, SCROLL_LINE_PIXELS, AUTO_ORBIT_GRACE};
+
+impl Viewer {
+    /// Handle a window or device event, true when it's time to draw a frame
+    pub fn handle_event(&mut self, event: glutin::event::Event<()>, control_flow: &mut glutin::event_loop::ControlFlow) -> bool {
+        match event {
+            glutin::event::Event::WindowEvent { event, .. } => {
+                if matches!(event, glutin::event::WindowEvent::KeyboardInput { .. } | glutin::event::WindowEvent::MouseInput { .. }
+                    | glutin::event::WindowEvent::MouseWheel { .. } | glutin::event::WindowEvent::CursorMoved { .. }) {
+                    self.last_input = Instant::now();
+                }
+                
+                // Next key pressed while rebinding goes to the keymap rather than the UI. Escape cancels.
+                if let (Some(action), glutin::event::WindowEvent::KeyboardInput { input, .. }) = (self.rebinding, &event) {
+                    if let (ElementState::Pressed, Some(key)) = (input.state, input.virtual_keycode) {
+                        if key == VirtualKeyCode::Escape {
+                            self.rebinding = None;
+                        } else if self.keymap.bind(action, key) {
+                            self.keymap.save();
+                            self.rebinding = None;
+                        }
+
+                        return false;
+                    }
+                }
+
+                if self.egui_glium.on_event(&event) {
+                    return false;
+                }
+                
+                match event {
+                    glutin::event::WindowEvent::CloseRequested => {
+                        *control_flow = glutin::event_loop::ControlFlow::Exit;
+                        return false;
+                    },
+                    glutin::event::WindowEvent::KeyboardInput { input, .. } => {
+                        self.keyboard.update(input);
+
+                        if input.state == ElementState::Pressed {
+                            if let Some(key) = input.virtual_keycode {
+                                match key {
+                                    VirtualKeyCode::Escape if self.drawing_mode => {
+                                        self.exit_drawing_mode = true;
+                                    },
+                                    VirtualKeyCode::Escape => {
+                                        let gl_window = self.display.gl_window();
+                                        let window = gl_window.window();
+
+                                        let _ = window.set_cursor_grab(glutin::window::CursorGrabMode::None);
+                                        let _ = window.set_cursor_visible(true);
+            
+                                        self.mouse_locked = false;
+                                    },
+                                    // VirtualKeyCode::F => {
+                                    //     if colour_format == 1 {
+                                    //         colour_format = 2;
+                                    //     } else if colour_format == 2 {
+                                    //         colour_format = 1;
+                                    //     }
+
+                                    //     println!("Colour Format: {}", colour_format * 8);
+                                    // },
+                                    _ if self.keymap.action(key) == Some(keymap::Action::ToggleSlice) => {
+                                        self.show_slice = !self.show_slice;
+                                    },
+                                    VirtualKeyCode::Return => {
+                                        self.finish_shape = true;
+                                    },
+                                    VirtualKeyCode::Back if self.measuring && !self.drawing_mode => {
+                                        self.measure_points.pop();
+                                    },
+                                    VirtualKeyCode::Delete if !self.drawing_mode && self.selected_points > 0 => {
+                                        self.delete_selection_queued = true;
+                                    },
+                                    _ if self.keymap.action(key) == Some(keymap::Action::QuickRender) && !self.drawing_mode && self.batch_number < 0 => {
+                                        self.cutaway_queued = true;
+                                        self.quick_render_queued = true;
+                                    },
+                                    _ => {},
+                                }
+                            }
+                        }
+
+                        return false;
+                    },
+                    glutin::event::WindowEvent::MouseInput { button, state, .. } => {
+                        self.mouse.update(button, state);
+                        
+                        if state == ElementState::Pressed {
+                            match button {
+                                // Dragged out in the update
+                                MouseButton::Left if self.select_tool.is_some() && !self.drawing_mode => {},
+                                MouseButton::Left if (self.measuring || self.inspecting || self.profiling) && !self.drawing_mode => {
+                                    self.pick_queued = true;
+                                },
+                                MouseButton::Left => {
+                                    let gl_window = self.display.gl_window();
+                                    let window = gl_window.window();
+                                    
+                                    if let Err(_) = window.set_cursor_grab(glutin::window::CursorGrabMode::Locked) {
+                                        // eprintln!("Failed to lock cursor, confining to window instead! {:?}", err);
+                                        if let Err(err) = window.set_cursor_grab(glutin::window::CursorGrabMode::Confined) {
+                                            eprintln!("Failed to lock or confine cursor! {:?}", err);
+                                            return false;
+                                        }
+                                    }
+                                    window.set_cursor_visible(false);
+
+                                    self.mouse_locked = true;
+                                },
+                                MouseButton::Right => {
+                                    let gl_window = self.display.gl_window();
+                                    let window = gl_window.window();
+                                    
+                                    let _ = window.set_cursor_grab(glutin::window::CursorGrabMode::None);
+                                    let _ = window.set_cursor_visible(true);
+        
+                                    self.mouse_locked = false;
+                                },
+                                _ => {},
+                            }
+                        }
+                        return false;
+                    },
+                    glutin::event::WindowEvent::MouseWheel { delta, .. } => {
+                        match delta {
+                            glutin::event::MouseScrollDelta::LineDelta(_x, y) => {
+                                self.camera_zoom += y;
+                            },
+                            // Touchpads scroll by pixels: two finger scrolling pans, pinching (Ctrl + scroll on most platforms) zooms
+                            glutin::event::MouseScrollDelta::PixelDelta(delta) => {
+                                if self.modifiers.ctrl() {
+                                    self.camera_zoom += delta.y as f32 / SCROLL_LINE_PIXELS;
+                                } else if !self.drawing_mode {
+                                    let window_width = self.display.gl_window().window().inner_size().width.max(1);
+                                    let units_per_pixel = 2.0_f32.powf(-self.camera_zoom / 10.0) / window_width as f32;
+                                    let rotation = glam::Quat::from_euler(glam::EulerRot::YXZ, self.camera_rotation.x, self.camera_rotation.y, 0.0);
+
+                                    // Move the cloud with the fingers
+                                    self.camera_position += rotation * glam::vec3(-delta.x as f32, delta.y as f32, 0.0) * units_per_pixel;
+                                }
+                            },
+                        };
+                        return false;
+                    },
+                    glutin::event::WindowEvent::ModifiersChanged(state) => {
+                        self.modifiers = state;
+                        return false;
+                    },
+                    glutin::event::WindowEvent::CursorMoved { position, .. } => {
+                        self.mouse.update_position(glam::Vec2::new(position.x as f32, position.y as f32));
+                        return false;
+                    }
+                    _ => return false,
+                };
+            },
+            glutin::event::Event::DeviceEvent { event, .. } => match event {
+                glutin::event::DeviceEvent::MouseMotion { delta } => {
+                    self.mouse_delta += glam::vec2(delta.0 as f32, delta.1 as f32);
+
+                    // Motion is reported outside the window too, only count it while looking around
+                    if self.mouse_locked {
+                        self.last_input = Instant::now();
+                    }
+                    return false;
+                },
+                _ => return false,
+            },
+            glutin::event::Event::NewEvents(cause) => match cause {
+                glutin::event::StartCause::ResumeTimeReached { .. } => (),
+                glutin::event::StartCause::Init => (),
+                glutin::event::StartCause::Poll => (),
+                _ => return false,
+            },
+            // glutin::event::Event::MainEventsCleared => {
+            //     display.gl_window().window().request_redraw();
+            // }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Look around with the mouse and move with the keys and gamepad, or orbit on its own
+    pub(super) fn move_camera(&mut self, frame_length: f32, window_width: u32, window_height: u32) {
+        if !self.mouse_locked {
+            self.mouse_delta = glam::Vec2::ZERO;
+        }
+
+        let gamepad_input = self.gamepad.as_mut().map_or_else(gamepad::GamepadInput::default, |gamepad| gamepad.poll(frame_length));
+
+        if !gamepad_input.is_idle() {
+            self.last_input = Instant::now();
+        }
+
+        // Auto-orbit, stopped by any input
+        if self.auto_orbit_when_idle && !self.auto_orbit && self.last_input.elapsed().as_secs_f32() >= self.auto_orbit_idle_time {
+            self.auto_orbit = true;
+            self.auto_orbit_started = Instant::now();
+        }
+
+        if self.auto_orbit {
+            if self.last_input.saturating_duration_since(self.auto_orbit_started).as_secs_f32() > AUTO_ORBIT_GRACE {
+                self.auto_orbit = false;
+            } else {
+                let angle = self.auto_orbit_speed.to_radians() * frame_length;
+
+                self.camera_position = glam::Quat::from_rotation_y(angle) * self.camera_position;
+                self.camera_rotation.x += angle;
+            }
+        }
+
+        // GPS time playback, the filter follows the time as it plays
+        if let Some(span) = self.point_counts.gps_time {
+            if self.playback.advance(span, frame_length) {
+                self.point_filter.gps_time = self.playback.filter();
+                self.rebuild_vertex_buffers = true;
+            }
+        }
+
+        // Handle movement
+        
+        // speed in units per second
+        let speed = if self.keyboard.is_pressed(self.keymap.key(keymap::Action::Sprint)) || gamepad_input.sprint {
+            75.0
+        } else {
+            15.0
+        };
+        let angular_speed = 0.1 / DEFAULT_FPS as f32; // radians per pixel the mouse moves
+        let forward = glam::Quat::from_euler(glam::EulerRot::YZX, self.camera_rotation.x, self.camera_rotation.y, 0.0) * glam::Vec3::Z;
+        let right = glam::Quat::from_axis_angle(glam::Vec3::Y, self.camera_rotation.x + std::f32::consts::PI / 2.0) * glam::Vec3::Z;
+
+        let mut direction = glam::Vec3::ZERO;
+
+        if self.keyboard.is_pressed(self.keymap.key(keymap::Action::Forward)) {
+            direction += forward;
+        }
+        
+        if self.keyboard.is_pressed(self.keymap.key(keymap::Action::Back)) {
+            direction += -forward;
+        }
+        
+        if self.keyboard.is_pressed(self.keymap.key(keymap::Action::Left)) {
+            direction += -right;
+        }
+        
+        if self.keyboard.is_pressed(self.keymap.key(keymap::Action::Right)) {
+            direction += right;
+        }
+        
+        if self.keyboard.is_pressed(self.keymap.key(keymap::Action::Up)) {
+            direction += glam::Vec3::Y;
+        }
+        
+        if self.keyboard.is_pressed(self.keymap.key(keymap::Action::Down)) {
+            direction += glam::Vec3::NEG_Y;
+        }
+
+        // Gamepad sticks and triggers can move slower than the keys
+        let analog = gamepad_input.movement;
+        direction = (direction.normalize_or_zero() + right * analog.x + glam::Vec3::Y * analog.y + forward * analog.z).clamp_length_max(1.0);
+
+        self.mouse_delta += gamepad_input.look;
+        self.camera_zoom += gamepad_input.zoom;
+
+        self.camera_position += direction * speed * frame_length;
+        self.camera_rotation += self.mouse_delta * angular_speed;
+
+        self.camera_rotation.y = self.camera_rotation.y.clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+
+        self.mouse_delta = glam::Vec2::ZERO;
+
+        if self.mouse_locked {
+            let _ = self.display.gl_window().window().set_cursor_position(PhysicalPosition::new(window_width / 2, window_height / 2));
+        }
+    }
+
+    /// Give the cursor back, e.g. when leaving the 3D view
+    pub(super) fn unlock_mouse(&mut self) {
+    if self.mouse_locked {
+        let gl_window = self.display.gl_window();
+        let window = gl_window.window();
+        
+        let _ = window.set_cursor_grab(glutin::window::CursorGrabMode::None);
+        let _ = window.set_cursor_visible(true);
+
+        self.mouse_locked = false;
+    }
+    }
+}
@@ -0,0 +1,394 @@
+use std::{sync::mpsc::{self, Receiver}, path::PathBuf};
+
+use crate::{colour, filter, footprint, georef, jobs, locale, pdal, playback, recent, render::{self, batch_vertices}, reproject, stream, transform::Transform, tutorial, watch};
+use crate::loader::{self, load_point_cloud, LoaderMessage, PointBatch, LoadedFile, LoadRequest};
+
+use super::{Viewer, advance_tutorial};
+
+impl Viewer {
+    /// Load picked, pasted and watched files, taking in points as they're read or streamed
+    pub(super) fn poll_loading(&mut self) {
+        let locale = &self.languages[self.language];
+
+        if let Some(r) = &self.path_rx {
+            match r.try_recv() {
+                Ok((path, append)) => {
+                    self.load_job = Some(queue_load(&mut self.job_queue, locale, path, append, self.pdal_pipeline.clone().filter(|_| self.preprocess_with_pdal)));
+                },
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.path_rx = None;
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+            }
+        }
+
+        if let Some(r) = &self.watch_folder_rx {
+            match r.try_recv() {
+                Ok(dir) => match watch::FolderWatch::new(&dir) {
+                    Ok(watch) => {
+                        self.watch_queue.clear();
+                        self.folder_watch = Some(watch);
+                    },
+                    Err(err) => self.load_warnings.push(err),
+                },
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.watch_folder_rx = None;
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+            }
+        }
+
+        if let Some(watch) = &mut self.folder_watch {
+            match watch.poll() {
+                Ok(paths) => {
+                    for path in paths.into_iter().map(|path| path.to_string_lossy().into_owned()) {
+                        if !self.watch_queue.contains(&path) {
+                            self.watch_queue.push_back(path);
+                        }
+                    }
+                },
+                Err(err) => {
+                    eprintln!("{}", err);
+                    self.load_warnings.push(err);
+                    self.folder_watch = None;
+                },
+            }
+        }
+
+        // Watched files load one at a time, after anything else loading
+        if self.path_rx.is_none() && self.load_job.is_none() && self.rx.is_none() {
+            if let Some(path) = self.watch_queue.pop_front() {
+                self.load_job = Some(queue_load(&mut self.job_queue, locale, path, !self.loaded_files.is_empty(), self.pdal_pipeline.clone().filter(|_| self.preprocess_with_pdal)));
+            }
+        }
+
+        // After any file being opened, which would replace the stream's points
+        if self.listen_queued && self.path_rx.is_none() && self.load_job.is_none() {
+            self.listen_queued = false;
+
+            match stream::PointStream::listen(&self.stream_address) {
+                Ok(listener) => {
+                    println!("Listening for points on {}", listener.address());
+
+                    self.loaded_files.push(LoadedFile {
+                        path: format!("tcp://{}", listener.address()),
+                        bounds: None,
+                        preprocessed: false,
+                        georeference: georef::Georeference::default(),
+                        extra_bytes: vec![],
+                    });
+                    self.point_stream = Some((listener, self.loaded_files.len() - 1));
+                },
+                Err(err) => {
+                    eprintln!("{}", err);
+                    self.load_warnings.push(err);
+                },
+            }
+        }
+
+        if let Some(result) = self.load_job.as_ref().and_then(jobs::Job::poll) {
+            self.load_job = None;
+
+            match result {
+                Ok(LoadRequest { path, append, preprocessed }) => {
+                    let load_path = preprocessed.as_ref().map_or(path.clone(), |output| output.to_string_lossy().into_owned());
+
+                    let source_georeference = georef::Georeference::read(&load_path).unwrap_or_else(|err| {
+                        eprintln!("{}", err);
+                        georef::Georeference::default()
+                    });
+                    let reprojection = self.reproject_to.map_or(Ok(None), |target| reproject::Reprojection::new(&source_georeference, target))
+                        .map_err(|err| format!("Couldn't reproject {}: {}", path, err));
+                    let georeference = match &reprojection {
+                        Ok(Some(reprojection)) => reprojection.georeference(&source_georeference),
+                        _ => source_georeference,
+                    };
+
+                    match reprojection.and_then(|reprojection| load_point_cloud(&load_path, self.num_points, reprojection)) {
+                        Ok((n, c, r, reader)) => {
+                            if !append {
+                                self.vertex_buffers = vec![];
+                                self.point_batches = vec![];
+                                self.loaded_files = vec![];
+                                self.point_counts = filter::AttributeCounts::default();
+                                self.point_filter.hidden_point_sources.clear();
+                                self.point_filter.hidden_classifications.clear();
+                                self.point_filter.gps_time = None;
+                                self.playback = playback::Playback::default();
+                                self.point_transform = Transform::default();
+                                self.registration = None;
+                                self.register_job = None;
+                                self.section.path.clear();
+                                self.section_image = None;
+                                self.profile_line.clear();
+                                self.profile = None;
+                                self.contours.clear();
+                                self.contour_lines = None;
+                                self.elevation_raster = None;
+                                self.raster_layer = None;
+                                self.mesh_preview = None;
+                                self.mesh_preview_stats = None;
+                                self.mesh_job = None;
+                                self.selected_points = 0;
+                                self.centre = None;
+                                self.density_grid = None;
+                                self.cloud_distances = None;
+                                self.clusters = None;
+                                self.clusters_job = None;
+                                self.contours_job = None;
+                                self.raster_job = None;
+                                self.planes_job = None;
+                                self.minimap = None;
+                                self.live_slice = None;
+                                // Its file is gone
+                                self.point_stream = None;
+                            }
+
+                            // Header centre until the file has loaded, recomputed from all the loaded files afterwards
+                            if self.centre.is_none() {
+                                self.centre = Some(c);
+                            }
+
+                            recent::add(&mut self.recent_files, &path);
+
+                            let extra_bytes = loader::ExtraBytes::read(&load_path).unwrap_or_else(|err| {
+                                eprintln!("{}", err);
+                                vec![]
+                            });
+
+                            let file = LoadedFile {
+                                path,
+                                bounds: None,
+                                preprocessed: preprocessed.is_some(),
+                                georeference,
+                                extra_bytes,
+                            };
+
+                            // Adding a file that's already loaded (e.g. a watched file the scanner added to) replaces
+                            // its points, keeping its place so per-file settings still apply
+                            match self.loaded_files.iter().position(|loaded| append && loaded.path == file.path) {
+                                Some(index) => {
+                                    let mut removed = filter::AttributeCounts::default();
+                                    for i in (0..self.point_batches.len()).rev() {
+                                        if self.point_batches[i].file == index {
+                                            for point in &self.point_batches.remove(i).points {
+                                                removed.add(point);
+                                            }
+                                            self.vertex_buffers.remove(i);
+                                        }
+                                    }
+                                    self.point_counts.subtract(&removed);
+                                    self.points_version += 1;
+
+                                    self.density_grid = None;
+                                    self.cloud_distances = None;
+                                    self.minimap = None;
+                                    self.live_slice = None;
+                                    self.picked_point = None;
+
+                                    self.loaded_files[index] = file;
+                                    self.loading_file = index;
+                                },
+                                None => {
+                                    self.loaded_files.push(file);
+                                    self.loading_file = self.loaded_files.len() - 1;
+                                },
+                            }
+                            self._preprocessed_file = preprocessed;
+
+                            self.total_points = n;
+                            self.rx = Some(r);
+                            self._read_job = Some(self.job_queue.submit(locale.format("Reading {}", &[&recent::file_name(&self.loaded_files[self.loading_file].path)]), move |context| reader.read(context)));
+                            self.batch_number = 0;
+                        },
+                        // Whatever was loaded before stays
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            self.load_error = Some(err);
+                            self.pending_view = None;
+                        },
+                    }
+                },
+                Err(err) => {
+                    eprintln!("{}", err);
+                    self.load_warnings.push(err);
+                },
+            }
+        }
+
+        if let Some(r) = &self.footprint_rx {
+            match r.try_recv() {
+                Ok(path) => match footprint::Footprint::read(&path) {
+                    Ok(imported) => {
+                        let elevation = self.footprint.as_ref().and_then(|(clip, _)| clip.elevation.clone());
+                        let clip = footprint::FootprintClip { footprint: imported, elevation };
+
+                        self.point_filter.footprint = Some(clip.clone());
+                        self.footprint = Some((clip, path));
+                        self.footprint_error = None;
+                        self.rebuild_vertex_buffers = true;
+                    },
+                    Err(err) => self.footprint_error = Some(err),
+                },
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.footprint_rx = None;
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+            }
+        }
+
+        if let Some(r) = &self.pdal_pipeline_rx {
+            match r.try_recv() {
+                Ok(path) => {
+                    self.pdal_pipeline = Some(path);
+                    self.preprocess_with_pdal = true;
+                },
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.pdal_pipeline_rx = None;
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+            }
+        }
+
+        if let Some(r) = &self.output_dir_rx {
+            match r.try_recv() {
+                Ok(path) => self.output_dir = path,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.output_dir_rx = None;
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+            }
+        }
+
+        // Points from the file loading, otherwise from the scanner streaming them
+        let received = match self.rx.as_ref().map(Receiver::try_recv) {
+            None | Some(Err(mpsc::TryRecvError::Empty)) => self.point_stream.as_ref().map(|(listener, file)| (*file, true, listener.try_recv())),
+            Some(message) => Some((self.loading_file, false, message)),
+        };
+
+        if let Some((file, streamed, message)) = received {
+            match message {
+                Ok(LoaderMessage::Batch(points)) => {
+                    for point in &points {
+                        self.point_counts.add(point);
+                    }
+
+                    if let Some((min, max)) = loader::bounds(&points) {
+                        self.loaded_files[file].bounds = loader::union_bounds(self.loaded_files[file].bounds, Some((min, max)));
+
+                        // Header bounds were unusable, use the valid points loaded so far
+                        if !self.centre.is_some_and(|c| c.is_finite()) {
+                            self.centre = Some((min + max) / 2.0);
+                        }
+
+                        let batch = PointBatch::new(points, (min + max) / 2.0, file);
+
+                        self.vertex_buffers.push(glium::VertexBuffer::new(&self.display, &batch_vertices(&batch, &self.point_filter, &colour::Colouring {
+                            mode: self.colour_mode,
+                            ramps: &self.colour_ramps,
+                            elevation_range: loader::elevation_range(&self.loaded_files),
+                            planes: &self.detected_planes,
+                            plane_threshold: self.plane_threshold as f64,
+                            density: self.density_grid.as_ref(),
+                            distances: self.cloud_distances.as_ref().and_then(|distances| distances.as_ref().ok()),
+                            distance_threshold: self.distance_threshold as f64,
+                            clusters: self.clusters.as_ref(),
+                        })).expect("Failed to create point vertex buffer."));
+                        self.point_batches.push(batch);
+                        self.points_version += 1;
+                    }
+
+                    // Streams go on without a progress bar
+                    if !streamed {
+                        self.batch_number += 1;
+
+                        println!("Processed Batch {}", self.batch_number);
+                    }
+                },
+                Ok(LoaderMessage::Finished { invalid_points, error }) => {
+                    advance_tutorial(&mut self.tutorial_step, tutorial::Step::Load);
+
+                    if invalid_points > 0 {
+                        self.load_warnings.push(locale.format("Dropped {} points with NaN or infinite coordinates", &[&invalid_points]));
+                    }
+                    if let Some(error) = error {
+                        self.load_error = Some(error);
+                    }
+
+                    if !streamed {
+                        self._preprocessed_file = None;
+                    }
+
+                    // Thumbnail of the file just loaded, for the recent files
+                    if let Some(loaded) = self.loaded_files.get(file).filter(|loaded| !streamed && !recent::has_thumbnail(&loaded.path)) {
+                        if let Some(bounds) = loaded.bounds {
+                            let points = self.point_batches.iter().filter(|batch| batch.file == file).flat_map(|batch| batch.points.iter());
+                            let stride = (points.clone().count() / recent::THUMBNAIL_POINTS).max(1);
+
+                            recent::save_thumbnail(&loaded.path, &recent::render_thumbnail(points.step_by(stride), bounds, recent::THUMBNAIL_SIZE));
+                            self.thumbnail_textures.remove(&loaded.path);
+                        }
+                    }
+
+                    // Recentre on the valid data of all loaded files, moving the camera with it so the view doesn't jump
+                    let bounds = self.loaded_files.iter().fold(None, |bounds, file| loader::union_bounds(bounds, file.bounds));
+
+                    if let Some((min, max)) = bounds {
+                        let new_centre = (min + max) / 2.0;
+                        let old_centre = self.centre.filter(|c| c.is_finite()).unwrap_or(new_centre);
+
+                        self.camera_position += render::COORDINATE_SYSTEM.transform_vector3((old_centre - new_centre).as_vec3());
+                        self.centre = Some(new_centre);
+                    }
+
+                    // Opened from a view link
+                    if let Some(view) = self.pending_view.take().filter(|_| !streamed) {
+                        self.camera_position = render::COORDINATE_SYSTEM.transform_point3((view.position - self.centre.unwrap_or(glam::DVec3::ZERO)).as_vec3());
+                        self.camera_rotation = view.rotation;
+                        self.camera_zoom = view.zoom;
+                    }
+
+                    // Batches were coloured with the elevation range, densities and distances known at the time, and
+                    // judged by the roughness of the points before them
+                    self.density_grid = None;
+                    self.cloud_distances = None;
+                    if matches!(self.colour_mode, colour::ColourMode::Elevation(_) | colour::ColourMode::Density(_) | colour::ColourMode::Distance(_))
+                        || self.point_filter.vegetation.as_ref().is_some_and(|vegetation| vegetation.roughness.is_some()) {
+                        self.rebuild_vertex_buffers = true;
+                    }
+                },
+                Err(mpsc::TryRecvError::Disconnected) if streamed => {
+                    self.point_stream = None;
+                },
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.batch_number = -1;
+                    self.rx = None;
+                    self._read_job = None;
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+            }
+        }
+    }
+}
+
+/// Queue a file to load, running it through the PDAL pipeline first if there is one
+pub(super) fn queue_load(job_queue: &mut jobs::JobQueue, locale: &locale::Language, path: String, append: bool, pipeline: Option<PathBuf>) -> jobs::Job<LoadRequest> {
+    let name = locale.format("Opening {}", &[&recent::file_name(&path)]);
+
+    job_queue.submit(name, move |_| match pipeline {
+        Some(pipeline) => {
+            println!("Running PDAL pipeline {} on {}", pipeline.display(), path);
+
+            pdal::preprocess(&pipeline, &path).map(|output| LoadRequest {
+                path,
+                append,
+                preprocessed: Some(output),
+            })
+        },
+        None => Ok(LoadRequest {
+            path,
+            append,
+            preprocessed: None,
+        }),
+    })
+}
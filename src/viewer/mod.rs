@@ -0,0 +1,1085 @@
+use std::{sync::{mpsc::Receiver, Arc}, time::{Duration, Instant}, path::{Path, PathBuf}, collections::{HashMap, VecDeque}};
+
+use glium::{glutin::{self, event_loop::EventLoopWindowTarget}, Surface, program::ProgramCreationInput};
+
+use crate::{camera, cluster, colour, config::Config, context, contour, dock, export, filter, footprint, gamepad, geometry, ground, hough, jobs, keymap, link, loader, locale, mesh, minimap, plan, playback, profile, raster, recent, refine, registration, section, selection, slice, split, stream, transform::Transform, tutorial, watch};
+use crate::render::{Background, Fog, Vertex};
+use crate::input::{KeyboardManager, MouseManager};
+use crate::loader::{LoaderMessage, PointBatch, LoadedFile, LoadRequest};
+
+use loading::queue_load;
+
+mod input;
+mod loading;
+mod panels;
+mod plan_editor;
+mod render;
+mod tasks;
+
+#[derive(PartialEq, Eq, Debug)]
+enum DrawTool {
+    Pencil,
+    Eraser,
+    RoomIdentification,
+    Line,
+    Rectangle,
+    Polygon,
+    /// Accept or reject proposed wall segments
+    ReviewWalls,
+    /// Place and edit text labels
+    Text,
+    /// Place architectural symbols
+    Stamp,
+    /// Reshape the traced wall outlines exports use
+    EditOutlines,
+}
+
+/// Look of the egui panels and windows
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    fn name(&self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+
+    fn visuals(&self) -> egui::Visuals {
+        match self {
+            Theme::Dark => egui::Visuals::dark(),
+            Theme::Light => egui::Visuals::light(),
+        }
+    }
+}
+
+/// What a slice was captured for, done once it's processed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SliceUse {
+    DumpStages,
+    QuickRender,
+    FloorPlan,
+    /// Replace the walls of the floor plan being edited, keeping the edits and rooms
+    Reprocess,
+}
+
+/// A captured cutaway and slice, kept so the slice can be processed again with other settings
+#[derive(Clone)]
+struct SliceCapture {
+    cutaway: image::RgbaImage,
+    raw: image::RgbaImage,
+    transform: plan::CaptureTransform,
+    /// Where the slice's points were, to export them
+    band: slice::SliceBand,
+    /// Connect radius from the point size and zoom it was captured at
+    auto_connect_radius: i32,
+}
+
+/// A captured slice, post-processed off the render thread
+struct ProcessedSlice {
+    capture: SliceCapture,
+    /// Pixels of the raw slice that points landed on
+    found_points: Vec<(u32, u32)>,
+    image: image::RgbaImage,
+    connect_radius: i32,
+    alpha_threshold: u8,
+    slice_use: SliceUse,
+}
+
+/// What points accumulated for progressive refinement were drawn with: the modelview and projection matrices, point
+/// size, clipping, slice, colour mode, version of the vertex buffers, the fog and background they fade into, and the
+/// transform previewed on the points
+type RefineView = (glam::Mat4, glam::Mat4, f32, bool, bool, colour::ColourMode, u64, Fog, Background, Transform);
+
+/// Frame rate cap on start, changed in the side panel
+const DEFAULT_FPS: u32 = 60;
+/// Threads running background jobs, most jobs use every core themselves
+const JOB_WORKERS: usize = 2;
+/// Address listened on for streamed points, unless another is given
+const DEFAULT_STREAM_ADDRESS: &str = "0.0.0.0:7878";
+/// Longest frame movement is integrated over, so a stall (e.g. saving a file) doesn't jump the camera, in seconds
+const MAX_FRAME_LENGTH: f32 = 0.1;
+
+/// Touchpad scrolling, in pixels, that zooms as much as one line of a scroll wheel
+const SCROLL_LINE_PIXELS: f32 = 20.0;
+
+/// Input right after the auto-orbit starts (e.g. releasing the button) doesn't stop it, in seconds
+const AUTO_ORBIT_GRACE: f32 = 1.0;
+
+/// Points loaded per file in reduced mode
+const REDUCED_POINT_BUDGET: u64 = 2_000_000;
+
+/// Largest text labels are drawn on screen when zoomed in, in points
+const MAX_LABEL_POINTS: f32 = 200.0;
+/// Scan angle limit when it's first turned on, in degrees from nadir
+const DEFAULT_MAX_SCAN_ANGLE: f32 = 15.0;
+/// Scan seconds shown at once when GPS time playback is windowed
+const DEFAULT_PLAYBACK_WINDOW: f64 = 30.0;
+/// Largest clusters listed in the side panel, the rest are usually debris
+const MAX_LISTED_CLUSTERS: usize = 200;
+
+/// Settings the viewer starts with, from the command line
+pub struct Options {
+    /// Point cloud file to load once the window is open
+    pub file: Option<String>,
+    /// Base size of the points, in same units as the file
+    pub point_size: f32,
+    /// Points loaded from each file, 0 to load them all
+    pub num_points: u64,
+    /// Directory quick renders are written to
+    pub output_dir: String,
+    /// OpenGL profile offscreen contexts for scripts are created with
+    pub gl_profile: context::GlProfile,
+    /// The display's vertical sync paces frames rather than the frame rate cap
+    pub vsync: bool,
+    /// Profile data is being served, and the Profiler window starts open
+    pub profile: bool,
+    /// Point budget, no MSAA and square points, on anyway with software renderers
+    pub reduced: bool,
+    /// Directory colour ramp and point shader plugins are loaded from, instead of the config directory
+    pub plugin_dir: Option<String>,
+    /// PDAL pipeline files are run through before loading
+    pub pdal_pipeline: Option<String>,
+    /// Folder to load LAS and LAZ files from as they appear or change
+    pub watch: Option<String>,
+    /// EPSG code files are reprojected to as they load
+    pub reproject: Option<u16>,
+    /// Address to listen for a scanner streaming points on
+    pub listen: Option<String>,
+    /// Seconds without input before the camera starts orbiting
+    pub auto_orbit_after: Option<f32>,
+}
+
+/// Interactive viewer: the 3D view of the loaded point clouds with its panels, and the floor plan editor. Feed it the
+/// window's events with `handle_event`, and draw a `frame` whenever that asks for one.
+pub struct Viewer {
+    point_size: f32,
+    renderer: String,
+    /// Samples the context was created with, after any fallback
+    msaa: u16,
+    software_renderer: bool,
+    reduced_mode: bool,
+    egui_glium: egui_glium::EguiGlium,
+    display: glium::Display,
+    /// Organisation and project settings
+    config: Config,
+    /// Profile data is being served, so frames are recorded with the Profiler window closed
+    serving_profile: bool,
+    /// Profile offscreen contexts for scripts are created with
+    gl_profile: context::GlProfile,
+    show_profiler: bool,
+    camera_position: glam::Vec3,
+    camera_rotation: glam::Vec2,
+    camera_zoom: f32,
+    mouse_delta: glam::Vec2,
+    mouse_locked: bool,
+    /// Camera orbits the centre of the loaded files, started from the panel or after a while without input
+    auto_orbit: bool,
+    auto_orbit_when_idle: bool,
+    auto_orbit_idle_time: f32,
+    /// Degrees per second
+    auto_orbit_speed: f32,
+    auto_orbit_started: Instant,
+    last_input: Instant,
+    clipping: bool,
+    show_slice: bool,
+    show_outline_plane: bool,
+    show_bounds: bool,
+    /// Step of the guided walkthrough being shown, opened by itself the first time
+    tutorial_step: Option<tutorial::Step>,
+    drawing_mode: bool,
+    /// Set by the back button or Esc, the floor plan is kept so it can be reopened
+    exit_drawing_mode: bool,
+    active_tool: DrawTool,
+    pencil_size: f32,
+    pencil_colour: [u8; 3],
+    eraser_size: f32,
+    /// Layer the eraser works on
+    erase_layer: plan::LayerKind,
+    /// Vertices of the polyline/polygon being drawn, in floor plan image coordinates
+    shape_points: Vec<glam::Vec2>,
+    rectangle_start: Option<glam::Vec2>,
+    finish_shape: bool,
+    fill_shapes: bool,
+    fill_settings: plan::FillSettings,
+    /// Shown cutaway only, exports are left as rendered
+    cutaway_brightness: f32,
+    cutaway_contrast: f32,
+    cutaway_desaturate: f32,
+    /// Raw slice left of the split and processed slice right of it, as a fraction of the width
+    compare_slices: bool,
+    compare_split: f32,
+    highlight_added: bool,
+    text_size: f32,
+    stamp_symbol: plan::Symbol,
+    /// Width of the symbol's opening in pixels, and its clockwise turn in degrees
+    stamp_size: f32,
+    stamp_rotation: f32,
+    /// Pixels traced wall outlines may stray from the walls layer, and the vertex being dragged
+    trace_tolerance: f32,
+    dragged_vertex: Option<(usize, usize)>,
+    trace_walls_queued: bool,
+    /// Degrees off the building's axes walls are straightened from, and pixels apart corners are joined within
+    regularise_tolerance: f32,
+    join_distance: f32,
+    regularise_walls_queued: bool,
+    /// Label open in the label window, with the index of the label it's editing
+    label_draft: Option<(Option<usize>, plan::TextLabel)>,
+    focus_label: bool,
+    /// Set when the label window closes, true to keep the label
+    close_label: Option<bool>,
+    /// Pixels of the last rendered slice, and straight wall segments proposed from them
+    slice_points: Vec<(u32, u32)>,
+    hough_parameters: hough::HoughParameters,
+    slice_processors: slice::PostProcessors,
+    slice_processor: usize,
+    gpu_closing: slice::GpuClosing,
+    process_slice_on_gpu: bool,
+    /// Slice being processed in the background, the viewer keeps running meanwhile
+    processed_slice: Option<jobs::Job<ProcessedSlice>>,
+    /// Connect radius in pixels, from the point size and zoom if unset
+    connect_radius: Option<i32>,
+    alpha_threshold: u8,
+    last_slice_capture: Option<SliceCapture>,
+    reprocess_slice_queued: bool,
+    wall_proposals: Vec<hough::Segment>,
+    detect_walls_queued: bool,
+    burn_in_grid: bool,
+    /// Ground grid in 3D and over the floor plan while drawing, every grid spacing in file coordinates
+    show_grid: bool,
+    grid_spacing: f32,
+    burn_in_scale_bar: bool,
+    burn_in_north_arrow: bool,
+    floor_plan_depth: plan::ColourDepth,
+    label_font: rusttype::Font<'static>,
+    final_render_queued: bool,
+    save_cutaway_queued: bool,
+    save_floor_plan_queued: bool,
+    export_svg_queued: bool,
+    export_dxf_queued: bool,
+    export_pdf_queued: bool,
+    export_obj_queued: bool,
+    export_web_viewer_queued: bool,
+    export_slice_points_queued: bool,
+    accept_all_walls: bool,
+    floor_plan: Option<plan::FloorPlan>,
+    capture_transform: Option<plan::CaptureTransform>,
+    /// Rooms filled on the rooms layer, recounted whenever it changes
+    rooms: Vec<plan::Room>,
+    /// Colour left clicks fill rooms with, None for a new colour each time
+    room_fill: Option<[u8; 3]>,
+    rooms_changed: bool,
+    keyboard: KeyboardManager,
+    keymap: keymap::Keymap,
+    /// Action waiting for the next key press to be bound to it
+    rebinding: Option<keymap::Action>,
+    gamepad: Option<gamepad::GamepadManager>,
+    modifiers: glutin::event::ModifiersState,
+    mouse: MouseManager,
+    num_points: u64,
+    total_points: u64,
+    /// Shared rendering origin, in file coordinates
+    centre: Option<glam::DVec3>,
+    loaded_files: Vec<LoadedFile>,
+    load_warnings: Vec<String>,
+    /// Recently opened files and their cached thumbnails, loaded as they're shown
+    recent_files: Vec<String>,
+    thumbnail_textures: HashMap<String, Option<egui::TextureHandle>>,
+    open_recent_queued: Option<String>,
+    /// Pasted file path or view link, and the view to show once its file has loaded
+    paste_queued: Option<String>,
+    pending_view: Option<link::View>,
+    /// Why the last file couldn't be loaded, shown until dismissed
+    load_error: Option<String>,
+    /// Header of a loaded file shown in the File Info window, with the file's path
+    file_info: Option<(String, Result<loader::FileInfo, String>)>,
+    file_info_queued: Option<usize>,
+    rx: Option<Receiver<LoaderMessage>>,
+    /// Reading the file being loaded, sending its points to `rx`. Replacing it stops the read
+    _read_job: Option<jobs::Job<()>>,
+    /// Keeps track of loading progress, -1 = no loading happening right now
+    batch_number: i32,
+    /// Optional PDAL pipeline files are run through before loading
+    pdal_pipeline: Option<PathBuf>,
+    preprocess_with_pdal: bool,
+    pdal_pipeline_rx: Option<Receiver<PathBuf>>,
+    /// Pipeline output being loaded, deleted once the loader is done with it
+    _preprocessed_file: Option<tempfile::TempPath>,
+    /// Long operations run in the background, listed with their progress in the Jobs window
+    job_queue: jobs::JobQueue,
+    /// Exports running in the background, each finishing with a message to print
+    export_jobs: Vec<jobs::Job<String>>,
+    /// Files picked to load and whether to add them to the ones loaded, then the file being preprocessed
+    path_rx: Option<Receiver<(String, bool)>>,
+    load_job: Option<jobs::Job<LoadRequest>>,
+    /// Index in the loaded files of the file being loaded
+    loading_file: usize,
+    /// Folder watched for point clouds to load as they're scanned, and the files waiting their turn to load
+    folder_watch: Option<watch::FolderWatch>,
+    watch_queue: VecDeque<String>,
+    watch_folder_rx: Option<Receiver<PathBuf>>,
+    /// EPSG code files are reprojected to as they load
+    reproject_to: Option<u16>,
+    /// Scanner streaming points over the network, and the index in the loaded files its points go to
+    point_stream: Option<(stream::PointStream, usize)>,
+    stream_address: String,
+    listen_queued: bool,
+    vertex_buffers: Vec<glium::VertexBuffer<Vertex>>,
+    /// CPU copies of the loaded points, used by analysis passes
+    point_batches: Vec<PointBatch>,
+    point_filter: filter::PointFilter,
+    rebuild_vertex_buffers: bool,
+    /// Counts changes to the vertex buffers, so points accumulated before are drawn again
+    points_version: u64,
+    /// Number of loaded points from each point source (scan station) and of each classification
+    point_counts: filter::AttributeCounts,
+    /// Points revealed in acquisition order, for mobile scans
+    playback: playback::Playback,
+    /// Imported building footprint and the file it came from, kept while clipping is turned off
+    footprint: Option<(footprint::FootprintClip, PathBuf)>,
+    footprint_error: Option<String>,
+    footprint_rx: Option<Receiver<PathBuf>>,
+    program: glium::Program,
+    debug_program: glium::Program,
+    drawing_program: glium::Program,
+    plugin_errors: Vec<String>,
+    colour_ramps: Vec<colour::ColourRamp>,
+    point_shaders: Vec<(String, glium::Program)>,
+    colour_mode: colour::ColourMode,
+    /// English and any `*.lang` translations beside the plugins and shared palettes
+    languages: Vec<locale::Language>,
+    language: usize,
+    /// RANSAC plane detection
+    detect_planes_queued: bool,
+    detected_planes: Vec<geometry::DetectedPlane>,
+    planes_job: Option<jobs::Job<Vec<geometry::DetectedPlane>>>,
+    plane_threshold: f32,
+    max_planes: usize,
+    ground_settings: ground::ClothSettings,
+    classify_ground_queued: bool,
+    /// Settling cloth, with the number of points in each batch when it started so it's only applied to the same points
+    ground_job: Option<(jobs::Job<ground::Cloth>, Vec<usize>)>,
+    cluster_gap: f32,
+    min_cluster_points: u64,
+    clusters: Option<cluster::Clusters>,
+    find_clusters_queued: bool,
+    clusters_job: Option<jobs::Job<cluster::Clusters>>,
+    select_cluster_queued: Option<(usize, selection::SelectMode)>,
+    /// Points per voxel, for the density colour mode. Counted when first needed after the points change.
+    density_grid: Option<colour::DensityGrid>,
+    density_voxel_size: f32,
+    /// Distances to a reference file, for the distance colour mode. Found when first needed after the points change.
+    cloud_distances: Option<Result<colour::CloudDistances, String>>,
+    /// Reference and compared files
+    change_files: (usize, usize),
+    distance_threshold: f32,
+    /// Top down thumbnail in the corner of the 3D view, drawn again when the points change or the camera changes floor
+    minimap: Option<minimap::Minimap>,
+    show_minimap: bool,
+    /// Top down slice at the clip height beside the 3D view, drawn again whenever the clip height changes
+    live_slice: Option<split::LiveSlice>,
+    /// Cross-section along a path drawn on the live slice, with its image and pixel size once rendered
+    section: section::Section,
+    drawing_section: bool,
+    section_pixel_size: f32,
+    section_queued: bool,
+    section_image: Option<(image::RgbaImage, egui::TextureHandle, f64)>,
+    split_view: bool,
+    /// Side panel sections floated out into their own windows
+    dock: dock::Dock,
+    show_compass: bool,
+    north_offset: f32,
+    background: Background,
+    fog: Fog,
+    theme: Theme,
+    /// Theme and background from before presentation mode, put back when it's turned off
+    presentation: Option<(Theme, Background)>,
+    /// Height above a floor the clipping plane is snapped to
+    cut_height: f32,
+    snap_clip_height: Option<f64>,
+    /// Floors and ceilings found from the elevation histogram
+    detect_levels_queued: bool,
+    levels: Vec<geometry::Level>,
+    selected_level: Option<usize>,
+    /// Transform previewed on the points until it's applied to them
+    point_transform: Transform,
+    /// Matrix to move the points by, and the file to move if not all of them
+    move_points_queued: Option<(glam::DMat4, Option<usize>)>,
+    /// Aligning one loaded file to another
+    register_queued: bool,
+    registration_files: (usize, usize),
+    coarse_registration: bool,
+    registration: Option<Result<registration::Registration, String>>,
+    register_job: Option<(jobs::Job<registration::Registration>, usize)>,
+    mesh_program: glium::Program,
+    grid_program: glium::Program,
+    background_program: glium::Program,
+    refine_program: glium::Program,
+    raster_program: glium::Program,
+    /// Points drawn a part at a time, filling in while the view holds still, once there are more than a frame's worth
+    progressive_refinement: bool,
+    refine_points_per_frame: f32,
+    refinement: Option<refine::Refinement<RefineView>>,
+    last_time: Instant,
+    /// Frames drawn per second at most, None to draw as fast as possible (or at the display's rate with --vsync)
+    vsync: bool,
+    fps_cap: Option<u32>,
+    capture_supersampling: u32,
+    idle_time: f32,
+    cutaway_queued: bool,
+    quick_render_queued: bool,
+    output_dir: String,
+    output_dir_rx: Option<Receiver<String>>,
+    last_quick_render: Option<String>,
+    dump_stages_queued: bool,
+    last_stage_dump: Option<String>,
+    mesh_preview_queued: bool,
+    show_mesh_preview: bool,
+    mesh_region_size: f32,
+    mesh_cell_size: f32,
+    mesh_tolerance: f32,
+    mesh_preview: Option<(glium::VertexBuffer<Vertex>, glium::IndexBuffer<u32>)>,
+    mesh_preview_stats: Option<mesh::MeshPreview>,
+    mesh_job: Option<jobs::Job<mesh::MeshPreview>>,
+    /// Polygon picked on the point cloud, file coordinates
+    measuring: bool,
+    measure_points: Vec<glam::DVec3>,
+    measure_volume: bool,
+    volume_bottom: f64,
+    volume_top: f64,
+    /// Section line picked on the point cloud and the profile of the points along it
+    profiling: bool,
+    profile_line: Vec<glam::DVec3>,
+    profile_buffer: f32,
+    profile_queued: bool,
+    profile: Option<profile::Profile>,
+    /// Elevation contours of the visible points, drawn over the 3D view as lines relative to the centre they were made at
+    contour_cell_size: f32,
+    contour_interval: f32,
+    contour_major_every: u32,
+    contours: Vec<contour::Contour>,
+    contour_lines: Option<(glium::VertexBuffer<Vertex>, glam::DVec3)>,
+    show_contours: bool,
+    contours_queued: bool,
+    contours_job: Option<jobs::Job<Vec<contour::Contour>>>,
+    /// Elevation raster of the visible points, exported as a GeoTIFF or shown hillshaded under the points
+    raster_statistic: raster::Statistic,
+    raster_cell_size: f32,
+    elevation_raster: Option<Arc<raster::ElevationGrid>>,
+    /// Hillshade texture, the raster's corners and the elevation it's drawn at
+    raster_layer: Option<(glium::texture::Texture2d, (glam::DVec2, glam::DVec2), f64)>,
+    show_raster: bool,
+    raster_opacity: f32,
+    raster_queued: bool,
+    raster_job: Option<jobs::Job<raster::ElevationGrid>>,
+    export_raster_queued: bool,
+    /// Point clicked on while inspecting, shown with its attributes
+    inspecting: bool,
+    picked_point: Option<(las::Point, usize)>,
+    pick_queued: bool,
+    /// Points selected by dragging a box or lasso on screen
+    select_tool: Option<selection::SelectTool>,
+    select_outline: Vec<glam::Vec2>,
+    selected_points: usize,
+    clear_selection_queued: bool,
+    delete_selection_queued: bool,
+    export_selection_queued: bool,
+    /// Script picked to run, and the script running on a worker with its own offscreen context
+    script_rx: Option<Receiver<PathBuf>>,
+    script_job: Option<jobs::Job<()>>,
+    fullscreen_quad: glium::VertexBuffer<Vertex>,
+}
+
+impl Viewer {
+    /// Viewer drawing to `display`, loading `options.file` if there is one. Slices can be processed with any of
+    /// `slice_processors`, and `config_errors` are shown as warnings.
+    pub fn new(display: glium::Display, event_loop: &EventLoopWindowTarget<()>, options: Options, slice_processors: slice::PostProcessors, config: Config, config_errors: &[String]) -> Viewer {
+        let filename = options.file;
+        let renderer = display.get_opengl_renderer_string().to_owned();
+        let msaa = display.gl_window().get_pixel_format().multisampling.unwrap_or(0);
+        let software_renderer = crate::render::is_software_renderer(&renderer);
+        let reduced_mode = options.reduced || software_renderer;
+
+        if software_renderer {
+            eprintln!("Software renderer detected ({}), running in reduced mode", renderer);
+        }
+
+        let egui_glium = egui_glium::EguiGlium::new(&display, event_loop);
+
+        {
+            let mut fonts = egui::FontDefinitions::default();
+
+            // Install my own font (maybe supporting non-latin characters).
+            // .ttf and .otf files supported.
+            fonts.font_data.insert(
+                "icons".to_owned(),
+                egui::FontData::from_static(include_bytes!(
+                    "../../fonts/Font Awesome 6 Free-Solid-900.otf"
+                )),
+            );
+
+            fonts
+                .families
+                .entry(egui::FontFamily::Name("icons".into()))
+                .or_default()
+                .push("icons".to_owned());
+
+            // Tell egui to use these fonts:
+            egui_glium.egui_ctx.set_fonts(fonts);
+        }
+
+        let gpu_closing = slice::GpuClosing::new(&display);
+
+        let (keymap, keymap_errors) = keymap::Keymap::load();
+
+        for err in &keymap_errors {
+            eprintln!("Invalid keymap {}", err);
+        }
+
+        let num_points = if reduced_mode && (options.num_points == 0 || options.num_points > REDUCED_POINT_BUDGET) {
+            REDUCED_POINT_BUDGET
+        } else {
+            options.num_points
+        };
+
+        let mut load_warnings: Vec<String> = config_errors.iter().map(|err| format!("Invalid setting in {}", err)).collect();
+
+        let pdal_pipeline: Option<PathBuf> = options.pdal_pipeline.map(PathBuf::from);
+        let preprocess_with_pdal = pdal_pipeline.is_some();
+
+        let mut job_queue = jobs::JobQueue::new(JOB_WORKERS);
+
+        let mut load_job: Option<jobs::Job<LoadRequest>> = None;
+
+        let mut folder_watch: Option<watch::FolderWatch> = None;
+
+        if let Some(dir) = &options.watch {
+            match watch::FolderWatch::new(Path::new(dir)) {
+                Ok(watch) => folder_watch = Some(watch),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    load_warnings.push(err);
+                },
+            }
+        }
+
+        let program = crate::render::point_program(&display);
+        let debug_program = crate::render::slice_program(&display);
+
+        let drawing_program = {
+            let vertex_shader_src = include_str!("../shaders/drawing.vert");
+            let fragment_shader_src = include_str!("../shaders/drawing.frag");
+            
+            glium::Program::new(&display, ProgramCreationInput::SourceCode {
+                vertex_shader: vertex_shader_src,
+                fragment_shader: fragment_shader_src,
+                uses_point_size: true,
+                tessellation_control_shader: None,
+                tessellation_evaluation_shader: None,
+                geometry_shader: None,
+                transform_feedback_varyings: None,
+                outputs_srgb: true,
+            }).expect("Failed to parse drawing shader.")
+        };
+
+        // Built-in and plugin colour ramps, and compiled plugin point shaders
+        let plugin_dir = options.plugin_dir.map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|dir| dir.join("point-cloud-cutaway").join("plugins")));
+        let mut plugins = plugin_dir.as_deref().map(colour::load_plugins).unwrap_or_default();
+
+        // Shared palettes from the settings
+        if let Some(palette_dir) = &config.palette_dir {
+            let shared = colour::load_plugins(palette_dir);
+
+            plugins.ramps.extend(shared.ramps);
+            plugins.shaders.extend(shared.shaders);
+            plugins.errors.extend(shared.errors);
+        }
+
+        let mut plugin_errors = plugins.errors;
+        let mut colour_ramps = colour::builtin_ramps();
+        colour_ramps.extend(plugins.ramps);
+
+        let mut point_shaders = vec![];
+        for shader in plugins.shaders {
+            let vertex_shader_src = colour::vertex_shader_with(&shader.source);
+
+            let program = crate::render::program(&display, &vertex_shader_src, include_str!("../shaders/main.frag"));
+
+            match program {
+                Ok(program) => point_shaders.push((shader.name, program)),
+                Err(err) => plugin_errors.push(format!("{}.glsl: {}", shader.name, err)),
+            }
+        }
+
+        let colour_mode = match &config.palette {
+            Some(name) => match colour_ramps.iter().position(|ramp| ramp.name.eq_ignore_ascii_case(name)) {
+                Some(ramp) => colour::ColourMode::Elevation(ramp),
+                None => {
+                    plugin_errors.push(format!("{}: no colour ramp with this name (set as the palette)", name));
+                    colour::ColourMode::Rgb
+                },
+            },
+            None => colour::ColourMode::Rgb,
+        };
+
+        let mut languages = vec![locale::Language::english()];
+        for directory in plugin_dir.iter().chain(&config.palette_dir) {
+            let (found, errors) = locale::load_languages(directory);
+
+            languages.extend(found);
+            plugin_errors.extend(errors);
+        }
+
+        let language = match &config.language {
+            Some(name) => languages.iter().position(|language| language.name.eq_ignore_ascii_case(name)).unwrap_or_else(|| {
+                plugin_errors.push(format!("{}: no translation with this name (set as the language)", name));
+                0
+            }),
+            None => 0,
+        };
+
+        for err in &plugin_errors {
+            eprintln!("Failed to load plugin {}", err);
+        }
+
+        display.gl_window().window().set_title(languages[language].tr("Point Cloud Cutaway Renderer"));
+
+        if let Some(filename) = filename {
+            load_job = Some(queue_load(&mut job_queue, &languages[language], filename, false, pdal_pipeline.clone()));
+        }
+
+        let mesh_program = {
+            let vertex_shader_src = include_str!("../shaders/mesh.vert");
+            let fragment_shader_src = include_str!("../shaders/mesh.frag");
+            
+            glium::Program::new(&display, ProgramCreationInput::SourceCode {
+                vertex_shader: vertex_shader_src,
+                fragment_shader: fragment_shader_src,
+                uses_point_size: false,
+                tessellation_control_shader: None,
+                tessellation_evaluation_shader: None,
+                geometry_shader: None,
+                transform_feedback_varyings: None,
+                outputs_srgb: true,
+            }).expect("Failed to parse mesh shader.")
+        };
+
+        let grid_program = crate::render::grid_program(&display);
+        let background_program = crate::render::background_program(&display);
+        let refine_program = crate::render::refine_program(&display);
+        let raster_program = crate::render::raster_program(&display);
+
+        let vsync = options.vsync;
+        let fps_cap = (!vsync).then_some(DEFAULT_FPS);
+
+        let fullscreen_quad = glium::VertexBuffer::new(&display, &[
+            Vertex {
+                position: [-1.0, -1.0, 0.0],
+                colour: [0, 0, 0],
+                intensity: 0.0,
+            },
+            Vertex {
+                position: [-1.0, 1.0, 0.0],
+                colour: [0, 0, 0],
+                intensity: 0.0,
+            },
+            Vertex {
+                position: [1.0, 1.0, 0.0],
+                colour: [0, 0, 0],
+                intensity: 0.0,
+            },
+            Vertex {
+                position: [-1.0, -1.0, 0.0],
+                colour: [0, 0, 0],
+                intensity: 0.0,
+            },
+            Vertex {
+                position: [1.0, 1.0, 0.0],
+                colour: [0, 0, 0],
+                intensity: 0.0,
+            },
+            Vertex {
+                position: [1.0, -1.0, 0.0],
+                colour: [0, 0, 0],
+                intensity: 0.0,
+            },
+        ]).expect("Failed to create fullscreen quad.");
+
+        Viewer {
+            point_size: options.point_size,
+            renderer,
+            msaa,
+            software_renderer,
+            reduced_mode,
+            egui_glium,
+            serving_profile: options.profile,
+            gl_profile: options.gl_profile,
+            show_profiler: options.profile,
+            camera_position: glam::Vec3::ZERO,
+            camera_rotation: glam::vec2(0.0, std::f32::consts::FRAC_PI_2),
+            camera_zoom: -64.0,
+            mouse_delta: glam::Vec2::ZERO,
+            mouse_locked: false,
+            auto_orbit: false,
+            auto_orbit_when_idle: options.auto_orbit_after.is_some(),
+            auto_orbit_idle_time: options.auto_orbit_after.unwrap_or(60.0),
+            auto_orbit_speed: 10.0_f32,
+            auto_orbit_started: Instant::now(),
+            last_input: Instant::now(),
+            clipping: false,
+            show_slice: false,
+            show_outline_plane: false,
+            show_bounds: false,
+            tutorial_step: (!tutorial::seen()).then_some(tutorial::Step::Load),
+            drawing_mode: false,
+            exit_drawing_mode: false,
+            active_tool: DrawTool::Pencil,
+            pencil_size: 1.0_f32,
+            pencil_colour: [0_u8; 3],
+            eraser_size: 10.0_f32,
+            erase_layer: plan::LayerKind::Walls,
+            shape_points: vec![],
+            rectangle_start: None,
+            finish_shape: false,
+            fill_shapes: false,
+            fill_settings: plan::FillSettings::default(),
+            cutaway_brightness: 0.0_f32,
+            cutaway_contrast: 1.0_f32,
+            cutaway_desaturate: 0.0_f32,
+            compare_slices: false,
+            compare_split: 0.5_f32,
+            highlight_added: false,
+            text_size: 24.0_f32,
+            stamp_symbol: plan::Symbol::Door,
+            stamp_size: 40.0_f32,
+            stamp_rotation: 0.0_f32,
+            trace_tolerance: export::SIMPLIFY_TOLERANCE as f32,
+            dragged_vertex: None,
+            trace_walls_queued: false,
+            regularise_tolerance: 10.0_f32,
+            join_distance: 3.0_f32,
+            regularise_walls_queued: false,
+            label_draft: None,
+            focus_label: false,
+            close_label: None,
+            slice_points: vec![],
+            hough_parameters: hough::HoughParameters::default(),
+            slice_processors,
+            slice_processor: 0,
+            gpu_closing,
+            process_slice_on_gpu: false,
+            processed_slice: None,
+            connect_radius: None,
+            alpha_threshold: config.alpha_threshold,
+            last_slice_capture: None,
+            reprocess_slice_queued: false,
+            wall_proposals: vec![],
+            detect_walls_queued: false,
+            burn_in_grid: false,
+            show_grid: false,
+            grid_spacing: 1.0_f32,
+            burn_in_scale_bar: true,
+            burn_in_north_arrow: true,
+            floor_plan_depth: plan::ColourDepth::Colour,
+            label_font: plan::label_font(),
+            final_render_queued: false,
+            save_cutaway_queued: false,
+            save_floor_plan_queued: false,
+            export_svg_queued: false,
+            export_dxf_queued: false,
+            export_pdf_queued: false,
+            export_obj_queued: false,
+            export_web_viewer_queued: false,
+            export_slice_points_queued: false,
+            accept_all_walls: false,
+            floor_plan: None,
+            capture_transform: None,
+            rooms: vec![],
+            room_fill: None,
+            rooms_changed: false,
+            keyboard: KeyboardManager::new(),
+            keymap,
+            rebinding: None,
+            gamepad: gamepad::GamepadManager::new(),
+            modifiers: glutin::event::ModifiersState::empty(),
+            mouse: MouseManager::new(),
+            num_points,
+            total_points: 0,
+            centre: None,
+            loaded_files: vec![],
+            load_warnings,
+            recent_files: recent::load(),
+            thumbnail_textures: HashMap::new(),
+            open_recent_queued: None,
+            paste_queued: None,
+            pending_view: None,
+            load_error: None,
+            file_info: None,
+            file_info_queued: None,
+            rx: None,
+            _read_job: None,
+            batch_number: -1,
+            pdal_pipeline,
+            preprocess_with_pdal,
+            pdal_pipeline_rx: None,
+            _preprocessed_file: None,
+            job_queue,
+            export_jobs: vec![],
+            path_rx: None,
+            load_job,
+            loading_file: 0,
+            folder_watch,
+            watch_queue: VecDeque::new(),
+            watch_folder_rx: None,
+            reproject_to: options.reproject,
+            point_stream: None,
+            stream_address: options.listen.clone().unwrap_or_else(|| DEFAULT_STREAM_ADDRESS.to_owned()),
+            listen_queued: options.listen.is_some(),
+            vertex_buffers: vec![],
+            point_batches: vec![],
+            point_filter: filter::PointFilter::default(),
+            rebuild_vertex_buffers: false,
+            points_version: 0_u64,
+            point_counts: filter::AttributeCounts::default(),
+            playback: playback::Playback::default(),
+            footprint: None,
+            footprint_error: None,
+            footprint_rx: None,
+            program,
+            debug_program,
+            drawing_program,
+            plugin_errors,
+            colour_ramps,
+            point_shaders,
+            colour_mode,
+            languages,
+            language,
+            detect_planes_queued: false,
+            detected_planes: vec![],
+            planes_job: None,
+            plane_threshold: 0.03_f32,
+            max_planes: 8,
+            ground_settings: ground::ClothSettings::default(),
+            classify_ground_queued: false,
+            ground_job: None,
+            cluster_gap: 0.5_f32,
+            min_cluster_points: 1000_u64,
+            clusters: None,
+            find_clusters_queued: false,
+            clusters_job: None,
+            select_cluster_queued: None,
+            density_grid: None,
+            density_voxel_size: 0.1_f32,
+            cloud_distances: None,
+            change_files: (0, 1),
+            distance_threshold: 0.05_f32,
+            minimap: None,
+            show_minimap: true,
+            live_slice: None,
+            section: section::Section { path: vec![], half_width: 0.1 },
+            drawing_section: false,
+            section_pixel_size: 0.02_f32,
+            section_queued: false,
+            section_image: None,
+            split_view: false,
+            dock: dock::Dock::default(),
+            show_compass: true,
+            north_offset: config.north_offset,
+            background: Background::default(),
+            fog: Fog::Off,
+            theme: Theme::Dark,
+            presentation: None,
+            cut_height: 1.2_f32,
+            snap_clip_height: None,
+            detect_levels_queued: false,
+            levels: vec![],
+            selected_level: None,
+            point_transform: Transform::default(),
+            move_points_queued: None,
+            register_queued: false,
+            registration_files: (0, 1),
+            coarse_registration: true,
+            registration: None,
+            register_job: None,
+            mesh_program,
+            grid_program,
+            background_program,
+            refine_program,
+            raster_program,
+            progressive_refinement: true,
+            refine_points_per_frame: 4.0_f32,
+            refinement: None,
+            last_time: Instant::now(),
+            vsync,
+            fps_cap,
+            capture_supersampling: config.capture_supersampling,
+            idle_time: 0.0,
+            cutaway_queued: false,
+            quick_render_queued: false,
+            output_dir: options.output_dir,
+            output_dir_rx: None,
+            last_quick_render: None,
+            dump_stages_queued: false,
+            last_stage_dump: None,
+            mesh_preview_queued: false,
+            show_mesh_preview: true,
+            mesh_region_size: 2.0_f32,
+            mesh_cell_size: 0.05_f32,
+            mesh_tolerance: 0.01_f32,
+            mesh_preview: None,
+            mesh_preview_stats: None,
+            mesh_job: None,
+            measuring: false,
+            measure_points: vec![],
+            measure_volume: false,
+            volume_bottom: 0.0_f64,
+            volume_top: 2.5_f64,
+            profiling: false,
+            profile_line: vec![],
+            profile_buffer: 0.05_f32,
+            profile_queued: false,
+            profile: None,
+            contour_cell_size: 0.5_f32,
+            contour_interval: 1.0_f32,
+            contour_major_every: 5_u32,
+            contours: vec![],
+            contour_lines: None,
+            show_contours: true,
+            contours_queued: false,
+            contours_job: None,
+            raster_statistic: raster::Statistic::Min,
+            raster_cell_size: 0.5_f32,
+            elevation_raster: None,
+            raster_layer: None,
+            show_raster: true,
+            raster_opacity: 0.8_f32,
+            raster_queued: false,
+            raster_job: None,
+            export_raster_queued: false,
+            inspecting: false,
+            picked_point: None,
+            pick_queued: false,
+            select_tool: None,
+            select_outline: vec![],
+            selected_points: 0,
+            clear_selection_queued: false,
+            delete_selection_queued: false,
+            export_selection_queued: false,
+            script_rx: None,
+            script_job: None,
+            fullscreen_quad,
+            config,
+            display,
+        }
+    }
+
+    /// Update the viewer and draw the next frame, then wait until the one after is due
+    pub fn frame(&mut self, window_target: &EventLoopWindowTarget<()>, control_flow: &mut glutin::event_loop::ControlFlow) {
+        puffin::GlobalProfiler::lock().new_frame(); // call once per frame!
+        
+        let target = self.display.draw();
+        let (window_width, window_height) = target.get_dimensions();
+
+        let now = Instant::now();
+        let delta_t = now - self.last_time;
+        self.last_time = now;
+        // Seconds to move the camera over
+        let frame_length = delta_t.as_secs_f32().min(MAX_FRAME_LENGTH);
+
+        // Drawing mode matrix, used in update, and render functions
+        let drawing_mvp = {
+            let dimensions = self.floor_plan.as_ref().map_or((1, 1), |floor_plan| floor_plan.dimensions());
+            
+            let cutaway_aspect = dimensions.1 as f32 / dimensions.0 as f32;
+            let aspect = window_height as f32 / window_width as f32;
+            
+            let width = 0.75;
+            let width = if width * cutaway_aspect > 0.9 * aspect {
+                0.9 * aspect / cutaway_aspect
+            } else {
+                width
+            };
+            
+            let model = glam::Mat4::from_scale_rotation_translation(glam::vec3(width, width * cutaway_aspect, 1.0), glam::Quat::IDENTITY, glam::vec3(0.15, 0.0, 0.0));
+            let view = glam::Mat4::IDENTITY;
+            let perspective = glam::Mat4::orthographic_lh(-1.0, 1.0, -1.0 * aspect, 1.0 * aspect, -1.0, 1.0);
+            
+            perspective * view * model
+        };
+
+        if !self.drawing_mode {
+            puffin::profile_scope!("update");
+
+            self.poll_loading();
+            self.move_camera(frame_length, window_width, window_height);
+
+            // File coordinates relative to the centre to clip space
+            let camera_mvp = camera::projection(2.0_f32.powf(-self.camera_zoom / 10.0), window_width, window_height)
+                * camera::view(self.camera_position, self.camera_rotation) * crate::render::COORDINATE_SYSTEM;
+
+            self.update_gui(camera_mvp, delta_t, window_width, window_height);
+            self.run_queued(window_target, camera_mvp, window_width, window_height);
+            self.mouse.on_new_frame();
+        } else {
+            self.unlock_mouse();
+            self.plan_gui(drawing_mvp, window_width, window_height);
+            self.edit_plan(drawing_mvp, window_width, window_height);
+            self.run_exports();
+            self.mouse.on_new_frame();
+        }
+
+        self.render(target, drawing_mvp, window_width, window_height);
+        self.poll_processed_slice();
+
+        // Sleep until the next frame is due, events arriving meanwhile are handled without drawing
+        match self.fps_cap {
+            Some(fps) => {
+                let next_frame_time = now + Duration::from_secs_f32(1.0 / fps as f32);
+
+                self.idle_time = next_frame_time.saturating_duration_since(Instant::now()).as_secs_f32();
+                *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
+            },
+            None => {
+                self.idle_time = 0.0;
+                *control_flow = glutin::event_loop::ControlFlow::Poll;
+            },
+        }
+    }
+}
+
+/// Job post-processing a captured slice, or just passing it on if it was already processed on the GPU. Replacing the
+/// job it's submitted as drops any slice still being processed.
+fn process_slice(capture: SliceCapture, processor: Arc<dyn slice::SlicePostProcessor>, processed: Option<image::RgbaImage>, connect_radius: i32, alpha_threshold: u8, slice_use: SliceUse) -> impl FnOnce(&jobs::JobContext) -> Result<ProcessedSlice, String> {
+    move |context| {
+        let found_points = slice::slice_pixels(&capture.raw, alpha_threshold);
+        let image = match processed {
+            Some(image) => image,
+            None => processor.process(&capture.raw, &slice::ProcessContext {
+                connect_radius,
+                alpha_threshold,
+                job: context,
+            })?,
+        };
+
+        Ok(ProcessedSlice {
+            capture,
+            found_points,
+            image,
+            connect_radius,
+            alpha_threshold,
+            slice_use,
+        })
+    }
+}
+
+/// Move the walkthrough on from `done` if that's the step it's at
+fn advance_tutorial(step: &mut Option<tutorial::Step>, done: tutorial::Step) {
+    if *step == Some(done) {
+        *step = done.next();
+    }
+}
@@ -0,0 +1,1908 @@
+use std::{sync::mpsc, thread, time::{Duration, Instant}, path::Path, collections::HashMap};
+
+use crate::{camera::{self, Z_NEAR}, colour, config, export, filter, geometry, georef, jobs, keymap, link, locale, minimap, plan, raster, recent, render::{self, Background, Fog}, reproject, selection, split, transform::Transform, tutorial, vegetation};
+use crate::loader::{self, BATCH_SIZE};
+
+use super::{Viewer, Theme, DEFAULT_FPS, REDUCED_POINT_BUDGET, DEFAULT_MAX_SCAN_ANGLE, DEFAULT_PLAYBACK_WINDOW, MAX_LISTED_CLUSTERS};
+use super::plan_editor::{save_image_dialog, save_text_dialog};
+
+impl Viewer {
+    /// Side panel and windows of the 3D view
+    pub(super) fn update_gui(&mut self, camera_mvp: glam::Mat4, delta_t: Duration, window_width: u32, window_height: u32) {
+        let locale = &self.languages[self.language];
+
+        self.egui_glium.run(&self.display, |egui_ctx| {
+            puffin::profile_scope!("update_gui");
+
+            // Pastes into text fields are theirs
+            if !egui_ctx.wants_keyboard_input() {
+                self.paste_queued = egui_ctx.input().events.iter().find_map(|event| match event {
+                    egui::Event::Paste(text) => Some(text.clone()),
+                    _ => None,
+                });
+            }
+
+            egui::SidePanel::left("my_side_panel").show(egui_ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading(egui::RichText::new(locale.tr("Point Cloud Cutaway Renderer")).strong());
+                });
+
+                ui.separator();
+
+                if self.software_renderer {
+                    ui.colored_label(egui::Color32::YELLOW, locale.format("Software renderer detected ({}). Running in reduced mode: at most {} points per file, no MSAA, square points.", &[&self.renderer, &REDUCED_POINT_BUDGET]));
+                    ui.separator();
+                } else if self.reduced_mode {
+                    ui.colored_label(egui::Color32::YELLOW, locale.format("Reduced mode: at most {} points per file, no MSAA, square points.", &[&REDUCED_POINT_BUDGET]));
+                    ui.separator();
+                }
+
+                if self.batch_number >= 0 {
+                    ui.label(locale.tr("Loading Point Cloud File"));
+                    ui.add(egui::ProgressBar::new(self.batch_number as f32 / (self.total_points / BATCH_SIZE + 1) as f32).show_percentage());
+                } else {
+                    if !self.load_warnings.is_empty() {
+                        ui.collapsing(locale.format("Warnings ({})", &[&self.load_warnings.len()]), |ui| {
+                            for warning in &self.load_warnings {
+                                ui.colored_label(egui::Color32::YELLOW, warning);
+                            }
+
+                            if ui.button(locale.tr("Clear")).clicked() {
+                                self.load_warnings.clear();
+                            }
+                        });
+
+                        ui.separator();
+                    }
+
+                    let mut pick_file = None;
+
+                    ui.horizontal(|ui| {
+                        let load = ui.add_enabled(self.path_rx.is_none() && self.load_job.is_none(), egui::Button::new(locale.tr("Load Point Cloud")))
+                            .on_hover_text(locale.tr("Or paste a file path or view link into the window (Ctrl+V)"));
+                        if highlighted(egui_ctx, load, self.tutorial_step == Some(tutorial::Step::Load)).clicked() {
+                            pick_file = Some(false);
+                        }
+                        if ui.add_enabled(self.path_rx.is_none() && self.load_job.is_none() && !self.loaded_files.is_empty(), egui::Button::new(locale.tr("Add Point Cloud")))
+                            .on_hover_text(locale.tr("Load another file (e.g. an adjacent tile) alongside the current ones")).clicked() {
+                            pick_file = Some(true);
+                        }
+                    });
+
+                    if ui.add_enabled(self.loaded_files.len() == 1, egui::Button::new(locale.tr("Copy View Link")))
+                        .on_hover_text(locale.tr("Copy a link that opens this file from the current view, paste it back with Ctrl+V")).clicked() {
+                        let view = link::View {
+                            position: render::COORDINATE_SYSTEM.transform_point3(self.camera_position).as_dvec3() + self.centre.unwrap_or(glam::DVec3::ZERO),
+                            rotation: self.camera_rotation,
+                            zoom: self.camera_zoom,
+                        };
+
+                        ui.output().copied_text = link::view_link(&self.loaded_files[0].path, &view);
+                    }
+
+                    ui.horizontal(|ui| {
+                        let pipeline_name = self.pdal_pipeline.as_ref().map_or(locale.tr("Choose a pipeline first").to_owned(), |path| path.display().to_string());
+
+                        ui.add_enabled(self.pdal_pipeline.is_some(), egui::Checkbox::new(&mut self.preprocess_with_pdal, locale.tr("Preprocess with PDAL")))
+                            .on_hover_text(pipeline_name);
+                        if ui.add_enabled(self.pdal_pipeline_rx.is_none(), egui::Button::new(locale.tr("Pipeline...")))
+                            .on_hover_text(locale.tr("PDAL pipeline (JSON) to run on files before they're loaded, e.g. ground classification or outlier removal")).clicked() {
+                            let channels = mpsc::channel();
+                            self.pdal_pipeline_rx = Some(channels.1);
+                            let tx = channels.0;
+
+                            thread::spawn(move || {
+                                if let Some(path) = rfd::FileDialog::new().add_filter("PDAL Pipeline", &["json"]).pick_file() {
+                                    tx.send(path).expect("Failed to send pipeline path to main thread.");
+                                }
+                            });
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(self.watch_folder_rx.is_none(), egui::Button::new(locale.tr("Watch Folder...")))
+                            .on_hover_text(locale.tr("Load LAS and LAZ files in a folder as they appear or change, e.g. to see coverage build up while scanning")).clicked() {
+                            let channels = mpsc::channel();
+                            self.watch_folder_rx = Some(channels.1);
+                            let tx = channels.0;
+
+                            thread::spawn(move || {
+                                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                    tx.send(path).expect("Failed to send watched folder to main thread.");
+                                }
+                            });
+                        }
+
+                        if self.folder_watch.is_some() && ui.button(locale.tr("Stop Watching")).clicked() {
+                            self.folder_watch = None;
+                            self.watch_queue.clear();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let mut reproject = self.reproject_to.is_some();
+
+                        if ui.checkbox(&mut reproject, locale.tr("Reproject to EPSG:"))
+                            .on_hover_text(locale.tr("Move files loaded from now on into this coordinate system, so scans delivered in different ones line up")).changed() {
+                            // The loaded files' coordinate system to start with
+                            self.reproject_to = reproject.then(|| self.loaded_files.iter().find_map(|file| file.georeference.epsg).unwrap_or(4326));
+                        }
+                        if let Some(code) = &mut self.reproject_to {
+                            ui.add(egui::DragValue::new(code).speed(0.0));
+                        }
+                    });
+
+                    if let Some(code) = self.reproject_to {
+                        match reproject::crs_name(code) {
+                            Some(name) => ui.small(name),
+                            None => ui.colored_label(egui::Color32::YELLOW, locale.tr("Unknown EPSG code")),
+                        };
+                    }
+
+                    if let Some(watch) = &self.folder_watch {
+                        ui.small(locale.format("Watching {}", &[&watch.dir().display()]))
+                            .on_hover_text(locale.format("{} files changing, {} waiting to load", &[&watch.pending(), &self.watch_queue.len()]));
+                    }
+
+                    ui.horizontal(|ui| {
+                        if self.point_stream.is_some() {
+                            if ui.button(locale.tr("Stop Listening")).clicked() {
+                                self.point_stream = None;
+                            }
+                        } else {
+                            ui.add(egui::TextEdit::singleline(&mut self.stream_address).desired_width(120.0));
+
+                            if ui.add_enabled(self.path_rx.is_none() && self.load_job.is_none() && !self.listen_queued, egui::Button::new(locale.tr("Listen")))
+                                .on_hover_text(locale.tr("Show points streamed from a scanner or server as they arrive, over TCP or a WebSocket")).clicked() {
+                                self.listen_queued = true;
+                            }
+                        }
+                    });
+
+                    if let Some((listener, _)) = &self.point_stream {
+                        let status = if listener.is_connected() { locale.tr("Scanner connected") } else { locale.tr("Waiting for a scanner") };
+
+                        ui.small(locale.format("Listening on {}, {} points received", &[&listener.address(), &listener.points_received()]))
+                            .on_hover_text(status);
+                    }
+
+                    if !self.recent_files.is_empty() {
+                        ui.collapsing(locale.tr("Recent Files"), |ui| {
+                            for path in &self.recent_files {
+                                ui.horizontal(|ui| {
+                                    match thumbnail_texture(&mut self.thumbnail_textures, egui_ctx, path) {
+                                        Some(texture) => ui.image(texture.id(), [32.0, 32.0]),
+                                        None => ui.allocate_exact_size(egui::vec2(32.0, 32.0), egui::Sense::hover()).1,
+                                    };
+
+                                    if ui.add_enabled(self.path_rx.is_none() && self.load_job.is_none(), egui::Button::new(recent::file_name(path))).on_hover_text(path).clicked() {
+                                        self.open_recent_queued = Some(path.clone());
+                                    }
+                                });
+                            }
+                        });
+                    }
+
+                    if let Some(append) = pick_file {
+                        let channels = mpsc::channel();
+                        self.path_rx = Some(channels.1);
+                        let tx = channels.0;
+                        
+                        thread::spawn(move || {
+                            if let Some(path) = rfd::FileDialog::new().pick_file() {
+                                if let Some(path) = path.to_str() {
+                                    tx.send((path.to_owned(), append)).expect("Failed to send file path to main thread.");
+                                }
+                            }
+                        });
+                    }
+
+                    if self.loaded_files.len() > 1 {
+                        ui.collapsing(locale.format("Loaded Files ({})", &[&self.loaded_files.len()]), |ui| {
+                            for (i, file) in self.loaded_files.iter().enumerate() {
+                                let name = Path::new(&file.path).file_name().map_or(file.path.clone(), |name| name.to_string_lossy().into_owned());
+                                let points: usize = self.point_batches.iter().filter(|batch| batch.file == i).map(|batch| batch.points.len()).sum();
+
+                                let preprocessed = if file.preprocessed { ", PDAL" } else { "" };
+
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{} ({} points{})", name, points, preprocessed)).on_hover_text(&file.path);
+                                    if ui.small_button(locale.tr("Info")).clicked() {
+                                        self.file_info_queued = Some(i);
+                                    }
+                                });
+                            }
+                        });
+                    } else if self.loaded_files.len() == 1 && ui.button(locale.tr("File Info")).on_hover_text(locale.tr("Version, point format, bounds and coordinate system from the file's header")).clicked() {
+                        self.file_info_queued = Some(0);
+                    }
+
+                    ui.separator();
+                    
+                    // ui.add(egui::Slider::new(&mut clipping_dist, 0.4..=1.0).logarithmic(true));
+                    highlighted(egui_ctx, ui.checkbox(&mut self.clipping, locale.tr("Show Cutaway")), self.tutorial_step == Some(tutorial::Step::PositionClip));
+                    ui.small(locale.tr("Use W/S keys to control clipping distance."));
+                    ui.checkbox(&mut self.show_minimap, locale.tr("Show Minimap"));
+                    ui.checkbox(&mut self.split_view, locale.tr("Split View")).on_hover_text(locale.tr("Show the slice at the clip height beside the 3D view"));
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.show_compass, locale.tr("Show Compass"));
+                        ui.add(egui::DragValue::new(&mut self.north_offset).clamp_range(0.0..=359.9).speed(0.5).suffix("°"))
+                            .on_hover_text(locale.tr("North Offset\nBearing of north from the file's +Y axis, clockwise. 0 is grid north for projected coordinates."));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.show_grid, locale.tr("Show Grid"));
+                        ui.add(egui::DragValue::new(&mut self.grid_spacing).clamp_range(0.01..=1000.0).speed(0.1).suffix(" m")).on_hover_text(locale.tr("Grid Spacing"));
+                    });
+                    ui.add(egui::Slider::new(&mut self.cut_height, 0.0..=3.0).text(locale.tr("Cut Height Above Floor")));
+
+                    ui.add(egui::Slider::new(&mut self.point_size, 0.001..=20.0).logarithmic(true).text(locale.tr("Point Size")));
+                    
+                    // egui::ComboBox::from_label("Colour Format")
+                    // .selected_text(colour_format_options[colour_format as usize])
+                    // .show_ui(ui, |ui| {
+                    //     for option in colour_format_options.iter().enumerate() {
+                    //         ui.selectable_value(&mut colour_format, option.0 as i32, *option.1);
+                    //     }
+                    // });
+
+                    ui.checkbox(&mut self.process_slice_on_gpu, locale.tr("Process Slice on GPU"))
+                        .on_hover_text(locale.tr("Close gaps between walls on the graphics card, much faster on large captures but rounds off tight corners"));
+
+                    if self.slice_processors.len() > 1 && !self.process_slice_on_gpu {
+                        egui::ComboBox::from_label(locale.tr("Slice Processing"))
+                            .selected_text(locale.tr(self.slice_processors.get(self.slice_processor).name()))
+                            .show_ui(ui, |ui| {
+                                for (i, processor) in self.slice_processors.iter().enumerate() {
+                                    ui.selectable_value(&mut self.slice_processor, i, locale.tr(processor.name()));
+                                }
+                            });
+
+                        // Can't be changed while a slice is still being processed with it
+                        if let Some(processor) = self.slice_processors.get_mut(self.slice_processor) {
+                            processor.settings(ui, locale);
+                        }
+                    }
+                    wall_connection_settings(ui, locale, &mut self.connect_radius, &mut self.alpha_threshold, self.config.connect_radius(self.point_size, 2.0_f32.powf(-self.camera_zoom / 10.0)));
+
+                    if highlighted(egui_ctx, ui.button(locale.tr("Render")), self.tutorial_step == Some(tutorial::Step::Render)).clicked() {
+                        self.cutaway_queued = true;
+                    }
+                    if let Some(job) = &self.processed_slice {
+                        ui.add(egui::ProgressBar::new(job.status().progress()).text(locale.tr("Processing slice")));
+                    }
+                    if ui.add_enabled(self.floor_plan.is_some(), egui::Button::new(locale.tr("Return to Floor Plan")))
+                        .on_hover_text(locale.tr("Reopen the last rendered floor plan with its annotations")).clicked() {
+                        self.drawing_mode = true;
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label(locale.tr("Output Directory"));
+                        if ui.add_enabled(self.output_dir_rx.is_none(), egui::Button::new("...")).clicked() {
+                            let channels = mpsc::channel();
+                            self.output_dir_rx = Some(channels.1);
+                            let tx = channels.0;
+
+                            thread::spawn(move || {
+                                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                    if let Some(path) = path.to_str() {
+                                        tx.send(path.to_owned()).expect("Failed to send output directory to main thread.");
+                                    }
+                                }
+                            });
+                        }
+                    });
+                    ui.text_edit_singleline(&mut self.output_dir);
+                    if ui.button(locale.tr("Quick Render")).on_hover_text(locale.format("Render and save to the output directory ({})", &[&keymap::key_name(self.keymap.key(keymap::Action::QuickRender))])).clicked() {
+                        self.cutaway_queued = true;
+                        self.quick_render_queued = true;
+                    }
+                    if let Some(name) = &self.last_quick_render {
+                        ui.small(locale.format("Saved {}", &[&name]));
+                    }
+                    ui.horizontal(|ui| {
+                        floor_plan_depth_combo(ui, locale, &mut self.floor_plan_depth, 64.0);
+                        ui.label(locale.tr("Floor Plan Format"));
+                    });
+                    ui.horizontal(|ui| {
+                        let button = ui.add_enabled(self.script_rx.is_none() && self.script_job.is_none(), egui::Button::new(locale.tr("Run Script...")));
+                        if button.on_hover_text(locale.tr("Run a Rhai script that loads files, renders slices and saves images. Scripts don't change what's open here.")).clicked() {
+                            let channels = mpsc::channel();
+                            self.script_rx = Some(channels.1);
+                            let tx = channels.0;
+
+                            thread::spawn(move || {
+                                if let Some(path) = rfd::FileDialog::new().add_filter("Rhai Script", &["rhai"]).pick_file() {
+                                    tx.send(path).expect("Failed to send script path to main thread.");
+                                }
+                            });
+                        }
+
+                        if self.script_job.is_some() {
+                            ui.spinner();
+                        }
+                    });
+
+                    ui.separator();
+
+                    let mode_name = |mode: colour::ColourMode| match mode {
+                        colour::ColourMode::Rgb => "RGB".to_owned(),
+                        colour::ColourMode::Elevation(ramp) => locale.format("Elevation ({})", &[&self.colour_ramps[ramp].name]),
+                        colour::ColourMode::Intensity(ramp) => locale.format("Intensity ({})", &[&self.colour_ramps[ramp].name]),
+                        colour::ColourMode::Density(ramp) => locale.format("Density ({})", &[&self.colour_ramps[ramp].name]),
+                        colour::ColourMode::Distance(ramp) => locale.format("Distance to Reference ({})", &[&self.colour_ramps[ramp].name]),
+                        colour::ColourMode::Shader(shader) => self.point_shaders[shader].0.clone(),
+                        colour::ColourMode::Planes => locale.tr("Detected Planes").to_owned(),
+                        colour::ColourMode::Classification => locale.tr("Classification").to_owned(),
+                        colour::ColourMode::Clusters => locale.tr("Clusters").to_owned(),
+                    };
+
+                    let modes = std::iter::once(colour::ColourMode::Rgb)
+                        .chain((0..self.colour_ramps.len()).map(colour::ColourMode::Elevation))
+                        .chain((0..self.colour_ramps.len()).map(colour::ColourMode::Intensity))
+                        .chain((0..self.colour_ramps.len()).map(colour::ColourMode::Density))
+                        .chain((0..self.colour_ramps.len()).filter(|_| self.loaded_files.len() >= 2).map(colour::ColourMode::Distance))
+                        .chain((0..self.point_shaders.len()).map(colour::ColourMode::Shader))
+                        .chain((!self.detected_planes.is_empty()).then_some(colour::ColourMode::Planes))
+                        .chain((!self.point_counts.classifications.is_empty()).then_some(colour::ColourMode::Classification))
+                        .chain(self.clusters.is_some().then_some(colour::ColourMode::Clusters));
+
+                    egui::ComboBox::from_label(locale.tr("Colour"))
+                        .selected_text(mode_name(self.colour_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in modes {
+                                if ui.selectable_value(&mut self.colour_mode, mode, mode_name(mode)).changed() {
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                            }
+                        });
+
+                    if matches!(self.colour_mode, colour::ColourMode::Density(_)) {
+                        if ui.add(egui::Slider::new(&mut self.density_voxel_size, 0.01..=2.0).logarithmic(true).suffix(" m").text(locale.tr("Voxel Size"))).changed() {
+                            self.density_grid = None;
+                            self.rebuild_vertex_buffers = true;
+                        }
+                        if let Some(grid) = &self.density_grid {
+                            ui.small(locale.format("Top of the ramp: {} or more points per voxel", &[&grid.full]));
+                        }
+                    }
+
+                    if !self.plugin_errors.is_empty() {
+                        ui.collapsing(locale.format("Plugin Errors ({})", &[&self.plugin_errors.len()]), |ui| {
+                            for err in &self.plugin_errors {
+                                ui.label(err);
+                            }
+                        });
+                    }
+
+                    ui.separator();
+
+                    if !self.point_counts.point_sources.is_empty() {
+                        ui.collapsing(locale.tr("Point Sources"), |ui| {
+                            for (&id, &count) in &self.point_counts.point_sources {
+                                let mut visible = !self.point_filter.hidden_point_sources.contains(&id);
+
+                                if ui.checkbox(&mut visible, locale.format("Station {} ({} points)", &[&id, &count])).changed() {
+                                    if visible {
+                                        self.point_filter.hidden_point_sources.remove(&id);
+                                    } else {
+                                        self.point_filter.hidden_point_sources.insert(id);
+                                    }
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                            }
+
+                            ui.horizontal(|ui| {
+                                if ui.button(locale.tr("Show All")).clicked() {
+                                    self.point_filter.hidden_point_sources.clear();
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                                if ui.button(locale.tr("Hide All")).clicked() {
+                                    self.point_filter.hidden_point_sources.extend(self.point_counts.point_sources.keys());
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                            });
+                        });
+
+                        ui.separator();
+                    }
+
+                    if !self.point_counts.classifications.is_empty() {
+                        ui.collapsing(locale.tr("Classifications"), |ui| {
+                            for (&code, &count) in &self.point_counts.classifications {
+                                let mut visible = !self.point_filter.hidden_classifications.contains(&code);
+                                let name = locale.tr(filter::classification_name(code));
+
+                                if ui.checkbox(&mut visible, locale.format("{} {} ({} points)", &[&code, &name, &count])).changed() {
+                                    if visible {
+                                        self.point_filter.hidden_classifications.remove(&code);
+                                    } else {
+                                        self.point_filter.hidden_classifications.insert(code);
+                                    }
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                            }
+
+                            ui.horizontal(|ui| {
+                                if ui.button(locale.tr("Show All")).clicked() {
+                                    self.point_filter.hidden_classifications.clear();
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                                if ui.button(locale.tr("Hide All")).clicked() {
+                                    self.point_filter.hidden_classifications.extend(self.point_counts.classifications.keys());
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                            });
+                            ui.small(locale.tr("Hidden classes are left out of slices too"));
+                        });
+
+                        ui.separator();
+                    }
+
+                    if !self.point_counts.point_sources.is_empty() {
+                        ui.collapsing(locale.tr("Returns"), |ui| {
+                            egui::ComboBox::from_label(locale.tr("Pulse Returns")).selected_text(locale.tr(self.point_filter.returns.name())).show_ui(ui, |ui| {
+                                for option in filter::Returns::ALL {
+                                    if ui.selectable_value(&mut self.point_filter.returns, option, locale.tr(option.name())).changed() {
+                                        self.rebuild_vertex_buffers = true;
+                                    }
+                                }
+                            }).response.on_hover_text(locale.tr("Last returns only removes the tree canopy from aerial lidar"));
+
+                            let mut limit_scan_angle = self.point_filter.max_scan_angle.is_some();
+                            ui.horizontal(|ui| {
+                                if ui.checkbox(&mut limit_scan_angle, locale.tr("Max Scan Angle")).changed() {
+                                    self.point_filter.max_scan_angle = limit_scan_angle.then_some(DEFAULT_MAX_SCAN_ANGLE);
+                                    self.rebuild_vertex_buffers = true;
+                                }
+                                if let Some(max) = &mut self.point_filter.max_scan_angle {
+                                    if ui.add(egui::DragValue::new(max).clamp_range(0.0..=90.0).speed(0.5).suffix("°")).changed() {
+                                        self.rebuild_vertex_buffers = true;
+                                    }
+                                }
+                            }).response.on_hover_text(locale.tr("Hide points scanned further than this from straight down, either side"));
+                        });
+
+                        ui.separator();
+                    }
+
+                    if let Some((start, end)) = self.point_counts.gps_time {
+                        ui.collapsing(locale.tr("GPS Time Playback"), |ui| {
+                            let mut changed = ui.checkbox(&mut self.playback.enabled, locale.tr("Reveal in Acquisition Order"))
+                                .on_hover_text(locale.tr("Show the points scanned up to a time, to find registration drift or a single pass of a mobile scan"))
+                                .changed();
+
+                            if changed && !(start..=end).contains(&self.playback.time) {
+                                self.playback.time = end;
+                            }
+
+                            ui.add_enabled_ui(self.playback.enabled, |ui| {
+                                let mut elapsed = self.playback.time - start;
+                                if ui.add(egui::Slider::new(&mut elapsed, 0.0..=end - start).suffix(" s").text(locale.tr("Time"))).changed() {
+                                    self.playback.time = start + elapsed;
+                                    changed = true;
+                                }
+
+                                ui.horizontal(|ui| {
+                                    if ui.button(locale.tr(if self.playback.playing { "Pause" } else { "Play" })).clicked() {
+                                        if !self.playback.playing && self.playback.time >= end {
+                                            self.playback.time = start;
+                                            changed = true;
+                                        }
+                                        self.playback.playing = !self.playback.playing;
+                                    }
+                                    ui.add(egui::DragValue::new(&mut self.playback.speed).clamp_range(0.1..=1000.0).speed(0.5).suffix("x"))
+                                        .on_hover_text(locale.tr("Scan seconds played each second"));
+                                });
+
+                                ui.horizontal(|ui| {
+                                    let mut windowed = self.playback.window.is_some();
+                                    if ui.checkbox(&mut windowed, locale.tr("Only the Last")).changed() {
+                                        self.playback.window = windowed.then_some(DEFAULT_PLAYBACK_WINDOW);
+                                        changed = true;
+                                    }
+                                    if let Some(window) = &mut self.playback.window {
+                                        changed |= ui.add(egui::DragValue::new(window).clamp_range(0.1..=f64::MAX).speed(0.5).suffix(" s")).changed();
+                                    }
+                                });
+                            });
+
+                            if changed {
+                                self.point_filter.gps_time = self.playback.filter();
+                                self.rebuild_vertex_buffers = true;
+                            }
+                        });
+
+                        ui.separator();
+                    }
+
+                    ui.collapsing(locale.tr("Vegetation"), |ui| {
+                        let mut enabled = self.point_filter.vegetation.is_some();
+                        let mut changed = ui.checkbox(&mut enabled, locale.tr("Hide Vegetation"))
+                            .on_hover_text(locale.tr("Hide trees and bushes around a building from the view and slices"))
+                            .changed();
+                        if changed {
+                            self.point_filter.vegetation = enabled.then(vegetation::VegetationFilter::default);
+                        }
+
+                        if let Some(vegetation) = &mut self.point_filter.vegetation {
+                            changed |= ui.checkbox(&mut vegetation.early_returns, locale.tr("Early Returns"))
+                                .on_hover_text(locale.tr("Points a pulse hit before its last return, the canopy over the ground and roofs"))
+                                .changed();
+
+                            ui.horizontal(|ui| {
+                                let mut green = vegetation.greenness.is_some();
+                                if ui.checkbox(&mut green, locale.tr("Green")).on_hover_text(locale.tr("Greenness from RGB, standing in for NDVI")).changed() {
+                                    vegetation.greenness = green.then_some(vegetation::DEFAULT_GREENNESS);
+                                    changed = true;
+                                }
+                                if let Some(greenness) = &mut vegetation.greenness {
+                                    changed |= ui.add(egui::Slider::new(greenness, 0.0..=0.5)).changed();
+                                }
+                            });
+
+                            ui.horizontal(|ui| {
+                                let mut rough = vegetation.roughness.is_some();
+                                if ui.checkbox(&mut rough, locale.tr("Rough")).on_hover_text(locale.tr("How far points scatter off a plane, low on walls and roofs and high in foliage")).changed() {
+                                    vegetation.roughness = rough.then_some(vegetation::DEFAULT_ROUGHNESS);
+                                    changed = true;
+                                }
+                                if let Some(roughness) = &mut vegetation.roughness {
+                                    changed |= ui.add(egui::Slider::new(roughness, 0.0..=0.33)).changed();
+                                }
+                            });
+
+                            ui.small(locale.tr("Points that are green and rough are hidden, or either when only one is ticked"));
+                        }
+
+                        if changed {
+                            self.rebuild_vertex_buffers = true;
+                        }
+                    });
+
+                    ui.separator();
+
+                    ui.collapsing(locale.tr("Footprint Clip"), |ui| {
+                        if ui.add_enabled(self.footprint_rx.is_none(), egui::Button::new(locale.tr("Import Footprint...")))
+                            .on_hover_text(locale.tr("Building outline as GeoJSON or WKT polygons, in the same coordinate system as the scans")).clicked() {
+                            let channels = mpsc::channel();
+                            self.footprint_rx = Some(channels.1);
+                            let tx = channels.0;
+
+                            thread::spawn(move || {
+                                if let Some(path) = rfd::FileDialog::new().add_filter("Footprint", &["geojson", "json", "wkt", "txt"]).pick_file() {
+                                    tx.send(path).expect("Failed to send footprint path to main thread.");
+                                }
+                            });
+                        }
+
+                        if let Some(err) = &self.footprint_error {
+                            ui.colored_label(egui::Color32::RED, err);
+                        }
+
+                        let mut clear = false;
+                        if let Some((clip, path)) = &mut self.footprint {
+                            ui.label(locale.format("{} ({} rings)", &[&recent::file_name(&path.to_string_lossy()), &clip.footprint.ring_count()]));
+
+                            let mut changed = false;
+                            let mut enabled = self.point_filter.footprint.is_some();
+                            if ui.checkbox(&mut enabled, locale.tr("Clip to Footprint")).changed() {
+                                changed = true;
+                            }
+
+                            let mut limited = clip.elevation.is_some();
+                            if ui.checkbox(&mut limited, locale.tr("Limit Height")).on_hover_text(locale.tr("Also clip points below and above these elevations, e.g. the ground or a neighbour's roof")).changed() {
+                                let bounds = self.loaded_files.iter().fold(None, |bounds, file| loader::union_bounds(bounds, file.bounds));
+                                let (bottom, top) = bounds.map_or((0.0, 10.0), |(min, max)| (min.z, max.z));
+                                clip.elevation = limited.then_some(bottom..=top);
+                                changed = true;
+                            }
+
+                            if let Some(elevation) = &mut clip.elevation {
+                                let (mut bottom, mut top) = elevation.clone().into_inner();
+                                ui.horizontal(|ui| {
+                                    changed |= ui.add(egui::DragValue::new(&mut bottom).speed(0.05).suffix(" m")).changed();
+                                    ui.label(locale.tr("to"));
+                                    changed |= ui.add(egui::DragValue::new(&mut top).speed(0.05).suffix(" m")).changed();
+                                });
+                                *elevation = bottom.min(top)..=top.max(bottom);
+                            }
+
+                            if ui.button(locale.tr("Remove Footprint")).clicked() {
+                                clear = true;
+                            } else if changed {
+                                self.point_filter.footprint = enabled.then(|| clip.clone());
+                                self.rebuild_vertex_buffers = true;
+                            }
+                        }
+
+                        if clear {
+                            self.footprint = None;
+                            self.point_filter.footprint = None;
+                            self.rebuild_vertex_buffers = true;
+                        }
+                    });
+
+                    ui.separator();
+
+                    self.dock.dock_all_button(ui, locale);
+
+                    self.dock.section(egui_ctx, ui, locale, "Floor Levels", |ui| {
+                        let detect = ui.button(locale.tr("Detect Levels")).on_hover_text(locale.tr("Find floor and ceiling slabs from the spread of point elevations"));
+                        if highlighted(egui_ctx, detect, self.tutorial_step == Some(tutorial::Step::PositionClip)).clicked() {
+                            self.detect_levels_queued = true;
+                        }
+
+                        let floors: Vec<(usize, &geometry::Level)> = self.levels.iter().enumerate()
+                            .filter(|(_, level)| level.kind == geometry::PlaneKind::Floor)
+                            .collect();
+
+                        if !self.levels.is_empty() {
+                            let selected_text = match self.selected_level {
+                                Some(i) => locale.format("Floor at {}", &[&format!("{:.2}", self.levels[i].height)]),
+                                None => locale.tr("Choose a floor").to_owned(),
+                            };
+
+                            egui::ComboBox::from_label(locale.tr("Floor")).selected_text(selected_text).show_ui(ui, |ui| {
+                                for (n, &(i, level)) in floors.iter().enumerate() {
+                                    let text = locale.format("Floor {} at {} ({}% of points)", &[&(n + 1), &format!("{:.2}", level.height), &format!("{:.0}", level.fraction * 100.0)]);
+
+                                    if ui.selectable_label(self.selected_level == Some(i), text).clicked() {
+                                        self.selected_level = Some(i);
+                                        self.snap_clip_height = Some(level.height);
+                                    }
+                                }
+                            });
+
+                            let ceilings = self.levels.len() - floors.len();
+                            ui.small(format!("{} floors and {} ceilings found", floors.len(), ceilings));
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Plane Detection", |ui| {
+                        ui.add(egui::Slider::new(&mut self.plane_threshold, 0.005..=0.2).logarithmic(true).text(locale.tr("Distance Threshold")));
+                        ui.add(egui::Slider::new(&mut self.max_planes, 1..=32).text(locale.tr("Max Planes")));
+                        let button = ui.add_enabled(self.planes_job.is_none(), egui::Button::new(locale.tr("Detect Planes")));
+                        if button.on_hover_text(locale.tr("Find dominant planes (RANSAC) and snap the clipping plane to the nearest floor")).clicked() {
+                            self.detect_planes_queued = true;
+                        }
+                        if let Some(job) = &self.planes_job {
+                            ui.add(egui::ProgressBar::new(job.status().progress()).show_percentage());
+                        }
+
+                        for (i, detected) in self.detected_planes.iter().enumerate() {
+                            let [r, g, b] = colour::plane_colour(i);
+                            let description = match detected.kind {
+                                geometry::PlaneKind::Floor => locale.format("Floor at {}", &[&format!("{:.2}", detected.plane.origin.z)]),
+                                geometry::PlaneKind::Ceiling => locale.format("Ceiling at {}", &[&format!("{:.2}", detected.plane.origin.z)]),
+                                geometry::PlaneKind::Wall => locale.tr("Wall").to_owned(),
+                                geometry::PlaneKind::Other => locale.tr("Sloped").to_owned(),
+                            };
+
+                            ui.horizontal(|ui| {
+                                ui.colored_label(egui::Color32::from_rgb(r, g, b), format!("{} ({:.0}%)", description, detected.inlier_fraction * 100.0));
+
+                                if detected.kind == geometry::PlaneKind::Floor && ui.small_button(locale.tr("Snap")).clicked() {
+                                    self.snap_clip_height = Some(detected.plane.origin.z);
+                                }
+                            });
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Ground Filter", |ui| {
+                        ui.add(egui::Slider::new(&mut self.ground_settings.resolution, 0.1..=5.0).logarithmic(true).suffix(" m").text(locale.tr("Cloth Resolution")))
+                            .on_hover_text(locale.tr("Coarser cloths span larger buildings"));
+                        ui.add(egui::Slider::new(&mut self.ground_settings.rigidness, 1..=10).text(locale.tr("Rigidness")))
+                            .on_hover_text(locale.tr("1 for steep terrain, 3 or more for flat terrain with large buildings"));
+                        ui.add(egui::Slider::new(&mut self.ground_settings.threshold, 0.05..=2.0).logarithmic(true).suffix(" m").text(locale.tr("Ground Threshold")));
+
+                        let button = ui.add_enabled(self.ground_job.is_none() && !self.point_batches.is_empty(), egui::Button::new(locale.tr("Classify Ground")));
+                        if button.on_hover_text(locale.tr("Class the shown points as ground or not with a cloth simulation (CSF), to split aerial lidar into terrain and structures. Points with other classes keep them.")).clicked() {
+                            self.classify_ground_queued = true;
+                        }
+
+                        if let Some((job, _)) = &self.ground_job {
+                            ui.add(egui::ProgressBar::new(job.status().progress()).show_percentage());
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Clustering", |ui| {
+                        ui.add(egui::Slider::new(&mut self.cluster_gap, 0.05..=5.0).logarithmic(true).suffix(" m").text(locale.tr("Gap")))
+                            .on_hover_text(locale.tr("Points closer than this are in the same cluster"));
+                        ui.add(egui::Slider::new(&mut self.min_cluster_points, 1..=100_000).logarithmic(true).text(locale.tr("Min Points")));
+
+                        ui.horizontal(|ui| {
+                            let button = ui.add_enabled(!self.point_batches.is_empty() && self.clusters_job.is_none(), egui::Button::new(locale.tr("Find Clusters")));
+                            if button.on_hover_text(locale.tr("Split the shown points into groups separated by gaps, e.g. to isolate one building on a large tile")).clicked() {
+                                self.find_clusters_queued = true;
+                            }
+                            if self.clusters_job.is_some() {
+                                ui.spinner();
+                            }
+                        });
+
+                        if let Some(clusters) = &self.clusters {
+                            ui.label(locale.format("{} clusters", &[&clusters.sizes.len()]));
+
+                            egui::ScrollArea::vertical().id_source("clusters").max_height(200.0).show(ui, |ui| {
+                                for (i, size) in clusters.sizes.iter().enumerate().take(MAX_LISTED_CLUSTERS) {
+                                    let [r, g, b] = colour::cluster_colour(i);
+                                    let text = egui::RichText::new(locale.format("Cluster {} ({} points)", &[&(i + 1), size])).color(egui::Color32::from_rgb(r, g, b));
+
+                                    if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked() {
+                                        let mode = if self.modifiers.shift() { selection::SelectMode::Add } else { selection::SelectMode::Replace };
+                                        self.select_cluster_queued = Some((i, mode));
+                                    }
+                                }
+                            });
+                            ui.small(locale.tr("Click a cluster to select it, Shift to add it, then export or delete it under Selection"));
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Interface", |ui| {
+                        egui::ComboBox::from_label(locale.tr("Language")).selected_text(&self.languages[self.language].name).show_ui(ui, |ui| {
+                            for (i, option) in self.languages.iter().enumerate() {
+                                ui.selectable_value(&mut self.language, i, &option.name);
+                            }
+                        });
+
+                        let previous = self.theme;
+
+                        egui::ComboBox::from_label(locale.tr("Theme")).selected_text(locale.tr(self.theme.name())).show_ui(ui, |ui| {
+                            for option in [Theme::Dark, Theme::Light] {
+                                ui.selectable_value(&mut self.theme, option, locale.tr(option.name()));
+                            }
+                        });
+
+                        let mut presenting = self.presentation.is_some();
+                        if ui.checkbox(&mut presenting, locale.tr("Presentation Mode")).on_hover_text(locale.tr("White background and dark interface, for projectors")).changed() {
+                            if presenting {
+                                self.presentation = Some((self.theme, self.background));
+                                self.theme = Theme::Dark;
+                                self.background = Background::Solid([255; 3]);
+                            } else if let Some((previous_theme, previous_background)) = self.presentation.take() {
+                                self.theme = previous_theme;
+                                self.background = previous_background;
+                            }
+                        }
+
+                        if self.theme != previous {
+                            egui_ctx.set_visuals(self.theme.visuals());
+                        }
+
+                        if ui.button(locale.tr("Show Walkthrough")).on_hover_text(locale.tr("Go through loading a scan and drawing its floor plan step by step")).clicked() {
+                            self.tutorial_step = Some(tutorial::Step::Load);
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Transform", |ui| {
+                        egui::Grid::new("transform").num_columns(4).show(ui, |ui| {
+                            ui.label(locale.tr("Move"));
+                            for value in self.point_transform.translation.as_mut() {
+                                ui.add(egui::DragValue::new(value).speed(0.01).suffix(" m"));
+                            }
+                            ui.end_row();
+
+                            ui.label(locale.tr("Rotate"));
+                            for value in self.point_transform.rotation.as_mut() {
+                                ui.add(egui::DragValue::new(value).clamp_range(-180.0..=180.0).speed(0.1).suffix("°"));
+                            }
+                            ui.end_row();
+
+                            ui.label(locale.tr("Scale"));
+                            ui.add(egui::DragValue::new(&mut self.point_transform.scale).clamp_range(0.001..=1000.0).speed(0.001));
+                            ui.end_row();
+                        });
+
+                        // Largest detected floor and wall, detected on the points before the transform
+                        let largest = |kind: geometry::PlaneKind| self.detected_planes.iter().filter(|detected| detected.kind == kind)
+                            .max_by(|a, b| a.inlier_fraction.total_cmp(&b.inlier_fraction));
+
+                        ui.horizontal(|ui| {
+                            let floor = largest(geometry::PlaneKind::Floor);
+                            if ui.add_enabled(floor.is_some(), egui::Button::new(locale.tr("Level to Floor")))
+                                .on_hover_text(locale.tr("Tilt so the largest detected floor is horizontal, detect planes first")).clicked() {
+                                if let Some(floor) = floor {
+                                    self.point_transform.level(floor.plane.normal);
+                                }
+                            }
+
+                            let wall = largest(geometry::PlaneKind::Wall);
+                            if ui.add_enabled(wall.is_some(), egui::Button::new(locale.tr("Square to Wall")))
+                                .on_hover_text(locale.tr("Turn so the largest detected wall lines up with the X or Y axis, detect planes first")).clicked() {
+                                if let Some(wall) = wall {
+                                    self.point_transform.square(wall.plane.normal);
+                                }
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(!self.point_transform.is_identity(), egui::Button::new(locale.tr("Apply")))
+                                .on_hover_text(locale.tr("Move the loaded points, so slices, picking and exports use the transformed points")).clicked() {
+                                self.move_points_queued = Some((self.point_transform.matrix(self.centre.unwrap_or(glam::DVec3::ZERO)), None));
+                                self.point_transform = Transform::default();
+                            }
+                            if ui.add_enabled(!self.point_transform.is_identity(), egui::Button::new(locale.tr("Reset"))).clicked() {
+                                self.point_transform = Transform::default();
+                            }
+                        });
+
+                        if !self.point_transform.is_identity() {
+                            ui.small(locale.tr("Previewed in the 3D view and its renders. Picking, selection and measuring use the points as they were until it's applied."));
+                        }
+                    });
+
+                    if self.loaded_files.len() >= 2 {
+                        self.dock.section(egui_ctx, ui, locale, "Registration", |ui| {
+                            let file_name = |i: usize| self.loaded_files.get(i).map_or(String::new(), |file| recent::file_name(&file.path));
+                            for (label, index) in [(locale.tr("Reference"), &mut self.registration_files.0), (locale.tr("Align"), &mut self.registration_files.1)] {
+                                egui::ComboBox::from_label(label).selected_text(file_name(*index)).show_ui(ui, |ui| {
+                                    for i in 0..self.loaded_files.len() {
+                                        ui.selectable_value(index, i, file_name(i));
+                                    }
+                                });
+                            }
+
+                            ui.checkbox(&mut self.coarse_registration, locale.tr("Coarse Alignment"))
+                                .on_hover_text(locale.tr("Also try matching the clouds' centres and main directions, for scans that aren't roughly aligned already. Scans should be levelled."));
+
+                            let (reference, moving) = self.registration_files;
+                            let valid = reference != moving && reference.max(moving) < self.loaded_files.len();
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(valid && self.rx.is_none() && self.register_job.is_none(), egui::Button::new(locale.tr("Register")))
+                                    .on_hover_text(locale.tr("Move the aligned file onto the reference by iterative closest points")).clicked() {
+                                    self.register_queued = true;
+                                }
+                                if self.register_job.is_some() {
+                                    ui.spinner();
+                                }
+                            });
+
+                            match &self.registration {
+                                Some(Ok(result)) => {
+                                    ui.label(locale.format("RMSE: {} m", &[&format!("{:.4}", result.rmse)]));
+                                    ui.small(locale.format("{} iterations, {}% of points matched", &[&result.iterations, &format!("{:.0}", result.overlap * 100.0)]));
+                                },
+                                Some(Err(err)) => {
+                                    ui.colored_label(egui::Color32::RED, err);
+                                },
+                                None => {},
+                            }
+                        });
+
+                        self.dock.section(egui_ctx, ui, locale, "Change Detection", |ui| {
+                            let file_name = |i: usize| self.loaded_files.get(i).map_or(String::new(), |file| recent::file_name(&file.path));
+                            let previous = self.change_files;
+                            for (label, index) in [(locale.tr("Reference"), &mut self.change_files.0), (locale.tr("Compared"), &mut self.change_files.1)] {
+                                egui::ComboBox::from_label(label).selected_text(file_name(*index)).show_ui(ui, |ui| {
+                                    for i in 0..self.loaded_files.len() {
+                                        ui.selectable_value(index, i, file_name(i));
+                                    }
+                                });
+                            }
+
+                            let (reference, comparison) = self.change_files;
+                            let valid = reference != comparison && reference.max(comparison) < self.loaded_files.len();
+                            let comparing = matches!(self.colour_mode, colour::ColourMode::Distance(_));
+
+                            if comparing && self.change_files != previous {
+                                self.cloud_distances = None;
+                                self.rebuild_vertex_buffers = valid;
+                            }
+
+                            if ui.add_enabled(valid && !comparing && !self.colour_ramps.is_empty(), egui::Button::new(locale.tr("Compare")))
+                                .on_hover_text(locale.tr("Colour the compared file by the distance of each point to the nearest point of the reference")).clicked() {
+                                self.colour_mode = colour::ColourMode::Distance(0);
+                                self.cloud_distances = None;
+                                self.rebuild_vertex_buffers = true;
+                            }
+
+                            if ui.add(egui::Slider::new(&mut self.distance_threshold, 0.001..=1.0).logarithmic(true).suffix(" m").text(locale.tr("Threshold")))
+                                .on_hover_text(locale.tr("Distance at the top of the colour ramp, changes larger than this all look the same")).changed() && comparing {
+                                self.rebuild_vertex_buffers = true;
+                            }
+
+                            match self.cloud_distances.as_ref().filter(|_| comparing) {
+                                Some(Ok(distances)) => {
+                                    ui.label(locale.format("{}% of points within the threshold", &[&format!("{:.1}", distances.within(self.distance_threshold as f64) * 100.0)]));
+                                    ui.small(locale.format("Median {} m, 95th percentile {} m", &[&format!("{:.4}", distances.percentile(0.5)), &format!("{:.4}", distances.percentile(0.95))]));
+                                },
+                                Some(Err(err)) => {
+                                    ui.colored_label(egui::Color32::RED, err);
+                                },
+                                None => {},
+                            }
+                        });
+                    }
+
+                    self.dock.section(egui_ctx, ui, locale, "Background", |ui| {
+                        // Switching keeps the colours picked so far
+                        let (solid, gradient) = match self.background {
+                            Background::Solid(colour) => (self.background, Background::Gradient { top: colour, bottom: colour.map(|c| c + (255 - c) / 2) }),
+                            Background::Gradient { top, .. } => (Background::Solid(top), self.background),
+                        };
+
+                        egui::ComboBox::from_label(locale.tr("Style")).selected_text(locale.tr(self.background.name())).show_ui(ui, |ui| {
+                            for option in [solid, gradient] {
+                                ui.selectable_value(&mut self.background, option, locale.tr(option.name()));
+                            }
+                        });
+
+                        match &mut self.background {
+                            Background::Solid(colour) => {
+                                ui.horizontal(|ui| {
+                                    ui.color_edit_button_srgb(colour);
+                                    ui.label(locale.tr("Colour"));
+                                });
+                            },
+                            Background::Gradient { top, bottom } => {
+                                ui.horizontal(|ui| {
+                                    ui.color_edit_button_srgb(top);
+                                    ui.label(locale.tr("Top"));
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.color_edit_button_srgb(bottom);
+                                    ui.label(locale.tr("Bottom"));
+                                });
+                            },
+                        }
+
+                        if ui.button(locale.tr("Reset")).clicked() {
+                            self.background = Background::default();
+                        }
+                        ui.small(locale.tr("Cutaways are always captured on white."));
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Fog", |ui| {
+                        egui::ComboBox::from_label(locale.tr("Fade Distant Points")).selected_text(locale.tr(self.fog.name())).show_ui(ui, |ui| {
+                            for option in [Fog::Off, Fog::Linear { start: 5.0, end: 50.0 }, Fog::Exponential { density: 0.05 }] {
+                                // Keeps the settings of the one already picked
+                                if ui.selectable_label(self.fog.name() == option.name(), locale.tr(option.name())).clicked() && self.fog.name() != option.name() {
+                                    self.fog = option;
+                                }
+                            }
+                        });
+
+                        match &mut self.fog {
+                            Fog::Off => {},
+                            Fog::Linear { start, end } => {
+                                ui.add(egui::DragValue::new(start).clamp_range(0.0..=*end).speed(0.1).prefix(locale.tr("From ")).suffix(" m"));
+                                ui.add(egui::DragValue::new(end).clamp_range(*start..=camera::Z_FAR).speed(0.1).prefix(locale.tr("To ")).suffix(" m"));
+                            },
+                            Fog::Exponential { density } => {
+                                ui.add(egui::Slider::new(density, 0.001..=1.0).logarithmic(true).text(locale.tr("Density")));
+                            },
+                        }
+                        ui.small(locale.tr("Measured from the clipping plane, fading into the background."));
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Performance", |ui| {
+                        let mut capped = self.fps_cap.is_some();
+
+                        ui.horizontal(|ui| {
+                            if ui.checkbox(&mut capped, locale.tr("Cap at")).changed() {
+                                self.fps_cap = capped.then_some(DEFAULT_FPS);
+                            }
+                            if let Some(fps) = &mut self.fps_cap {
+                                ui.add(egui::DragValue::new(fps).clamp_range(10..=240).suffix(" FPS"));
+                            }
+                        });
+
+                        if self.fps_cap.is_none() {
+                            ui.small(locale.tr(if self.vsync { "Drawing at the display's refresh rate" } else { "Drawing as fast as possible" }));
+                        }
+
+                        ui.label(locale.format("Multisampling: {}", &[&if self.msaa > 0 { format!("{}x", self.msaa) } else { "Off".to_owned() }]))
+                            .on_hover_text(locale.tr("Set with --msaa when starting"));
+
+                        egui::ComboBox::from_label(locale.tr("Capture Supersampling"))
+                            .selected_text(format!("{}x", self.capture_supersampling))
+                            .show_ui(ui, |ui| {
+                                for factor in 1..=config::MAX_SUPERSAMPLING {
+                                    ui.selectable_value(&mut self.capture_supersampling, factor, format!("{}x", factor));
+                                }
+                            })
+                            .response.on_hover_text(locale.tr("Draw cutaways at a multiple of the window size and scale them down, for smoother line work"));
+
+                        ui.checkbox(&mut self.progressive_refinement, locale.tr("Progressive Refinement"))
+                            .on_hover_text(locale.tr("Draw part of the points each frame while moving, filling in the rest once the view stops"));
+                        if self.progressive_refinement {
+                            ui.add(egui::Slider::new(&mut self.refine_points_per_frame, 0.5..=50.0).logarithmic(true).suffix("M").text(locale.tr("Points per Frame")));
+                        }
+                        if let Some(progress) = self.refinement.as_ref().map(|refinement| refinement.progress()).filter(|progress| *progress < 1.0) {
+                            ui.add(egui::ProgressBar::new(progress).text(locale.tr("Refining")));
+                        }
+
+                        ui.checkbox(&mut self.show_profiler, locale.tr("Profiler"))
+                            .on_hover_text(locale.tr("Time spent in each part of every frame"));
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Auto-Orbit", |ui| {
+                        ui.add(egui::Slider::new(&mut self.auto_orbit_speed, -90.0..=90.0).text(locale.tr("Speed")).suffix("°/s"));
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.auto_orbit_when_idle, locale.tr("Start when idle for"));
+                            ui.add_enabled(self.auto_orbit_when_idle, egui::DragValue::new(&mut self.auto_orbit_idle_time).clamp_range(1.0..=3600.0).suffix(" s"));
+                        });
+
+                        if ui.button(locale.tr("Start Now")).on_hover_text(locale.tr("Orbit the loaded files until there's any input")).clicked() {
+                            self.auto_orbit = true;
+                            self.auto_orbit_started = Instant::now();
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Controls", |ui| {
+                        egui::Grid::new("keymap").num_columns(2).show(ui, |ui| {
+                            for action in keymap::Action::ALL {
+                                ui.label(locale.tr(action.name()));
+
+                                let text = if self.rebinding == Some(action) { locale.tr("Press a key...").to_owned() } else { keymap::key_name(self.keymap.key(action)) };
+
+                                if ui.button(text).on_hover_text(locale.tr("Click, then press the key to use (Escape to cancel)")).clicked() {
+                                    self.rebinding = Some(action);
+                                }
+                                ui.end_row();
+                            }
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label(locale.tr("Movement Layout"));
+
+                            for (name, keys) in keymap::LAYOUTS {
+                                if ui.button(name).on_hover_text(locale.tr("Put the movement keys where WASD is on a QWERTY keyboard")).clicked() {
+                                    self.keymap.use_layout(keys);
+                                    self.keymap.save();
+                                }
+                            }
+                        });
+
+                        if ui.button(locale.tr("Reset to Defaults")).clicked() {
+                            self.keymap = keymap::Keymap::default();
+                            self.keymap.save();
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Inspect", |ui| {
+                        ui.checkbox(&mut self.inspecting, locale.tr("Pick Points")).on_hover_text(locale.tr("Left click a point to show its coordinates and attributes"));
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Selection", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(&mut self.select_tool, None, locale.tr("Off"));
+                            ui.selectable_value(&mut self.select_tool, Some(selection::SelectTool::Box), locale.tr("Box"));
+                            ui.selectable_value(&mut self.select_tool, Some(selection::SelectTool::Lasso), locale.tr("Lasso"));
+                        });
+                        ui.small(locale.tr("Drag with the left mouse button, hold Shift to add to the selection or Alt to remove from it"));
+
+                        ui.label(locale.format("Selected: {} points", &[&self.selected_points]));
+
+                        if self.selected_points > 0 {
+                            if ui.button(locale.tr("Clear Selection")).clicked() {
+                                self.clear_selection_queued = true;
+                            }
+                            if ui.button(locale.tr("Export Selection as LAS...")).on_hover_text(locale.tr("Write the selected points to a new file with the original header, scale and offset")).clicked() {
+                                self.export_selection_queued = true;
+                            }
+                            if ui.button(locale.tr("Delete Selected Points")).on_hover_text(locale.tr("Remove the selected points from the loaded cloud, e.g. people, tripods or noise (Delete). The files aren't changed.")).clicked() {
+                                self.delete_selection_queued = true;
+                            }
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Measure", |ui| {
+                        ui.checkbox(&mut self.measuring, locale.tr("Pick Points")).on_hover_text(locale.tr("Left click points on the cloud to outline an area, Backspace removes the last point"));
+
+                        ui.label(locale.format("Points: {}", &[&self.measure_points.len()]));
+
+                        if let Some((area, plane)) = geometry::polygon_area(&self.measure_points) {
+                            let tilt = plane.normal.z.abs().clamp(0.0, 1.0).acos().to_degrees();
+                            let footprint = geometry::footprint_area(&self.measure_points);
+
+                            ui.label(locale.format("Area: {} m²", &[&format!("{:.2}", area)])).on_hover_text(locale.format("On the best fit plane, tilted {}° from horizontal", &[&format!("{:.0}", tilt)]));
+                            ui.label(locale.format("Footprint: {} m²", &[&format!("{:.2}", footprint)])).on_hover_text(locale.tr("Seen from above"));
+
+                            if ui.checkbox(&mut self.measure_volume, locale.tr("Volume Between Heights")).changed() && self.measure_volume {
+                                // Start from the floor of the outline
+                                self.volume_bottom = self.measure_points.iter().map(|p| p.z).fold(f64::INFINITY, f64::min);
+                                self.volume_top = self.volume_bottom + 2.5;
+                            }
+
+                            if self.measure_volume {
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::DragValue::new(&mut self.volume_bottom).speed(0.01).fixed_decimals(2));
+                                    ui.label(locale.tr("to"));
+                                    ui.add(egui::DragValue::new(&mut self.volume_top).speed(0.01).fixed_decimals(2));
+                                });
+                                ui.label(locale.format("Volume: {} m³", &[&format!("{:.2}", footprint * (self.volume_top - self.volume_bottom).max(0.0))]));
+                            }
+                        }
+
+                        if !self.measure_points.is_empty() && ui.button(locale.tr("Clear")).clicked() {
+                            self.measure_points.clear();
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Profile", |ui| {
+                        ui.checkbox(&mut self.profiling, locale.tr("Pick Section Line")).on_hover_text(locale.tr("Left click two points on the cloud to plot the height of the points along the line between them"));
+
+                        if ui.add(egui::Slider::new(&mut self.profile_buffer, 0.005..=1.0).logarithmic(true).suffix(" m").text(locale.tr("Buffer")))
+                            .on_hover_text(locale.tr("Points up to this far either side of the line are plotted")).changed() && self.profile_line.len() == 2 {
+                            self.profile_queued = true;
+                        }
+
+                        if let [start, end] = self.profile_line[..] {
+                            ui.label(locale.format("Line: {} m", &[&format!("{:.2}", start.truncate().distance(end.truncate()))]));
+
+                            if self.profile.is_none() && ui.button(locale.tr("Show Profile")).clicked() {
+                                self.profile_queued = true;
+                            }
+                        }
+
+                        if !self.profile_line.is_empty() && ui.button(locale.tr("Clear")).clicked() {
+                            self.profile_line.clear();
+                            self.profile = None;
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Contours", |ui| {
+                        ui.add(egui::Slider::new(&mut self.contour_interval, 0.05..=50.0).logarithmic(true).suffix(" m").text(locale.tr("Interval")));
+                        ui.add(egui::Slider::new(&mut self.contour_major_every, 0..=10).text(locale.tr("Index Every")))
+                            .on_hover_text(locale.tr("Every this many contours is drawn heavier, none if 0"));
+                        ui.add(egui::Slider::new(&mut self.contour_cell_size, 0.05..=10.0).logarithmic(true).suffix(" m").text(locale.tr("Grid Cell Size")))
+                            .on_hover_text(locale.tr("Points are averaged over cells this wide before contouring, larger cells give smoother lines"));
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(self.contours_job.is_none(), egui::Button::new(locale.tr("Generate Contours")))
+                                .on_hover_text(locale.tr("Contour the visible points. Show only ground points first for terrain.")).clicked() {
+                                self.contours_queued = true;
+                            }
+                            if self.contours_job.is_some() {
+                                ui.spinner();
+                            }
+                        });
+
+                        if !self.contours.is_empty() {
+                            ui.label(locale.format("{} contours", &[&self.contours.len()]));
+                            ui.checkbox(&mut self.show_contours, locale.tr("Show Contours"));
+
+                            ui.horizontal(|ui| {
+                                let georeference = loader::plan_georeference(&self.loaded_files);
+
+                                if ui.button(locale.tr("Export SVG...")).clicked() {
+                                    save_text_dialog(&export::contours_svg(&self.contours, &georeference), "contours.svg", "SVG", "svg", &georeference);
+                                }
+                                if ui.button(locale.tr("Export DXF...")).clicked() {
+                                    save_text_dialog(&export::contours_dxf(&self.contours, &georeference), "contours.dxf", "DXF", "dxf", &georeference);
+                                }
+                            });
+
+                            if ui.button(locale.tr("Clear")).clicked() {
+                                self.contours.clear();
+                                self.contour_lines = None;
+                            }
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Elevation Raster", |ui| {
+                        egui::ComboBox::from_label(locale.tr("Cell Elevation"))
+                            .selected_text(locale.tr(self.raster_statistic.name()))
+                            .show_ui(ui, |ui| {
+                                for statistic in raster::Statistic::ALL {
+                                    ui.selectable_value(&mut self.raster_statistic, statistic, locale.tr(statistic.name()));
+                                }
+                            });
+                        ui.add(egui::Slider::new(&mut self.raster_cell_size, 0.05..=10.0).logarithmic(true).suffix(" m").text(locale.tr("Cell Size")));
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(self.raster_job.is_none(), egui::Button::new(locale.tr("Generate Raster")))
+                                .on_hover_text(locale.tr("Grid the visible points. Show only ground points first for a terrain model.")).clicked() {
+                                self.raster_queued = true;
+                            }
+                            if self.raster_job.is_some() {
+                                ui.spinner();
+                            }
+                        });
+
+                        if let Some(grid) = &self.elevation_raster {
+                            ui.label(locale.format("{} × {} cells of {} m", &[&grid.width, &grid.height, &format!("{:.2}", grid.cell_size)]));
+                            ui.checkbox(&mut self.show_raster, locale.tr("Show Hillshade"));
+                            ui.add_enabled(self.show_raster, egui::Slider::new(&mut self.raster_opacity, 0.0..=1.0).text(locale.tr("Opacity")));
+
+                            ui.horizontal(|ui| {
+                                if ui.button(locale.tr("Export GeoTIFF...")).clicked() {
+                                    self.export_raster_queued = true;
+                                }
+                                if ui.button(locale.tr("Clear")).clicked() {
+                                    self.elevation_raster = None;
+                                    self.raster_layer = None;
+                                }
+                            });
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Mesh Preview (Experimental)", |ui| {
+                        ui.add_enabled(self.selected_points == 0, egui::Slider::new(&mut self.mesh_region_size, 0.1..=20.0).logarithmic(true).text(locale.tr("Region Size")));
+                        ui.add(egui::Slider::new(&mut self.mesh_cell_size, 0.005..=1.0).logarithmic(true).text(locale.tr("Cell Size")));
+                        ui.add(egui::Slider::new(&mut self.mesh_tolerance, 0.001..=0.1).logarithmic(true).text(locale.tr("Flatness Tolerance")));
+
+                        ui.horizontal(|ui| {
+                            let label = if self.selected_points > 0 { "Mesh Selection" } else { "Mesh Region at View Centre" };
+                            if ui.add_enabled(self.mesh_job.is_none(), egui::Button::new(locale.tr(label)))
+                                .on_hover_text(locale.tr("Mesh the selected points, or the shown points in a cube around the view centre when none are selected. Select one surface, e.g. a floor, to check its flatness.")).clicked() {
+                                self.mesh_preview_queued = true;
+                            }
+                            if self.mesh_job.is_some() {
+                                ui.spinner();
+                            }
+                        });
+
+                        if let Some(stats) = &self.mesh_preview_stats {
+                            ui.label(locale.format("Points: {}", &[&stats.num_points]));
+                            ui.label(locale.format("Triangles: {}", &[&(stats.indices.len() / 3)]));
+                            ui.label(locale.format("RMS Deviation: {} mm", &[&format!("{:.1}", stats.rms_deviation * 1000.0)]));
+                            ui.label(locale.format("Max Deviation: {} mm", &[&format!("{:.1}", stats.max_deviation * 1000.0)]));
+                            ui.checkbox(&mut self.show_mesh_preview, locale.tr("Show Mesh"));
+
+                            if ui.button(locale.tr("Clear Mesh")).clicked() {
+                                self.mesh_preview = None;
+                                self.mesh_preview_stats = None;
+                            }
+                        }
+                    });
+
+                    self.dock.section(egui_ctx, ui, locale, "Debug", |ui| {
+                        ui.checkbox(&mut self.show_slice, locale.tr("Show Slice"));
+                        ui.checkbox(&mut self.show_outline_plane, locale.tr("Show Outline Plane"));
+                        ui.checkbox(&mut self.show_bounds, locale.tr("Show Bounds and Axes"))
+                            .on_hover_text(locale.tr("Box around the loaded points with its dimensions, and the X, Y and Z axes"));
+
+                        if ui.button(locale.tr("Dump Pipeline Stages")).on_hover_text(locale.tr("Render and save every intermediate slice image to a new folder in the output directory")).clicked() {
+                            self.cutaway_queued = true;
+                            self.dump_stages_queued = true;
+                        }
+                        if let Some(name) = &self.last_stage_dump {
+                            ui.small(locale.format("Saved {}", &[&name]));
+                        }
+                    });
+                }
+
+                ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
+                    ui.label(locale.format("Idle: {} ms", &[&format!("{:.2}", self.idle_time * 1000.0)]));
+                    ui.label(format!("FPS: {:.2}", 1.0e9 / (delta_t.as_nanos() as f64)));
+                    ui.label(format!("MS: {:.2} ms", delta_t.as_nanos() as f64 / 1.0e6));
+                });
+            });
+
+            // Start screen
+            if self.loaded_files.is_empty() && self.path_rx.is_none() && self.load_job.is_none() && !self.recent_files.is_empty() {
+                egui::Window::new(locale.tr("Recent Point Clouds")).anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0]).collapsible(false).resizable(false).show(egui_ctx, |ui| {
+                    egui::Grid::new("recent_files").spacing([8.0, 8.0]).show(ui, |ui| {
+                        for (i, path) in self.recent_files.iter().enumerate() {
+                            let size = recent::THUMBNAIL_SIZE as f32;
+
+                            ui.vertical(|ui| {
+                                let clicked = match thumbnail_texture(&mut self.thumbnail_textures, egui_ctx, path) {
+                                    Some(texture) => ui.add(egui::ImageButton::new(texture.id(), [size, size])),
+                                    None => ui.add_sized([size, size], egui::Button::new(locale.tr("No Preview"))),
+                                }.on_hover_text(path).clicked();
+
+                                ui.add(egui::Label::new(recent::file_name(path)).wrap(true));
+
+                                if clicked {
+                                    self.open_recent_queued = Some(path.clone());
+                                }
+                            });
+
+                            if i % 4 == 3 {
+                                ui.end_row();
+                            }
+                        }
+                    });
+                });
+            }
+
+            if !self.loaded_files.is_empty() {
+                // Points are only moved to the centre for rendering, show where the view is in the world
+                egui::TopBottomPanel::bottom("status_bar").show(egui_ctx, |ui| {
+                    let georeference = loader::plan_georeference(&self.loaded_files);
+                    let forward = glam::Quat::from_euler(glam::EulerRot::YXZ, self.camera_rotation.x, self.camera_rotation.y, 0.0) * glam::Vec3::Z;
+                    let view_centre = render::COORDINATE_SYSTEM.transform_point3(self.camera_position + forward * Z_NEAR).as_dvec3() + self.centre.unwrap_or(glam::DVec3::ZERO);
+
+                    ui.horizontal(|ui| {
+                        ui.label(locale.format("View Centre {}", &[&georeference.format(view_centre)]));
+                        if let Some(name) = georeference.crs_name() {
+                            ui.separator();
+                            ui.label(name).on_hover_text(georeference.wkt.as_deref().unwrap_or(locale.tr("From the file's GeoTIFF keys")));
+                        }
+                    });
+                });
+            }
+
+            let bounds = self.loaded_files.iter().fold(None, |bounds, file| loader::union_bounds(bounds, file.bounds));
+            // Horizontal direction the camera faces in file X and Y, from the yaw alone so it holds looking straight down
+            let heading = render::COORDINATE_SYSTEM.transform_vector3(glam::Quat::from_rotation_y(self.camera_rotation.x) * glam::Vec3::Z).as_dvec3().truncate();
+
+            if let Some(bounds) = bounds.filter(|_| self.split_view && self.rx.is_none() && !self.point_batches.is_empty()) {
+                let forward = glam::Quat::from_euler(glam::EulerRot::YXZ, self.camera_rotation.x, self.camera_rotation.y, 0.0) * glam::Vec3::Z;
+                let view = split::LiveView {
+                    elevation: render::COORDINATE_SYSTEM.transform_point3(self.camera_position + forward * Z_NEAR).z as f64 + self.centre.unwrap_or(glam::DVec3::ZERO).z,
+                    bounds,
+                    point_size: self.point_size,
+                    slice_width: self.config.slice_width(),
+                    points_version: self.points_version,
+                };
+
+                if self.live_slice.is_none() {
+                    match split::LiveSlice::new(&self.display) {
+                        Ok(slice) => self.live_slice = Some(slice),
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            self.split_view = false;
+                        },
+                    }
+                }
+
+                if let Some(slice) = self.live_slice.as_mut().filter(|slice| slice.is_stale(&view)) {
+                    if let Err(err) = slice.draw(&self.display, egui_ctx, (&self.vertex_buffers, &self.point_batches), (&self.program, &self.debug_program), view) {
+                        eprintln!("{}", err);
+                    }
+                }
+
+                if let Some(slice) = &self.live_slice {
+                    let decimals = loader::plan_georeference(&self.loaded_files).decimals();
+
+                    egui::SidePanel::right("split_view").default_width(480.0).resizable(true).show(egui_ctx, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui.toggle_value(&mut self.drawing_section, locale.tr("Draw Section"))
+                                .on_hover_text(locale.tr("Click along a path on the slice for a vertical cross-section, right click to finish")).changed() && self.drawing_section {
+                                self.section.path.clear();
+                            }
+                            let mut width = self.section.half_width * 2.0;
+                            if ui.add(egui::DragValue::new(&mut width).clamp_range(0.01..=10.0).speed(0.01).suffix(" m")).on_hover_text(locale.tr("Section Width")).changed() {
+                                self.section.half_width = width / 2.0;
+                            }
+                            ui.add(egui::DragValue::new(&mut self.section_pixel_size).clamp_range(0.001..=1.0).speed(0.001).suffix(" m/px")).on_hover_text(locale.tr("Section Resolution"));
+                            if ui.add_enabled(self.section.path.len() >= 2 && !self.drawing_section, egui::Button::new(locale.tr("Section"))).clicked() {
+                                self.section_queued = true;
+                            }
+                        });
+
+                        if slice.show(ui, locale, |elevation| format!("{:.*}", decimals, elevation), &mut self.section.path, self.drawing_section) {
+                            self.drawing_section = false;
+                            self.section_queued = true;
+                        }
+                    });
+                }
+            }
+
+            if !self.loaded_files.is_empty() && self.show_compass {
+                compass(egui_ctx, georef::bearing(heading, self.north_offset as f64));
+            }
+
+            if let Some(bounds) = bounds.filter(|_| self.show_minimap && !self.split_view && self.rx.is_none() && !self.point_batches.is_empty()) {
+                let camera = render::COORDINATE_SYSTEM.transform_point3(self.camera_position).as_dvec3() + self.centre.unwrap_or(glam::DVec3::ZERO);
+
+                if self.minimap.as_ref().is_none_or(|minimap| minimap.is_stale(bounds, camera)) {
+                    self.minimap = Some(minimap::Minimap::new(egui_ctx, &self.point_batches, bounds, minimap::Minimap::ceiling_for(bounds, camera)));
+                }
+
+                if let Some(minimap) = &self.minimap {
+                    minimap.show(egui_ctx, camera, heading);
+                }
+            }
+
+            if let Some(shown) = &mut self.profile {
+                let mut open = true;
+                shown.show(egui_ctx, locale, &mut open);
+
+                if !open {
+                    self.profile = None;
+                }
+            }
+
+            if let Some((image, texture, pixel_size)) = &self.section_image {
+                let mut open = true;
+
+                egui::Window::new(locale.tr("Cross-Section")).open(&mut open).default_width(640.0).resizable(true).show(egui_ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{:.2} m long, {:.3} m per pixel", self.section.length(), pixel_size));
+                        if ui.button(locale.tr("Save Image...")).clicked() {
+                            save_image_dialog(image, "section.png", self.floor_plan_depth);
+                        }
+                    });
+
+                    let size = egui::vec2(image.width() as f32, image.height() as f32);
+                    let scale = (ui.available_width() / size.x).min(1.0);
+                    egui::ScrollArea::both().show(ui, |ui| {
+                        ui.image(texture.id(), size * scale);
+                    });
+                });
+
+                if !open {
+                    self.section_image = None;
+                }
+            }
+
+            file_info_window(egui_ctx, locale, &mut self.file_info);
+            jobs_window(egui_ctx, locale, &mut self.job_queue);
+            load_error_window(egui_ctx, locale, &mut self.load_error);
+            profiler_window(egui_ctx, &mut self.show_profiler, self.serving_profile);
+
+            if let Some((point, file)) = &self.picked_point {
+                let mut open = true;
+                let decimals = loader::plan_georeference(&self.loaded_files).decimals();
+
+                egui::Window::new(locale.tr("Picked Point")).open(&mut open).resizable(false).show(egui_ctx, |ui| {
+                    egui::Grid::new("picked_point").num_columns(2).show(ui, |ui| {
+                        for (axis, value) in [("X", point.x), ("Y", point.y), ("Z", point.z)] {
+                            ui.label(axis);
+                            ui.label(format!("{:.*}", decimals, value));
+                            ui.end_row();
+                        }
+
+                        ui.label(locale.tr("Colour"));
+                        match point.color {
+                            Some(colour) => {
+                                let [r, g, b] = [colour.red, colour.green, colour.blue].map(|c| (c / 256) as u8);
+                                ui.horizontal(|ui| {
+                                    egui::color_picker::show_color(ui, egui::Color32::from_rgb(r, g, b), egui::vec2(16.0, 16.0));
+                                    ui.label(format!("{}, {}, {}", colour.red, colour.green, colour.blue));
+                                });
+                            },
+                            None => {
+                                ui.label(locale.tr("None"));
+                            },
+                        }
+                        ui.end_row();
+
+                        ui.label(locale.tr("Intensity"));
+                        ui.label(point.intensity.to_string());
+                        ui.end_row();
+
+                        ui.label(locale.tr("Classification"));
+                        ui.label(format!("{} ({:?})", u8::from(point.classification), point.classification));
+                        ui.end_row();
+
+                        ui.label(locale.tr("Point Source"));
+                        ui.label(point.point_source_id.to_string());
+                        ui.end_row();
+
+                        ui.label(locale.tr("Return"));
+                        ui.label(format!("{} of {}", point.return_number, point.number_of_returns));
+                        ui.end_row();
+
+                        ui.label(locale.tr("Scan Angle"));
+                        ui.label(format!("{:.1}°", point.scan_angle));
+                        ui.end_row();
+
+                        ui.label(locale.tr("Scan Direction"));
+                        ui.label(match point.scan_direction {
+                            las::point::ScanDirection::LeftToRight => locale.tr("Left to Right"),
+                            las::point::ScanDirection::RightToLeft => locale.tr("Right to Left"),
+                        });
+                        ui.end_row();
+
+                        ui.label(locale.tr("Scanner Channel"));
+                        ui.label(point.scanner_channel.to_string());
+                        ui.end_row();
+
+                        ui.label(locale.tr("User Data"));
+                        ui.label(point.user_data.to_string());
+                        ui.end_row();
+
+                        ui.label(locale.tr("GPS Time"));
+                        ui.label(point.gps_time.map_or(locale.tr("None").to_owned(), |time| format!("{:.6}", time)));
+                        ui.end_row();
+
+                        if let Some(nir) = point.nir {
+                            ui.label(locale.tr("Near Infrared"));
+                            ui.label(nir.to_string());
+                            ui.end_row();
+                        }
+
+                        let flags = [
+                            (point.is_edge_of_flight_line, "Edge of Flight Line"),
+                            (point.is_synthetic, "Synthetic"),
+                            (point.is_key_point, "Key Point"),
+                            (point.is_withheld, "Withheld"),
+                            (point.is_overlap, "Overlap"),
+                        ].into_iter().filter(|(set, _)| *set).map(|(_, name)| locale.tr(name)).collect::<Vec<_>>();
+
+                        ui.label(locale.tr("Flags"));
+                        ui.label(if flags.is_empty() { locale.tr("None").to_owned() } else { flags.join(", ") });
+                        ui.end_row();
+
+                        // Named values if the file describes them, the raw bytes otherwise
+                        let fields = self.loaded_files.get(*file).map_or(&[][..], |file| &file.extra_bytes[..]);
+                        let mut start = 0;
+
+                        for field in fields {
+                            ui.label(if field.name.is_empty() { locale.tr("Extra Bytes") } else { &field.name });
+                            ui.label(field.format(point.extra_bytes.get(start..).unwrap_or_default()));
+                            ui.end_row();
+
+                            start += field.size;
+                        }
+
+                        if point.extra_bytes.len() > start {
+                            ui.label(locale.tr("Extra Bytes"));
+                            ui.label(loader::hex(&point.extra_bytes[start..]));
+                            ui.end_row();
+                        }
+                    });
+
+                    if ui.button(locale.tr("Copy Coordinates")).clicked() {
+                        ui.output().copied_text = format!("{:.*}, {:.*}, {:.*}", decimals, point.x, decimals, point.y, decimals, point.z);
+                    }
+                });
+
+                if !open {
+                    self.picked_point = None;
+                }
+            }
+
+            // Measured outline and picked point, drawn over the cloud
+            {
+                let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("picking")));
+                let pixels_per_point = egui_ctx.pixels_per_point();
+                let centre = self.centre.unwrap_or(glam::DVec3::ZERO);
+
+                let to_screen = |p: glam::DVec3| {
+                    let ndc = camera_mvp.project_point3((p - centre).as_vec3());
+                    let screen = (ndc.truncate() * glam::vec2(0.5, -0.5) + 0.5) * glam::vec2(window_width as f32, window_height as f32) / pixels_per_point;
+                    egui::pos2(screen.x, screen.y)
+                };
+
+                let outline: Vec<egui::Pos2> = self.measure_points.iter().map(|p| to_screen(*p)).collect();
+                let stroke = egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 220, 0));
+
+                if outline.len() >= 3 {
+                    painter.add(egui::Shape::closed_line(outline.clone(), stroke));
+                } else {
+                    painter.add(egui::Shape::line(outline.clone(), stroke));
+                }
+
+                for p in outline {
+                    painter.circle_filled(p, 4.0, egui::Color32::from_rgb(255, 220, 0));
+                }
+
+                if self.select_outline.len() >= 2 {
+                    let outline: Vec<egui::Pos2> = self.select_outline.iter().map(|p| egui::pos2(p.x / pixels_per_point, p.y / pixels_per_point)).collect();
+                    let stroke = egui::Stroke::new(1.5, egui::Color32::from_rgb(255, 0, 255));
+
+                    if self.select_tool == Some(selection::SelectTool::Box) {
+                        painter.rect_stroke(egui::Rect::from_two_pos(outline[0], outline[outline.len() - 1]), 0.0, stroke);
+                    } else {
+                        painter.add(egui::Shape::closed_line(outline, stroke));
+                    }
+                }
+
+                let line: Vec<egui::Pos2> = self.profile_line.iter().map(|p| to_screen(*p)).collect();
+                painter.add(egui::Shape::line(line.clone(), egui::Stroke::new(2.0, egui::Color32::from_rgb(0, 220, 255))));
+                for p in line {
+                    painter.circle_filled(p, 4.0, egui::Color32::from_rgb(0, 220, 255));
+                }
+
+                if let Some((point, _)) = &self.picked_point {
+                    painter.circle_stroke(to_screen(glam::dvec3(point.x, point.y, point.z)), 6.0, egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 0, 255)));
+                }
+
+                let bounds = self.loaded_files.iter().fold(None, |bounds, file| loader::union_bounds(bounds, file.bounds));
+
+                if let Some((min, max)) = bounds.filter(|_| self.show_bounds) {
+                    // Lines clipped to in front of the camera, unlike single points they often reach behind it
+                    let to_screen_line = |a: glam::DVec3, b: glam::DVec3| {
+                        const MIN_W: f32 = 1.0e-4;
+
+                        let clip = |p: glam::DVec3| camera_mvp * (p - centre).as_vec3().extend(1.0);
+                        let (a, b) = (clip(a), clip(b));
+                        if a.w < MIN_W && b.w < MIN_W {
+                            return None;
+                        }
+
+                        let towards = |from: glam::Vec4, to: glam::Vec4| from + (to - from) * ((MIN_W - from.w) / (to.w - from.w));
+                        let (a, b) = (if a.w < MIN_W { towards(a, b) } else { a }, if b.w < MIN_W { towards(b, a) } else { b });
+
+                        let screen = |clip: glam::Vec4| {
+                            let screen = ((clip.truncate().truncate() / clip.w) * glam::vec2(0.5, -0.5) + 0.5) * glam::vec2(window_width as f32, window_height as f32) / pixels_per_point;
+                            egui::pos2(screen.x, screen.y)
+                        };
+                        Some([screen(a), screen(b)])
+                    };
+
+                    let font = egui::FontId::proportional(14.0);
+                    let size = max - min;
+
+                    let corner = |i: usize| glam::dvec3(
+                        if i & 1 == 0 { min.x } else { max.x },
+                        if i & 2 == 0 { min.y } else { max.y },
+                        if i & 4 == 0 { min.z } else { max.z },
+                    );
+                    let stroke = egui::Stroke::new(1.0, egui::Color32::from_gray(200));
+
+                    // Corners differing in one coordinate
+                    for i in 0..8 {
+                        for axis in [1, 2, 4].into_iter().filter(|axis| i & axis == 0) {
+                            if let Some(line) = to_screen_line(corner(i), corner(i | axis)) {
+                                painter.line_segment(line, stroke);
+                            }
+                        }
+                    }
+
+                    for (axis, length) in [(1, size.x), (2, size.y), (4, size.z)] {
+                        let (a, b) = (corner(0), corner(axis));
+                        if let Some([a, b]) = to_screen_line(a, b) {
+                            painter.text(a + (b - a) / 2.0, egui::Align2::CENTER_CENTER, format!("{:.2} m", length), font.clone(), egui::Color32::WHITE);
+                        }
+                    }
+
+                    // At the origin when it's around the points, at the corner of the box when they're georeferenced
+                    // far from it
+                    let padded = (min - size.max_element(), max + size.max_element());
+                    let origin = if glam::DVec3::ZERO.cmpge(padded.0).all() && glam::DVec3::ZERO.cmple(padded.1).all() { glam::DVec3::ZERO } else { min };
+                    let length = size.max_element().max(1.0) / 5.0;
+
+                    for (direction, name, colour) in [
+                        (glam::DVec3::X, "X", egui::Color32::from_rgb(230, 50, 50)),
+                        (glam::DVec3::Y, "Y", egui::Color32::from_rgb(50, 200, 50)),
+                        (glam::DVec3::Z, "Z", egui::Color32::from_rgb(60, 110, 255)),
+                    ] {
+                        if let Some([a, b]) = to_screen_line(origin, origin + direction * length) {
+                            painter.line_segment([a, b], egui::Stroke::new(3.0, colour));
+                            painter.text(b, egui::Align2::LEFT_BOTTOM, name, font.clone(), colour);
+                        }
+                    }
+                }
+            }
+
+            tutorial_window(egui_ctx, &mut self.tutorial_step, locale);
+        });
+    }
+}
+
+/// Compass in the bottom right corner of the 3D view, turned so its needle points north, with the camera's bearing
+pub(super) fn compass(ctx: &egui::Context, bearing: f64) {
+    egui::Area::new("compass").anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0]).interactable(false).show(ctx, |ui| {
+        egui::Frame::popup(ui.style()).show(ui, |ui| {
+            ui.vertical_centered(|ui| {
+                let (response, painter) = ui.allocate_painter(egui::Vec2::splat(64.0), egui::Sense::hover());
+                let centre = response.rect.center();
+                let radius = 28.0;
+
+                // Up on screen is the way the camera faces
+                let angle = -(bearing.to_radians() as f32);
+                let north = egui::vec2(angle.sin(), -angle.cos());
+                let side = egui::vec2(-north.y, north.x);
+
+                painter.circle_stroke(centre, radius, egui::Stroke::new(1.0, egui::Color32::GRAY));
+                painter.add(egui::Shape::convex_polygon(vec![centre + north * (radius - 10.0), centre + side * 5.0, centre - side * 5.0], egui::Color32::RED, egui::Stroke::none()));
+                painter.add(egui::Shape::convex_polygon(vec![centre - north * (radius - 10.0), centre - side * 5.0, centre + side * 5.0], egui::Color32::WHITE, egui::Stroke::new(1.0, egui::Color32::GRAY)));
+                painter.text(centre + north * (radius - 4.0), egui::Align2::CENTER_CENTER, "N", egui::FontId::proportional(12.0), egui::Color32::RED);
+
+                ui.label(format!("{:03.0}° {}", bearing.round().rem_euclid(360.0), georef::compass_point(bearing)));
+            });
+        });
+    });
+}
+
+/// Texture of a recent file's cached thumbnail, None if it has none
+pub(super) fn thumbnail_texture(textures: &mut HashMap<String, Option<egui::TextureHandle>>, ctx: &egui::Context, path: &str) -> Option<egui::TextureHandle> {
+    textures.entry(path.to_owned())
+        .or_insert_with(|| {
+            recent::load_thumbnail(path).map(|image| {
+                let image = egui::ColorImage::from_rgba_unmultiplied([image.width() as usize, image.height() as usize], image.as_raw());
+                ctx.load_texture(path, image, egui::TextureFilter::Linear)
+            })
+        })
+        .clone()
+}
+
+/// Jobs queued or running, with their progress and a button to cancel each, hidden while there are none
+pub(super) fn jobs_window(ctx: &egui::Context, locale: &locale::Language, job_queue: &mut jobs::JobQueue) {
+    let active = job_queue.active();
+    if active.is_empty() {
+        return;
+    }
+
+    egui::Window::new(locale.tr("Jobs")).resizable(false).show(ctx, |ui| {
+        egui::Grid::new("jobs").num_columns(3).show(ui, |ui| {
+            for job in active {
+                ui.label(job.name());
+                match job.state() {
+                    jobs::JobState::Queued => ui.label(locale.tr("Queued")),
+                    _ if job.is_cancelled() => ui.label(locale.tr("Cancelling...")),
+                    _ => ui.add(egui::ProgressBar::new(job.progress()).desired_width(120.0).show_percentage()),
+                };
+                if ui.add_enabled(!job.is_cancelled(), egui::Button::new(locale.tr("Cancel")).small()).clicked() {
+                    job.cancel();
+                }
+                ui.end_row();
+            }
+        });
+    });
+}
+
+pub(super) fn file_info_window(ctx: &egui::Context, locale: &locale::Language, file_info: &mut Option<(String, Result<loader::FileInfo, String>)>) {
+    let Some((path, info)) = file_info.as_ref() else {
+        return;
+    };
+
+    let mut open = true;
+
+    egui::Window::new(locale.tr("File Info")).open(&mut open).show(ctx, |ui| {
+        ui.label(egui::RichText::new(recent::file_name(path)).strong()).on_hover_text(path);
+
+        let info = match info {
+            Ok(info) => info,
+            Err(err) => {
+                ui.colored_label(egui::Color32::YELLOW, err);
+                return;
+            },
+        };
+
+        let georeference = &info.georeference;
+        let vector = |v: glam::DVec3| format!("{}, {}, {}", v.x, v.y, v.z);
+
+        egui::Grid::new("file_info").num_columns(2).striped(true).show(ui, |ui| {
+            let mut row = |name: &str, value: String| {
+                ui.label(locale.tr(name));
+                ui.label(value);
+                ui.end_row();
+            };
+
+            row("LAS Version", info.version.clone());
+            row("Point Format", info.point_format.clone());
+            row("Points", info.points.to_string());
+            if !info.points_by_return.is_empty() {
+                row("Points by Return", info.points_by_return.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", "));
+            }
+            row("Min", georeference.format(info.bounds.0));
+            row("Max", georeference.format(info.bounds.1));
+            row("Size", georeference.format(info.bounds.1 - info.bounds.0));
+            row("Scale", vector(georeference.scale));
+            row("Offset", vector(georeference.offset));
+            row("Coordinate System", georeference.crs_name().unwrap_or_else(|| locale.tr("None").to_owned()));
+            row("System", info.system_identifier.clone());
+            row("Software", info.generating_software.clone());
+            row("Created", info.created.clone().unwrap_or_else(|| locale.tr("Unknown").to_owned()));
+            row("File Source ID", info.file_source_id.to_string());
+            row("GUID", info.guid.clone());
+        });
+
+        if let Some(wkt) = &georeference.wkt {
+            ui.collapsing("WKT", |ui| {
+                ui.add(egui::Label::new(egui::RichText::new(wkt).monospace()).wrap(true));
+            });
+        }
+
+        ui.collapsing(locale.format("VLRs ({})", &[&info.vlrs.len()]), |ui| {
+            egui::Grid::new("file_info_vlrs").num_columns(4).striped(true).show(ui, |ui| {
+                for vlr in &info.vlrs {
+                    // Coordinate system records stand out
+                    if vlr.is_crs() {
+                        ui.label(egui::RichText::new(&vlr.user_id).strong());
+                    } else {
+                        ui.label(&vlr.user_id);
+                    }
+                    ui.label(vlr.record_id.to_string());
+                    ui.label(&vlr.description);
+                    ui.label(locale.format(if vlr.extended { "{} bytes, extended" } else { "{} bytes" }, &[&vlr.size]));
+                    ui.end_row();
+                }
+            });
+        });
+    });
+
+    if !open {
+        *file_info = None;
+    }
+}
+
+pub(super) fn load_error_window(ctx: &egui::Context, locale: &locale::Language, load_error: &mut Option<String>) {
+    let Some(error) = load_error.as_ref() else {
+        return;
+    };
+
+    let mut dismissed = false;
+
+    egui::Window::new(locale.tr("Couldn't Load File")).collapsible(false).resizable(false).anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0]).show(ctx, |ui| {
+        ui.label(error);
+        dismissed = ui.button(locale.tr("OK")).clicked();
+    });
+
+    if dismissed {
+        *load_error = None;
+    }
+}
+
+/// Frame timings while `open`, recorded only then unless profile data is being served
+pub(super) fn profiler_window(ctx: &egui::Context, open: &mut bool, serving: bool) {
+    puffin::set_scopes_on(*open || serving);
+
+    if *open {
+        *open = puffin_egui::profiler_window(ctx);
+    }
+}
+
+/// Connect radius and alpha threshold slices are processed with. `auto_radius` is the radius from the point size
+/// and zoom, used when the radius isn't set.
+pub(super) fn wall_connection_settings(ui: &mut egui::Ui, locale: &locale::Language, connect_radius: &mut Option<i32>, alpha_threshold: &mut u8, auto_radius: i32) {
+    ui.horizontal(|ui| {
+        let mut auto = connect_radius.is_none();
+
+        if ui.checkbox(&mut auto, locale.tr("Auto Connect Radius")).on_hover_text(locale.format("From the point size and zoom, {} px", &[&auto_radius])).changed() {
+            *connect_radius = if auto { None } else { Some(auto_radius) };
+        }
+        if let Some(radius) = connect_radius {
+            ui.add(egui::DragValue::new(radius).clamp_range(0..=200).suffix(" px")).on_hover_text(locale.tr("Connect Radius"));
+        }
+    });
+    ui.add(egui::Slider::new(alpha_threshold, 0..=254).text(locale.tr("Alpha Threshold")))
+        .on_hover_text(locale.tr("Slice pixels more opaque than this are points. Raise it to drop faint points at the edge of the slice."));
+}
+
+/// Picker for the colour depth floor plans are saved in
+pub(super) fn floor_plan_depth_combo(ui: &mut egui::Ui, locale: &locale::Language, depth: &mut plan::ColourDepth, width: f32) {
+    egui::ComboBox::from_id_source("floor_plan_depth")
+        .width(width)
+        .selected_text(locale.tr(depth.name()))
+        .show_ui(ui, |ui| {
+            for option in plan::ColourDepth::ALL {
+                ui.selectable_value(depth, option, locale.tr(option.name())).on_hover_text(locale.tr(option.description()));
+            }
+        })
+        .response
+        .on_hover_text(locale.format("Floor Plan Export: {}", &[&locale.tr(depth.description())]));
+}
+
+/// Outline a control the walkthrough is pointing out, when `active`
+pub(super) fn highlighted(ctx: &egui::Context, response: egui::Response, active: bool) -> egui::Response {
+    if active {
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("tutorial_highlight")));
+        painter.rect_stroke(response.rect.expand(3.0), 4.0, egui::Stroke::new(2.5, egui::Color32::from_rgb(255, 140, 0)));
+    }
+
+    response
+}
+
+/// Guided walkthrough along the bottom of the window, going from loading a scan to exporting its floor plan. The
+/// controls for each step are outlined with `highlighted`.
+pub(super) fn tutorial_window(ctx: &egui::Context, step: &mut Option<tutorial::Step>, locale: &locale::Language) {
+    let Some(current) = *step else {
+        return;
+    };
+
+    let mut open = true;
+
+    egui::Window::new(locale.tr("Walkthrough"))
+        .open(&mut open)
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -8.0])
+        .collapsible(true)
+        .resizable(false)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            ui.small(locale.format("Step {} of {}", &[&(current.index() + 1), &tutorial::Step::ALL.len()]));
+            ui.label(egui::RichText::new(locale.tr(current.title())).strong());
+            ui.label(locale.tr(current.instructions()));
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(current.previous().is_some(), egui::Button::new(locale.tr("Back"))).clicked() {
+                    *step = current.previous();
+                }
+
+                match current.next() {
+                    Some(next) => if ui.button(locale.tr("Next")).clicked() {
+                        *step = Some(next);
+                    },
+                    None => if ui.button(locale.tr("Finish")).clicked() {
+                        *step = None;
+                    },
+                }
+            });
+        });
+
+    if !open {
+        *step = None;
+    }
+    if step.is_none() {
+        tutorial::mark_seen();
+    }
+}
@@ -0,0 +1,1224 @@
+use std::{borrow::BorrowMut, path::{Path, PathBuf}};
+
+use glium::glutin::event::{VirtualKeyCode, MouseButton};
+
+use crate::{colour, export, geometry, georef, hough, pdf, plan, recent, subset, tutorial, vector, web};
+use crate::input::MouseButtonState;
+use crate::loader;
+
+use super::{Viewer, DrawTool, MAX_LABEL_POINTS, advance_tutorial};
+use super::panels::{jobs_window, load_error_window, profiler_window, wall_connection_settings, floor_plan_depth_combo, highlighted, tutorial_window};
+
+impl Viewer {
+    /// Tool panel and windows while editing a floor plan
+    pub(super) fn plan_gui(&mut self, drawing_mvp: glam::Mat4, window_width: u32, window_height: u32) {
+        let locale = &self.languages[self.language];
+
+        self.egui_glium.run(&self.display, |egui_ctx| {
+            puffin::profile_scope!("update_gui");
+            egui::SidePanel::left("my_side_panel").max_width(64.0).show(egui_ctx, |ui| {
+                let back = egui::RichText::new('\u{f060}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let pencil = egui::RichText::new('\u{f303}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let eraser = egui::RichText::new('\u{f12d}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let room = egui::RichText::new('\u{f015}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let line = egui::RichText::new('\u{f5ae}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let rectangle = egui::RichText::new('\u{f0c8}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let polygon = egui::RichText::new('\u{f5ee}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let text = egui::RichText::new('\u{f031}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let stamp = egui::RichText::new('\u{f5bf}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let edit_outlines = egui::RichText::new('\u{f5cb}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let fill = egui::RichText::new('\u{f576}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let image = egui::RichText::new('\u{f03e}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let save_cutaway = egui::RichText::new('\u{f030}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let save_floor_plan = egui::RichText::new('\u{f279}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let grid = egui::RichText::new('\u{f00a}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let show_grid_icon = egui::RichText::new('\u{f009}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let scale_bar = egui::RichText::new('\u{f545}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let north_arrow = egui::RichText::new('\u{f14e}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let export_svg = egui::RichText::new('\u{f55b}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let export_dxf = egui::RichText::new('\u{f568}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let export_pdf = egui::RichText::new('\u{f1c1}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let export_obj = egui::RichText::new('\u{f1b2}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let export_web_viewer = egui::RichText::new('\u{f0ac}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let export_slice_points = egui::RichText::new('\u{f1c0}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let review_walls = egui::RichText::new('\u{f0d0}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let accept_all = egui::RichText::new('\u{f00c}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                let reject_all = egui::RichText::new('\u{f00d}'.to_string()).family(egui::FontFamily::Name("icons".into()));
+                
+                if ui.button(back).on_hover_text(locale.tr("Back to 3D (Esc)")).clicked() {
+                    self.exit_drawing_mode = true;
+                }
+                if highlighted(egui_ctx, ui.button(pencil), self.tutorial_step == Some(tutorial::Step::Trace)).clicked() {
+                    self.active_tool = DrawTool::Pencil;
+                }
+                if highlighted(egui_ctx, ui.button(eraser), self.tutorial_step == Some(tutorial::Step::Trace)).clicked() {
+                    self.active_tool = DrawTool::Eraser;
+                }
+                if highlighted(egui_ctx, ui.button(room), self.tutorial_step == Some(tutorial::Step::IdentifyRooms)).clicked() {
+                    self.active_tool = DrawTool::RoomIdentification;
+                }
+                if ui.button(line).on_hover_text(locale.tr("Line (Shift to constrain, right click or Enter to finish)")).clicked() {
+                    self.active_tool = DrawTool::Line;
+                }
+                if ui.button(rectangle).on_hover_text(locale.tr("Rectangle (drag)")).clicked() {
+                    self.active_tool = DrawTool::Rectangle;
+                }
+                if ui.button(polygon).on_hover_text(locale.tr("Polygon (right click or Enter to close)")).clicked() {
+                    self.active_tool = DrawTool::Polygon;
+                }
+                if ui.button(text).on_hover_text(locale.tr("Text (click to place or edit a label, right click deletes one)")).clicked() {
+                    self.active_tool = DrawTool::Text;
+                }
+                if ui.button(stamp).on_hover_text(locale.tr("Stamp Doors, Windows and Stairs (right click turns a quarter)")).clicked() {
+                    self.active_tool = DrawTool::Stamp;
+                }
+                if ui.button(edit_outlines).on_hover_text(locale.tr("Edit Traced Walls (drag vertices or edges, right click removes a vertex)\nVector exports use these outlines once they're traced")).clicked() {
+                    self.active_tool = DrawTool::EditOutlines;
+                }
+                let review = ui.button(review_walls).on_hover_text(locale.format("Review Detected Walls ({} left, left click accepts, right click rejects)", &[&self.wall_proposals.len()]));
+                if highlighted(egui_ctx, review, self.tutorial_step == Some(tutorial::Step::Trace)).clicked() {
+                    self.active_tool = DrawTool::ReviewWalls;
+                }
+                if ui.button(image).clicked() {
+                    self.final_render_queued = true;
+                }
+                if ui.button(save_cutaway).on_hover_text(locale.tr("Save Cutaway...")).clicked() {
+                    self.save_cutaway_queued = true;
+                }
+                if highlighted(egui_ctx, ui.button(save_floor_plan).on_hover_text(locale.tr("Save Floor Plan...")), self.tutorial_step == Some(tutorial::Step::Export)).clicked() {
+                    self.save_floor_plan_queued = true;
+                }
+                if highlighted(egui_ctx, ui.button(export_svg).on_hover_text(locale.tr("Export Floor Plan to SVG...")), self.tutorial_step == Some(tutorial::Step::Export)).clicked() {
+                    self.export_svg_queued = true;
+                }
+                if highlighted(egui_ctx, ui.button(export_dxf).on_hover_text(locale.tr("Export Floor Plan to DXF...")), self.tutorial_step == Some(tutorial::Step::Export)).clicked() {
+                    self.export_dxf_queued = true;
+                }
+                if highlighted(egui_ctx, ui.button(export_pdf).on_hover_text(locale.tr("Export Floor Plan to PDF...\nWith the labels as text")), self.tutorial_step == Some(tutorial::Step::Export)).clicked() {
+                    self.export_pdf_queued = true;
+                }
+                if ui.button(export_obj).on_hover_text(locale.tr("Export Walls as a 3D Model (OBJ)...\nExtruded between the detected floor and ceiling")).clicked() {
+                    self.export_obj_queued = true;
+                }
+                if ui.button(export_web_viewer).on_hover_text(locale.tr("Export Web Viewer...\nA folder with the cutaway, rooms and a thinned out cloud to open in any browser")).clicked() {
+                    self.export_web_viewer_queued = true;
+                }
+                if ui.button(export_slice_points).on_hover_text(locale.tr("Export Slice Points (LAS, LAZ or PLY)...\nThe 3D points the slice was drawn from")).clicked() {
+                    self.export_slice_points_queued = true;
+                }
+                ui.toggle_value(&mut self.show_grid, show_grid_icon).on_hover_text(locale.tr("Show Grid"));
+                ui.toggle_value(&mut self.burn_in_grid, grid).on_hover_text(locale.tr("Burn Measurement Grid into Exports"));
+                if self.burn_in_grid || self.show_grid {
+                    ui.add(egui::DragValue::new(&mut self.grid_spacing).clamp_range(0.01..=1000.0).speed(0.1).suffix(" m")).on_hover_text(locale.tr("Grid Spacing"));
+                }
+                ui.toggle_value(&mut self.burn_in_scale_bar, scale_bar).on_hover_text(locale.tr("Burn Scale Bar into Exports"));
+                ui.toggle_value(&mut self.burn_in_north_arrow, north_arrow).on_hover_text(locale.tr("Burn North Arrow into Exports"));
+                floor_plan_depth_combo(ui, locale, &mut self.floor_plan_depth, 48.0);
+
+                ui.separator();
+
+                let brush_size = match self.active_tool {
+                    DrawTool::Pencil | DrawTool::Line | DrawTool::Rectangle | DrawTool::Polygon | DrawTool::Stamp => Some(&mut self.pencil_size),
+                    DrawTool::Eraser => Some(&mut self.eraser_size),
+                    _ => None,
+                };
+
+                if let Some(brush_size) = brush_size {
+                    ui.add(egui::DragValue::new(brush_size).clamp_range(1.0..=100.0).speed(0.25).suffix(" px")).on_hover_text(locale.tr("Brush Size"));
+                }
+                if self.active_tool == DrawTool::Text {
+                    ui.add(egui::DragValue::new(&mut self.text_size).clamp_range(4.0..=500.0).speed(0.25).suffix(" px")).on_hover_text(locale.tr("Text Size"));
+                }
+                if self.active_tool == DrawTool::EditOutlines {
+                    ui.add(egui::DragValue::new(&mut self.trace_tolerance).clamp_range(0.0..=20.0).speed(0.05).suffix(" px")).on_hover_text(locale.tr("Simplification Tolerance"));
+                    if ui.button(locale.tr("Trace")).on_hover_text(locale.tr("Trace the walls layer again, replacing the outlines and any changes to them")).clicked() {
+                        self.trace_walls_queued = true;
+                    }
+                    ui.separator();
+                    ui.add(egui::DragValue::new(&mut self.regularise_tolerance).clamp_range(0.0..=45.0).speed(0.25).suffix("°")).on_hover_text(locale.tr("Straighten walls this close to the building's axes"));
+                    ui.add(egui::DragValue::new(&mut self.join_distance).clamp_range(0.0..=50.0).speed(0.1).suffix(" px")).on_hover_text(locale.tr("Join corners this close together"));
+                    if ui.button(locale.tr("Regularise")).on_hover_text(locale.tr("Square up the outlines to the building's two main directions")).clicked() {
+                        self.regularise_walls_queued = true;
+                    }
+                    if let Some(floor_plan) = &mut self.floor_plan {
+                        if ui.add_enabled(floor_plan.traced_walls.is_some(), egui::Button::new(locale.tr("Discard"))).on_hover_text(locale.tr("Drop the outlines, exports trace the walls layer as it is")).clicked() {
+                            floor_plan.traced_walls = None;
+                            self.active_tool = DrawTool::Pencil;
+                        }
+                    }
+                }
+                if self.active_tool == DrawTool::Stamp {
+                    egui::ComboBox::from_id_source("stamp_symbol").width(96.0).selected_text(locale.tr(self.stamp_symbol.name())).show_ui(ui, |ui| {
+                        for symbol in plan::Symbol::ALL {
+                            ui.selectable_value(&mut self.stamp_symbol, symbol, locale.tr(symbol.name()));
+                        }
+                    });
+                    ui.add(egui::DragValue::new(&mut self.stamp_size).clamp_range(4.0..=2000.0).speed(0.5).suffix(" px")).on_hover_text(locale.tr("Symbol Width"));
+                    if ui.add(egui::DragValue::new(&mut self.stamp_rotation).speed(1.0).suffix("°")).on_hover_text(locale.tr("Symbol Rotation (clockwise)")).changed() {
+                        self.stamp_rotation = self.stamp_rotation.rem_euclid(360.0);
+                    }
+                }
+                if matches!(self.active_tool, DrawTool::Pencil | DrawTool::Line | DrawTool::Rectangle | DrawTool::Polygon | DrawTool::Text | DrawTool::Stamp) {
+                    ui.color_edit_button_srgb(&mut self.pencil_colour).on_hover_text(locale.tr("Brush Colour"));
+                }
+                if matches!(self.active_tool, DrawTool::Rectangle | DrawTool::Polygon) {
+                    ui.toggle_value(&mut self.fill_shapes, fill).on_hover_text(locale.tr("Fill Shapes"));
+                }
+                if self.active_tool == DrawTool::RoomIdentification {
+                    ui.add(egui::Slider::new(&mut self.fill_settings.tolerance, 0..=128).text(locale.tr("Tolerance"))).on_hover_text(locale.tr("Fill pixels this close in colour together, and stop at walls this close to opaque"));
+
+                    let mut close_gaps = self.fill_settings.gap > 0;
+                    if ui.checkbox(&mut close_gaps, locale.tr("Fill Gaps")).on_hover_text(locale.tr("Don't leak through gaps in the walls up to this wide")).changed() {
+                        self.fill_settings.gap = if close_gaps { 4 } else { 0 };
+                    }
+                    if close_gaps {
+                        ui.add(egui::DragValue::new(&mut self.fill_settings.gap).clamp_range(1..=100).suffix(" px")).on_hover_text(locale.tr("Widest Gap"));
+                    }
+
+                    ui.selectable_value(&mut self.room_fill, None, locale.tr("Auto")).on_hover_text(locale.tr("A new colour for each room, right click for walls and the exterior"));
+
+                    let mut colours: Vec<[u8; 3]> = vec![];
+                    for room in &self.rooms {
+                        if !colours.contains(&room.colour) {
+                            colours.push(room.colour);
+                        }
+                    }
+
+                    for colour in colours {
+                        let [r, g, b] = colour;
+                        let stroke = if self.room_fill == Some(colour) { egui::Stroke::new(2.0, egui::Color32::WHITE) } else { egui::Stroke::none() };
+
+                        if ui.add(egui::Button::new("    ").fill(egui::Color32::from_rgb(r, g, b)).stroke(stroke)).on_hover_text(locale.tr("Fill with this colour, to group rooms of a kind")).clicked() {
+                            self.room_fill = Some(colour);
+                        }
+                    }
+                }
+                if self.active_tool == DrawTool::ReviewWalls {
+                    if ui.add_enabled(!self.wall_proposals.is_empty(), egui::Button::new(accept_all)).on_hover_text(locale.tr("Accept All")).clicked() {
+                        self.accept_all_walls = true;
+                    }
+                    if ui.add_enabled(!self.wall_proposals.is_empty(), egui::Button::new(reject_all)).on_hover_text(locale.tr("Reject All")).clicked() {
+                        self.wall_proposals.clear();
+                    }
+
+                    ui.add(egui::DragValue::new(&mut self.hough_parameters.threshold).clamp_range(1..=1000)).on_hover_text(locale.tr("Detection Threshold (votes)"));
+                    ui.add(egui::DragValue::new(&mut self.hough_parameters.min_length).clamp_range(1.0..=10000.0).suffix(" px")).on_hover_text(locale.tr("Minimum Wall Length"));
+                    ui.add(egui::DragValue::new(&mut self.hough_parameters.max_gap).clamp_range(0.0..=1000.0).suffix(" px")).on_hover_text(locale.tr("Maximum Gap"));
+                    if ui.button(locale.tr("Detect")).on_hover_text(locale.tr("Detect walls again with these settings")).clicked() {
+                        self.detect_walls_queued = true;
+                    }
+                }
+
+                // ui.label(egui::RichText::new(locale.tr("Room Identification")).strong());
+                // ui.colored_label(egui::Color32::RED, "Wall/Floor: Red");
+                // ui.colored_label(egui::Color32::BLUE, "Air: Blue");
+
+                // ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
+                //     ui.label(locale.format("Idle: {} ms", &[&format!("{:.2}", idle_time * 1000.0)]));
+                //     ui.label(format!("FPS: {:.2}", 1.0e9 / (delta_t.as_nanos() as f64)));
+                //     ui.label(format!("MS: {:.2} ms", delta_t.as_nanos() as f64 / 1.0e6));
+                // });
+            });
+
+            if let (Some(transform), Some(floor_plan)) = (&self.capture_transform, &self.floor_plan) {
+                egui::TopBottomPanel::bottom("status_bar").show(egui_ctx, |ui| {
+                    let georeference = loader::plan_georeference(&self.loaded_files);
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                    let pixel = plan::screen_to_image(self.mouse.position(), window_size, drawing_mvp, image_size);
+
+                    ui.horizontal(|ui| {
+                        if pixel.cmpge(glam::Vec2::ZERO).all() && pixel.cmplt(image_size).all() {
+                            ui.label(locale.format("Cursor {}", &[&georeference.format(transform.pixel_to_world(pixel.as_dvec2()))]));
+                        } else {
+                            ui.label(locale.tr("Cursor outside the floor plan"));
+                        }
+                        if let Some(name) = georeference.crs_name() {
+                            ui.separator();
+                            ui.label(name);
+                        }
+                    });
+                });
+            }
+
+            jobs_window(egui_ctx, locale, &mut self.job_queue);
+            load_error_window(egui_ctx, locale, &mut self.load_error);
+            profiler_window(egui_ctx, &mut self.show_profiler, self.serving_profile);
+
+            if let Some(capture) = &self.last_slice_capture {
+                egui::Window::new(locale.tr("Wall Connection")).anchor(egui::Align2::LEFT_BOTTOM, [8.0, -8.0]).resizable(false).show(egui_ctx, |ui| {
+                    wall_connection_settings(ui, locale, &mut self.connect_radius, &mut self.alpha_threshold, capture.auto_connect_radius);
+
+                    if let Some(job) = &self.processed_slice {
+                        ui.add(egui::ProgressBar::new(job.status().progress()).text(locale.tr("Processing slice")));
+                    } else if ui.button(locale.tr("Re-process Slice")).on_hover_text(locale.tr("Join up the walls of the rendered slice again with these settings, keeping edits and rooms")).clicked() {
+                        self.reprocess_slice_queued = true;
+                    }
+                });
+            }
+
+            if let Some(floor_plan) = &mut self.floor_plan {
+                egui::Window::new(locale.tr("Layers")).anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0]).resizable(false).show(egui_ctx, |ui| {
+                    egui::Grid::new("layers").show(ui, |ui| {
+                        // Top layer first
+                        for kind in plan::LayerKind::ALL.into_iter().rev() {
+                            // Between the cutaway and the walls, to flip between the two
+                            if kind == plan::LayerKind::Cutaway {
+                                let raw_slice = &mut floor_plan.raw_slice;
+
+                                ui.checkbox(&mut raw_slice.visible, locale.tr("Raw Slice")).on_hover_text(locale.tr("The slice as rendered, before its points were joined into walls. Never exported."));
+                                ui.add_enabled(raw_slice.visible, egui::Slider::new(&mut raw_slice.opacity, 0.0..=1.0).show_value(false)).on_hover_text(locale.tr("Opacity"));
+                                ui.end_row();
+                            }
+
+                            let layer = floor_plan.layer_mut(kind);
+
+                            ui.checkbox(&mut layer.visible, locale.tr(kind.name()));
+                            ui.add_enabled(layer.visible, egui::Slider::new(&mut layer.opacity, 0.0..=1.0).show_value(false)).on_hover_text(locale.tr("Opacity"));
+                            if kind != plan::LayerKind::Cutaway {
+                                ui.radio_value(&mut self.erase_layer, kind, "").on_hover_text(locale.tr("Erase on this layer"));
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.compare_slices, locale.tr("Compare")).on_hover_text(locale.tr("Raw slice on the left, processed slice on the right"));
+                        ui.add_enabled(self.compare_slices, egui::Slider::new(&mut self.compare_split, 0.0..=1.0).show_value(false)).on_hover_text(locale.tr("Split"));
+                    });
+                    ui.checkbox(&mut self.highlight_added, locale.tr("Highlight Added Walls")).on_hover_text(locale.tr("Wall pixels where the raw slice has no points, added joining them up"));
+
+                    egui::CollapsingHeader::new(locale.tr("Cutaway Adjustments")).show(ui, |ui| {
+                        ui.add(egui::Slider::new(&mut self.cutaway_brightness, -1.0..=1.0).text(locale.tr("Brightness")));
+                        ui.add(egui::Slider::new(&mut self.cutaway_contrast, 0.0..=4.0).text(locale.tr("Contrast")));
+                        ui.add(egui::Slider::new(&mut self.cutaway_desaturate, 0.0..=1.0).text(locale.tr("Desaturate")));
+                        if ui.button(locale.tr("Reset")).on_hover_text(locale.tr("Show the cutaway as it was rendered, as it's exported")).clicked() {
+                            (self.cutaway_brightness, self.cutaway_contrast, self.cutaway_desaturate) = (0.0, 1.0, 0.0);
+                        }
+                    });
+                });
+            }
+
+            if let Some((editing, draft)) = &mut self.label_draft {
+                let title = locale.tr(if editing.is_some() { "Edit Label" } else { "New Label" });
+
+                egui::Window::new(title).id(egui::Id::new("text_label")).anchor(egui::Align2::CENTER_TOP, [0.0, 8.0]).collapsible(false).resizable(false).show(egui_ctx, |ui| {
+                    let response = ui.add(egui::TextEdit::singleline(&mut draft.text).hint_text(locale.tr("Kitchen")));
+                    if self.focus_label {
+                        response.request_focus();
+                        self.focus_label = false;
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut draft.size).clamp_range(4.0..=500.0).speed(0.25).suffix(" px")).on_hover_text(locale.tr("Text Size"));
+                        ui.color_edit_button_srgb(&mut draft.colour).on_hover_text(locale.tr("Text Colour"));
+                    });
+                    ui.label(egui::RichText::new(locale.tr("Click elsewhere on the plan to move it")).weak());
+
+                    ui.horizontal(|ui| {
+                        if ui.button(locale.tr("OK")).clicked() || (response.lost_focus() && ui.input().key_pressed(egui::Key::Enter)) {
+                            self.close_label = Some(true);
+                        }
+                        if ui.button(locale.tr("Cancel")).clicked() {
+                            self.close_label = Some(false);
+                        }
+                        if editing.is_some() && ui.button(locale.tr("Delete")).clicked() {
+                            draft.text.clear();
+                            self.close_label = Some(true);
+                        }
+                    });
+                });
+            }
+
+            if let Some(transform) = self.capture_transform.as_ref().filter(|_| !self.rooms.is_empty()) {
+                egui::Window::new(locale.tr("Rooms")).anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0]).resizable(false).show(egui_ctx, |ui| {
+                    let pixel_area = transform.pixel_area();
+
+                    egui::Grid::new("rooms").striped(true).show(ui, |ui| {
+                        for (i, room) in self.rooms.iter().enumerate() {
+                            let [r, g, b] = room.colour;
+                            let (swatch, _) = ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+                            ui.painter().rect_filled(swatch, 2.0, egui::Color32::from_rgb(r, g, b));
+
+                            ui.label(locale.format("Room {}", &[&(i + 1)]));
+                            ui.label(format!("{:.2} m²", room.pixels as f64 * pixel_area));
+                            ui.end_row();
+                        }
+
+                        let total: usize = self.rooms.iter().map(|room| room.pixels).sum();
+                        ui.label("");
+                        ui.label(egui::RichText::new(locale.tr("Total")).strong());
+                        ui.label(egui::RichText::new(format!("{:.2} m²", total as f64 * pixel_area)).strong());
+                        ui.end_row();
+                    });
+                });
+            }
+
+            // File coordinate grid matching the ground grid in 3D, labelled along the top and left of the canvas
+            if let (Some(floor_plan), Some(transform), true) = (&self.floor_plan, &self.capture_transform, self.show_grid) {
+                let window_size = glam::vec2(window_width as f32, window_height as f32);
+                let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                let pixels_per_point = egui_ctx.pixels_per_point();
+                let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("grid")));
+                let decimals = plan::grid_decimals(self.grid_spacing as f64);
+
+                for line in plan::grid_lines(transform, floor_plan.dimensions(), self.grid_spacing as f64, plan::MAX_GRID_LINES) {
+                    let [from, to] = [line.from, line.to].map(|p| plan::image_to_screen(p.as_vec2(), window_size, drawing_mvp, image_size) / pixels_per_point);
+                    let (from, to) = (egui::pos2(from.x, from.y), egui::pos2(to.x, to.y));
+
+                    painter.line_segment([from, to], egui::Stroke::new(1.0, egui::Color32::from_black_alpha(96)));
+
+                    // Label the end nearest the top left
+                    let end = if from.x + from.y <= to.x + to.y { from } else { to };
+                    let label = format!("{} {:.*}", ["X", "Y", "Z"][line.axis], decimals, line.value);
+                    painter.text(end + egui::vec2(3.0, 2.0), egui::Align2::LEFT_TOP, label, egui::FontId::proportional(11.0), egui::Color32::DARK_GRAY);
+                }
+            }
+
+            // Scale bar sized for the current view, in the bottom left of the canvas
+            if let (Some(floor_plan), Some(transform)) = (&self.floor_plan, &self.capture_transform) {
+                let window_size = glam::vec2(window_width as f32, window_height as f32);
+                let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                let pixels_per_point = egui_ctx.pixels_per_point();
+
+                // Screen points per floor plan pixel
+                let pixel_scale = (plan::image_to_screen(glam::vec2(1.0, 0.0), window_size, drawing_mvp, image_size)
+                    - plan::image_to_screen(glam::Vec2::ZERO, window_size, drawing_mvp, image_size)).length() / pixels_per_point;
+                let metres_per_pixel = transform.pixel_x.length();
+
+                if pixel_scale > 0.0 && metres_per_pixel > 0.0 {
+                    let length = plan::scale_bar_length(150.0 / pixel_scale as f64 * metres_per_pixel);
+                    let bar_width = (length / metres_per_pixel) as f32 * pixel_scale;
+
+                    let canvas = egui_ctx.available_rect();
+                    let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("scale_bar")));
+                    let start = canvas.left_bottom() + egui::vec2(16.0, -16.0);
+                    let stroke = egui::Stroke::new(2.0, egui::Color32::BLACK);
+
+                    painter.rect_filled(egui::Rect::from_min_max(start + egui::vec2(-8.0, -40.0), start + egui::vec2(bar_width.max(120.0) + 8.0, 8.0)), 4.0, egui::Color32::from_white_alpha(220));
+                    painter.line_segment([start, start + egui::vec2(bar_width, 0.0)], stroke);
+                    for x in [0.0, bar_width] {
+                        painter.line_segment([start + egui::vec2(x, -6.0), start + egui::vec2(x, 0.0)], stroke);
+                    }
+                    painter.text(start + egui::vec2(0.0, -8.0), egui::Align2::LEFT_BOTTOM, plan::format_length(length), egui::FontId::proportional(14.0), egui::Color32::BLACK);
+                    painter.text(start + egui::vec2(0.0, -24.0), egui::Align2::LEFT_BOTTOM, format!("1 px = {}", plan::format_length(metres_per_pixel)), egui::FontId::proportional(12.0), egui::Color32::DARK_GRAY);
+                }
+            }
+
+            // Cursor, brush preview and pending shapes
+            let brush_size = match self.active_tool {
+                DrawTool::Pencil | DrawTool::Line | DrawTool::Rectangle | DrawTool::Polygon | DrawTool::Stamp => Some(self.pencil_size),
+                DrawTool::Eraser => Some(self.eraser_size),
+                _ => None,
+            };
+
+            if let Some(floor_plan) = &self.floor_plan {
+                // Text labels, with the one in the label window as it's being written
+                if floor_plan.edits.visible {
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                    let pixels_per_point = egui_ctx.pixels_per_point();
+                    let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("text_labels")));
+
+                    // Screen points per floor plan pixel
+                    let pixel_scale = (plan::image_to_screen(glam::vec2(1.0, 0.0), window_size, drawing_mvp, image_size)
+                        - plan::image_to_screen(glam::Vec2::ZERO, window_size, drawing_mvp, image_size)).length() / pixels_per_point;
+                    let alpha = (floor_plan.edits.opacity * 255.0) as u8;
+
+                    let editing = self.label_draft.as_ref().and_then(|(editing, _)| *editing);
+                    let labels = floor_plan.labels.iter()
+                        .enumerate()
+                        .filter(|(i, _)| Some(*i) != editing)
+                        .map(|(_, label)| label)
+                        .chain(self.label_draft.as_ref().map(|(_, draft)| draft));
+
+                    for label in labels {
+                        // Too small to read is skipped, too large is capped rather than filling the font atlas
+                        let size = label.size * pixel_scale;
+                        if size < 2.0 {
+                            continue;
+                        }
+
+                        let p = plan::image_to_screen(label.position, window_size, drawing_mvp, image_size) / pixels_per_point;
+                        let [r, g, b] = label.colour;
+
+                        painter.text(egui::pos2(p.x, p.y), egui::Align2::CENTER_CENTER, &label.text, egui::FontId::proportional(size.min(MAX_LABEL_POINTS)), egui::Color32::from_rgba_unmultiplied(r, g, b, alpha));
+                    }
+                }
+
+                // Traced wall outlines, with their vertices while they're being edited
+                if let Some(outlines) = floor_plan.traced_walls.as_ref().filter(|_| floor_plan.walls.visible) {
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                    let pixels_per_point = egui_ctx.pixels_per_point();
+                    let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("traced_walls")));
+                    let colour = egui::Color32::from_rgb(0, 150, 255);
+
+                    for outline in outlines {
+                        let points: Vec<egui::Pos2> = outline.iter()
+                            .map(|p| {
+                                let p = plan::image_to_screen(p.as_vec2(), window_size, drawing_mvp, image_size) / pixels_per_point;
+                                egui::pos2(p.x, p.y)
+                            })
+                            .collect();
+
+                        if self.active_tool == DrawTool::EditOutlines {
+                            for p in &points {
+                                painter.circle_filled(*p, 3.0, colour);
+                            }
+                        }
+                        painter.add(egui::Shape::closed_line(points, egui::Stroke::new(2.0, colour)));
+                    }
+                }
+
+                // Where the raw and processed slices meet
+                if self.compare_slices {
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                    let pixels_per_point = egui_ctx.pixels_per_point();
+                    let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("compare_split")));
+
+                    let [top, bottom] = [0.0, image_size.y].map(|y| {
+                        let p = plan::image_to_screen(glam::vec2(self.compare_split * image_size.x, y), window_size, drawing_mvp, image_size) / pixels_per_point;
+                        egui::pos2(p.x, p.y)
+                    });
+                    painter.line_segment([top, bottom], egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 140, 0)));
+                    painter.text(top + egui::vec2(-4.0, 4.0), egui::Align2::RIGHT_TOP, locale.tr("Raw"), egui::FontId::proportional(14.0), egui::Color32::from_rgb(255, 140, 0));
+                    painter.text(top + egui::vec2(4.0, 4.0), egui::Align2::LEFT_TOP, locale.tr("Processed"), egui::FontId::proportional(14.0), egui::Color32::from_rgb(255, 140, 0));
+                }
+
+                // Room numbers, matching the list
+                if floor_plan.rooms.visible {
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                    let pixels_per_point = egui_ctx.pixels_per_point();
+                    let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("room_labels")));
+
+                    for (i, room) in self.rooms.iter().enumerate() {
+                        let p = plan::image_to_screen(room.centre, window_size, drawing_mvp, image_size) / pixels_per_point;
+
+                        painter.text(egui::pos2(p.x, p.y), egui::Align2::CENTER_CENTER, (i + 1).to_string(), egui::FontId::proportional(16.0), egui::Color32::from_rgb(0, 0, 160));
+                    }
+                }
+
+                // Proposed walls, the one that would be picked highlighted
+                if !self.wall_proposals.is_empty() {
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                    let pixels_per_point = egui_ctx.pixels_per_point();
+                    let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("wall_proposals")));
+
+                    let picked = if self.active_tool == DrawTool::ReviewWalls && !egui_ctx.is_pointer_over_area() {
+                        plan::pick_wall_proposal(&self.wall_proposals, self.mouse.position(), window_size, drawing_mvp, image_size)
+                    } else {
+                        None
+                    };
+
+                    for (i, segment) in self.wall_proposals.iter().enumerate() {
+                        let [from, to] = [segment.from, segment.to].map(|p| {
+                            let p = plan::image_to_screen(p + glam::Vec2::splat(0.5), window_size, drawing_mvp, image_size) / pixels_per_point;
+                            egui::pos2(p.x, p.y)
+                        });
+                        let width = if picked == Some(i) { 4.0 } else { 2.0 };
+
+                        painter.line_segment([from, to], egui::Stroke::new(width, egui::Color32::from_rgb(255, 140, 0)));
+                    }
+                }
+
+                if !egui_ctx.is_pointer_over_area() && self.mouse.position().is_finite() {
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                    let pixel_scale = (plan::image_to_screen(glam::vec2(1.0, 0.0), window_size, drawing_mvp, image_size)
+                        - plan::image_to_screen(glam::Vec2::ZERO, window_size, drawing_mvp, image_size)).length();
+
+                    let pixels_per_point = egui_ctx.pixels_per_point();
+                    let position = self.mouse.position() / pixels_per_point;
+
+                    let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("brush_preview")));
+                    let pointer = egui::pos2(position.x, position.y);
+
+                    // Replace the system cursor with a crosshair and the active tool's icon
+                    egui_ctx.output().cursor_icon = egui::CursorIcon::None;
+
+                    let arm = 6.0;
+                    painter.line_segment([pointer - egui::vec2(arm, 0.0), pointer + egui::vec2(arm, 0.0)], egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+                    painter.line_segment([pointer - egui::vec2(0.0, arm), pointer + egui::vec2(0.0, arm)], egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+
+                    let tool_icon = match self.active_tool {
+                        DrawTool::Pencil => '\u{f303}',
+                        DrawTool::Eraser => '\u{f12d}',
+                        DrawTool::RoomIdentification => '\u{f576}',
+                        DrawTool::Line => '\u{f5ae}',
+                        DrawTool::Rectangle => '\u{f0c8}',
+                        DrawTool::Polygon => '\u{f5ee}',
+                        DrawTool::ReviewWalls => '\u{f0d0}',
+                        DrawTool::Text => '\u{f031}',
+                        DrawTool::Stamp => '\u{f5bf}',
+                        DrawTool::EditOutlines => '\u{f5cb}',
+                    };
+
+                    painter.text(
+                        pointer + egui::vec2(arm + 2.0, -arm - 2.0),
+                        egui::Align2::LEFT_BOTTOM,
+                        tool_icon,
+                        egui::FontId::new(14.0, egui::FontFamily::Name("icons".into())),
+                        egui::Color32::DARK_GRAY,
+                    );
+
+                    if let Some(brush_size) = brush_size {
+                        painter.circle_stroke(
+                            pointer,
+                            (brush_size * pixel_scale / 2.0).max(1.0) / pixels_per_point,
+                            egui::Stroke::new(1.0, egui::Color32::DARK_GRAY),
+                        );
+                    }
+
+                    // Pending shape outline
+                    let mut end = plan::screen_to_image(self.mouse.position(), window_size, drawing_mvp, image_size);
+                    if let Some(&last) = self.shape_points.last() {
+                        if self.keyboard.is_pressed(VirtualKeyCode::LShift) || self.keyboard.is_pressed(VirtualKeyCode::RShift) {
+                            end = plan::constrain_axis(last, end);
+                        }
+                    }
+
+                    let to_screen = |p: glam::Vec2| {
+                        let p = plan::image_to_screen(p, window_size, drawing_mvp, image_size) / pixels_per_point;
+                        egui::pos2(p.x, p.y)
+                    };
+                    let stroke = egui::Stroke::new(1.0, egui::Color32::DARK_GRAY);
+
+                    match self.active_tool {
+                        DrawTool::Line | DrawTool::Polygon if !self.shape_points.is_empty() => {
+                            let mut outline: Vec<_> = self.shape_points.iter().map(|p| to_screen(*p)).collect();
+                            outline.push(to_screen(end));
+
+                            if self.active_tool == DrawTool::Polygon {
+                                outline.push(to_screen(self.shape_points[0]));
+                                painter.add(egui::Shape::line(outline, stroke));
+                            } else {
+                                painter.line_segment([outline[outline.len() - 2], outline[outline.len() - 1]], stroke);
+                            }
+                        },
+                        DrawTool::Rectangle => {
+                            if let Some(start) = self.rectangle_start {
+                                painter.add(egui::Shape::closed_line(plan::rectangle(start, end).iter().map(|p| to_screen(*p)).collect(), stroke));
+                            }
+                        },
+                        DrawTool::Stamp => {
+                            for line in self.stamp_symbol.placed(end, self.stamp_size, self.stamp_rotation) {
+                                painter.add(egui::Shape::line(line.into_iter().map(to_screen).collect(), stroke));
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+            }
+
+            tutorial_window(egui_ctx, &mut self.tutorial_step, locale);
+        });
+    }
+
+    /// Drawing tools, wall proposals and traced outlines
+    pub(super) fn edit_plan(&mut self, drawing_mvp: glam::Mat4, window_width: u32, window_height: u32) {
+        if self.detect_walls_queued {
+            if let Some(floor_plan) = &self.floor_plan {
+                let (width, height) = floor_plan.dimensions();
+                self.wall_proposals = hough::detect_segments(width, height, &self.slice_points, &self.hough_parameters);
+            }
+
+            self.detect_walls_queued = false;
+        }
+
+        // Accepted walls are drawn onto the walls layer with the pencil size
+        if let Some(floor_plan) = self.floor_plan.borrow_mut() {
+            let mut accepted = vec![];
+
+            if self.accept_all_walls {
+                accepted.append(&mut self.wall_proposals);
+                self.accept_all_walls = false;
+            } else if self.active_tool == DrawTool::ReviewWalls {
+                let window_size = glam::vec2(window_width as f32, window_height as f32);
+                let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+
+                if let Some(i) = plan::pick_wall_proposal(&self.wall_proposals, self.mouse.position(), window_size, drawing_mvp, image_size) {
+                    match (self.mouse.button_state(MouseButton::Left), self.mouse.button_state(MouseButton::Right)) {
+                        (MouseButtonState::JustPressed, _) => accepted.push(self.wall_proposals.remove(i)),
+                        (_, MouseButtonState::JustPressed) => {
+                            self.wall_proposals.remove(i);
+                        },
+                        _ => {},
+                    }
+                }
+            }
+
+            for segment in accepted {
+                plan::draw_line(&mut floor_plan.walls.image, segment.from, segment.to, self.pencil_size, image::Rgba([0, 0, 0, 255]));
+            }
+        }
+
+        // Shape tools, click to place vertices
+        if let Some(floor_plan) = self.floor_plan.borrow_mut() {
+            let image = &mut floor_plan.edits.image;
+            let window_size = glam::vec2(window_width as f32, window_height as f32);
+            let image_size = glam::vec2(image.width() as f32, image.height() as f32);
+            let mut pos = plan::screen_to_image(self.mouse.position(), window_size, drawing_mvp, image_size);
+
+            if let Some(&last) = self.shape_points.last() {
+                if self.keyboard.is_pressed(VirtualKeyCode::LShift) || self.keyboard.is_pressed(VirtualKeyCode::RShift) {
+                    pos = plan::constrain_axis(last, pos);
+                }
+            }
+
+            let [r, g, b] = self.pencil_colour;
+            let colour = image::Rgba([r, g, b, 255]);
+
+            match self.active_tool {
+                DrawTool::Line | DrawTool::Polygon => {
+                    if self.mouse.button_state(MouseButton::Left) == MouseButtonState::JustPressed {
+                        if let (DrawTool::Line, Some(&last)) = (&self.active_tool, self.shape_points.last()) {
+                            plan::draw_line(image, last, pos, self.pencil_size, colour);
+                        }
+
+                        self.shape_points.push(pos);
+                    }
+
+                    if self.mouse.button_state(MouseButton::Right) == MouseButtonState::JustPressed || self.finish_shape {
+                        if self.active_tool == DrawTool::Polygon && self.shape_points.len() >= 3 {
+                            plan::draw_polygon(image, &self.shape_points, self.pencil_size, colour, self.fill_shapes);
+                        }
+
+                        self.shape_points.clear();
+                    }
+                },
+                DrawTool::Stamp => {
+                    if self.mouse.button_state(MouseButton::Left) == MouseButtonState::JustPressed {
+                        plan::draw_symbol(image, self.stamp_symbol, pos, self.stamp_size, self.stamp_rotation, self.pencil_size, colour);
+                    }
+                    if self.mouse.button_state(MouseButton::Right) == MouseButtonState::JustPressed {
+                        self.stamp_rotation = (self.stamp_rotation + 90.0).rem_euclid(360.0);
+                    }
+                },
+                DrawTool::Rectangle => {
+                    match self.mouse.button_state(MouseButton::Left) {
+                        MouseButtonState::JustPressed => self.rectangle_start = Some(pos),
+                        MouseButtonState::JustReleased => {
+                            if let Some(start) = self.rectangle_start.take() {
+                                plan::draw_polygon(image, &plan::rectangle(start, pos), self.pencil_size, colour, self.fill_shapes);
+                            }
+                        },
+                        _ => {},
+                    }
+                },
+                _ => {
+                    self.shape_points.clear();
+                    self.rectangle_start = None;
+                },
+            }
+        }
+
+        self.finish_shape = false;
+
+        // Traced walls, drag a vertex to move it or an edge to add one, right click a vertex to remove it
+        if let Some(floor_plan) = &mut self.floor_plan {
+            if self.trace_walls_queued || (self.active_tool == DrawTool::EditOutlines && floor_plan.traced_walls.is_none()) {
+                floor_plan.traced_walls = Some(export::trace_walls(floor_plan, self.trace_tolerance as f64));
+                self.dragged_vertex = None;
+            }
+
+            if let (true, Some(outlines)) = (self.regularise_walls_queued, &mut floor_plan.traced_walls) {
+                *outlines = vector::regularise(outlines, (self.regularise_tolerance as f64).to_radians(), self.join_distance as f64);
+                self.dragged_vertex = None;
+            }
+
+            if let (DrawTool::EditOutlines, Some(outlines)) = (&self.active_tool, &mut floor_plan.traced_walls) {
+                let window_size = glam::vec2(window_width as f32, window_height as f32);
+                let image_size = glam::UVec2::from(floor_plan.walls.image.dimensions()).as_vec2();
+                let pos = plan::screen_to_image(self.mouse.position(), window_size, drawing_mvp, image_size).as_dvec2();
+                let picked = plan::pick_outline(outlines, self.mouse.position(), window_size, drawing_mvp, image_size);
+
+                match self.mouse.button_state(MouseButton::Left) {
+                    MouseButtonState::JustPressed => {
+                        self.dragged_vertex = match picked {
+                            Some(plan::OutlinePick::Vertex(outline, i)) => Some((outline, i)),
+                            Some(plan::OutlinePick::Edge(outline, i)) => {
+                                outlines[outline].insert(i + 1, pos);
+                                Some((outline, i + 1))
+                            },
+                            None => None,
+                        };
+                    },
+                    MouseButtonState::JustReleased => self.dragged_vertex = None,
+                    _ => {},
+                }
+
+                if let Some((outline, i)) = self.dragged_vertex {
+                    outlines[outline][i] = pos;
+                } else if let (MouseButtonState::JustPressed, Some(plan::OutlinePick::Vertex(outline, i))) = (self.mouse.button_state(MouseButton::Right), picked) {
+                    outlines[outline].remove(i);
+                    if outlines[outline].len() < 3 {
+                        outlines.remove(outline);
+                    }
+                }
+            } else {
+                self.dragged_vertex = None;
+            }
+        }
+        self.trace_walls_queued = false;
+        self.regularise_walls_queued = false;
+
+        // Text tool, click to place or edit a label
+        if self.active_tool == DrawTool::Text {
+            if let Some(floor_plan) = &mut self.floor_plan {
+                let window_size = glam::vec2(window_width as f32, window_height as f32);
+                let image_size = glam::UVec2::from(floor_plan.dimensions()).as_vec2();
+                let pos = plan::screen_to_image(self.mouse.position(), window_size, drawing_mvp, image_size);
+
+                let left_pressed = self.mouse.button_state(MouseButton::Left) == MouseButtonState::JustPressed;
+                let right_pressed = self.mouse.button_state(MouseButton::Right) == MouseButtonState::JustPressed;
+
+                if let Some((_, draft)) = &mut self.label_draft {
+                    // Clicking elsewhere moves the label being written
+                    if left_pressed {
+                        draft.position = pos;
+                    }
+                } else if left_pressed {
+                    self.label_draft = Some(match floor_plan.label_at(pos, &self.label_font) {
+                        Some(i) => (Some(i), floor_plan.labels[i].clone()),
+                        None => (None, plan::TextLabel { text: String::new(), position: pos, size: self.text_size, colour: self.pencil_colour }),
+                    });
+                    self.focus_label = true;
+                } else if right_pressed {
+                    if let Some(i) = floor_plan.label_at(pos, &self.label_font) {
+                        floor_plan.labels.remove(i);
+                    }
+                }
+            }
+        }
+
+        if let Some(keep) = self.close_label.take() {
+            if let (Some((editing, mut draft)), Some(floor_plan)) = (self.label_draft.take(), &mut self.floor_plan) {
+                draft.text = draft.text.trim().to_owned();
+
+                // Kept without any text deletes the label
+                match (editing, keep, draft.text.is_empty()) {
+                    (Some(i), true, false) => floor_plan.labels[i] = draft,
+                    (Some(i), true, true) => {
+                        floor_plan.labels.remove(i);
+                    },
+                    (None, true, false) => floor_plan.labels.push(draft),
+                    _ => {},
+                }
+            }
+        }
+
+        // Drawing tools
+        if self.mouse.is_pressed(MouseButton::Left) || self.mouse.is_pressed(MouseButton::Right) {
+            if let Some(floor_plan) = self.floor_plan.borrow_mut() {
+                let window_size = glam::vec2(window_width as f32, window_height as f32);
+                let (width, height) = floor_plan.dimensions();
+                let image_size = glam::vec2(width as f32, height as f32);
+                let last_pos = plan::screen_to_image(self.mouse.last_position(), window_size, drawing_mvp, image_size);
+                let pos = plan::screen_to_image(self.mouse.position(), window_size, drawing_mvp, image_size);
+                
+                for (lx, ly) in line_drawing::Bresenham::new((last_pos.x as i32, last_pos.y as i32), (pos.x as i32, pos.y as i32)) {
+                    let lx = lx as u32;
+                    let ly = ly as u32;
+                    
+                    if !(0..width).contains(&lx) || !(0..height).contains(&ly) {
+                        continue;
+                    }
+                    
+                    match self.active_tool {
+                        DrawTool::Pencil => {
+                            let [r, g, b] = self.pencil_colour;
+                            plan::stamp(&mut floor_plan.edits.image, (lx as i32, ly as i32), self.pencil_size, image::Rgba([r, g, b, 255]));
+                        },
+                        DrawTool::Eraser => {
+                            plan::stamp(&mut floor_plan.layer_mut(self.erase_layer).image, (lx as i32, ly as i32), self.eraser_size, plan::ERASED);
+                            self.rooms_changed |= self.erase_layer == plan::LayerKind::Rooms;
+                        },
+                        DrawTool::Line | DrawTool::Rectangle | DrawTool::Polygon | DrawTool::ReviewWalls | DrawTool::Text | DrawTool::Stamp | DrawTool::EditOutlines => {},
+                        DrawTool::RoomIdentification => {
+                            let left_pressed = self.mouse.button_state(MouseButton::Left) == MouseButtonState::JustPressed;
+                            let right_pressed = self.mouse.button_state(MouseButton::Right) == MouseButtonState::JustPressed;
+
+                            if left_pressed || right_pressed {
+                                let target_colour = if left_pressed {
+                                    self.room_fill.map_or_else(|| floor_plan.next_room_colour(), |[r, g, b]| image::Rgba([r, g, b, 0]))
+                                } else {
+                                    plan::EXTERIOR
+                                };
+
+                                floor_plan.fill_room((pos.x as u32, pos.y as u32), target_colour, &self.fill_settings);
+                                self.rooms_changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.rooms_changed {
+            self.rooms = self.floor_plan.as_ref().map_or(vec![], |floor_plan| floor_plan.rooms());
+            self.rooms_changed = false;
+
+            if !self.rooms.is_empty() {
+                advance_tutorial(&mut self.tutorial_step, tutorial::Step::IdentifyRooms);
+            }
+        }
+
+        if self.exit_drawing_mode {
+            // Drop any half drawn shape or label, the floor plan itself is kept
+            self.shape_points.clear();
+            self.rectangle_start = None;
+            self.label_draft = None;
+            self.drawing_mode = false;
+            self.exit_drawing_mode = false;
+        }
+    }
+
+    /// Saving the cutaway and floor plan, and exporting them to other formats
+    pub(super) fn run_exports(&mut self) {
+        let locale = &self.languages[self.language];
+
+        // Render final cutaway
+        if self.final_render_queued {
+            // Check if all pixels have been coloured
+            if let Some(floor_plan) = &self.floor_plan {
+                let mut base = floor_plan.background();
+                
+                for (x, y, pixel) in floor_plan.merged().enumerate_pixels() {
+                    match *pixel {
+                        plan::EXTERIOR => base.put_pixel(x, y, image::Rgba([0,0,0,255])),
+                        image::Rgba([_,_,_,255]) => base.put_pixel(x, y, *pixel),
+                        _ => {},
+                    };
+                }
+                floor_plan.draw_labels(&mut base, &self.label_font);
+                
+                if let Some(transform) = &self.capture_transform {
+                    if self.burn_in_grid {
+                        plan::draw_grid(&mut base, transform, self.grid_spacing as f64, &self.label_font);
+                    }
+                    if self.burn_in_scale_bar {
+                        plan::draw_scale_bar(&mut base, transform, &self.label_font);
+                    }
+                    if self.burn_in_north_arrow {
+                        plan::draw_north_arrow(&mut base, transform, georef::north(self.north_offset as f64), &self.label_font);
+                    }
+                }
+
+                let saved = save_image_dialog(&base, "output.png", plan::ColourDepth::Colour);
+                if let (Some(path), Some(transform)) = (saved, &self.capture_transform) {
+                    georeference_image(&path, transform, &loader::plan_georeference(&self.loaded_files));
+                }
+            }
+
+            self.final_render_queued = false;
+        }
+
+        // Save cutaway with hand-drawn annotations
+        if self.save_cutaway_queued {
+            if let Some(floor_plan) = &self.floor_plan {
+                let mut base = plan::annotated_cutaway(&floor_plan.background(), &floor_plan.merged());
+                floor_plan.draw_labels(&mut base, &self.label_font);
+
+                if let Some(transform) = &self.capture_transform {
+                    if self.burn_in_grid {
+                        plan::draw_grid(&mut base, transform, self.grid_spacing as f64, &self.label_font);
+                    }
+                    if self.burn_in_scale_bar {
+                        plan::draw_scale_bar(&mut base, transform, &self.label_font);
+                    }
+                    if self.burn_in_north_arrow {
+                        plan::draw_north_arrow(&mut base, transform, georef::north(self.north_offset as f64), &self.label_font);
+                    }
+                }
+
+                let saved = save_image_dialog(&base, "cutaway.png", plan::ColourDepth::Colour);
+                if let (Some(path), Some(transform)) = (saved, &self.capture_transform) {
+                    georeference_image(&path, transform, &loader::plan_georeference(&self.loaded_files));
+                }
+            }
+
+            self.save_cutaway_queued = false;
+        }
+
+        // Save floor plan (processed slice, annotations and room colours) in the chosen colour depth
+        if self.save_floor_plan_queued {
+            if let Some(floor_plan) = &self.floor_plan {
+                let mut base = floor_plan.merged();
+                floor_plan.draw_labels(&mut base, &self.label_font);
+
+                if let Some(transform) = &self.capture_transform {
+                    if self.burn_in_grid {
+                        plan::draw_grid(&mut base, transform, self.grid_spacing as f64, &self.label_font);
+                    }
+                    if self.burn_in_scale_bar {
+                        plan::draw_scale_bar(&mut base, transform, &self.label_font);
+                    }
+                    if self.burn_in_north_arrow {
+                        plan::draw_north_arrow(&mut base, transform, georef::north(self.north_offset as f64), &self.label_font);
+                    }
+                }
+
+                let saved = save_image_dialog(&base, "floor_plan.png", self.floor_plan_depth);
+                if let (Some(path), Some(transform)) = (saved, &self.capture_transform) {
+                    georeference_image(&path, transform, &loader::plan_georeference(&self.loaded_files));
+                }
+            }
+
+            self.save_floor_plan_queued = false;
+        }
+
+        // Trace walls, rooms and annotations into a scaled vector drawing
+        if self.export_svg_queued {
+            if let (Some(floor_plan), Some(transform)) = (&self.floor_plan, &self.capture_transform) {
+                let georeference = loader::plan_georeference(&self.loaded_files);
+                let svg = export::svg(&export::vectorise(floor_plan), transform, &georeference);
+
+                save_text_dialog(&svg, "floor_plan.svg", "SVG", "svg", &georeference);
+            }
+
+            self.export_svg_queued = false;
+        }
+
+        // Same outlines in file coordinates, for CAD
+        if self.export_dxf_queued {
+            if let (Some(floor_plan), Some(transform)) = (&self.floor_plan, &self.capture_transform) {
+                let georeference = loader::plan_georeference(&self.loaded_files);
+                let dxf = export::dxf(&export::vectorise(floor_plan), transform, &georeference);
+
+                save_text_dialog(&dxf, "floor_plan.dxf", "DXF", "dxf", &georeference);
+            }
+
+            self.export_dxf_queued = false;
+        }
+
+        // Cutaway and floor plan on a page, the labels as text
+        if self.export_pdf_queued {
+            if let Some(floor_plan) = &self.floor_plan {
+                let mut base = plan::annotated_cutaway(&floor_plan.background(), &floor_plan.merged());
+
+                if let Some(transform) = &self.capture_transform {
+                    if self.burn_in_grid {
+                        plan::draw_grid(&mut base, transform, self.grid_spacing as f64, &self.label_font);
+                    }
+                    if self.burn_in_scale_bar {
+                        plan::draw_scale_bar(&mut base, transform, &self.label_font);
+                    }
+                    if self.burn_in_north_arrow {
+                        plan::draw_north_arrow(&mut base, transform, georef::north(self.north_offset as f64), &self.label_font);
+                    }
+                }
+
+                let text = floor_plan.visible_labels().iter()
+                    .map(|label| pdf::PageText {
+                        text: label.text.clone(),
+                        position: label.baseline(&self.label_font),
+                        size: label.size,
+                        colour: label.colour,
+                    })
+                    .collect();
+                let title = self.loaded_files.first().map_or(locale.tr("Floor Plan").to_owned(), |file| recent::file_name(&file.path));
+
+                let dialog = rfd::FileDialog::new().set_file_name("floor_plan.pdf").add_filter("PDF", &["pdf"]);
+                if let Some(mut path) = dialog.save_file() {
+                    if path.extension().is_none() {
+                        path.set_extension("pdf");
+                    }
+
+                    let mut document = pdf::Document::new();
+                    let written = document.add_page_with_text(&base, &title, text)
+                        .and_then(|_| std::fs::File::create(&path))
+                        .and_then(|file| document.write(&mut std::io::BufWriter::new(file)));
+
+                    if let Err(err) = written {
+                        eprintln!("Failed to save {}: {}", path.display(), err);
+                    }
+                }
+            }
+
+            self.export_pdf_queued = false;
+        }
+
+        // Walls extruded into a massing model
+        if self.export_obj_queued {
+            if let (Some(floor_plan), Some(transform)) = (&self.floor_plan, &self.capture_transform) {
+                let surfaces: Vec<(geometry::PlaneKind, f64)> = self.levels.iter().map(|level| (level.kind, level.height))
+                    .chain(self.detected_planes.iter().map(|detected| (detected.kind, detected.plane.origin.z)))
+                    .collect();
+                let (floor, ceiling) = geometry::storey_heights(&surfaces, transform.origin.z, self.cut_height as f64);
+                let georeference = loader::plan_georeference(&self.loaded_files);
+
+                match export::extruded_walls_obj(floor_plan, transform, floor, ceiling, &georeference) {
+                    // No .prj, the vertices are offset from the world coordinates
+                    Ok(obj) => save_text_dialog(&obj, "walls.obj", "Wavefront OBJ", "obj", &georef::Georeference::default()),
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+
+            self.export_obj_queued = false;
+        }
+
+        // Cutaway, rooms and points for sharing with people who won't install anything
+        if self.export_web_viewer_queued {
+            if let (Some(floor_plan), Some(transform)) = (&self.floor_plan, &self.capture_transform) {
+                if let Some(dir) = rfd::FileDialog::new().set_title("Export Web Viewer").pick_folder() {
+                    let mut cutaway = plan::annotated_cutaway(&floor_plan.background(), &floor_plan.merged());
+                    floor_plan.draw_labels(&mut cutaway, &self.label_font);
+
+                    if self.burn_in_grid {
+                        plan::draw_grid(&mut cutaway, transform, self.grid_spacing as f64, &self.label_font);
+                    }
+                    if self.burn_in_scale_bar {
+                        plan::draw_scale_bar(&mut cutaway, transform, &self.label_font);
+                    }
+                    if self.burn_in_north_arrow {
+                        plan::draw_north_arrow(&mut cutaway, transform, georef::north(self.north_offset as f64), &self.label_font);
+                    }
+
+                    let colouring = colour::Colouring {
+                        mode: self.colour_mode,
+                        ramps: &self.colour_ramps,
+                        elevation_range: loader::elevation_range(&self.loaded_files),
+                        planes: &self.detected_planes,
+                        plane_threshold: self.plane_threshold as f64,
+                        density: self.density_grid.as_ref(),
+                        distances: self.cloud_distances.as_ref().and_then(|distances| distances.as_ref().ok()),
+                        distance_threshold: self.distance_threshold as f64,
+                        clusters: self.clusters.as_ref(),
+                    };
+
+                    let viewer = web::WebViewer {
+                        title: self.loaded_files.first().map_or("Point Cloud Cutaway".to_owned(), |file| recent::file_name(&file.path)),
+                        cutaway,
+                        transform: *transform,
+                        rooms: web::rooms(floor_plan, transform),
+                        points: web::WebPoints::new(&self.point_batches, &self.point_filter, &colouring, web::WEB_VIEWER_POINTS),
+                        georeference: loader::plan_georeference(&self.loaded_files),
+                    };
+
+                    self.export_jobs.push(self.job_queue.submit(locale.tr("Exporting web viewer"), move |_| {
+                        viewer.write(&dir).map(|_| format!("Saved web viewer to {}", dir.join("index.html").display()))
+                    }));
+                }
+            }
+
+            self.export_web_viewer_queued = false;
+        }
+
+        if self.export_slice_points_queued {
+            if let Some(capture) = &self.last_slice_capture {
+                let dialog = rfd::FileDialog::new()
+                    .add_filter("LAS", &["las"])
+                    .add_filter("LAZ", &["laz"])
+                    .add_filter("PLY", &["ply"])
+                    .set_file_name("slice.las");
+
+                if let Some(path) = dialog.save_file() {
+                    let files: Vec<String> = self.loaded_files.iter().map(|file| file.path.clone()).collect();
+
+                    match subset::export_slice(&self.point_batches, &self.point_filter, &capture.band, &files, &path) {
+                        Ok(written) => {
+                            println!("Saved {} points to {}", written, path.display());
+
+                            // LAS files carry their coordinate system in the header
+                            if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ply")) {
+                                if let Err(err) = loader::plan_georeference(&self.loaded_files).write_prj(&path) {
+                                    eprintln!("{}", err);
+                                }
+                            }
+                        },
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
+            }
+
+            self.export_slice_points_queued = false;
+        }
+    }
+}
+
+/// Returns where the image was saved
+pub(super) fn save_image_dialog(image: &image::RgbaImage, file_name: &str, depth: plan::ColourDepth) -> Option<PathBuf> {
+    let valid_formats = hashmap! {
+        "PNG" => vec!["png"],
+        "JPEG" => vec!["jpeg", "jpg"],
+        "GIF" => vec!["gif"],
+        "WebP" => vec!["webp"],
+        "Tiff" => vec!["tiff"],
+    };
+
+    let dialog = {
+        let mut d = rfd::FileDialog::new().set_file_name(file_name);
+
+        for (name, extensions) in &valid_formats {
+            d = d.add_filter(name, &extensions);
+        }
+
+        d
+    };
+
+    if let Some(mut path) = dialog.save_file() {
+        let mut valid = false;
+
+        if path.extension().is_some() {
+            for (_, extensions) in valid_formats {
+                for extention in extensions {
+                    if path.extension().expect("No file extension (this shouldn't ever occur)").to_ascii_lowercase() == extention {
+                        valid = true;
+                        break;
+                    }
+                }
+                if valid {
+                    break;
+                }
+            }
+        }
+
+        if !valid {
+            path.set_extension("png"); // force png if no extension chosen
+        }
+
+        match plan::save_image(image, depth, &path) {
+            Ok(_) => return Some(path),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    None
+}
+
+/// Georeference a saved floor plan image so it can be loaded into GIS software as a basemap, if the loaded files have
+/// a coordinate system
+pub(super) fn georeference_image(path: &Path, transform: &plan::CaptureTransform, georeference: &georef::Georeference) {
+    match georeference.write_image_georeferencing(path, transform) {
+        Ok(written) => {
+            for file in written {
+                println!("Saved {}", file.display());
+            }
+        },
+        Err(err) => eprintln!("{}", err),
+    }
+}
+
+/// Save a text export, with a `.prj` of the coordinate system beside it when it's known
+pub(super) fn save_text_dialog(contents: &str, file_name: &str, format_name: &str, extension: &str, georeference: &georef::Georeference) {
+    let dialog = rfd::FileDialog::new().set_file_name(file_name).add_filter(format_name, &[extension]);
+
+    if let Some(mut path) = dialog.save_file() {
+        if path.extension().is_none() {
+            path.set_extension(extension);
+        }
+
+        if let Err(err) = std::fs::write(&path, contents) {
+            eprintln!("{}", err);
+            return;
+        }
+
+        if let Err(err) = georeference.write_prj(&path) {
+            eprintln!("{}", err);
+        }
+    }
+}
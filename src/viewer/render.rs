@@ -0,0 +1,398 @@
+use std::cell::RefCell;
+
+use glium::{framebuffer::SimpleFrameBuffer, Surface};
+
+use crate::{camera::{self, Z_NEAR}, colour, plan, refine, render, slice};
+use crate::loader;
+
+use super::{Viewer, SliceUse, SliceCapture, process_slice};
+
+impl Viewer {
+    /// Draw the points (or the floor plan being edited) and the interface, capturing a cutaway when one is queued
+    pub(super) fn render(&mut self, mut target: glium::Frame, drawing_mvp: glam::Mat4, window_width: u32, window_height: u32) {
+        puffin::profile_scope!("render");
+
+        let locale = &self.languages[self.language];
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::Points);
+        let quad_indices = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+        
+        // Update camera/matrices
+        // Model space is file coordinates relative to the shared centre, each batch is offset by its own origin
+        let model = render::COORDINATE_SYSTEM;
+        let view = camera::view(self.camera_position, self.camera_rotation);
+        
+        // Perspective
+        // let projection = {
+        //     let (width, height) = target.get_dimensions();
+        //     let aspect = width as f32 / height as f32;
+        //     glam::Mat4::perspective_lh(FOVY, aspect, 0.1, 10_000.0)
+        // };
+
+        let zoom = 2.0_f32.powf(-self.camera_zoom / 10.0);
+
+        // Orthographic
+        let projection = {
+            let (width, height) = target.get_dimensions();
+            camera::projection(zoom, width, height)
+        };
+
+        let modelview = view * model;
+
+        // Render
+
+        let mut cutaway_texture = None;
+        let mut cutaway_slice_texture = None;
+        let mut _cutaway_depth = None;
+        
+        let mut cutaway_buffer: RefCell<Option<SimpleFrameBuffer>> = RefCell::new(None);
+        let mut cutaway_slice_buffer: RefCell<Option<SimpleFrameBuffer>> = RefCell::new(None);
+
+        // Cutaways are drawn larger and scaled down, slices are processed at the window size
+        let supersampling = render::supersampling(&self.display, window_width, window_height, self.capture_supersampling);
+
+        if self.cutaway_queued {
+            cutaway_texture = Some(render::capture_texture(&self.display, window_width * supersampling, window_height * supersampling));
+            cutaway_slice_texture = Some(render::capture_texture(&self.display, window_width, window_height));
+            _cutaway_depth = Some(glium::framebuffer::DepthRenderBuffer::new(&self.display, 
+                glium::texture::DepthFormat::F32, window_width * supersampling, window_height * supersampling).expect("Failed to create processed cutaway slice texture"));
+            
+            if let Some(cutaway_texture) = &cutaway_texture {
+                if let Some(cutaway_depth) = &_cutaway_depth {
+                    cutaway_buffer = RefCell::new(glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(&self.display, cutaway_texture, cutaway_depth).ok());
+                }
+            }
+            if let Some(cutaway_slice_texture) = &cutaway_slice_texture {
+                cutaway_slice_buffer = RefCell::new(glium::framebuffer::SimpleFrameBuffer::new(&self.display, cutaway_slice_texture).ok());
+            }
+
+            self.cutaway_queued = false;
+        }
+
+        {
+            puffin::profile_scope!("clear_colour");
+            if self.show_outline_plane {
+                target.clear_color_and_depth((1.0, 1.0, 1.0, 0.0), 1.0);
+            } else {
+                target.clear_color_and_depth(self.background.clear_colour(), 1.0);
+
+                if let (Some((top, bottom)), false) = (self.background.gradient(), self.drawing_mode) {
+                    let uniforms = uniform! {
+                        u_top: top.map(|c| c as f32),
+                        u_bottom: bottom.map(|c| c as f32),
+                    };
+
+                    target.draw(&self.fullscreen_quad, quad_indices, &self.background_program, &uniforms, &Default::default()).expect("Failed to draw background.");
+                }
+            }
+
+            // Captures get a neutral background whatever the 3D view's
+            if let Some(cutaway_buffer) = &mut *cutaway_buffer.borrow_mut() {
+                cutaway_buffer.clear_color_and_depth(render::CAPTURE_CLEAR_COLOUR, 1.0);
+            }
+            if let Some(cutaway_slice_buffer) = &mut *cutaway_slice_buffer.borrow_mut() {
+                cutaway_slice_buffer.clear_color(1.0, 1.0, 1.0, 0.0);
+            }
+        }
+        
+        if !self.drawing_mode {
+            // Ground grid under the lowest points, drawn first so the points cover it
+            let bounds = self.loaded_files.iter().fold(None, |bounds, file| loader::union_bounds(bounds, file.bounds));
+
+            if let (Some((min, max)), true) = (bounds, self.show_grid) {
+                let lines = render::ground_grid(min.truncate(), max.truncate(), min.z, self.grid_spacing as f64, self.centre.unwrap_or(glam::DVec3::ZERO));
+                let grid_buffer = glium::VertexBuffer::new(&self.display, &lines).expect("Failed to create ground grid vertex buffer.");
+
+                let uniforms = uniform! {
+                    u_modelview: modelview.to_cols_array_2d(),
+                    u_projection: projection.to_cols_array_2d(),
+                    u_opacity: 0.6_f32,
+                };
+
+                let draw_params = glium::DrawParameters {
+                    depth: glium::Depth {
+                        test: glium::DepthTest::IfLess,
+                        write: false,
+                        ..Default::default()
+                    },
+                    blend: glium::Blend::alpha_blending(),
+                    ..Default::default()
+                };
+
+                target.draw(&grid_buffer, glium::index::NoIndices(glium::index::PrimitiveType::LinesList), &self.grid_program, &uniforms, &draw_params).expect("Failed to draw ground grid.");
+            }
+
+            // Hillshade at the lowest cell, under the points like the grid
+            if let (Some((texture, (min, max), elevation)), true) = (&self.raster_layer, self.show_raster) {
+                let centre = self.centre.unwrap_or(glam::DVec3::ZERO);
+
+                let uniforms = uniform! {
+                    u_modelview: modelview.to_cols_array_2d(),
+                    u_projection: projection.to_cols_array_2d(),
+                    u_min: (*min - centre.truncate()).as_vec2().to_array(),
+                    u_max: (*max - centre.truncate()).as_vec2().to_array(),
+                    u_elevation: (*elevation - centre.z) as f32,
+                    u_raster: texture.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest),
+                    u_opacity: self.raster_opacity,
+                };
+
+                let draw_params = glium::DrawParameters {
+                    depth: glium::Depth {
+                        test: glium::DepthTest::IfLess,
+                        write: false,
+                        ..Default::default()
+                    },
+                    blend: glium::Blend::alpha_blending(),
+                    ..Default::default()
+                };
+
+                target.draw(&self.fullscreen_quad, quad_indices, &self.raster_program, &uniforms, &draw_params).expect("Failed to draw hillshade.");
+            }
+
+            puffin::profile_scope!("queue_points");
+
+            // Too many points to draw every frame, draw the next part of each buffer into the accumulated points
+            let total_vertices: usize = self.vertex_buffers.iter().map(|buffer| buffer.len()).sum();
+            let budget = (self.refine_points_per_frame * 1.0e6) as usize;
+
+            if !self.progressive_refinement || self.show_outline_plane || total_vertices <= budget {
+                self.refinement = None;
+            } else if self.refinement.as_ref().is_none_or(|refinement| refinement.dimensions() != (window_width, window_height)) {
+                self.refinement = refine::Refinement::new(&self.display, window_width, window_height).map_err(|err| eprintln!("{}", err)).ok();
+            }
+
+            let refining = self.refinement.is_some();
+            let refine_view = (modelview, projection, self.point_size, self.clipping, self.show_slice, self.colour_mode, self.points_version, self.fog, self.background, self.point_transform);
+            let mut refine_part = match &mut self.refinement {
+                Some(refinement) => refinement.next(&self.display, refine_view, budget as f32 / total_vertices as f32).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    None
+                }),
+                None => None,
+            };
+
+            // Not in captures, which are on white
+            let (fog_mode, fog_parameters) = self.fog.uniforms();
+            let (fog_top, fog_bottom) = self.background.colours();
+
+            for (vertex_buffer, batch) in self.vertex_buffers.iter().zip(&self.point_batches) {
+                let modelview = modelview * self.point_transform.batch_model(batch.origin, self.centre.unwrap_or(glam::DVec3::ZERO));
+
+                let point_program = match self.colour_mode {
+                    colour::ColourMode::Shader(shader) => &self.point_shaders[shader].1,
+                    _ => &self.program,
+                };
+
+                let p = if self.show_outline_plane {
+                    &self.debug_program
+                } else {
+                    point_program
+                };
+
+                let uniforms = uniform! {
+                    u_modelview: modelview.to_cols_array_2d(),
+                    u_projection: projection.to_cols_array_2d(),
+                    // u_colour_format: colour_format,
+                    // u_clipping_dist: clipping_dist,
+                    u_clipping: self.clipping,
+                    u_slice: self.show_slice,
+                    u_slice_width: self.config.slice_width(),
+                    u_zoom: window_width as f32 / zoom,
+                    u_size: self.point_size,
+                    u_round_points: !self.reduced_mode,
+                    u_origin: (batch.origin - self.centre.unwrap_or(glam::DVec3::ZERO)).as_vec3().to_array(),
+                    u_centre: self.centre.unwrap_or(glam::DVec3::ZERO).as_vec3().to_array(),
+                    u_fog: fog_mode,
+                    u_fog_parameters: fog_parameters,
+                    u_depth_range: camera::Z_FAR - Z_NEAR,
+                    u_fog_top: fog_top.map(|c| c as f32),
+                    u_fog_bottom: fog_bottom.map(|c| c as f32),
+                    u_window_height: window_height as f32,
+                };
+
+                let draw_params = glium::DrawParameters {
+                    depth: glium::Depth {
+                        test: glium::DepthTest::IfLess,
+                        write: true,
+                        ..Default::default()
+                    },
+                    multisampling: !self.reduced_mode,
+                    ..Default::default()
+                };
+                
+                if !refining {
+                    target.draw(vertex_buffer, &indices, p, &uniforms, &draw_params).expect("Failed to draw to screen.");
+                } else if let Some((range, framebuffer)) = &mut refine_part {
+                    if let Some(part) = vertex_buffer.slice(refine::vertex_range(range, vertex_buffer.len())) {
+                        let draw_params = glium::DrawParameters { multisampling: false, ..draw_params.clone() };
+
+                        framebuffer.draw(part, indices, p, &uniforms, &draw_params).expect("Failed to draw to refinement buffer.");
+                    }
+                }
+
+                if let Some(cutaway_buffer) = &mut *cutaway_buffer.borrow_mut() {
+                    puffin::profile_scope!("draw_render_frame");
+
+                    let uniforms = uniform! {
+                        u_modelview: modelview.to_cols_array_2d(),
+                        u_projection: projection.to_cols_array_2d(),
+                        u_clipping: self.clipping,
+                        u_slice: self.show_slice,
+                        u_slice_width: self.config.slice_width(),
+                        u_zoom: (window_width * supersampling) as f32 / zoom,
+                        u_size: self.point_size,
+                        u_round_points: !self.reduced_mode,
+                        u_origin: (batch.origin - self.centre.unwrap_or(glam::DVec3::ZERO)).as_vec3().to_array(),
+                        u_centre: self.centre.unwrap_or(glam::DVec3::ZERO).as_vec3().to_array(),
+                    };
+
+                    cutaway_buffer.draw(vertex_buffer, &indices, point_program, &uniforms, &draw_params).expect("Failed to draw to cutaway buffer.");
+                }
+                if let Some(cutaway_slice_buffer) = &mut *cutaway_slice_buffer.borrow_mut() {
+                    puffin::profile_scope!("draw_render_slice");
+                    cutaway_slice_buffer.draw(vertex_buffer, &indices, &self.debug_program, &uniforms, &Default::default()).expect("Failed to draw to cutaway slice buffer.");
+                }
+            }
+
+            drop(refine_part);
+            if let Some(refinement) = &self.refinement {
+                refinement.composite(&mut target, &self.refine_program, &self.fullscreen_quad).expect("Failed to draw refined points.");
+            }
+
+            if let (Some((mesh_vertices, mesh_indices)), Some(stats)) = (&self.mesh_preview, &self.mesh_preview_stats) {
+                if self.show_mesh_preview {
+                    let modelview = modelview * glam::Mat4::from_translation((stats.origin - self.centre.unwrap_or(glam::DVec3::ZERO)).as_vec3());
+
+                    let uniforms = uniform! {
+                        u_modelview: modelview.to_cols_array_2d(),
+                        u_projection: projection.to_cols_array_2d(),
+                    };
+
+                    let draw_params = glium::DrawParameters {
+                        depth: glium::Depth {
+                            test: glium::DepthTest::IfLess,
+                            write: true,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    };
+
+                    target.draw(mesh_vertices, mesh_indices, &self.mesh_program, &uniforms, &draw_params).expect("Failed to draw mesh preview.");
+                }
+            }
+
+            // Over the points, contours lie in them
+            if let (Some((lines, origin)), true) = (&self.contour_lines, self.show_contours) {
+                let modelview = modelview * glam::Mat4::from_translation((*origin - self.centre.unwrap_or(glam::DVec3::ZERO)).as_vec3());
+
+                let uniforms = uniform! {
+                    u_modelview: modelview.to_cols_array_2d(),
+                    u_projection: projection.to_cols_array_2d(),
+                    u_opacity: 0.9_f32,
+                };
+
+                let draw_params = glium::DrawParameters {
+                    blend: glium::Blend::alpha_blending(),
+                    line_width: Some(2.0),
+                    ..Default::default()
+                };
+
+                target.draw(lines, glium::index::NoIndices(glium::index::PrimitiveType::LinesList), &self.grid_program, &uniforms, &draw_params).expect("Failed to draw contours.");
+            }
+        } else {
+            let floor_plan = self.floor_plan.as_ref().expect("Failed to fetch floor plan from memory");
+            let textures = plan::LayerKind::ALL.map(|kind| {
+                let image = &floor_plan.layer(kind).image;
+                let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(image.as_raw(), image.dimensions());
+
+                glium::texture::Texture2d::new(&self.display, raw).expect("Failed to create floor plan layer texture")
+            });
+            let [cutaway_texture, walls_texture, edits_texture, rooms_texture] = textures;
+            let raw_slice_texture = {
+                let image = &floor_plan.raw_slice.image;
+                let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(image.as_raw(), image.dimensions());
+
+                glium::texture::Texture2d::new(&self.display, raw).expect("Failed to create raw slice texture")
+            };
+
+            target.draw(&self.fullscreen_quad, &quad_indices, &self.drawing_program, 
+                &uniform! {
+                    u_cutaway: cutaway_texture,
+                    u_raw_slice: raw_slice_texture,
+                    u_walls: walls_texture,
+                    u_edits: edits_texture,
+                    u_rooms: rooms_texture,
+                    u_cutaway_opacity: floor_plan.cutaway.display_opacity(),
+                    u_raw_slice_opacity: floor_plan.raw_slice.display_opacity(),
+                    u_walls_opacity: floor_plan.walls.display_opacity(),
+                    u_edits_opacity: floor_plan.edits.display_opacity(),
+                    u_rooms_opacity: floor_plan.rooms.display_opacity(),
+                    u_brightness: self.cutaway_brightness,
+                    u_contrast: self.cutaway_contrast,
+                    u_desaturate: self.cutaway_desaturate,
+                    u_compare_split: if self.compare_slices { self.compare_split } else { -1.0 },
+                    u_highlight_added: self.highlight_added,
+                    u_alpha_threshold: self.alpha_threshold as f32 / 255.0,
+                    u_mvp: drawing_mvp.to_cols_array_2d(),
+                }, 
+                &glium::DrawParameters {
+                backface_culling: glium::BackfaceCullingMode::CullingDisabled,
+                ..Default::default()
+            }).expect("Failed to draw to cutaway image screen");
+        }
+
+        {
+            puffin::profile_scope!("queue_gui");
+            self.egui_glium.paint(&self.display, &mut target);
+        }
+        
+        {
+            puffin::profile_scope!("finish_frame");
+            target.finish().expect("Failed to finish frame");
+        }
+
+        // Process cutaway
+        if let Some(cutaway_texture) = cutaway_texture {
+            let cutaway_capture = render::downsample(&render::read_image(&cutaway_texture), supersampling);
+            let (width, height) = cutaway_capture.dimensions();
+            let transform = plan::CaptureTransform::new(projection * modelview, width, height, self.centre.unwrap_or(glam::DVec3::ZERO));
+        
+            if let Some(cutaway_slice_texture) = cutaway_slice_texture {
+                let capture = SliceCapture {
+                    cutaway: cutaway_capture,
+                    raw: render::read_image(&cutaway_slice_texture),
+                    transform,
+                    band: slice::SliceBand {
+                        view_projection: projection * modelview,
+                        transform: self.point_transform,
+                        centre: self.centre.unwrap_or(glam::DVec3::ZERO),
+                        width: self.config.slice_width(),
+                    },
+                    auto_connect_radius: self.config.connect_radius(self.point_size, zoom),
+                };
+                let radius = self.connect_radius.unwrap_or(capture.auto_connect_radius);
+
+                let processed = if self.process_slice_on_gpu {
+                    self.gpu_closing.process(&self.display, &cutaway_slice_texture, radius, self.alpha_threshold)
+                        .map(|texture| render::read_image(&texture))
+                        .map_err(|err| eprintln!("{}, processing on the CPU instead", err))
+                        .ok()
+                } else {
+                    None
+                };
+
+                let slice_use = if self.dump_stages_queued {
+                    SliceUse::DumpStages
+                } else if self.quick_render_queued {
+                    SliceUse::QuickRender
+                } else {
+                    SliceUse::FloorPlan
+                };
+                self.dump_stages_queued = false;
+                self.quick_render_queued = false;
+
+                // Replaces any slice still being processed, its result is dropped
+                self.processed_slice = Some(self.job_queue.submit(locale.tr("Processing slice"), process_slice(capture, self.slice_processors.shared(self.slice_processor).clone(), processed, radius, self.alpha_threshold, slice_use)));
+            }
+        }
+    }
+}
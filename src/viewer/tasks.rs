@@ -0,0 +1,778 @@
+use std::{sync::{mpsc, Arc}, path::{Path, PathBuf}};
+
+use glium::glutin::{event::{VirtualKeyCode, MouseButton}, event_loop::EventLoopWindowTarget};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{camera::Z_NEAR, cluster, colour, config, context, contour, filter, geometry, ground, hough, jobs, link, mesh, picking, plan, profile, raster, recent, render::{self, batch_vertices}, registration, script, section, selection, stages, subset, transform, tutorial};
+use crate::input::MouseButtonState;
+use crate::loader;
+
+use super::{Viewer, SliceUse, advance_tutorial, process_slice};
+use super::loading::queue_load;
+use super::plan_editor::georeference_image;
+
+impl Viewer {
+    /// Work queued from the panels and shortcuts, and results of the jobs it started
+    pub(super) fn run_queued(&mut self, window_target: &EventLoopWindowTarget<()>, camera_mvp: glam::Mat4, window_width: u32, window_height: u32) {
+        let locale = &self.languages[self.language];
+
+        if self.pick_queued {
+            puffin::profile_scope!("pick");
+
+            let window_size = glam::vec2(window_width as f32, window_height as f32);
+            let ray = picking::Ray::from_screen(self.mouse.position(), window_size, camera_mvp, self.centre.unwrap_or(glam::DVec3::ZERO));
+            // Points within a few pixels of the cursor
+            let radius = 4.0 * 2.0_f64.powf(-self.camera_zoom as f64 / 10.0) / window_width as f64;
+
+            if let Some((point, file)) = picking::pick(&self.point_batches, &self.point_filter, &ray, radius) {
+                if self.measuring {
+                    self.measure_points.push(glam::dvec3(point.x, point.y, point.z));
+                }
+                if self.profiling {
+                    // A third click starts a new line
+                    if self.profile_line.len() == 2 {
+                        self.profile_line.clear();
+                    }
+                    self.profile_line.push(glam::dvec3(point.x, point.y, point.z));
+                    self.profile_queued = self.profile_line.len() == 2;
+                }
+                if self.inspecting {
+                    self.picked_point = Some((point, file));
+                }
+            }
+
+            self.pick_queued = false;
+        }
+
+        if let Some(text) = self.paste_queued.take() {
+            match link::parse(&text) {
+                // Already open, just move to the view
+                Some(link) if self.loaded_files.len() == 1 && self.loaded_files[0].path == link.path && self.batch_number < 0 => {
+                    if let Some(view) = link.view {
+                        self.camera_position = render::COORDINATE_SYSTEM.transform_point3((view.position - self.centre.unwrap_or(glam::DVec3::ZERO)).as_vec3());
+                        self.camera_rotation = view.rotation;
+                        self.camera_zoom = view.zoom;
+                    }
+                },
+                Some(link) if self.path_rx.is_none() && self.load_job.is_none() && self.rx.is_none() => {
+                    self.pending_view = link.view;
+                    self.open_recent_queued = Some(link.path);
+                },
+                Some(_) => {},
+                None => self.load_warnings.push(locale.tr("Pasted text isn't a point cloud file or view link").to_owned()),
+            }
+        }
+
+        if let Some(file) = self.file_info_queued.take().and_then(|index| self.loaded_files.get(index)) {
+            self.file_info = Some((file.path.clone(), loader::FileInfo::read(&file.path)));
+        }
+
+        if let Some(path) = self.open_recent_queued.take() {
+            self.load_job = Some(queue_load(&mut self.job_queue, locale, path, false, self.pdal_pipeline.clone().filter(|_| self.preprocess_with_pdal)));
+        }
+
+        if self.contours_queued {
+            puffin::profile_scope!("contours");
+
+            // Points copied here, gridded and contoured on a worker
+            let points = loader::positions(&self.point_batches, &self.point_filter);
+            let (cell_size, interval, major_every) = (self.contour_cell_size as f64, self.contour_interval as f64, self.contour_major_every);
+
+            self.contours_job = Some(self.job_queue.submit(locale.tr("Generating contours"), move |_| {
+                Ok(raster::ElevationGrid::new(&points, cell_size, raster::Statistic::Mean)
+                    .map_or(vec![], |grid| contour::contours(&grid, interval, major_every)))
+            }));
+
+            self.contours_queued = false;
+        }
+
+        if let Some(result) = self.contours_job.as_ref().and_then(jobs::Job::poll) {
+            self.contours_job = None;
+
+            match result {
+                Ok(found) => {
+                    self.contours = found;
+
+                    let origin = self.centre.unwrap_or(glam::DVec3::ZERO);
+                    let lines = render::contour_lines(&self.contours, origin);
+
+                    self.contour_lines = Some((glium::VertexBuffer::new(&self.display, &lines).expect("Failed to create contour vertex buffer."), origin));
+                    if self.contours.is_empty() {
+                        self.load_warnings.push(locale.tr("No contours, are any points shown?").to_owned());
+                    }
+                },
+                Err(err) => self.load_warnings.push(err),
+            }
+        }
+
+        self.export_jobs.retain(|job| match job.poll() {
+            Some(Ok(message)) => {
+                println!("{}", message);
+                false
+            },
+            Some(Err(err)) => {
+                eprintln!("{}", err);
+                self.load_warnings.push(err);
+                false
+            },
+            None => true,
+        });
+
+        if self.raster_queued {
+            puffin::profile_scope!("raster");
+
+            let points = loader::positions(&self.point_batches, &self.point_filter);
+            let (cell_size, statistic) = (self.raster_cell_size as f64, self.raster_statistic);
+            let empty = locale.tr("No raster, are any points shown?").to_owned();
+
+            self.raster_job = Some(self.job_queue.submit(locale.tr("Generating raster"), move |_| raster::ElevationGrid::new(&points, cell_size, statistic).ok_or(empty)));
+
+            self.raster_queued = false;
+        }
+
+        if let Some(result) = self.raster_job.as_ref().and_then(jobs::Job::poll) {
+            self.raster_job = None;
+
+            match result {
+                Ok(grid) => {
+                    let image = grid.hillshade();
+                    let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(image.as_raw(), image.dimensions());
+                    let texture = glium::texture::Texture2d::new(&self.display, raw).expect("Failed to create hillshade texture");
+
+                    self.raster_layer = Some((texture, grid.bounds(), grid.range().0));
+                    self.elevation_raster = Some(Arc::new(grid));
+                },
+                Err(err) => self.load_warnings.push(err),
+            }
+        }
+
+        if self.export_raster_queued {
+            if let Some(grid) = &self.elevation_raster {
+                let dialog = rfd::FileDialog::new().set_file_name("elevation.tif").add_filter("GeoTIFF", &["tif", "tiff"]);
+
+                if let Some(mut path) = dialog.save_file() {
+                    if path.extension().is_none() {
+                        path.set_extension("tif");
+                    }
+
+                    let grid = grid.clone();
+                    let georeference = loader::plan_georeference(&self.loaded_files);
+                    let name = locale.format("Exporting {}", &[&path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned())]);
+
+                    self.export_jobs.push(self.job_queue.submit(name, move |_| {
+                        grid.write_geotiff(&path, &georeference).map(|_| format!("Saved {}", path.display()))
+                    }));
+                }
+            }
+
+            self.export_raster_queued = false;
+        }
+
+        if self.profile_queued {
+            puffin::profile_scope!("profile");
+
+            let line = section::Section {
+                path: self.profile_line.iter().map(|p| p.truncate()).collect(),
+                half_width: self.profile_buffer as f64,
+            };
+            let colouring = colour::Colouring {
+                mode: colour::ColourMode::Rgb,
+                ramps: &self.colour_ramps,
+                elevation_range: loader::elevation_range(&self.loaded_files),
+                planes: &[],
+                plane_threshold: 0.0,
+                density: None,
+                distances: None,
+                distance_threshold: 1.0,
+                clusters: None,
+            };
+
+            self.profile = Some(profile::Profile::new(&line, &line.points(&self.point_batches, &self.point_filter, &colouring)));
+
+            self.profile_queued = false;
+        }
+
+        if self.section_queued {
+            puffin::profile_scope!("section");
+
+            let colouring = colour::Colouring {
+                mode: self.colour_mode,
+                ramps: &self.colour_ramps,
+                elevation_range: loader::elevation_range(&self.loaded_files),
+                planes: &self.detected_planes,
+                plane_threshold: self.plane_threshold as f64,
+                density: self.density_grid.as_ref(),
+                distances: self.cloud_distances.as_ref().and_then(|distances| distances.as_ref().ok()),
+                distance_threshold: self.distance_threshold as f64,
+                clusters: self.clusters.as_ref(),
+            };
+            let points = self.section.points(&self.point_batches, &self.point_filter, &colouring);
+
+            self.section_image = self.section.render(&points, self.section_pixel_size as f64).map(|(image, pixel_size, _)| {
+                let texture = self.egui_glium.egui_ctx.load_texture("section", egui::ColorImage::from_rgba_unmultiplied([image.width() as usize, image.height() as usize], image.as_raw()), egui::TextureFilter::Linear);
+                (image, texture, pixel_size)
+            });
+            if self.section_image.is_none() {
+                self.load_warnings.push(locale.tr("No points are within the section").to_owned());
+            }
+
+            self.section_queued = false;
+        }
+
+        if self.register_queued {
+            puffin::profile_scope!("register");
+
+            // Samples taken here, matched up on a worker
+            let (reference, moving) = self.registration_files;
+            let (target, source) = registration::samples(&self.point_batches, reference, moving);
+            let coarse = self.coarse_registration;
+            let job = self.job_queue.submit(locale.tr("Registering"), move |context| registration::register(target, source, coarse, context));
+            self.register_job = Some((job, moving));
+
+            self.register_queued = false;
+        }
+
+        if let Some(result) = self.register_job.as_ref().and_then(|(job, _)| job.poll()) {
+            let (_, moving) = self.register_job.take().expect("Registration job just polled");
+
+            if let Ok(result) = &result {
+                self.move_points_queued = Some((result.matrix, Some(moving)));
+            }
+            self.registration = Some(result);
+        }
+
+        if let Some((matrix, file)) = self.move_points_queued.take() {
+            puffin::profile_scope!("move_points");
+
+            transform::apply(self.point_batches.iter_mut().filter(|batch| file.is_none_or(|file| batch.file == file)), matrix);
+
+            for (i, file) in self.loaded_files.iter_mut().enumerate() {
+                file.bounds = self.point_batches.iter().filter(|batch| batch.file == i)
+                    .fold(None, |bounds, batch| loader::union_bounds(bounds, loader::bounds(&batch.points)));
+            }
+
+            // Everything found on the points where they were
+            self.detected_planes.clear();
+            self.planes_job = None;
+            self.levels.clear();
+            self.selected_level = None;
+            self.measure_points.clear();
+            self.contours.clear();
+            self.contour_lines = None;
+            self.elevation_raster = None;
+            self.raster_layer = None;
+            self.profile_line.clear();
+            self.profile = None;
+            self.picked_point = None;
+            self.mesh_preview = None;
+            self.mesh_preview_stats = None;
+            self.density_grid = None;
+            self.cloud_distances = None;
+            self.clusters = None;
+            self.minimap = None;
+            self.rebuild_vertex_buffers = true;
+        }
+
+        if self.rebuild_vertex_buffers {
+            puffin::profile_scope!("rebuild_vertex_buffers");
+
+            if matches!(self.colour_mode, colour::ColourMode::Density(_)) && self.density_grid.is_none() {
+                self.density_grid = Some(colour::DensityGrid::new(&self.point_batches, self.density_voxel_size as f64));
+            }
+            if matches!(self.colour_mode, colour::ColourMode::Distance(_)) && self.cloud_distances.is_none() {
+                let (reference, comparison) = self.change_files;
+                self.cloud_distances = Some(colour::CloudDistances::new(&self.point_batches, reference, comparison));
+            }
+            if let Some(vegetation) = &mut self.point_filter.vegetation {
+                vegetation.update_roughness(&self.point_batches);
+            }
+
+            let colouring = colour::Colouring {
+                mode: self.colour_mode,
+                ramps: &self.colour_ramps,
+                elevation_range: loader::elevation_range(&self.loaded_files),
+                planes: &self.detected_planes,
+                plane_threshold: self.plane_threshold as f64,
+                density: self.density_grid.as_ref(),
+                distances: self.cloud_distances.as_ref().and_then(|distances| distances.as_ref().ok()),
+                distance_threshold: self.distance_threshold as f64,
+                clusters: self.clusters.as_ref(),
+            };
+
+            self.vertex_buffers = self.point_batches.iter().map(|batch| {
+                glium::VertexBuffer::new(&self.display, &batch_vertices(batch, &self.point_filter, &colouring)).expect("Failed to create point vertex buffer.")
+            }).collect();
+            self.points_version += 1;
+
+            self.rebuild_vertex_buffers = false;
+        }
+
+        if self.detect_planes_queued {
+            puffin::profile_scope!("detect_planes");
+
+            // Evenly spaced sample of the visible points
+            const SAMPLE_SIZE: usize = 200_000;
+            let total: usize = self.point_batches.iter().map(|batch| batch.points.len()).sum();
+            let stride = (total / SAMPLE_SIZE).max(1);
+
+            let sample: Vec<glam::DVec3> = self.point_batches.iter()
+                .flat_map(|batch| batch.points.iter())
+                .step_by(stride)
+                .filter(|point| self.point_filter.accepts(point))
+                .map(|point| glam::dvec3(point.x, point.y, point.z))
+                .collect();
+
+            let threshold = self.plane_threshold as f64;
+            let max_planes = self.max_planes;
+            self.planes_job = Some(self.job_queue.submit(locale.tr("Detecting planes"), move |context| geometry::detect_planes(&sample, threshold, max_planes, 0.01, context)));
+
+            self.detect_planes_queued = false;
+        }
+
+        if let Some(result) = self.planes_job.as_ref().and_then(jobs::Job::poll) {
+            self.planes_job = None;
+
+            match result {
+                Ok(planes) => {
+                    self.detected_planes = planes;
+
+                    // Snap to the highest floor below the current clipping plane, or the lowest floor
+                    let forward = glam::Quat::from_euler(glam::EulerRot::YXZ, self.camera_rotation.x, self.camera_rotation.y, 0.0) * glam::Vec3::Z;
+                    let clip_height = render::COORDINATE_SYSTEM.transform_point3(self.camera_position + forward * Z_NEAR).z as f64 + self.centre.unwrap_or(glam::DVec3::ZERO).z;
+                    let floors = self.detected_planes.iter().filter(|detected| detected.kind == geometry::PlaneKind::Floor).map(|detected| detected.plane.origin.z);
+
+                    self.snap_clip_height = floors.clone().filter(|z| *z <= clip_height).reduce(f64::max).or_else(|| floors.reduce(f64::min));
+
+                    if !self.detected_planes.is_empty() {
+                        self.colour_mode = colour::ColourMode::Planes;
+                        self.rebuild_vertex_buffers = true;
+                    }
+                },
+                Err(err) => self.load_warnings.push(err),
+            }
+        }
+
+        if self.classify_ground_queued {
+            puffin::profile_scope!("classify_ground");
+
+            // Lowest shown points found here, the cloth settles against them on a worker
+            let bounds = self.loaded_files.iter().fold(None, |bounds, file| loader::union_bounds(bounds, file.bounds));
+            let points = self.point_batches.iter()
+                .flat_map(|batch| batch.points.iter())
+                .filter(|point| self.point_filter.accepts(point))
+                .map(|point| glam::dvec3(point.x, point.y, point.z));
+
+            let surface = bounds.ok_or_else(|| "There are no points to classify".to_owned())
+                .and_then(|bounds| ground::Surface::new(points, bounds, self.ground_settings.resolution));
+
+            match surface {
+                Ok(surface) => {
+                    if surface.spacing() > self.ground_settings.resolution {
+                        self.load_warnings.push(locale.format("Too large an area for a {} cloth resolution, classifying ground with {}", &[&format!("{:.2}", self.ground_settings.resolution), &format!("{:.2}", surface.spacing())]));
+                    }
+
+                    let settings = self.ground_settings;
+                    let job = self.job_queue.submit(locale.tr("Classifying ground"), move |context| ground::Cloth::settle(surface, &settings, context));
+                    self.ground_job = Some((job, self.point_batches.iter().map(|batch| batch.points.len()).collect()));
+                },
+                Err(err) => self.load_warnings.push(err),
+            }
+
+            self.classify_ground_queued = false;
+        }
+
+        if let Some(result) = self.ground_job.as_ref().and_then(|(job, _)| job.poll()) {
+            let (_, batch_sizes) = self.ground_job.take().expect("Ground job just polled");
+
+            match result {
+                Ok(cloth) if batch_sizes.iter().copied().eq(self.point_batches.iter().map(|batch| batch.points.len())) => {
+                    puffin::profile_scope!("apply_ground");
+
+                    // Only never classified, unclassified and ground points, so classes from elsewhere are kept
+                    let mut removed = filter::AttributeCounts::default();
+                    for point in self.point_batches.iter_mut().flat_map(|batch| batch.points.iter_mut()) {
+                        if u8::from(point.classification) > 2 || !self.point_filter.accepts(point) {
+                            continue;
+                        }
+
+                        let classification = if cloth.is_ground(glam::dvec3(point.x, point.y, point.z), self.ground_settings.threshold) {
+                            las::point::Classification::Ground
+                        } else {
+                            las::point::Classification::Unclassified
+                        };
+
+                        if point.classification != classification {
+                            removed.add(point);
+                            point.classification = classification;
+                            self.point_counts.add(point);
+                        }
+                    }
+                    self.point_counts.subtract(&removed);
+                    self.point_filter.retain_present(&self.point_counts);
+
+                    self.minimap = None;
+                    self.live_slice = None;
+                    self.colour_mode = colour::ColourMode::Classification;
+                    self.rebuild_vertex_buffers = true;
+                },
+                Ok(_) => self.load_warnings.push(locale.tr("Points were added or removed while classifying ground, classify again").to_owned()),
+                Err(err) => self.load_warnings.push(err),
+            }
+        }
+
+        if self.find_clusters_queued {
+            puffin::profile_scope!("find_clusters");
+
+            let points = loader::positions(&self.point_batches, &self.point_filter);
+            let (gap, min_points) = (self.cluster_gap as f64, self.min_cluster_points);
+
+            self.clusters_job = Some(self.job_queue.submit(locale.tr("Finding clusters"), move |_| Ok(cluster::Clusters::find(&points, gap, min_points))));
+
+            self.find_clusters_queued = false;
+        }
+
+        if let Some(result) = self.clusters_job.as_ref().and_then(jobs::Job::poll) {
+            self.clusters_job = None;
+
+            match result {
+                Ok(found) => {
+                    if found.sizes.is_empty() {
+                        self.load_warnings.push(locale.tr("No clusters have enough points, try a larger gap or fewer points").to_owned());
+                    }
+
+                    self.clusters = Some(found);
+                    self.colour_mode = colour::ColourMode::Clusters;
+                    self.rebuild_vertex_buffers = true;
+                },
+                Err(err) => self.load_warnings.push(err),
+            }
+        }
+
+        if let Some((cluster, mode)) = self.select_cluster_queued.take() {
+            if let Some(clusters) = &self.clusters {
+                clusters.select(&mut self.point_batches, &self.point_filter, cluster, mode);
+                self.selected_points = selection::count(&self.point_batches);
+                self.rebuild_vertex_buffers = true;
+            }
+        }
+
+        if self.detect_levels_queued {
+            puffin::profile_scope!("detect_levels");
+
+            // Evenly spaced sample of the visible points' elevations
+            const SAMPLE_SIZE: usize = 2_000_000;
+            let total: usize = self.point_batches.iter().map(|batch| batch.points.len()).sum();
+            let stride = (total / SAMPLE_SIZE).max(1);
+
+            let heights: Vec<f64> = self.point_batches.iter()
+                .flat_map(|batch| batch.points.iter())
+                .step_by(stride)
+                .filter(|point| self.point_filter.accepts(point))
+                .map(|point| point.z)
+                .collect();
+
+            self.levels = geometry::detect_levels(&heights, 0.01, 0.2);
+            self.selected_level = None;
+
+            self.detect_levels_queued = false;
+        }
+
+        // Look straight down with the clipping plane `cut_height` above the floor
+        if let Some(floor) = self.snap_clip_height.take() {
+            self.camera_rotation.y = std::f32::consts::FRAC_PI_2;
+            self.camera_position.y = (floor + self.cut_height as f64 - self.centre.unwrap_or(glam::DVec3::ZERO).z) as f32 + Z_NEAR;
+        }
+
+        if self.mesh_preview_queued {
+            puffin::profile_scope!("mesh_preview");
+
+            // The selected surface, or with nothing selected a cube at the centre of the view on the clipping plane
+            let points = if self.selected_points > 0 {
+                selection::positions(&self.point_batches, &self.point_filter)
+            } else {
+                let forward = glam::Quat::from_euler(glam::EulerRot::YXZ, self.camera_rotation.x, self.camera_rotation.y, 0.0) * glam::Vec3::Z;
+                let view_centre = render::COORDINATE_SYSTEM.transform_point3(self.camera_position + forward * Z_NEAR).as_dvec3() + self.centre.unwrap_or(glam::DVec3::ZERO);
+                let half_size = glam::DVec3::splat(self.mesh_region_size as f64 / 2.0);
+                let (region_min, region_max) = (view_centre - half_size, view_centre + half_size);
+
+                self.point_batches.par_iter().flat_map_iter(|batch| {
+                    batch.points.iter()
+                        .filter(|point| self.point_filter.accepts(point))
+                        .map(|point| glam::dvec3(point.x, point.y, point.z))
+                        .filter(|p| p.cmpge(region_min).all() && p.cmple(region_max).all())
+                }).collect()
+            };
+
+            let (cell_size, tolerance) = (self.mesh_cell_size as f64, self.mesh_tolerance as f64);
+            let unmeshed = locale.format("Couldn't mesh {} points, too few or too many cells at this cell size", &[&points.len()]);
+            self.mesh_job = Some(self.job_queue.submit(locale.tr("Meshing"), move |_| mesh::triangulate(&points, cell_size, tolerance).ok_or(unmeshed)));
+
+            self.mesh_preview_queued = false;
+        }
+
+        if let Some(result) = self.mesh_job.as_ref().and_then(jobs::Job::poll) {
+            self.mesh_job = None;
+
+            match result {
+                Ok(preview) => {
+                    self.mesh_preview = Some((
+                        glium::VertexBuffer::new(&self.display, &preview.vertices).expect("Failed to create mesh vertex buffer."),
+                        glium::IndexBuffer::new(&self.display, glium::index::PrimitiveType::TrianglesList, &preview.indices).expect("Failed to create mesh index buffer."),
+                    ));
+                    self.mesh_preview_stats = Some(preview);
+                },
+                Err(err) => self.load_warnings.push(err),
+            }
+        }
+
+        // Drag out a box or lasso, selecting once it's released
+        if let Some(tool) = self.select_tool {
+            let position = self.mouse.position();
+
+            match self.mouse.button_state(MouseButton::Left) {
+                MouseButtonState::JustPressed if position.is_finite() => self.select_outline = vec![position],
+                MouseButtonState::Pressed | MouseButtonState::JustPressed if !self.select_outline.is_empty() => match tool {
+                    selection::SelectTool::Box => self.select_outline = vec![self.select_outline[0], position],
+                    selection::SelectTool::Lasso => {
+                        if self.select_outline.last().is_some_and(|last| last.distance(position) >= 2.0) {
+                            self.select_outline.push(position);
+                        }
+                    },
+                },
+                MouseButtonState::JustReleased | MouseButtonState::Released if !self.select_outline.is_empty() => {
+                    puffin::profile_scope!("select");
+
+                    let outline = match tool {
+                        selection::SelectTool::Box => plan::rectangle(self.select_outline[0], position).to_vec(),
+                        selection::SelectTool::Lasso => std::mem::take(&mut self.select_outline),
+                    };
+                    let mode = if self.keyboard.is_pressed(VirtualKeyCode::LShift) {
+                        selection::SelectMode::Add
+                    } else if self.keyboard.is_pressed(VirtualKeyCode::LAlt) {
+                        selection::SelectMode::Remove
+                    } else {
+                        selection::SelectMode::Replace
+                    };
+
+                    let window_size = glam::vec2(window_width as f32, window_height as f32);
+                    selection::select(&mut self.point_batches, &self.point_filter, &outline, window_size, camera_mvp, self.centre.unwrap_or(glam::DVec3::ZERO), mode);
+                    self.selected_points = selection::count(&self.point_batches);
+
+                    self.select_outline.clear();
+                    self.rebuild_vertex_buffers = true;
+                },
+                _ => {},
+            }
+        } else {
+            self.select_outline.clear();
+        }
+
+        if let Some(r) = &self.script_rx {
+            match r.try_recv() {
+                // Scripts render on their own context, so they can run on a worker like the --script command
+                Ok(path) => match context::headless_context(window_target, self.gl_profile) {
+                    Ok(context) => {
+                        let point_size = self.point_size;
+                        let config = self.config.clone();
+                        let name = locale.format("Running {}", &[&recent::file_name(&path.to_string_lossy())]);
+
+                        self.script_job = Some(self.job_queue.submit(name, move |job| {
+                            let renderer = glium::HeadlessRenderer::new(context).map_err(|err| format!("Unable to create an OpenGL context: {}", err))?;
+
+                            script::run(&renderer, &path, point_size, &config, job).map(|()| println!("Finished {}", path.display()))
+                        }));
+                    },
+                    Err(err) => self.load_warnings.push(err),
+                },
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.script_rx = None;
+                },
+                Err(mpsc::TryRecvError::Empty) => {},
+            }
+        }
+
+        if let Some(result) = self.script_job.as_ref().and_then(jobs::Job::poll) {
+            self.script_job = None;
+
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                self.load_warnings.push(err);
+            }
+        }
+
+        if self.export_selection_queued {
+            let dialog = rfd::FileDialog::new()
+                .add_filter("LAS", &["las"])
+                .add_filter("LAZ", &["laz"])
+                .set_file_name("selection.las");
+
+            if let Some(path) = dialog.save_file() {
+                let files: Vec<String> = self.loaded_files.iter().map(|file| file.path.clone()).collect();
+
+                match subset::export_selection(&self.point_batches, &files, &path) {
+                    Ok(written) => println!("Saved {} points to {}", written, path.display()),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        self.load_warnings.push(err);
+                    },
+                }
+            }
+
+            self.export_selection_queued = false;
+        }
+
+        if self.clear_selection_queued {
+            selection::clear(&mut self.point_batches);
+            self.selected_points = 0;
+            self.rebuild_vertex_buffers = true;
+
+            self.clear_selection_queued = false;
+        }
+
+        if self.delete_selection_queued {
+            puffin::profile_scope!("delete_selection");
+
+            let (changed, removed) = selection::delete(&mut self.point_batches);
+
+            self.point_counts.subtract(&removed);
+            self.point_filter.retain_present(&self.point_counts);
+
+            let colouring = colour::Colouring {
+                mode: self.colour_mode,
+                ramps: &self.colour_ramps,
+                elevation_range: loader::elevation_range(&self.loaded_files),
+                planes: &self.detected_planes,
+                plane_threshold: self.plane_threshold as f64,
+                density: self.density_grid.as_ref(),
+                distances: self.cloud_distances.as_ref().and_then(|distances| distances.as_ref().ok()),
+                distance_threshold: self.distance_threshold as f64,
+                clusters: self.clusters.as_ref(),
+            };
+
+            // Only the batches points were removed from, dropping any left empty
+            for &i in changed.iter().rev() {
+                if self.point_batches[i].points.is_empty() {
+                    self.point_batches.remove(i);
+                    self.vertex_buffers.remove(i);
+                } else {
+                    self.vertex_buffers[i] = glium::VertexBuffer::new(&self.display, &batch_vertices(&self.point_batches[i], &self.point_filter, &colouring)).expect("Failed to create point vertex buffer.");
+                }
+            }
+            self.points_version += 1;
+
+            // Densities and distances of the remaining points are found again
+            self.density_grid = None;
+            self.cloud_distances = None;
+            self.minimap = None;
+            self.live_slice = None;
+            if matches!(self.colour_mode, colour::ColourMode::Density(_) | colour::ColourMode::Distance(_)) {
+                self.rebuild_vertex_buffers = true;
+            }
+            // And clusters, without the ones deleted
+            self.find_clusters_queued |= self.clusters.is_some();
+
+            self.selected_points = 0;
+            self.picked_point = None;
+
+            self.delete_selection_queued = false;
+        }
+    }
+
+    /// Floor plan, quick render or stage dump from a slice once it's been processed
+    pub(super) fn poll_processed_slice(&mut self) {
+        let locale = &self.languages[self.language];
+
+        if self.reprocess_slice_queued {
+            if let Some(capture) = &self.last_slice_capture {
+                let radius = self.connect_radius.unwrap_or(capture.auto_connect_radius);
+
+                self.processed_slice = Some(self.job_queue.submit(locale.tr("Processing slice"), process_slice(capture.clone(), self.slice_processors.shared(self.slice_processor).clone(), None, radius, self.alpha_threshold, SliceUse::Reprocess)));
+            }
+
+            self.reprocess_slice_queued = false;
+        }
+
+        if let Some(result) = self.processed_slice.as_ref().and_then(jobs::Job::poll) {
+            self.processed_slice = None;
+
+            match result {
+                Ok(processed) => {
+
+                    match processed.slice_use {
+                        SliceUse::DumpStages => {
+                            let stages = stages::Stages {
+                                cutaway: &processed.capture.cutaway,
+                                slice: &processed.capture.raw,
+                                slice_points: &processed.found_points,
+                                connected: &processed.image,
+                                connect_radius: processed.connect_radius,
+                                alpha_threshold: processed.alpha_threshold,
+                                hough_parameters: &self.hough_parameters,
+                            };
+
+                            match stages::dump(Path::new(&self.output_dir), &stages) {
+                                Ok(folder) => {
+                                    println!("Saved pipeline stages to {}", folder.display());
+                                    self.last_stage_dump = folder.file_name().map(|name| name.to_string_lossy().into_owned());
+                                },
+                                Err(err) => eprintln!("{}", err),
+                            }
+                        },
+                        SliceUse::QuickRender => {
+                            // Write straight to the output directory and stay in the 3D viewer
+                            let directory = PathBuf::from(&self.output_dir);
+                            let index = config::next_output_index(&directory, &self.config);
+
+                            let cutaway_path = directory.join(config::file_name(&self.config.cutaway_template, index));
+                            let floor_plan_path = directory.join(config::file_name(&self.config.floor_plan_template, index));
+
+                            // Leaves the floor plan being edited untouched
+                            match processed.capture.cutaway.save(&cutaway_path).and_then(|_| plan::save_image(&processed.image, self.floor_plan_depth, &floor_plan_path)) {
+                                Ok(_) => {
+                                    println!("Saved {}", cutaway_path.display());
+                                    self.last_quick_render = cutaway_path.file_name().map(|name| name.to_string_lossy().into_owned());
+
+                                    let georeference = loader::plan_georeference(&self.loaded_files);
+                                    for path in [&cutaway_path, &floor_plan_path] {
+                                        georeference_image(path, &processed.capture.transform, &georeference);
+                                    }
+                                },
+                                Err(err) => eprintln!("Failed to save quick render to {}: {}", directory.display(), err),
+                            }
+                        },
+                        SliceUse::FloorPlan => {
+                            let image = processed.image;
+
+                            // Propose straight walls from the raw slice, before any connecting lines
+                            self.wall_proposals = hough::detect_segments(image.width(), image.height(), &processed.found_points, &self.hough_parameters);
+                            self.slice_points = processed.found_points;
+
+                            self.floor_plan = Some(plan::FloorPlan::new(processed.capture.cutaway.clone(), processed.capture.raw.clone(), image));
+                            advance_tutorial(&mut self.tutorial_step, tutorial::Step::PositionClip);
+                            advance_tutorial(&mut self.tutorial_step, tutorial::Step::Render);
+                            self.label_draft = None;
+                            self.rooms_changed = true;
+                            self.capture_transform = Some(processed.capture.transform);
+                            self.last_slice_capture = Some(processed.capture);
+
+                            self.drawing_mode = true;
+                        },
+                        SliceUse::Reprocess => {
+                            if let Some(floor_plan) = &mut self.floor_plan {
+                                let image = processed.image;
+
+                                self.wall_proposals = hough::detect_segments(image.width(), image.height(), &processed.found_points, &self.hough_parameters);
+                                self.slice_points = processed.found_points;
+
+                                floor_plan.walls.image = image;
+                                floor_plan.traced_walls = None;
+                                self.rooms_changed = true;
+                            }
+                        },
+                    }
+                },
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    }
+}
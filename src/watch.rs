@@ -0,0 +1,82 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::mpsc::{self, Receiver}, time::{Duration, Instant}};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Time a file has to go unchanged before it's loaded, so files the scanner is still writing aren't loaded half done
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+/// Watches a folder for LAS and LAZ files appearing or changing as scanning goes on, e.g. a synced scanner export
+/// folder, so coverage builds up in the viewer live
+pub struct FolderWatch {
+    dir: PathBuf,
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+    /// Files changed and when they last changed, waiting to settle
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl FolderWatch {
+    /// Watch `dir`, with the point clouds already in it pending so they're loaded too
+    pub fn new(dir: &Path) -> Result<FolderWatch, String> {
+        let (tx, rx) = mpsc::channel();
+
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // Stops when the watch is dropped
+            let _ = tx.send(event);
+        }).map_err(|err| format!("Failed to watch {}: {}", dir.display(), err))?;
+        watcher.watch(dir, RecursiveMode::NonRecursive).map_err(|err| format!("Failed to watch {}: {}", dir.display(), err))?;
+
+        let existing = std::fs::read_dir(dir).map_err(|err| format!("Failed to read {}: {}", dir.display(), err))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| is_point_cloud(path));
+
+        // Already settled
+        let settled = Instant::now() - SETTLE_TIME;
+
+        Ok(FolderWatch {
+            dir: dir.to_owned(),
+            _watcher: watcher,
+            rx,
+            pending: existing.map(|path| (path, settled)).collect(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Files changed but not settled yet
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Point clouds created or changed that haven't changed again for a while, oldest first
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>, String> {
+        for event in self.rx.try_iter() {
+            let event = event.map_err(|err| format!("Stopped watching {}: {}", self.dir.display(), err))?;
+
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                for path in event.paths.into_iter().filter(|path| is_point_cloud(path)) {
+                    self.pending.insert(path, Instant::now());
+                }
+            }
+        }
+
+        let mut settled: Vec<(PathBuf, Instant)> = self.pending.iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE_TIME)
+            .map(|(path, changed)| (path.clone(), *changed))
+            .collect();
+        settled.sort_by_key(|(_, changed)| *changed);
+
+        for (path, _) in &settled {
+            self.pending.remove(path);
+        }
+
+        Ok(settled.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+fn is_point_cloud(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("las") || extension.eq_ignore_ascii_case("laz"))
+}
@@ -0,0 +1,131 @@
+use std::{fs, path::Path};
+
+use base64::Engine;
+use glam::{DVec2, DVec3};
+use image::RgbaImage;
+use serde_json::json;
+
+use crate::{colour::Colouring, export, filter::PointFilter, georef::Georeference, loader::{self, PointBatch}, plan::{CaptureTransform, FloorPlan}};
+
+/// Points in a web viewer unless asked for fewer, about as many as a phone's browser draws smoothly
+pub const WEB_VIEWER_POINTS: usize = 1_000_000;
+
+const VIEWER_PAGE: &str = include_str!("web/index.html");
+
+/// Room on the floor plan, for the web viewer
+pub struct Room {
+    pub name: String,
+    pub colour: [u8; 3],
+    /// Square file units
+    pub area: f64,
+    /// Outer boundary and any holes, in floor plan image coordinates
+    pub outlines: Vec<Vec<DVec2>>,
+}
+
+/// Filled rooms of the floor plan, numbered as in the rooms list
+pub fn rooms(floor_plan: &FloorPlan, transform: &CaptureTransform) -> Vec<Room> {
+    floor_plan.room_regions().into_iter()
+        .enumerate()
+        .map(|(i, (colour, region))| Room {
+            name: format!("Room {}", i + 1),
+            colour,
+            area: region.len() as f64 * transform.pixel_area(),
+            outlines: export::room_outlines(floor_plan, region),
+        })
+        .collect()
+}
+
+/// Evenly thinned out points in their current colours, relative to the centre of their bounds
+pub struct WebPoints {
+    pub origin: DVec3,
+    pub positions: Vec<[f32; 3]>,
+    pub colours: Vec<[u8; 3]>,
+}
+
+impl WebPoints {
+    /// At most about `max_points` of the points the filter shows, taking every nth so coverage stays even
+    pub fn new(batches: &[PointBatch], filter: &PointFilter, colouring: &Colouring, max_points: usize) -> WebPoints {
+        let total: usize = batches.iter().map(|batch| batch.points.len()).sum();
+        let stride = total.div_ceil(max_points.max(1)).max(1);
+
+        let points: Vec<(&las::Point, usize)> = batches.iter()
+            .flat_map(|batch| batch.points.iter().map(|point| (point, batch.file)))
+            .step_by(stride)
+            .filter(|(point, _)| filter.accepts(point))
+            .collect();
+
+        let origin = points.iter()
+            .map(|(point, _)| glam::dvec3(point.x, point.y, point.z))
+            .fold(None, |bounds, p| loader::union_bounds(bounds, Some((p, p))))
+            .map_or(DVec3::ZERO, |(min, max)| (min + max) / 2.0);
+
+        WebPoints {
+            origin,
+            positions: points.iter().map(|(point, _)| (glam::dvec3(point.x, point.y, point.z) - origin).as_vec3().to_array()).collect(),
+            colours: points.iter().map(|(point, file)| colouring.colour(point, *file)).collect(),
+        }
+    }
+}
+
+/// Self-contained folder showing the cutaway, its rooms and the points in any browser, opened straight from disk
+/// without a server or anything installed, for sharing results
+pub struct WebViewer {
+    pub title: String,
+    /// Cutaway with annotations, as saved
+    pub cutaway: RgbaImage,
+    pub transform: CaptureTransform,
+    pub rooms: Vec<Room>,
+    pub points: WebPoints,
+    pub georeference: Georeference,
+}
+
+impl WebViewer {
+    /// Write `index.html`, the cutaway and the data it shows into `dir`. The data is a script rather than JSON
+    /// because browsers won't fetch files next to a page opened from disk.
+    pub fn write(&self, dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+
+        let cutaway_path = dir.join("cutaway.png");
+        self.cutaway.save(&cutaway_path).map_err(|err| format!("Failed to save {}: {}", cutaway_path.display(), err))?;
+
+        let base64 = &base64::engine::general_purpose::STANDARD;
+        let positions: Vec<u8> = self.points.positions.iter().flatten().flat_map(|value| value.to_le_bytes()).collect();
+        let colours: Vec<u8> = self.points.colours.iter().flatten().copied().collect();
+
+        let data = json!({
+            "title": self.title,
+            "crs": self.georeference.crs_name(),
+            "decimals": self.georeference.decimals(),
+            "plan": {
+                "image": "cutaway.png",
+                "width": self.cutaway.width(),
+                "height": self.cutaway.height(),
+                "origin": self.transform.origin.to_array(),
+                "pixelX": self.transform.pixel_x.to_array(),
+                "pixelY": self.transform.pixel_y.to_array(),
+            },
+            "rooms": self.rooms.iter().map(|room| json!({
+                "name": room.name,
+                "colour": format!("#{:02x}{:02x}{:02x}", room.colour[0], room.colour[1], room.colour[2]),
+                "area": room.area,
+                "outlines": room.outlines.iter()
+                    .map(|outline| outline.iter().map(|point| [point.x, point.y]).collect::<Vec<_>>())
+                    .collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+            "points": {
+                "count": self.points.positions.len(),
+                "origin": self.points.origin.to_array(),
+                "positions": base64.encode(positions),
+                "colours": base64.encode(colours),
+            },
+        });
+
+        let write = |name: &str, contents: &str| {
+            let path = dir.join(name);
+            fs::write(&path, contents).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+        };
+
+        write("data.js", &format!("var VIEWER = {};\n", data))?;
+        write("index.html", VIEWER_PAGE)
+    }
+}